@@ -0,0 +1,611 @@
+//! A small passphrase-caching agent, which applications can embed.
+//!
+//! An [`Agent`] listens on a Unix socket, and answers requests for passphrases from
+//! [`Client`]s (usually other invocations of the same application). Each passphrase is
+//! cached under a key chosen by the client (such as the name or fingerprint of what it
+//! unlocks), so that only the first request prompts the user; later requests are
+//! answered from the cache until the passphrase expires, or has been returned a
+//! maximum number of times. Prompts are shown with the agent's [`PassphraseProvider`]
+//! (usually a [`PassphraseInput`]).
+//!
+//! Only processes of the same user may connect to the agent: on Linux, Android, macOS,
+//! and the BSDs, connections from other users are rejected. The socket is only
+//! accessible by its owner, but should still be placed in a directory that only the
+//! user can access (such as `$XDG_RUNTIME_DIR`).
+//!
+//! # Protocol
+//!
+//! The protocol is a small subset of Assuan, in the same style as `pinentry`'s. The
+//! agent greets each connection with `OK`, and answers these requests, whose parameters
+//! are percent-encoded with [`encoding::encode`]:
+//!
+//! - `SETDESC`, `SETPROMPT`, `SETERROR`, and `SETREPEAT` set the description, prompt,
+//!   error, and confirmation prompt used by later `GET` requests on the connection.
+//! - `GET <key>` returns the passphrase cached under the key in a data line, prompting
+//!   for it first if it is not cached.
+//! - `CLEAR <key>` removes the passphrase cached under the key, if any.
+//! - `BYE` closes the connection.
+//!
+//! Errors are returned as `ERR` responses with GPG error codes (for example,
+//! `ERR 99 Operation cancelled` if the user cancelled the prompt).
+//!
+//! [`encoding::encode`]: crate::encoding::encode
+//! [`PassphraseInput`]: crate::PassphraseInput
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use secrecy::{ExposeSecret, SecretString};
+use zeroize::Zeroizing;
+
+use crate::assuan::{self, Response};
+use crate::encoding;
+use crate::error::{GPG_ERR_CANCELED, GPG_ERR_NOT_CONFIRMED, GPG_ERR_TIMEOUT};
+use crate::unix;
+use crate::{Error, PassphraseProvider, PassphraseRequest, Result};
+
+/// How long passphrases are cached for, unless set with [`Agent::with_ttl`].
+///
+/// This is the same as `gpg-agent`'s `default-cache-ttl`.
+const DEFAULT_TTL: Duration = Duration::from_secs(600);
+
+/// The maximum length of a request or response line, including the trailing newline.
+const MAX_LINE_LEN: usize = 1000;
+
+/// `GPG_ERR_GENERAL`, returned for errors that have no more specific code.
+const GPG_ERR_GENERAL: u16 = 1;
+/// `GPG_ERR_ASS_UNKNOWN_CMD`, returned for requests that the agent does not support.
+const GPG_ERR_ASS_UNKNOWN_CMD: u16 = 275;
+
+/// A passphrase in the cache of an [`Agent`].
+struct Entry {
+    passphrase: SecretString,
+    expires: Instant,
+    /// The number of times that the passphrase may still be returned, if limited.
+    uses_left: Option<u32>,
+}
+
+/// A passphrase-caching agent, which answers requests from [`Client`]s over a Unix
+/// socket.
+///
+/// The agent handles one connection at a time, so a client that is waiting for the
+/// user to answer a prompt delays the others.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use pinentry::{agent::Agent, PassphraseInput};
+///
+/// if let Some(input) = PassphraseInput::with_default_binary() {
+///     let listener = Agent::bind("/run/user/1000/foobar-agent.sock")?;
+///     Agent::new(&input)
+///         .with_ttl(Duration::from_secs(15 * 60))
+///         .with_max_uses(100)
+///         .serve(&listener)?;
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct Agent<'a> {
+    provider: &'a dyn PassphraseProvider,
+    ttl: Duration,
+    max_uses: Option<u32>,
+    cache: HashMap<String, Entry>,
+}
+
+impl<'a> Agent<'a> {
+    /// Creates an agent with an empty cache, which prompts for passphrases with the
+    /// given provider.
+    ///
+    /// Passphrases are cached for 10 minutes, and may be returned any number of times in
+    /// that time.
+    pub fn new(provider: &'a dyn PassphraseProvider) -> Self {
+        Agent {
+            provider,
+            ttl: DEFAULT_TTL,
+            max_uses: None,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Sets how long passphrases are cached for, from when they are entered.
+    pub fn with_ttl(&mut self, ttl: Duration) -> &mut Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets the number of times that a passphrase may be returned (including the
+    /// request that prompted for it) before it is removed from the cache.
+    ///
+    /// With `1`, passphrases are never cached.
+    pub fn with_max_uses(&mut self, max_uses: u32) -> &mut Self {
+        self.max_uses = Some(max_uses);
+        self
+    }
+
+    /// Creates a socket at the given path, which only its owner can connect to, for
+    /// [`Agent::serve`].
+    ///
+    /// A socket left behind at the path by an agent that is no longer running is
+    /// replaced. Returns an error of kind [`io::ErrorKind::AddrInUse`] if an agent is
+    /// still listening on it.
+    ///
+    /// The socket is created in a temporary directory next to the path, so the
+    /// directory containing the path must be writable.
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<UnixListener> {
+        let path = path.as_ref();
+        if fs::symlink_metadata(path).map_or(false, |metadata| metadata.file_type().is_socket()) {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    "an agent is already listening on this socket",
+                ));
+            }
+            fs::remove_file(path)?;
+        }
+
+        // The socket is created in a new directory that only the user can access, and
+        // only linked to the path once its permissions are set, so that other users can
+        // never connect to it. Unlike a rename, linking never replaces another file.
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let dir = unix::private_dir(parent, ".pinentry-agent")?;
+        let socket = dir.join("socket");
+        let res = UnixListener::bind(&socket).and_then(|listener| {
+            fs::set_permissions(&socket, fs::Permissions::from_mode(0o600))?;
+            fs::hard_link(&socket, path)?;
+            Ok(listener)
+        });
+        let _ = fs::remove_dir_all(&dir);
+        res
+    }
+
+    /// Handles connections to the given socket, one at a time, until accepting a
+    /// connection fails.
+    ///
+    /// Errors on individual connections (such as a client disconnecting early) are
+    /// ignored.
+    pub fn serve(&mut self, listener: &UnixListener) -> io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept()?;
+            let _ = self.handle(stream);
+        }
+    }
+
+    /// Handles the requests on a single connection, until the client closes it.
+    ///
+    /// Returns an error of kind [`io::ErrorKind::PermissionDenied`] (without handling
+    /// anything) if the client is another user's process.
+    pub fn handle(&mut self, stream: UnixStream) -> io::Result<()> {
+        if peer_uid(&stream)? != Some(current_uid()) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "agent clients must be processes of the same user",
+            ));
+        }
+        let mut reader = BufReader::new(&stream);
+        let mut writer = &stream;
+        writer.write_all(b"OK Pleased to meet you\n")?;
+
+        let mut request = PassphraseRequest::new();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if (&mut reader)
+                .take(MAX_LINE_LEN as u64)
+                .read_line(&mut line)?
+                == 0
+            {
+                return Ok(());
+            }
+            let line = match line.strip_suffix('\n') {
+                Some(line) => line,
+                None => {
+                    // The request was too long (or the client disconnected mid-line), so
+                    // the rest of the connection cannot be parsed.
+                    return write_err(&mut writer, GPG_ERR_GENERAL, "Line too long");
+                }
+            };
+            let (command, parameter) = match line.split_once(' ') {
+                Some((command, parameter)) => (command, Some(parameter)),
+                None => (line, None),
+            };
+            let parameter = match parameter.map(|p| String::from_utf8(encoding::decode(p))) {
+                Some(Ok(parameter)) => parameter,
+                Some(Err(_)) => {
+                    write_err(&mut writer, GPG_ERR_GENERAL, "Invalid parameter")?;
+                    continue;
+                }
+                None => String::new(),
+            };
+
+            let res = match command {
+                "SETDESC" => {
+                    request.with_description(parameter);
+                    Ok(None)
+                }
+                "SETPROMPT" => {
+                    request.with_prompt(parameter);
+                    Ok(None)
+                }
+                "SETERROR" => {
+                    request.with_error(parameter);
+                    Ok(None)
+                }
+                "SETREPEAT" => {
+                    request.with_confirmation_prompt(parameter);
+                    Ok(None)
+                }
+                "GET" => self.get(&parameter, &request).map(Some),
+                "CLEAR" => {
+                    self.clear(&parameter);
+                    Ok(None)
+                }
+                "BYE" => return writer.write_all(b"OK closing connection\n"),
+                _ => {
+                    write_err(&mut writer, GPG_ERR_ASS_UNKNOWN_CMD, "Unknown IPC command")?;
+                    continue;
+                }
+            };
+            match res {
+                Ok(Some(passphrase)) => {
+                    let data = Zeroizing::new(encoding::encode(passphrase.expose_secret()));
+                    if data.len() + 3 > MAX_LINE_LEN {
+                        write_err(&mut writer, GPG_ERR_GENERAL, "Passphrase is too long")?;
+                        continue;
+                    }
+                    let mut response = Zeroizing::new(String::with_capacity(data.len() + 6));
+                    response.push_str("D ");
+                    response.push_str(&data);
+                    response.push_str("\nOK\n");
+                    writer.write_all(response.as_bytes())?;
+                }
+                Ok(None) => writer.write_all(b"OK\n")?,
+                Err(e) => write_err(&mut writer, error_code(&e), &e.to_string())?,
+            }
+        }
+    }
+
+    /// Returns the passphrase cached under the given key, or else asks the provider for
+    /// it (with the given request) and caches it under the key.
+    pub fn get(&mut self, key: &str, request: &PassphraseRequest<'_>) -> Result<SecretString> {
+        let now = Instant::now();
+        self.cache.retain(|_, entry| entry.expires > now);
+
+        if let Some(entry) = self.cache.get_mut(key) {
+            let passphrase = SecretString::from(entry.passphrase.expose_secret().to_owned());
+            if let Some(uses_left) = &mut entry.uses_left {
+                *uses_left -= 1;
+                if *uses_left == 0 {
+                    self.cache.remove(key);
+                }
+            }
+            return Ok(passphrase);
+        }
+
+        let passphrase = self.provider.passphrase(request)?;
+        let uses_left = self.max_uses.map(|max_uses| max_uses.saturating_sub(1));
+        if uses_left != Some(0) {
+            self.cache.insert(
+                key.to_owned(),
+                Entry {
+                    passphrase: SecretString::from(passphrase.expose_secret().to_owned()),
+                    expires: now + self.ttl,
+                    uses_left,
+                },
+            );
+        }
+        Ok(passphrase)
+    }
+
+    /// Removes the passphrase cached under the given key, if any.
+    pub fn clear(&mut self, key: &str) {
+        self.cache.remove(key);
+    }
+
+    /// Removes every cached passphrase.
+    pub fn clear_all(&mut self) {
+        self.cache.clear();
+    }
+}
+
+/// Writes an `ERR` response.
+fn write_err(writer: &mut impl Write, code: u16, description: &str) -> io::Result<()> {
+    writer.write_all(format!("ERR {} {}\n", code, encoding::encode(description)).as_bytes())
+}
+
+/// Returns the GPG error code that is sent to clients for the given error.
+fn error_code(e: &Error) -> u16 {
+    match e {
+        Error::Cancelled => GPG_ERR_CANCELED,
+        Error::Timeout => GPG_ERR_TIMEOUT,
+        Error::NotConfirmed => GPG_ERR_NOT_CONFIRMED,
+        Error::Gpg(e) => e.code(),
+        _ => GPG_ERR_GENERAL,
+    }
+}
+
+fn current_uid() -> libc::uid_t {
+    // Safety: `getuid` has no memory-safety preconditions.
+    unsafe { libc::getuid() }
+}
+
+/// Returns the user ID of the process at the other end of the given socket, or `None`
+/// if it cannot be determined on this platform.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> io::Result<Option<libc::uid_t>> {
+    use std::os::unix::io::AsRawFd;
+
+    // Safety: `ucred` is valid when zeroed.
+    let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // Safety: `cred` is valid for `len` bytes.
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Some(cred.uid))
+}
+
+/// Returns the user ID of the process at the other end of the given socket, or `None`
+/// if it cannot be determined on this platform.
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+))]
+fn peer_uid(stream: &UnixStream) -> io::Result<Option<libc::uid_t>> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut uid = 0;
+    let mut gid = 0;
+    // Safety: `uid` and `gid` are valid for writes.
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Some(uid))
+}
+
+/// Returns the user ID of the process at the other end of the given socket, or `None`
+/// if it cannot be determined on this platform.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+)))]
+fn peer_uid(_: &UnixStream) -> io::Result<Option<libc::uid_t>> {
+    // Only the permissions of the socket restrict who can connect.
+    Ok(Some(current_uid()))
+}
+
+/// A client of an [`Agent`].
+///
+/// Each request is made over a new connection to the agent's socket.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{agent::Client, PassphraseRequest};
+///
+/// let client = Client::new("/run/user/1000/foobar-agent.sock");
+/// let passphrase = client.get(
+///     "foobar/vault",
+///     PassphraseRequest::new().with_description("Unlock FooBar"),
+/// )?;
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Client {
+    path: PathBuf,
+}
+
+impl Client {
+    /// Creates a client of the agent listening on the socket at the given path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Client { path: path.into() }
+    }
+
+    /// Returns the passphrase cached by the agent under the given key, prompting for it
+    /// with the given request if it is not cached.
+    ///
+    /// Returns [`Error::Cancelled`] if the user cancelled the prompt, and [`Error::Io`]
+    /// if the agent is not running.
+    pub fn get(&self, key: &str, request: &PassphraseRequest<'_>) -> Result<SecretString> {
+        let settings = [
+            ("SETDESC", request.description()),
+            ("SETPROMPT", request.prompt()),
+            ("SETERROR", request.error()),
+            ("SETREPEAT", request.confirmation_prompt()),
+            ("GET", Some(key)),
+        ];
+        let passphrase = self.request(&settings)?;
+        passphrase.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "agent returned no passphrase").into()
+        })
+    }
+
+    /// Asks the agent to remove the passphrase cached under the given key, if any.
+    pub fn clear(&self, key: &str) -> Result<()> {
+        self.request(&[("CLEAR", Some(key))]).map(|_| ())
+    }
+
+    /// Returns a [`PassphraseProvider`] that asks the agent for the passphrase cached
+    /// under the given key.
+    pub fn provider<'a>(&'a self, key: &'a str) -> impl PassphraseProvider + 'a {
+        move |request: &PassphraseRequest<'_>| self.get(key, request)
+    }
+
+    /// Sends the given requests (skipping any without a parameter) over a new
+    /// connection, and returns the data returned by the last one.
+    fn request(&self, requests: &[(&str, Option<&str>)]) -> Result<Option<SecretString>> {
+        let stream = UnixStream::connect(&self.path)?;
+        let mut reader = BufReader::new(&stream);
+        let mut writer = &stream;
+        read_response(&mut reader)?;
+        let mut data = None;
+        for (command, parameter) in requests {
+            if let Some(parameter) = parameter {
+                let request = assuan::encode_request(command, Some(parameter))?;
+                writer.write_all(request.as_bytes())?;
+                data = read_response(&mut reader)?;
+            }
+        }
+        // Wait for the agent to acknowledge, so that it does not write to a closed
+        // connection.
+        let _ = writer
+            .write_all(b"BYE\n")
+            .map(|()| read_response(&mut reader));
+        Ok(data)
+    }
+}
+
+/// Reads a response from an agent, returning the data in it (if any).
+fn read_response(reader: &mut impl BufRead) -> Result<Option<SecretString>> {
+    let mut data: Option<Zeroizing<Vec<u8>>> = None;
+    loop {
+        let mut line = Zeroizing::new(String::new());
+        if (&mut *reader)
+            .take(MAX_LINE_LEN as u64)
+            .read_line(&mut line)?
+            == 0
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "agent closed the connection",
+            )
+            .into());
+        }
+        match assuan::parse_response(&line) {
+            Some(Response::Ok(_)) => break,
+            Some(Response::Err { code, description }) => {
+                return Err(Error::from_parts(code, description))
+            }
+            Some(Response::DataLine(data_line)) => {
+                let decoded = Zeroizing::new(encoding::decode(data_line));
+                let data = data.get_or_insert_with(|| Zeroizing::new(vec![]));
+                data.extend_from_slice(&decoded);
+            }
+            Some(_) => (),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid response from agent",
+                )
+                .into())
+            }
+        }
+    }
+    data.map(|mut data| {
+        String::from_utf8(std::mem::take(&mut *data))
+            .map(SecretString::from)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e).into())
+    })
+    .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::thread;
+    use std::time::Duration;
+
+    use secrecy::{ExposeSecret, SecretString};
+
+    use super::{Agent, Client};
+    use crate::{Error, PassphraseRequest};
+
+    #[test]
+    fn cache() {
+        let prompts = Cell::new(0);
+        let provider = |_: &PassphraseRequest<'_>| {
+            prompts.set(prompts.get() + 1);
+            Ok(SecretString::from("hunter2"))
+        };
+        let request = PassphraseRequest::new();
+
+        let mut agent = Agent::new(&provider);
+        agent.with_max_uses(2);
+        for _ in 0..3 {
+            assert_eq!(agent.get("a", &request).unwrap().expose_secret(), "hunter2");
+        }
+        // The third request found that the passphrase had been used twice.
+        assert_eq!(prompts.get(), 2);
+        agent.clear("a");
+        agent.get("a", &request).unwrap();
+        assert_eq!(prompts.get(), 3);
+
+        let mut agent = Agent::new(&provider);
+        agent.with_ttl(Duration::ZERO);
+        agent.get("a", &request).unwrap();
+        agent.get("a", &request).unwrap();
+        assert_eq!(prompts.get(), 5);
+    }
+
+    #[test]
+    fn socket() {
+        let dir = crate::unix::private_dir(&std::env::temp_dir(), "pinentry-agent").unwrap();
+        let path = dir.join("agent.sock");
+        let listener = Agent::bind(&path).unwrap();
+
+        let client = Client::new(&path);
+        let requests = thread::spawn(move || {
+            let get = |key| {
+                client.get(
+                    key,
+                    PassphraseRequest::new().with_description("Unlock 100% of FooBar"),
+                )
+            };
+            let passphrase = get("foobar").map(|p| p.expose_secret().to_owned());
+            (passphrase, get("cancel").map(|_| ()))
+        });
+
+        let provider = |request: &PassphraseRequest<'_>| {
+            assert_eq!(request.description(), Some("Unlock 100% of FooBar"));
+            Ok(SecretString::from("line\nbreak"))
+        };
+        let mut agent = Agent::new(&provider);
+        let (stream, _) = listener.accept().unwrap();
+        agent.handle(stream).unwrap();
+        let cancelled = |_: &PassphraseRequest<'_>| Err(Error::Cancelled);
+        let mut agent = Agent::new(&cancelled);
+        let (stream, _) = listener.accept().unwrap();
+        agent.handle(stream).unwrap();
+
+        let (passphrase, cancel) = requests.join().unwrap();
+        assert_eq!(passphrase.unwrap(), "line\nbreak");
+        assert!(matches!(cancel, Err(Error::Cancelled)));
+
+        assert_eq!(
+            Agent::bind(&path).map(|_| ()).unwrap_err().kind(),
+            std::io::ErrorKind::AddrInUse
+        );
+        drop(listener);
+        // The socket is stale once its listener is closed, so it can be replaced.
+        Agent::bind(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,257 +1,1574 @@
-use log::{debug, info};
-use percent_encoding::percent_decode_str;
 use secrecy::{ExposeSecret, SecretString};
 use std::borrow::Cow;
-use std::io::{self, BufRead, BufReader, Write};
+use std::fmt;
+use std::io::{self, Read, Write};
 use std::path::Path;
-use std::process::{ChildStdin, ChildStdout};
-use std::process::{Command, Stdio};
-use zeroize::Zeroize;
+#[cfg(not(any(unix, windows)))]
+use std::process::Command;
+use std::process::Stdio;
+use std::process::{Child, ExitStatus};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use zeroize::{Zeroize, Zeroizing};
 
-use crate::{Error, Result};
+use crate::error::{
+    InvalidSettingError, InvalidSettingReason, NotAPinentryError, UnavailableError,
+    UnavailableReason,
+};
+mod reader;
+use reader::{ReadError, ZeroizingReader};
+
+use crate::charset::Charset;
+use crate::diagnostics::{DiagnosticLevel, Diagnostics};
+#[cfg(feature = "diceware")]
+use crate::diceware::Diceware;
+use crate::dismiss::Shown;
+use crate::encoding;
+use crate::mlock;
+#[cfg(unix)]
+use crate::signals;
+use crate::supervisor::{Registration, Supervisor};
+use crate::trace;
+use crate::Transcript;
+use crate::{DismissHandle, SecretBuffer};
+use crate::{Error, PinentryInfo, ProtocolError, Result, Utf8Policy};
 
 /// Possible response lines from an Assuan server.
 ///
 /// Reference: https://gnupg.org/documentation/manuals/assuan/Server-responses.html
-#[derive(Debug)]
+///
+/// This deliberately does not implement `Debug`, so that data lines are never logged by
+/// accident.
 #[allow(dead_code)]
-enum Response {
+pub enum Response<'a> {
     /// Request was successful.
     Ok(Option<String>),
     /// Request could not be fulfilled. The possible error codes are defined by
     /// `libgpg-error`.
     Err {
-        code: u16,
+        /// The error code.
+        code: u32,
+        /// A human-readable description of the error.
         description: Option<String>,
     },
     /// Informational output by the server, which is still processing the request.
     Information {
+        /// The status keyword.
         keyword: String,
+        /// The status information.
         status: Option<String>,
     },
     /// Comment line issued only for debugging purposes.
     Comment(String),
-    /// Raw data returned to client.
-    DataLine(SecretString),
+    /// Raw (still percent-encoded) data returned to client.
+    ///
+    /// This borrows from the connection's zeroizing line buffer, from which it is
+    /// decoded directly into its destination, so that no other copies are made.
+    DataLine(&'a str),
     /// The server needs further information from the client.
+    ///
+    /// The parameters may contain secrets (for example, `INQUIRE QUALITY` sends the
+    /// passphrase being entered).
     Inquire {
+        /// The keyword naming the information that is needed.
         keyword: String,
-        parameters: Option<String>,
+        /// The parameters of the inquiry.
+        parameters: Option<SecretString>,
     },
 }
 
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for Response<'a> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(match u.int_in_range(0..=5)? {
+            0 => Response::Ok(u.arbitrary()?),
+            1 => Response::Err {
+                code: u.arbitrary()?,
+                description: u.arbitrary()?,
+            },
+            2 => Response::Information {
+                keyword: u.arbitrary()?,
+                status: u.arbitrary()?,
+            },
+            3 => Response::Comment(u.arbitrary()?),
+            4 => Response::DataLine(u.arbitrary()?),
+            _ => Response::Inquire {
+                keyword: u.arbitrary()?,
+                parameters: u.arbitrary::<Option<String>>()?.map(SecretString::from),
+            },
+        })
+    }
+}
+
+/// Parses a single response line, including its line ending.
+#[cfg(any(unix, feature = "fuzzing"))]
+pub(crate) fn parse_response(input: &str) -> Option<Response<'_>> {
+    read::server_response(input)
+}
+
+/// Formats a [`Response`] for logging, with any data that could contain secrets
+/// replaced by a placeholder.
+///
+/// Responses must only ever be passed to the logging facade through this type.
+struct Redacted<'a>(&'a Response<'a>);
+
+impl<'a> fmt::Display for Redacted<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Response::Ok(None) => write!(f, "OK"),
+            Response::Ok(Some(info)) => write!(f, "OK {}", info),
+            Response::Err { code, description } => {
+                write!(f, "ERR {}", code)?;
+                if let Some(description) = description {
+                    write!(f, " {}", description)?;
+                }
+                Ok(())
+            }
+            Response::Information { keyword, status } => {
+                write!(f, "S {}", keyword)?;
+                if let Some(status) = status {
+                    write!(f, " {}", status)?;
+                }
+                Ok(())
+            }
+            Response::Comment(comment) => write!(f, "# {}", comment),
+            Response::DataLine(_) => write!(f, "D [REDACTED]"),
+            Response::Inquire {
+                keyword,
+                parameters,
+            } => {
+                write!(f, "INQUIRE {}", keyword)?;
+                if parameters.is_some() {
+                    write!(f, " [REDACTED]")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The maximum number of bytes of stderr output we keep from a `pinentry` binary.
+const MAX_STDERR_LEN: usize = 4096;
+
+/// Collects the (bounded) stderr output of a `pinentry` binary in the background, so
+/// that its diagnostics can be attached to errors.
+struct StderrCapture {
+    buf: Arc<Mutex<Vec<u8>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StderrCapture {
+    fn new(mut stderr: impl Read + Send + 'static) -> Self {
+        let buf = Arc::new(Mutex::new(Vec::new()));
+        let thread = {
+            let buf = buf.clone();
+            thread::spawn(move || {
+                let mut chunk = [0; 256];
+                // We keep reading after the buffer is full, so the child never blocks on
+                // a full pipe.
+                while let Ok(n @ 1..) = stderr.read(&mut chunk) {
+                    let mut buf = buf.lock().unwrap_or_else(|e| e.into_inner());
+                    let take = n.min(MAX_STDERR_LEN - buf.len());
+                    buf.extend_from_slice(&chunk[..take]);
+                }
+            })
+        };
+
+        StderrCapture {
+            buf,
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns the stderr output collected so far, if any.
+    ///
+    /// If `finished` is true, the child is known to have exited, and we wait for all
+    /// of its output to be collected first.
+    fn collected(&mut self, finished: bool) -> Option<String> {
+        if finished {
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+
+        let buf = self.buf.lock().unwrap_or_else(|e| e.into_inner());
+        let stderr = String::from_utf8_lossy(&buf);
+        let stderr = stderr.trim();
+        if stderr.is_empty() {
+            None
+        } else {
+            Some(stderr.to_owned())
+        }
+    }
+}
+
+/// A spawned `pinentry` process.
+pub(crate) trait Process: Send {
+    fn id(&self) -> u32;
+    fn kill(&mut self) -> io::Result<()>;
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>>;
+}
+
+impl Process for Child {
+    fn id(&self) -> u32 {
+        Child::id(self)
+    }
+
+    fn kill(&mut self) -> io::Result<()> {
+        Child::kill(self)
+    }
+
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        Child::try_wait(self)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+impl Process for crate::unix::spawn::Child {
+    fn id(&self) -> u32 {
+        crate::unix::spawn::Child::id(self)
+    }
+
+    fn kill(&mut self) -> io::Result<()> {
+        crate::unix::spawn::Child::kill(self)
+    }
+
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        crate::unix::spawn::Child::try_wait(self)
+    }
+}
+
+#[cfg(windows)]
+impl Process for crate::windows::session::Child {
+    fn id(&self) -> u32 {
+        crate::windows::session::Child::id(self)
+    }
+
+    fn kill(&mut self) -> io::Result<()> {
+        crate::windows::session::Child::kill(self)
+    }
+
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        crate::windows::session::Child::try_wait(self)
+    }
+}
+
+/// A `pinentry` process that is shared with the handles that can kill it.
+type SharedProcess = Arc<Mutex<Box<dyn Process>>>;
+
+/// A handle that can kill a `pinentry` process from another thread.
+pub(crate) struct Killer(SharedProcess);
+
+impl Killer {
+    /// Kills the `pinentry` process, returning false if it had already exited.
+    pub(crate) fn kill(&self) -> io::Result<bool> {
+        let mut child = lock_child(&self.0);
+        // `Process::kill` succeeds for a process that has exited but not yet been reaped,
+        // which would not have been killed by this.
+        if child.try_wait()?.is_some() {
+            return Ok(false);
+        }
+        child.kill().map(|()| true)
+    }
+}
+
+fn lock_child(child: &Mutex<Box<dyn Process>>) -> MutexGuard<'_, Box<dyn Process>> {
+    child.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// How often [`wait_child`] checks whether the process has exited.
+const WAIT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Waits for the process to exit.
+///
+/// The lock is only held while checking, so that the process can still be killed
+/// meanwhile (even if it ignores its first signal).
+fn wait_child(child: &Mutex<Box<dyn Process>>) -> io::Result<ExitStatus> {
+    loop {
+        if let Some(status) = lock_child(child).try_wait()? {
+            return Ok(status);
+        }
+        thread::sleep(WAIT_INTERVAL);
+    }
+}
+
+/// Waits for the process to exit, for at most the given time.
+///
+/// Returns `None` if the process is still running when the time is up.
+fn wait_child_timeout(
+    child: &Mutex<Box<dyn Process>>,
+    timeout: Duration,
+) -> io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = lock_child(child).try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(WAIT_INTERVAL);
+    }
+}
+
+/// A newly spawned `pinentry` process, with the parent's ends of its standard streams.
+struct Spawned {
+    process: Box<dyn Process>,
+    stdin: Box<dyn Write + Send>,
+    stdout: Box<dyn Read + Send>,
+    stderr: Box<dyn Read + Send>,
+}
+
+impl Spawned {
+    /// Takes the piped standard streams of a process spawned with `Command`.
+    fn from_child(mut child: Child) -> Result<Self> {
+        match (child.stdin.take(), child.stdout.take(), child.stderr.take()) {
+            (Some(stdin), Some(stdout), Some(stderr)) => Ok(Spawned {
+                process: Box::new(child),
+                stdin: Box::new(stdin),
+                stdout: Box::new(stdout),
+                stderr: Box::new(stderr),
+            }),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "could not open the standard streams of the pinentry process",
+                )
+                .into())
+            }
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+impl From<crate::unix::spawn::Spawned> for Spawned {
+    fn from(spawned: crate::unix::spawn::Spawned) -> Self {
+        Spawned {
+            process: Box::new(spawned.child),
+            stdin: Box::new(spawned.stdin),
+            stdout: Box::new(spawned.stdout),
+            stderr: Box::new(spawned.stderr),
+        }
+    }
+}
+
+#[cfg(windows)]
+impl From<crate::windows::session::Spawned> for Spawned {
+    fn from(spawned: crate::windows::session::Spawned) -> Self {
+        Spawned {
+            process: Box::new(spawned.child),
+            stdin: Box::new(spawned.stdin),
+            stdout: Box::new(spawned.stdout),
+            stderr: Box::new(spawned.stderr),
+        }
+    }
+}
+
+/// How long [`Connection::shutdown`] waits for the `pinentry` process to exit after it
+/// has acknowledged `BYE`, before killing it.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
 pub struct Connection {
-    output: ChildStdin,
-    input: BufReader<ChildStdout>,
+    child: SharedProcess,
+    output: Box<dyn Write + Send>,
+    input: ZeroizingReader<Box<dyn Read + Send>>,
+    stderr: StderrCapture,
+    supervision: Option<Registration>,
+    /// Kills the `pinentry` process if it takes too long to respond to a request.
+    watchdog: Option<Supervisor>,
+    /// Whether prompts are cancelled when the parent receives `SIGINT` or `SIGTSTP`.
+    #[cfg(unix)]
+    cancel_on_interrupt: bool,
+    /// Kills the `pinentry` process when the application dismisses the prompt.
+    dismiss: Option<DismissHandle>,
+    max_data_len: Option<usize>,
+    utf8_policy: Utf8Policy,
+    /// Answers `INQUIRE GENPIN`, sent when the user asks for a generated passphrase.
+    #[cfg(feature = "diceware")]
+    genpin: Option<Diceware>,
+    /// Scratch buffer for reading response lines, which is wiped after each response.
+    line: Vec<u8>,
+    /// The `SET*` values currently in effect, or `None` if they are unknown (because a
+    /// previous attempt to apply settings failed).
+    settings: Option<Vec<(String, String)>>,
+    /// Pre-encoded requests sent when the connection was opened that `RESET` clears, and
+    /// which are sent again after every reset.
+    reset_requests: Vec<String>,
+    transcript: Option<Transcript>,
+    diagnostics: Diagnostics,
+    charset: Charset,
+    /// What the `pinentry` binary reported about itself, once it has been asked.
+    info: Option<PinentryInfo>,
+    /// Whether the `pinentry` process has been shut down.
+    shut_down: bool,
+    /// Whether a `GETPIN` request has been written, after which the user may have
+    /// started typing.
+    prompted: bool,
 }
 
-// Percent escape some chars as described here:
+/// Settings that are cleared by the `pinentry` binary once the next prompt has used
+/// them, and so are never in effect for a later dialog.
+const VOLATILE_SETTINGS: &[&str] = &["SETERROR"];
+
+/// Returns the requests that change the settings in effect to `desired`.
+///
+/// `pinentry` binaries have no way to unset an individual setting, so if any setting in
+/// effect is absent from `desired`, the binary is reset and every desired setting is
+/// sent. Otherwise, only the settings that have changed are sent.
+fn settings_requests<'s>(
+    in_effect: Option<&[(String, String)]>,
+    desired: &[(&'s str, &'s str)],
+) -> Vec<(&'s str, Option<&'s str>)> {
+    let in_effect = match in_effect {
+        Some(in_effect)
+            if in_effect
+                .iter()
+                .all(|(command, _)| desired.iter().any(|(c, _)| c == command)) =>
+        {
+            Some(in_effect)
+        }
+        _ => None,
+    };
+
+    let mut requests = Vec::with_capacity(desired.len() + 1);
+    if in_effect.is_none() {
+        requests.push(("RESET", None));
+    }
+    requests.extend(
+        desired
+            .iter()
+            .filter(|(command, value)| {
+                !in_effect
+                    .into_iter()
+                    .flatten()
+                    .any(|(c, v)| c == command && v == value)
+            })
+            .map(|(command, value)| (*command, Some(*value))),
+    );
+    requests
+}
+
+// Percent escape some chars (see `crate::encoding`) as described here:
 // https://gnupg.org/documentation/manuals/assuan/Client-requests.html
-fn encode_request(command: &str, parameters: Option<&str>) -> String {
-    let cap = command.len() + parameters.map_or(0, |p| p.len() + 10) + 1;
-    let mut buf = String::with_capacity(cap);
+//
+// The request may contain secrets, so the buffer is wiped on drop. We reserve the full
+// encoded length up front, so that the buffer is never reallocated (which would leave
+// unwiped copies behind).
+//
+// Returns an error if the request is too long to send as a single line.
+pub(crate) fn encode_request(command: &str, parameters: Option<&str>) -> Result<Zeroizing<String>> {
+    let request = encode_line(command, parameters);
+    check_line_len(command, &request)?;
+    Ok(request)
+}
+
+/// Encodes a request in the same way as [`encode_request`], however long it is.
+fn encode_line(command: &str, parameters: Option<&str>) -> Zeroizing<String> {
+    // Space for the command, the encoded parameters (plus a separator), and the newline.
+    let cap = command.len() + parameters.map_or(0, |p| 1 + encoding::encoded_len(p)) + 1;
+    let mut buf = Zeroizing::new(String::with_capacity(cap));
     buf.push_str(command);
     if let Some(p) = parameters {
         buf.push(' ');
-        for c in p.chars() {
-            match c {
-                '\n' => buf.push_str("%0A"),
-                '\r' => buf.push_str("%0D"),
-                '%' => buf.push_str("%25"),
-                _ => buf.push(c),
+        encoding::encode_into(p, &mut buf);
+    }
+    buf.push('\n');
+    buf
+}
+
+/// Checks that an encoded request line (including its line ending) is short enough to
+/// send.
+fn check_line_len(command: &str, line: &str) -> Result<()> {
+    if line.len() > MAX_LINE_LEN {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} request is too long to send to pinentry", command),
+        )
+        .into())
+    } else {
+        Ok(())
+    }
+}
+
+/// The maximum number of lines that are skipped before the greeting, when the preamble
+/// is skipped.
+const MAX_PREAMBLE_LINES: usize = 32;
+
+/// The maximum length of an Assuan line, including the trailing newline.
+const MAX_LINE_LEN: usize = 1000;
+
+/// Checks that each of the given settings can be sent as a single request, and will be
+/// received intact by the `pinentry` binary.
+pub(crate) fn check_settings(
+    settings: &[(&'static str, Option<&str>)],
+) -> std::result::Result<(), InvalidSettingError> {
+    for &(command, parameters) in settings {
+        if let Some(p) = parameters {
+            if p.contains('\0') {
+                return Err(InvalidSettingError::new(
+                    command,
+                    InvalidSettingReason::ContainsNul,
+                ));
+            }
+            if command.len() + 1 + encoding::encoded_len(p) + 1 > MAX_LINE_LEN {
+                return Err(InvalidSettingError::new(
+                    command,
+                    InvalidSettingReason::TooLong,
+                ));
             }
         }
     }
-    if let Some(b'\\') = buf.as_bytes().last() {
-        buf.pop();
-        buf.push_str("%5C");
+    Ok(())
+}
+
+/// Returns the requests (mostly `OPTION`s) sent to every newly spawned `pinentry`
+/// binary, with their parameters already encoded.
+///
+/// The terminal device is a path, which may not be valid UTF-8, so these are encoded
+/// with [`encoding::encode_bytes`] rather than by [`encode_request`].
+fn initial_options(
+    #[cfg(unix)] unix: &crate::unix::Options<'_>,
+    #[cfg(target_os = "macos")] macos: &crate::macos::Options<'_>,
+    #[cfg(windows)] windows: &crate::windows::Options,
+) -> Vec<String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let options = vec![
+            format!(
+                "OPTION ttyname={}",
+                encoding::encode_bytes(unix.ttyname().as_os_str().as_bytes())
+            ),
+            format!("OPTION ttytype={}", encoding::encode(unix.ttytype())),
+        ];
+        #[cfg(target_os = "macos")]
+        let options = options.into_iter().chain(macos.requests()).collect();
+        options
+    }
+
+    #[cfg(windows)]
+    {
+        windows
+            .parent_wid()
+            .map(|wid| format!("OPTION parent-wid={}", wid))
+            .into_iter()
+            .collect()
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        vec![]
     }
-    buf.push('\n');
-    assert!(
-        buf.as_bytes().len() <= 1000,
-        "splitting of long lines yet implemented"
+}
+
+/// Returns the encoded request lines (without line endings) that would be sent to a
+/// newly spawned `pinentry` binary to show a dialog with the given settings and prompt.
+pub fn dry_run(
+    #[cfg(unix)] unix: &crate::unix::Options<'_>,
+    #[cfg(target_os = "macos")] macos: &crate::macos::Options<'_>,
+    #[cfg(windows)] windows: &crate::windows::Options,
+    flavor: bool,
+    settings: &[(&str, Option<&str>)],
+    prompt: &str,
+) -> Vec<String> {
+    let options = initial_options(
+        #[cfg(unix)]
+        unix,
+        #[cfg(target_os = "macos")]
+        macos,
+        #[cfg(windows)]
+        windows,
     );
-    buf
+    let desired: Vec<_> = settings
+        .iter()
+        .filter_map(|(command, value)| value.map(|value| (*command, value)))
+        .collect();
+
+    let requests: Vec<_> = if flavor {
+        vec![("GETINFO", Some("flavor"))]
+    } else {
+        vec![]
+    };
+
+    #[cfg(unix)]
+    let options = options.into_iter().chain(unix.display_options());
+
+    options
+        .into_iter()
+        .chain(
+            requests
+                .into_iter()
+                .chain(settings_requests(Some(&[]), &desired))
+                .chain([(prompt, None), ("BYE", None)])
+                .map(|(command, parameters)| {
+                    let mut line = encode_line(command, parameters).to_string();
+                    line.pop();
+                    line
+                }),
+        )
+        .collect()
+}
+
+/// Describes a request for use in error messages, without including any of its
+/// (potentially secret-bearing) parameters.
+///
+/// The name of the option being set by an `OPTION` request is included, as it is
+/// otherwise impossible to tell which option was rejected.
+fn command_context(command: &str, parameters: Option<&str>) -> String {
+    match (
+        command,
+        parameters.and_then(|p| p.split(&['=', ' '][..]).next()),
+    ) {
+        ("OPTION", Some(name)) => format!("{} {}", command, name),
+        _ => command.to_owned(),
+    }
 }
 
 impl Connection {
-    pub fn open(name: &Path) -> Result<Self> {
-        let process = Command::new(name)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()?;
-        let output = process.stdin.expect("could open stdin");
-        let input = BufReader::new(process.stdout.expect("could open stdin"));
-
-        let mut conn = Connection { output, input };
-        // There is always an initial OK server response
-        conn.read_response()?;
+    #[allow(clippy::too_many_arguments)]
+    pub fn open(
+        name: &Path,
+        #[cfg(unix)] unix: &crate::unix::Options<'_>,
+        #[cfg(target_os = "macos")] macos: &crate::macos::Options<'_>,
+        #[cfg(windows)] windows: &crate::windows::Options,
+        on_spawn: Option<&dyn Fn(&Child)>,
+        transcript: Option<&Transcript>,
+        diagnostics: Option<&Diagnostics>,
+        response_timeout: Option<Duration>,
+        skip_preamble: bool,
+    ) -> Result<Self> {
+        #[cfg(unix)]
+        let mut command = unix.command(name);
+        #[cfg(target_os = "macos")]
+        command.envs(macos.env());
+        #[cfg(windows)]
+        let mut command = windows.command(name);
+        #[cfg(not(any(unix, windows)))]
+        let mut command = Command::new(name);
 
+        // The spawn hook needs a `Child`, which only `Command` spawns.
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        let spawned = match on_spawn {
+            Some(_) => None,
+            None => unix
+                .posix_spawn_command(&command)
+                .transpose()?
+                .map(Spawned::from),
+        };
+        // Processes in other sessions are not spawned by `Command`, so never reach the
+        // spawn hook.
+        #[cfg(windows)]
+        let spawned = windows
+            .session_spawn(&command)
+            .transpose()?
+            .map(Spawned::from);
+        #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+        let spawned: Option<Spawned> = None;
+        let spawned = match spawned {
+            Some(spawned) => spawned,
+            None => {
+                command
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped());
+                let child = command.spawn()?;
+                if let Some(on_spawn) = on_spawn {
+                    on_spawn(&child);
+                }
+                Spawned::from_child(child)?
+            }
+        };
+        #[cfg(windows)]
+        windows.spawned(spawned.process.id());
+
+        let mut conn = Connection {
+            child: Arc::new(Mutex::new(spawned.process)),
+            output: spawned.stdin,
+            input: ZeroizingReader::new(spawned.stdout),
+            stderr: StderrCapture::new(spawned.stderr),
+            supervision: None,
+            watchdog: response_timeout.map(Supervisor::new),
+            #[cfg(unix)]
+            cancel_on_interrupt: false,
+            dismiss: None,
+            max_data_len: None,
+            utf8_policy: Utf8Policy::Strict,
+            #[cfg(feature = "diceware")]
+            genpin: None,
+            // Lines are read into this only up to the maximum length of 1000 bytes (and
+            // are at most twice that once transcoded into UTF-8), so this is never
+            // reallocated.
+            line: Vec::with_capacity(2048),
+            settings: Some(vec![]),
+            #[cfg(target_os = "macos")]
+            reset_requests: macos.requests(),
+            #[cfg(not(target_os = "macos"))]
+            reset_requests: vec![],
+            transcript: transcript.cloned(),
+            diagnostics: diagnostics.cloned().unwrap_or_default(),
+            charset: Charset::Utf8,
+            info: None,
+            shut_down: false,
+            prompted: false,
+        };
+        mlock::lock(&mut conn.line);
+        // There is always an initial OK server response, although wrappers around the
+        // `pinentry` binary may print other lines before it.
+        let mut greeting = StringSink::new(None);
+        let mut skipped = 0;
+        loop {
+            match conn.read_response(None, &mut greeting) {
+                Err(Error::Protocol(_)) if skip_preamble && skipped < MAX_PREAMBLE_LINES => {
+                    skipped += 1;
+                }
+                res => break res,
+            }
+        }
+        .map_err(|e| match e {
+            Error::Protocol(e) => Error::NotAPinentry(NotAPinentryError::new(name, e)),
+            e => conn.annotate_error(e),
+        })?;
+        if skipped > 0 {
+            conn.diagnostics.emit(
+                DiagnosticLevel::Debug,
+                format_args!("skipped {} lines before the greeting", skipped),
+            );
+        }
+
+        conn.write_lines(&initial_options(
+            #[cfg(unix)]
+            unix,
+            #[cfg(target_os = "macos")]
+            macos,
+            #[cfg(windows)]
+            windows,
+        ))?;
+        // `gpg-agent` ignores errors from `pinentry` binaries that do not support these
+        // options, and so do we.
         #[cfg(unix)]
-        {
-            conn.send_request("OPTION", Some("ttyname=/dev/tty"))?;
-            conn.send_request(
-                "OPTION",
-                Some(&format!(
-                    "ttytype={}",
-                    std::env::var("TERM")
-                        .as_ref()
-                        .map(|s| s.as_str())
-                        .unwrap_or("xterm-256color")
-                )),
-            )?;
+        match conn.write_lines(&unix.display_options()) {
+            Err(e) if e.is_exited() => return Err(e),
+            _ => (),
         }
 
         Ok(conn)
     }
 
+    /// Sends the given pre-encoded request lines (without line endings) at once, and
+    /// reads their responses.
+    fn write_lines(&mut self, lines: &[String]) -> Result<()> {
+        let contexts: Vec<_> = lines
+            .iter()
+            .map(|line| {
+                let (command, parameters) = match line.split_once(' ') {
+                    Some((command, parameters)) => (command, Some(parameters)),
+                    None => (line.as_str(), None),
+                };
+                (command, command_context(command, parameters))
+            })
+            .collect();
+        let encoded = lines
+            .iter()
+            .zip(&contexts)
+            .map(|(line, (command, _))| {
+                let line = Zeroizing::new(format!("{}\n", line));
+                check_line_len(command, &line).map(|()| line)
+            })
+            .collect::<Result<_>>()?;
+        self.write_requests(&contexts, encoded)
+    }
+
+    /// Returns the process ID of the `pinentry` process.
+    pub fn pid(&self) -> u32 {
+        lock_child(&self.child).id()
+    }
+
+    /// Places this connection under the supervision of the given [`Supervisor`].
+    pub fn supervise(&mut self, supervisor: &Supervisor, label: &str) {
+        self.supervision = Some(supervisor.register(label, Killer(self.child.clone())));
+    }
+
+    /// Returns the flavor reported by the `pinentry` binary, if any.
+    pub fn flavor(&mut self) -> Option<String> {
+        match &self.info {
+            Some(info) => info.flavor().map(str::to_owned),
+            None => self.getinfo("flavor"),
+        }
+    }
+
+    /// Returns what the `pinentry` binary reports about itself, asking it only the
+    /// first time.
+    pub fn info(&mut self) -> &PinentryInfo {
+        let info = match self.info.take() {
+            Some(info) => info,
+            None => {
+                let flavor = self.getinfo("flavor");
+                let version = self.getinfo("version");
+                PinentryInfo::new(flavor, version.as_deref())
+            }
+        };
+        self.info.get_or_insert(info)
+    }
+
+    /// Returns the data of a `GETINFO` request, or `None` if it fails.
+    fn getinfo(&mut self, what: &str) -> Option<String> {
+        self.send_request("GETINFO", Some(what))
+            .ok()
+            .flatten()
+            .map(|data| data.expose_secret().to_owned())
+    }
+
+    /// Asks the `pinentry` process to exit, and waits for it if it agrees.
+    ///
+    /// A process that agrees but is still running after [`SHUTDOWN_TIMEOUT`] is killed.
+    ///
+    /// Returns the exit status of the process, or `None` if it could not be shut down
+    /// cleanly. Only the first call has any effect.
+    pub fn shutdown(&mut self) -> Option<ExitStatus> {
+        if self.shut_down {
+            return None;
+        }
+        self.shut_down = true;
+        self.send_request("BYE", None).ok()?;
+        match wait_child_timeout(&self.child, SHUTDOWN_TIMEOUT) {
+            Ok(Some(status)) => Some(status),
+            _ => {
+                let _ = Killer(self.child.clone()).kill();
+                let _ = wait_child(&self.child);
+                None
+            }
+        }
+    }
+
+    /// Returns true if a `GETPIN` request has been written to the `pinentry` process.
+    pub fn prompted(&self) -> bool {
+        self.prompted
+    }
+
+    /// Records all subsequent requests and responses in the given transcript.
+    pub fn record(&mut self, transcript: &Transcript) {
+        self.transcript = Some(transcript.clone());
+    }
+
+    /// Transcodes all subsequent requests and responses from and to the given character
+    /// set.
+    pub fn set_charset(&mut self, charset: Charset) {
+        self.charset = charset;
+    }
+
+    /// Sends all subsequent diagnostic messages to the given sink.
+    pub fn set_diagnostics(&mut self, diagnostics: &Diagnostics) {
+        self.diagnostics = diagnostics.clone();
+    }
+
+    /// Sets how long to wait for the response to each subsequent request, after which
+    /// the `pinentry` process is killed and [`Error::Timeout`] is returned.
+    ///
+    /// Prompts are only bounded if the dialog has a timeout, in which case they are
+    /// allowed that much longer.
+    pub fn set_response_timeout(&mut self, response_timeout: Option<Duration>) {
+        self.watchdog = response_timeout.map(Supervisor::new);
+    }
+
+    /// Sets whether subsequent prompts are cancelled (returning [`Error::Cancelled`])
+    /// when the parent receives `SIGINT` or `SIGTSTP`, instead of the parent being
+    /// interrupted or stopped.
+    #[cfg(unix)]
+    pub fn set_cancel_on_interrupt(&mut self, cancel_on_interrupt: bool) {
+        self.cancel_on_interrupt = cancel_on_interrupt;
+    }
+
+    /// Sets the handle with which subsequent prompts can be dismissed, after which they
+    /// return [`Error::Dismissed`].
+    pub fn set_dismiss_handle(&mut self, dismiss: Option<DismissHandle>) {
+        self.dismiss = dismiss;
+    }
+
+    /// Sets the maximum length (in bytes) of the data that a single request may return.
+    pub fn set_max_data_len(&mut self, max_data_len: Option<usize>) {
+        self.max_data_len = max_data_len;
+    }
+
+    /// Sets how data returned by subsequent requests that is not valid UTF-8 is handled.
+    pub fn set_utf8_policy(&mut self, utf8_policy: Utf8Policy) {
+        self.utf8_policy = utf8_policy;
+    }
+
+    /// Sets the generator used to answer `INQUIRE GENPIN` during subsequent requests.
+    ///
+    /// Without one, `INQUIRE GENPIN` is answered by [`Diceware::default`].
+    #[cfg(feature = "diceware")]
+    pub fn set_genpin(&mut self, genpin: Option<Diceware>) {
+        self.genpin = genpin;
+    }
+
+    /// Applies the given `SET*` settings (skipping any that are unset), only sending
+    /// the ones that differ from the settings already in effect.
+    ///
+    /// If the `pinentry` binary has to be reset, the requests that the reset clears (the
+    /// `SETKEYINFO` and `SETTITLE` of the macOS options) are sent again before the
+    /// settings.
+    pub fn apply_settings(&mut self, settings: &[(&str, Option<&str>)]) -> Result<()> {
+        let desired: Vec<_> = settings
+            .iter()
+            .filter_map(|(command, value)| value.map(|value| (*command, value)))
+            .collect();
+
+        // If applying the settings fails, we no longer know which are in effect.
+        let in_effect = self.settings.take();
+        let requests = settings_requests(in_effect.as_deref(), &desired);
+        match requests.split_first() {
+            Some((("RESET", None), requests)) if !self.reset_requests.is_empty() => {
+                self.send_requests(&[("RESET", None)])?;
+                let reset_requests = self.reset_requests.clone();
+                self.write_lines(&reset_requests)?;
+                self.send_requests(requests)?;
+            }
+            _ => self.send_requests(&requests)?,
+        }
+
+        self.settings = Some(
+            desired
+                .iter()
+                .filter(|(command, _)| !VOLATILE_SETTINGS.contains(command))
+                .map(|(command, value)| (command.to_string(), value.to_string()))
+                .collect(),
+        );
+        Ok(())
+    }
+
     pub fn send_request(
         &mut self,
         command: &str,
         parameters: Option<&str>,
     ) -> Result<Option<SecretString>> {
-        let buf = encode_request(command, parameters);
-        self.output.write_all(buf.as_bytes())?;
-        self.read_response()
+        let mut sink = StringSink::new(self.max_data_len);
+        self.request(command, parameters, &mut sink)?;
+        sink.take(self.utf8_policy)
+    }
+
+    /// Sends a batch of requests (ignoring any data they return) in a single write, and
+    /// then reads their responses.
+    ///
+    /// All responses are read, even if an earlier request fails, so that the connection
+    /// stays in sync; the first error is returned.
+    pub fn send_requests(&mut self, requests: &[(&str, Option<&str>)]) -> Result<()> {
+        let contexts: Vec<_> = requests
+            .iter()
+            .map(|(command, parameters)| (*command, command_context(command, *parameters)))
+            .collect();
+        let encoded = requests
+            .iter()
+            .map(|(command, parameters)| encode_request(command, *parameters))
+            .collect::<Result<_>>()?;
+        self.write_requests(&contexts, encoded)
+    }
+
+    /// Writes a batch of encoded requests in a single write, and then reads their
+    /// responses. `contexts` gives the command of each request, and its description
+    /// (see [`command_context`]).
+    fn write_requests(
+        &mut self,
+        contexts: &[(&str, String)],
+        encoded: Vec<Zeroizing<String>>,
+    ) -> Result<()> {
+        let mut buf = Zeroizing::new(Vec::with_capacity(
+            encoded.iter().map(|request| request.len()).sum(),
+        ));
+        for request in &encoded {
+            self.charset.encode(request, &mut buf);
+        }
+        if let Some(transcript) = &self.transcript {
+            for request in &encoded {
+                transcript.request(request.trim_end_matches('\n'));
+            }
+        }
+
+        if let Err(e) = self.output.write_all(&buf) {
+            let context = contexts
+                .first()
+                .map(|(_, context)| context.as_str())
+                .unwrap_or_default();
+            return Err(self.annotate_error(e.into()).in_response_to(context));
+        }
+
+        let mut res = Ok(());
+        for (command, context) in contexts {
+            let mut sink = StringSink::new(None);
+            if let Err(e) = trace::request(context, || self.read_response(Some(command), &mut sink))
+            {
+                let exited = e.is_exited();
+                let e = self.annotate_error(e).in_response_to(context);
+                // There are no more responses once the process has exited, or has been
+                // killed by the watchdog.
+                if exited || matches!(e, Error::Timeout) {
+                    return Err(e);
+                }
+                res = res.and(Err(e));
+            }
+        }
+        res
+    }
+
+    /// Sends a request, decoding any data it returns directly into `buf`.
+    pub fn send_request_into(
+        &mut self,
+        command: &str,
+        parameters: Option<&str>,
+        buf: &mut SecretBuffer,
+    ) -> Result<()> {
+        buf.clear();
+        self.request(command, parameters, buf)
     }
 
-    fn read_response(&mut self) -> Result<Option<SecretString>> {
-        let mut line = String::new();
-        let mut data = None;
+    fn request(
+        &mut self,
+        command: &str,
+        parameters: Option<&str>,
+        sink: &mut dyn DataSink,
+    ) -> Result<()> {
+        let context = command_context(command, parameters);
+        let request = encode_request(command, parameters)?;
+        let mut buf = Zeroizing::new(Vec::with_capacity(request.len()));
+        self.charset.encode(&request, &mut buf);
+        if let Some(transcript) = &self.transcript {
+            transcript.request(request.trim_end_matches('\n'));
+        }
+        trace::request(&context, || {
+            self.output
+                .write_all(&buf)
+                .map_err(Error::from)
+                .and_then(|()| {
+                    self.prompted |= command == "GETPIN";
+                    self.read_response(Some(command), sink)
+                })
+        })
+        .map_err(|e| {
+            sink.wipe();
+            self.annotate_error(e).in_response_to(&context)
+        })
+    }
 
+    /// Attaches any diagnostics that the `pinentry` binary printed to stderr to the
+    /// given error, or replaces it with [`Error::Timeout`] if our supervisor killed the
+    /// `pinentry` process.
+    fn annotate_error(&mut self, e: Error) -> Error {
+        match e {
+            Error::Io(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::BrokenPipe | io::ErrorKind::UnexpectedEof
+                ) =>
+            {
+                // The child has exited; reap it so we can collect all of its output.
+                let _ = wait_child(&self.child);
+                if self
+                    .supervision
+                    .as_ref()
+                    .map_or(false, Registration::was_reaped)
+                {
+                    return Error::Timeout;
+                }
+                match self.stderr.collected(true) {
+                    Some(stderr) => match UnavailableReason::detect(&stderr) {
+                        Some(reason) => {
+                            Error::Unavailable(UnavailableError::new(reason, Some(stderr)))
+                        }
+                        None => Error::Io(io::Error::new(e.kind(), format!("{}: {}", e, stderr))),
+                    },
+                    None => Error::Io(e),
+                }
+            }
+            Error::Gpg(e) => {
+                let e = e.with_stderr(self.stderr.collected(false));
+                match UnavailableReason::from_gpg(&e) {
+                    Some(reason) => Error::Unavailable(UnavailableError::new(
+                        reason,
+                        e.description().or_else(|| e.stderr()).map(String::from),
+                    )),
+                    None => Error::Gpg(e),
+                }
+            }
+            e => e,
+        }
+    }
+
+    fn read_response(&mut self, command: Option<&str>, sink: &mut dyn DataSink) -> Result<()> {
+        let watch = self.watch(command);
+        #[cfg(unix)]
+        let cancellable = self.cancellable(command)?;
+        let shown = match (command, &self.dismiss) {
+            (Some("GETPIN") | Some("CONFIRM") | Some("MESSAGE"), Some(dismiss)) => {
+                Some(dismiss.shown(Killer(self.child.clone())))
+            }
+            _ => None,
+        };
+        let mut line = std::mem::take(&mut self.line);
+        let res = self.read_response_into(command, &mut line, sink);
+        line.zeroize();
+        self.line = line;
+        if res.is_err() && watch.as_ref().map_or(false, Registration::was_reaped) {
+            // Reap the killed process, so that it does not linger as a zombie.
+            let _ = wait_child(&self.child);
+            return Err(Error::Timeout);
+        }
+        if res.is_err() && shown.as_ref().map_or(false, Shown::was_dismissed) {
+            let _ = wait_child(&self.child);
+            return Err(Error::Dismissed);
+        }
+        #[cfg(unix)]
+        if res.is_err()
+            && cancellable
+                .as_ref()
+                .map_or(false, signals::Cancellable::was_cancelled)
+        {
+            let _ = wait_child(&self.child);
+            return Err(Error::Cancelled);
+        }
+        res
+    }
+
+    /// Makes the prompt issued by the given command cancellable with Ctrl-C or Ctrl-Z, if
+    /// enabled.
+    #[cfg(unix)]
+    fn cancellable(&self, command: Option<&str>) -> Result<Option<signals::Cancellable>> {
+        match command {
+            Some("GETPIN") | Some("CONFIRM") | Some("MESSAGE") if self.cancel_on_interrupt => {
+                Ok(Some(signals::Cancellable::new(Killer(self.child.clone()))?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Places the response to the given command under the watchdog, if there is one.
+    fn watch(&self, command: Option<&str>) -> Option<Registration> {
+        let watchdog = self.watchdog.as_ref()?;
+        let killer = Killer(self.child.clone());
+        match command {
+            Some("GETPIN") | Some("CONFIRM") | Some("MESSAGE") => {
+                // The user may take as long as the dialog's timeout to respond.
+                let timeout = self
+                    .settings
+                    .as_ref()?
+                    .iter()
+                    .find(|(command, _)| command == "SETTIMEOUT")?
+                    .1
+                    .parse()
+                    .ok()?;
+                let budget = watchdog
+                    .budget()
+                    .saturating_add(Duration::from_secs(timeout));
+                let label = command.unwrap_or_default();
+                Some(watchdog.register_with_budget(label, killer, budget))
+            }
+            _ => Some(watchdog.register(command.unwrap_or("greeting"), killer)),
+        }
+    }
+
+    fn read_response_into(
+        &mut self,
+        command: Option<&str>,
+        line: &mut Vec<u8>,
+        sink: &mut dyn DataSink,
+    ) -> Result<()> {
         // We loop until we find an OK or ERR response. This is probably sufficient for
         // pinentry, but other Assuan protocols might rely on INQUIRE, which needs
         // intermediate completion states or callbacks.
+        //
+        // If the data is too long, the rest of the response is still read (and
+        // discarded), so that the next request does not read it instead of its own.
+        let mut too_long = None;
         loop {
             line.zeroize();
-            self.input.read_line(&mut line)?;
-            match read::server_response(&line)
-                .map(|(_, r)| r)
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}", e)))?
-            {
-                Response::Ok(info) => {
-                    if let Some(info) = info {
-                        debug!("< OK {}", info);
+            match self.input.read_line(line, MAX_LINE_LEN) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "pinentry exited unexpectedly",
+                    )
+                    .into())
+                }
+                Ok(_) => (),
+                Err(ReadError::Io(e)) => return Err(e.into()),
+                Err(ReadError::TooLong) => {
+                    if too_long.is_none() {
+                        too_long = Some(self.protocol_error(command, line));
                     }
-                    line.zeroize();
-                    return Ok(data);
+                    continue;
+                }
+            }
+            if !self.charset.decode(line) {
+                return Err(self.protocol_error(command, line));
+            }
+            let response = match std::str::from_utf8(line)
+                .ok()
+                .and_then(read::server_response)
+            {
+                Some(response) => response,
+                None => return Err(self.protocol_error(command, line)),
+            };
+            if let Some(transcript) = &self.transcript {
+                transcript.response(Redacted(&response));
+            }
+            match &response {
+                Response::Ok(Some(_)) | Response::Comment(_) => self.diagnostics.emit(
+                    DiagnosticLevel::Debug,
+                    format_args!("< {}", Redacted(&response)),
+                ),
+                Response::Ok(None) | Response::Err { .. } | Response::DataLine(_) => (),
+                _ => self.diagnostics.emit(
+                    DiagnosticLevel::Info,
+                    format_args!("< {}", Redacted(&response)),
+                ),
+            }
+
+            match response {
+                Response::Ok(_) => {
+                    return too_long.map_or(Ok(()), Err);
                 }
                 Response::Err { code, description } => {
-                    line.zeroize();
-                    if let Some(mut buf) = data {
-                        buf.zeroize();
-                    }
-                    return Err(Error::from_parts(code, description));
+                    return Err(too_long.unwrap_or_else(|| Error::from_parts(code, description)));
                 }
-                Response::Comment(comment) => debug!("< # {}", comment),
+                Response::DataLine(_) if too_long.is_some() => (),
                 Response::DataLine(data_line) => {
-                    let buf = data.take();
-                    let data_line_decoded =
-                        percent_decode_str(data_line.expose_secret()).decode_utf8()?;
-
-                    // Concatenate into a new buffer so we can control allocations.
-                    let mut s = String::with_capacity(
-                        buf.as_ref()
-                            .map(|buf| buf.expose_secret().len())
-                            .unwrap_or(0)
-                            + data_line_decoded.len(),
-                    );
-                    if let Some(buf) = buf {
-                        s.push_str(buf.expose_secret());
+                    if let Err(e) = sink.push(data_line) {
+                        sink.wipe();
+                        too_long = Some(e);
                     }
-                    s.push_str(data_line_decoded.as_ref());
-                    data = Some(s.into());
+                }
+                #[cfg(feature = "diceware")]
+                Response::Inquire { keyword, .. } if keyword == "GENPIN" => {
+                    let passphrase = match &self.genpin {
+                        Some(genpin) => genpin.generate(),
+                        None => Diceware::default().generate(),
+                    };
+                    self.answer_genpin(passphrase)?;
+                }
+                _ => (),
+            }
+        }
+    }
 
-                    if let Cow::Owned(mut data_line_decoded) = data_line_decoded {
-                        data_line_decoded.zeroize();
-                    }
+    /// Returns the error for an invalid response line, recording it in the transcript.
+    fn protocol_error(&self, command: Option<&str>, line: &[u8]) -> Error {
+        let lossy = String::from_utf8_lossy(line);
+        let e = ProtocolError::new(command, &lossy);
+        if let Cow::Owned(mut lossy) = lossy {
+            lossy.zeroize();
+        }
+        if let Some(transcript) = &self.transcript {
+            transcript.response(e.line());
+        }
+        Error::Protocol(e)
+    }
+
+    /// Answers `INQUIRE GENPIN` with the given generated passphrase, or cancels the
+    /// inquiry if it could not be generated.
+    #[cfg(feature = "diceware")]
+    fn answer_genpin(&mut self, passphrase: io::Result<SecretString>) -> Result<()> {
+        let answer = passphrase.map_err(Error::from).and_then(|passphrase| {
+            let data = encode_request("D", Some(passphrase.expose_secret()))?;
+            let mut answer = Zeroizing::new(String::with_capacity(data.len() + 4));
+            answer.push_str(&data);
+            answer.push_str("END\n");
+            Ok(answer)
+        });
+        let answer = match answer {
+            Ok(answer) => answer,
+            Err(e) => {
+                self.diagnostics.emit(
+                    DiagnosticLevel::Info,
+                    format_args!("Could not generate a passphrase: {}", e),
+                );
+                Zeroizing::new("CAN\n".to_owned())
+            }
+        };
+        if let Some(transcript) = &self.transcript {
+            for line in answer.lines() {
+                // Data lines contain the generated passphrase.
+                if line.starts_with("D ") {
+                    transcript.request("D [REDACTED]");
+                } else {
+                    transcript.request(line);
                 }
-                res => info!("< {:?}", res),
             }
         }
+        let mut buf = Zeroizing::new(Vec::with_capacity(answer.len()));
+        self.charset.encode(&answer, &mut buf);
+        self.output.write_all(&buf)?;
+        Ok(())
+    }
+}
+
+/// Accumulates the data lines returned in response to a request.
+trait DataSink {
+    /// Decodes and appends a percent-encoded data line.
+    fn push(&mut self, data_line: &str) -> Result<()>;
+
+    /// Wipes any data accumulated so far.
+    fn wipe(&mut self);
+}
+
+/// Accumulates data into a [`SecretString`].
+///
+/// Data is decoded into a single buffer that is wiped whenever it is grown, so that the
+/// only copy of the data left behind is the returned `SecretString`.
+struct StringSink {
+    buf: Vec<u8>,
+    max_len: Option<usize>,
+}
+
+impl StringSink {
+    fn new(max_len: Option<usize>) -> Self {
+        StringSink {
+            buf: Vec::new(),
+            max_len,
+        }
+    }
+
+    /// Ensures that at least `additional` more bytes can be pushed without reallocating.
+    fn reserve(&mut self, additional: usize) {
+        let required = self.buf.len() + additional;
+        if required > self.buf.capacity() {
+            let mut grown = Vec::with_capacity(required.max(self.buf.capacity() * 2).max(64));
+            mlock::lock(&mut grown);
+            grown.extend_from_slice(&self.buf);
+            self.wipe();
+            self.buf = grown;
+        }
+    }
+
+    /// Returns the accumulated data, or `None` if there was none, and wipes the buffer.
+    fn take(&mut self, utf8_policy: Utf8Policy) -> Result<Option<SecretString>> {
+        let data = if self.buf.is_empty() {
+            Ok(None)
+        } else {
+            utf8_policy.to_secret_string(&self.buf).map(Some)
+        };
+        self.wipe();
+        data
+    }
+}
+
+impl DataSink for StringSink {
+    fn push(&mut self, data_line: &str) -> Result<()> {
+        // The encoded length is an upper bound on the decoded length.
+        self.reserve(match self.max_len {
+            Some(max) => data_line.len().min(max.saturating_sub(self.buf.len())),
+            None => data_line.len(),
+        });
+        for byte in encoding::decode_iter(data_line) {
+            if self.max_len.map_or(false, |max| self.buf.len() >= max) {
+                return Err(Error::TooLong);
+            }
+            self.buf.push(byte);
+        }
+        Ok(())
+    }
+
+    fn wipe(&mut self) {
+        self.buf.zeroize();
+        mlock::unlock(&mut self.buf);
+        self.buf = Vec::new();
+    }
+}
+
+impl Drop for StringSink {
+    fn drop(&mut self) {
+        self.wipe();
+    }
+}
+
+/// Decodes data directly into the caller's buffer, without intermediate allocations.
+impl DataSink for SecretBuffer {
+    fn push(&mut self, data_line: &str) -> Result<()> {
+        for byte in encoding::decode_iter(data_line) {
+            if !SecretBuffer::push(self, byte) {
+                return Err(Error::TooLong);
+            }
+        }
+        Ok(())
+    }
+
+    fn wipe(&mut self) {
+        self.clear();
     }
 }
 
 impl Drop for Connection {
     fn drop(&mut self) {
-        let _ = self.send_request("BYE", None);
+        self.shutdown();
+        self.line.zeroize();
+        mlock::unlock(&mut self.line);
     }
 }
 
 mod read {
-    use nom::{
-        branch::alt,
-        bytes::complete::{is_not, tag},
-        character::complete::{digit1, line_ending},
-        combinator::{map, opt},
-        sequence::{pair, preceded, terminated},
-        IResult,
-    };
-
     use super::Response;
 
-    fn gpg_error_code(input: &str) -> IResult<&str, u16> {
-        map(digit1, |code: &str| {
-            let full = code.parse::<u32>().expect("have decimal digits");
-            // gpg uses the lowest 16 bits for error codes.
-            full as u16
-        })(input)
-    }
-
-    pub(super) fn server_response(input: &str) -> IResult<&str, Response> {
-        terminated(
-            alt((
-                preceded(
-                    tag("OK"),
-                    map(opt(preceded(tag(" "), is_not("\r\n"))), |params| {
-                        Response::Ok(params.map(String::from))
-                    }),
-                ),
-                preceded(
-                    tag("ERR "),
-                    map(
-                        pair(gpg_error_code, opt(preceded(tag(" "), is_not("\r\n")))),
-                        |(code, description)| Response::Err {
-                            code,
-                            description: description.map(String::from),
-                        },
+    /// Parses a single response line, including its line ending.
+    pub(super) fn server_response(input: &str) -> Option<Response<'_>> {
+        imp::server_response(input)
+    }
+
+    #[cfg(feature = "nom")]
+    mod imp {
+        use nom::{
+            branch::alt,
+            bytes::complete::{is_not, tag},
+            character::complete::{digit1, line_ending},
+            combinator::{map, map_res, opt},
+            sequence::{pair, preceded, terminated},
+            IResult,
+        };
+
+        use super::super::Response;
+
+        fn gpg_error_code(input: &str) -> IResult<&str, u32> {
+            // gpg encodes the error source in the upper bits, and the error code in the
+            // lowest 16 bits; these are split apart by `Error::from_parts`.
+            // Codes that overflow are rejected, as by the hand-written parser.
+            map_res(digit1, str::parse::<u32>)(input)
+        }
+
+        pub(super) fn server_response(input: &str) -> Option<Response<'_>> {
+            response(input).ok().map(|(_, response)| response)
+        }
+
+        fn response(input: &str) -> IResult<&str, Response<'_>> {
+            terminated(
+                alt((
+                    preceded(
+                        tag("OK"),
+                        map(opt(preceded(tag(" "), is_not("\r\n"))), |params| {
+                            Response::Ok(params.map(String::from))
+                        }),
                     ),
-                ),
-                preceded(
-                    tag("S "),
-                    map(
-                        pair(is_not(" \r\n"), opt(preceded(tag(" "), is_not("\r\n")))),
-                        |(keyword, status): (&str, _)| Response::Information {
-                            keyword: keyword.to_owned(),
-                            status: status.map(String::from),
-                        },
+                    preceded(
+                        tag("ERR "),
+                        map(
+                            pair(gpg_error_code, opt(preceded(tag(" "), is_not("\r\n")))),
+                            |(code, description)| Response::Err {
+                                code,
+                                description: description.map(String::from),
+                            },
+                        ),
                     ),
-                ),
-                preceded(
-                    tag("# "),
-                    map(is_not("\r\n"), |comment: &str| {
-                        Response::Comment(comment.to_owned())
-                    }),
-                ),
-                preceded(
-                    tag("D "),
-                    map(is_not("\r\n"), |data: &str| {
-                        Response::DataLine(data.to_owned().into())
-                    }),
-                ),
-                preceded(
-                    tag("INQUIRE "),
-                    map(
-                        pair(is_not(" \r\n"), opt(preceded(tag(" "), is_not("\r\n")))),
-                        |(keyword, parameters): (&str, _)| Response::Inquire {
-                            keyword: keyword.to_owned(),
-                            parameters: parameters.map(String::from),
-                        },
+                    preceded(
+                        tag("S "),
+                        map(
+                            pair(is_not(" \r\n"), opt(preceded(tag(" "), is_not("\r\n")))),
+                            |(keyword, status): (&str, _)| Response::Information {
+                                keyword: keyword.to_owned(),
+                                status: status.map(String::from),
+                            },
+                        ),
                     ),
-                ),
-            )),
-            line_ending,
-        )(input)
+                    preceded(
+                        tag("# "),
+                        map(is_not("\r\n"), |comment: &str| {
+                            Response::Comment(comment.to_owned())
+                        }),
+                    ),
+                    preceded(tag("D "), map(is_not("\r\n"), Response::DataLine)),
+                    preceded(
+                        tag("INQUIRE "),
+                        map(
+                            pair(is_not(" \r\n"), opt(preceded(tag(" "), is_not("\r\n")))),
+                            |(keyword, parameters): (&str, _)| Response::Inquire {
+                                keyword: keyword.to_owned(),
+                                parameters: parameters.map(|p: &str| p.to_owned().into()),
+                            },
+                        ),
+                    ),
+                )),
+                line_ending,
+            )(input)
+        }
+    }
+
+    /// A hand-written parser that accepts exactly the same language as the `nom`-based
+    /// parser, for consumers that disable the `nom` feature.
+    #[cfg(not(feature = "nom"))]
+    mod imp {
+        use super::super::Response;
+
+        pub(super) fn server_response(input: &str) -> Option<Response<'_>> {
+            let line = line_contents(input)?;
+            if let Some(rest) = line.strip_prefix("OK") {
+                optional_param(rest).map(|params| Response::Ok(params.map(String::from)))
+            } else if let Some(rest) = line.strip_prefix("ERR ") {
+                // gpg encodes the error source in the upper bits, and the error code in
+                // the lowest 16 bits; these are split apart by `Error::from_parts`.
+                let digits = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                if digits == 0 {
+                    return None;
+                }
+                let (code, rest) = rest.split_at(digits);
+                Some(Response::Err {
+                    code: code.parse().ok()?,
+                    description: optional_param(rest)?.map(String::from),
+                })
+            } else if let Some(rest) = line.strip_prefix("S ") {
+                keyword_param(rest).map(|(keyword, status)| Response::Information {
+                    keyword: keyword.to_owned(),
+                    status: status.map(String::from),
+                })
+            } else if let Some(comment) = line.strip_prefix("# ") {
+                non_empty(comment).map(|comment| Response::Comment(comment.to_owned()))
+            } else if let Some(data) = line.strip_prefix("D ") {
+                non_empty(data).map(Response::DataLine)
+            } else if let Some(rest) = line.strip_prefix("INQUIRE ") {
+                keyword_param(rest).map(|(keyword, parameters)| Response::Inquire {
+                    keyword: keyword.to_owned(),
+                    parameters: parameters.map(|p| p.to_owned().into()),
+                })
+            } else {
+                None
+            }
+        }
+
+        /// Returns the contents of the first line of `input`, which must be terminated by
+        /// `\n` or `\r\n`.
+        fn line_contents(input: &str) -> Option<&str> {
+            let end = input.find(|c| c == '\r' || c == '\n')?;
+            let (line, ending) = input.split_at(end);
+            if ending.starts_with('\n') || ending.starts_with("\r\n") {
+                Some(line)
+            } else {
+                None
+            }
+        }
+
+        fn non_empty(s: &str) -> Option<&str> {
+            if s.is_empty() {
+                None
+            } else {
+                Some(s)
+            }
+        }
+
+        /// Parses either nothing, or a space followed by a non-empty parameter.
+        fn optional_param(rest: &str) -> Option<Option<&str>> {
+            if rest.is_empty() {
+                Some(None)
+            } else {
+                rest.strip_prefix(' ').and_then(non_empty).map(Some)
+            }
+        }
+
+        /// Parses a non-empty keyword (which cannot contain spaces), followed by an
+        /// optional parameter.
+        fn keyword_param(rest: &str) -> Option<(&str, Option<&str>)> {
+            let (keyword, rest) = rest.split_at(rest.find(' ').unwrap_or(rest.len()));
+            Some((non_empty(keyword)?, optional_param(rest)?))
+        }
     }
 }
 
@@ -259,9 +1576,44 @@ mod read {
 mod tests {
     use super::*;
 
+    #[test]
+    fn redaction() {
+        let redacted = |line: &str| {
+            let response = read::server_response(line).unwrap();
+            Redacted(&response).to_string()
+        };
+
+        assert_eq!(
+            redacted("OK Pleased to meet you\n"),
+            "OK Pleased to meet you"
+        );
+        assert_eq!(
+            redacted("ERR 83886179 Operation cancelled\n"),
+            "ERR 83886179 Operation cancelled"
+        );
+        assert_eq!(redacted("# a comment\n"), "# a comment");
+        assert_eq!(redacted("S PASSWORD_FROM_CACHE\n"), "S PASSWORD_FROM_CACHE");
+        assert_eq!(redacted("D hunter2\n"), "D [REDACTED]");
+        assert_eq!(
+            redacted("INQUIRE QUALITY hunter2\n"),
+            "INQUIRE QUALITY [REDACTED]"
+        );
+        assert_eq!(redacted("INQUIRE GENPIN\n"), "INQUIRE GENPIN");
+    }
+
+    #[test]
+    fn command_context() {
+        assert_eq!(super::command_context("SETDESC", Some("secret")), "SETDESC");
+        assert_eq!(
+            super::command_context("OPTION", Some("ttyname=/dev/tty")),
+            "OPTION ttyname"
+        );
+        assert_eq!(super::command_context("GETPIN", None), "GETPIN");
+    }
+
     #[test]
     fn encoding() {
-        assert_eq!(encode_request("CMD", None), "CMD\n");
+        assert_eq!(encode_request("CMD", None).unwrap().as_str(), "CMD\n");
         let pairs = [
             ("bar", " bar\n"),
             ("bar\nbaz", " bar%0Abaz\n"),
@@ -270,8 +1622,248 @@ mod tests {
             ("foo\\", " foo%5C\n"),
         ];
         for (p, want) in &pairs {
-            let have = encode_request("", Some(p));
-            assert_eq!(&have, want)
+            let have = encode_request("", Some(p)).unwrap();
+            assert_eq!(have.as_str(), *want);
+            // The buffer must not have been reallocated.
+            assert!(have.capacity() <= have.len() + 2);
+        }
+
+        assert!(encode_request("SETDESC", Some(&"a".repeat(991))).is_ok());
+        assert!(encode_request("SETDESC", Some(&"a".repeat(992))).is_err());
+        assert!(encode_request("SETDESC", Some(&"\n".repeat(400))).is_err());
+    }
+    #[test]
+    fn string_sink() {
+        let mut sink = StringSink::new(None);
+        assert!(sink.take(Utf8Policy::Strict).unwrap().is_none());
+
+        let line = "a%25".repeat(100);
+        sink.push(&line).unwrap();
+        sink.push("%C3%A9").unwrap();
+        let data = sink.take(Utf8Policy::Strict).unwrap().unwrap();
+        assert_eq!(data.expose_secret(), format!("{}é", "a%".repeat(100)));
+        assert_eq!(sink.buf.capacity(), 0);
+
+        sink.push("%E9").unwrap();
+        assert!(sink.take(Utf8Policy::Strict).is_err());
+        assert_eq!(sink.buf.capacity(), 0);
+        sink.push("%E9").unwrap();
+        let data = sink.take(Utf8Policy::Lossy).unwrap().unwrap();
+        assert_eq!(data.expose_secret(), "\u{FFFD}");
+
+        let mut sink = StringSink::new(Some(4));
+        sink.push("abcd").unwrap();
+        assert!(matches!(sink.push("e"), Err(Error::TooLong)));
+    }
+
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn response_timeout() {
+        use std::time::{Duration, Instant};
+
+        use crate::{test_util::Harness, ConfirmationDialog};
+
+        let mut harness = Harness::new();
+        harness.expect_options().expect("SETTITLE Wedged", &[]);
+
+        let start = Instant::now();
+        let res = ConfirmationDialog::with_binary(harness.path())
+            .unwrap()
+            .with_title("Wedged")
+            .with_response_timeout(Duration::from_millis(200))
+            .confirm("Continue?");
+        assert!(matches!(res, Err(Error::Timeout)));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn shutdown_timeout() {
+        use crate::test_util::Harness;
+
+        // The script acknowledges `BYE`, and then waits for another request.
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("BYE", &["OK closing connection"]);
+
+        let mut conn = Connection::open(
+            &harness.path(),
+            &Harness::unix_options(),
+            #[cfg(target_os = "macos")]
+            &crate::macos::Options::new(),
+            None,
+            None,
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        let start = Instant::now();
+        assert!(conn.shutdown().is_none());
+        assert!(start.elapsed() < Duration::from_secs(10));
+        harness.assert_complete();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn killer() {
+        use std::process::Command;
+
+        let spawn = |command: &mut Command| -> SharedProcess {
+            Arc::new(Mutex::new(Box::new(command.spawn().unwrap())))
+        };
+        let exited = spawn(&mut Command::new("true"));
+        while lock_child(&exited).try_wait().unwrap().is_none() {
+            thread::sleep(WAIT_INTERVAL);
+        }
+        assert!(!Killer(exited).kill().unwrap());
+
+        let running = spawn(Command::new("sleep").arg("10"));
+        assert!(Killer(running.clone()).kill().unwrap());
+        assert!(!wait_child(&running).unwrap().success());
+    }
+
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn line_too_long() {
+        use crate::{test_util::Harness, PassphraseInput};
+
+        let long = format!("D {}", "a".repeat(3000));
+        let mut harness = Harness::new();
+        harness.expect_options().expect("GETPIN", &[&long, "OK"]);
+
+        let mut input = PassphraseInput::with_binary(harness.path()).unwrap();
+        input.with_unix_options(&Harness::unix_options());
+        match input.interact() {
+            Err(Error::Protocol(e)) => {
+                assert_eq!(e.command(), Some("GETPIN"));
+                assert_eq!(e.line(), "D [REDACTED]");
+            }
+            res => panic!("unexpected result: {:?}", res),
         }
     }
+
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn skip_preamble() {
+        use crate::{test_util::Harness, MessageDialog};
+
+        let mut harness = Harness::new();
+        harness
+            .preamble(&["Starting pinentry...", ""])
+            .expect_options()
+            .expect("SETDESC Hello", &["OK"])
+            .expect("MESSAGE", &["OK"]);
+
+        let mut dialog = MessageDialog::with_binary(harness.path()).unwrap();
+        match dialog.show_message("Hello") {
+            Err(Error::NotAPinentry(e)) => {
+                assert_eq!(e.binary(), harness.path());
+                assert_eq!(e.greeting(), "Starting pinentry...");
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        dialog.skip_preamble().show_message("Hello").unwrap();
+    }
+
+    #[test]
+    fn parser() {
+        let parse = |line: &str| read::server_response(line).map(|r| Redacted(&r).to_string());
+
+        assert_eq!(parse("OK\n").as_deref(), Some("OK"));
+        assert_eq!(parse("OK\r\n").as_deref(), Some("OK"));
+        assert_eq!(parse("OK  two spaces\n").as_deref(), Some("OK  two spaces"));
+        assert_eq!(parse("ERR 99\n").as_deref(), Some("ERR 99"));
+        assert_eq!(
+            parse("ERR 99 Cancelled\n").as_deref(),
+            Some("ERR 99 Cancelled")
+        );
+        assert_eq!(parse("S KEY\n").as_deref(), Some("S KEY"));
+        assert_eq!(parse("S KEY a b\n").as_deref(), Some("S KEY a b"));
+        assert_eq!(parse("INQUIRE GENPIN\n").as_deref(), Some("INQUIRE GENPIN"));
+        assert_eq!(parse("D %25\n").as_deref(), Some("D [REDACTED]"));
+
+        for invalid in [
+            "",
+            "OK",
+            "OK \n",
+            "OKAY\n",
+            "OK\rfoo\n",
+            "ERR\n",
+            "ERR \n",
+            "ERR x\n",
+            "ERR 99x\n",
+            "ERR 99 \n",
+            "ERR 4294967296\n",
+            "S \n",
+            "S KEY \n",
+            "#\n",
+            "# \n",
+            "D \n",
+            "D data",
+            "INQUIRE\n",
+            "BYE\n",
+        ] {
+            assert!(parse(invalid).is_none(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn settings_requests() {
+        let in_effect = vec![
+            ("SETDESC".to_owned(), "Enter passphrase".to_owned()),
+            ("SETTITLE".to_owned(), "FooBar".to_owned()),
+        ];
+
+        // A fresh connection has nothing in effect.
+        assert_eq!(
+            super::settings_requests(Some(&[]), &[("SETDESC", "a"), ("SETERROR", "b")]),
+            [("SETDESC", Some("a")), ("SETERROR", Some("b"))],
+        );
+
+        // Only changed settings are sent.
+        assert_eq!(
+            super::settings_requests(
+                Some(&in_effect),
+                &[
+                    ("SETTITLE", "FooBar"),
+                    ("SETDESC", "Enter passphrase"),
+                    ("SETPROMPT", "PIN:"),
+                ],
+            ),
+            [("SETPROMPT", Some("PIN:"))],
+        );
+
+        // Unsetting a setting requires a reset.
+        assert_eq!(
+            super::settings_requests(Some(&in_effect), &[("SETTITLE", "FooBar")]),
+            [("RESET", None), ("SETTITLE", Some("FooBar"))],
+        );
+
+        // If the settings in effect are unknown, we must also reset.
+        assert_eq!(
+            super::settings_requests(None, &[("SETTITLE", "FooBar")]),
+            [("RESET", None), ("SETTITLE", Some("FooBar"))],
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn initial_options() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut unix = crate::unix::Options::new();
+        unix.tty_name(OsStr::from_bytes(b"/dev/pts/\xff%"))
+            .tty_type("dumb");
+        assert_eq!(
+            super::initial_options(
+                &unix,
+                #[cfg(target_os = "macos")]
+                &crate::macos::Options::new(),
+            ),
+            ["OPTION ttyname=/dev/pts/%FF%25", "OPTION ttytype=dumb"],
+        );
+    }
 }
@@ -0,0 +1,140 @@
+use std::io::{self, Read};
+use zeroize::Zeroize;
+
+use crate::mlock;
+
+/// The size of the internal buffer. Assuan lines are at most 1000 bytes long.
+const CAPACITY: usize = 1024;
+
+/// A buffered reader that wipes its internal buffer as lines are consumed, and on drop.
+///
+/// `BufReader` leaves consumed data (such as the passphrase contained in `D` lines) in
+/// its internal buffer until it happens to be overwritten. The internal buffer is also
+/// locked into memory if the `mlock` feature is enabled.
+pub(super) struct ZeroizingReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    pos: usize,
+    filled: usize,
+}
+
+impl<R: Read> ZeroizingReader<R> {
+    pub(super) fn new(inner: R) -> Self {
+        let mut buf = vec![0; CAPACITY];
+        mlock::lock(&mut buf);
+
+        ZeroizingReader {
+            inner,
+            buf,
+            pos: 0,
+            filled: 0,
+        }
+    }
+
+    /// Reads bytes up to and including the next newline, appending them to `line`.
+    ///
+    /// Returns the number of bytes read, which is zero once the end of the stream has
+    /// been reached. If the line is longer than `max_len` bytes (including the newline),
+    /// only its first bytes are appended to `line`, the rest of it is discarded, and
+    /// [`ReadError::TooLong`] is returned. `line` is never grown beyond `max_len`, so a
+    /// buffer with that capacity is never reallocated.
+    pub(super) fn read_line(
+        &mut self,
+        line: &mut Vec<u8>,
+        max_len: usize,
+    ) -> Result<usize, ReadError> {
+        let mut read = 0;
+        let mut too_long = false;
+        loop {
+            if self.pos == self.filled {
+                self.pos = 0;
+                self.filled = 0;
+                match self.inner.read(&mut self.buf) {
+                    Ok(0) if too_long => return Err(ReadError::TooLong),
+                    Ok(0) => return Ok(read),
+                    Ok(n) => self.filled = n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(ReadError::Io(e)),
+                }
+            }
+
+            let available = &mut self.buf[self.pos..self.filled];
+            let (used, done) = match available.iter().position(|&b| b == b'\n') {
+                Some(i) => (i + 1, true),
+                None => (available.len(), false),
+            };
+            let kept = used.min(max_len.saturating_sub(read));
+            line.extend_from_slice(&available[..kept]);
+            too_long |= kept < used;
+            available[..used].zeroize();
+            self.pos += used;
+            read += used;
+
+            if done {
+                return if too_long {
+                    Err(ReadError::TooLong)
+                } else {
+                    Ok(read)
+                };
+            }
+        }
+    }
+}
+
+/// An error returned when a line could not be read.
+#[derive(Debug)]
+pub(super) enum ReadError {
+    Io(io::Error),
+    /// The line was longer than the maximum length.
+    TooLong,
+}
+
+impl<R> Drop for ZeroizingReader<R> {
+    fn drop(&mut self) {
+        self.buf.zeroize();
+        mlock::unlock(&mut self.buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReadError, ZeroizingReader};
+
+    #[test]
+    fn read_lines() {
+        let mut reader = ZeroizingReader::new(&b"OK hello\nD secret\nOK"[..]);
+        let mut line = vec![];
+
+        assert_eq!(reader.read_line(&mut line, 1000).unwrap(), 9);
+        assert_eq!(line, b"OK hello\n");
+        line.clear();
+        assert_eq!(reader.read_line(&mut line, 1000).unwrap(), 9);
+        assert_eq!(line, b"D secret\n");
+        // The consumed lines have been wiped from the buffer.
+        assert!(reader.buf[..18].iter().all(|&b| b == 0));
+        line.clear();
+        assert_eq!(reader.read_line(&mut line, 1000).unwrap(), 2);
+        assert_eq!(line, b"OK");
+        line.clear();
+        assert_eq!(reader.read_line(&mut line, 1000).unwrap(), 0);
+    }
+
+    #[test]
+    fn too_long() {
+        let mut input = vec![b'D'; 3000];
+        input.extend_from_slice(b"\nOK\n");
+        let mut reader = ZeroizingReader::new(&input[..]);
+        let mut line = Vec::with_capacity(1000);
+
+        assert!(matches!(
+            reader.read_line(&mut line, 1000),
+            Err(ReadError::TooLong)
+        ));
+        assert_eq!(line.len(), 1000);
+        assert_eq!(line.capacity(), 1000);
+        // The rest of the long line was discarded.
+        line.clear();
+        assert_eq!(reader.read_line(&mut line, 1000).unwrap(), 3);
+        assert_eq!(line, b"OK\n");
+    }
+}
@@ -0,0 +1,148 @@
+//! Helpers for showing bidirectional text in dialogs.
+//!
+//! Descriptions often embed untrusted strings (such as file names or key labels) that
+//! may contain right-to-left characters. Without care, these can reorder the
+//! surrounding text when it is displayed, making a prompt ambiguous or misleading.
+//! [`isolate`] wraps such strings in Unicode directional isolates, so that they are
+//! displayed as a single unit that cannot affect the text around them.
+
+use std::fmt;
+
+/// FIRST STRONG ISOLATE
+const FSI: char = '\u{2068}';
+/// POP DIRECTIONAL ISOLATE
+const PDI: char = '\u{2069}';
+/// POP DIRECTIONAL FORMATTING
+const PDF: char = '\u{202c}';
+
+/// A problem with the bidirectional formatting characters in a string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BidiError {
+    offset: usize,
+}
+
+impl BidiError {
+    /// Returns the byte offset of the formatting character that caused this error.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl fmt::Display for BidiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unbalanced bidirectional formatting character at offset {}",
+            self.offset
+        )
+    }
+}
+
+impl std::error::Error for BidiError {}
+
+/// The maximum depth of embeddings, overrides, and isolates (from UAX #9).
+const MAX_DEPTH: usize = 125;
+
+/// Returns true if `c` opens an embedding or override.
+fn is_embedding(c: char) -> bool {
+    // LRE, RLE, LRO, RLO
+    matches!(c, '\u{202a}' | '\u{202b}' | '\u{202d}' | '\u{202e}')
+}
+
+/// Returns true if `c` opens an isolate.
+fn is_isolate(c: char) -> bool {
+    // LRI, RLI, FSI
+    matches!(c, '\u{2066}' | '\u{2067}' | FSI)
+}
+
+/// Returns true if `c` is a directional formatting character.
+fn is_formatting(c: char) -> bool {
+    is_embedding(c) || is_isolate(c) || c == PDF || c == PDI
+}
+
+/// Checks that every embedding, override, and isolate in `text` is closed within
+/// `text` (and nested at most 125 deep), and that nothing is closed that was not
+/// opened within `text`.
+///
+/// Strings that pass this check cannot change the direction of any text that follows
+/// them.
+pub fn validate(text: &str) -> Result<(), BidiError> {
+    // The offsets of the open embeddings and isolates, and whether each is an isolate.
+    let mut open: Vec<(usize, bool)> = vec![];
+    for (offset, c) in text.char_indices() {
+        if is_embedding(c) || is_isolate(c) {
+            if open.len() == MAX_DEPTH {
+                return Err(BidiError { offset });
+            }
+            open.push((offset, is_isolate(c)));
+        } else if c == PDF {
+            // A PDF only closes an embedding or override within the current isolate.
+            match open.last() {
+                Some((_, false)) => {
+                    open.pop();
+                }
+                _ => return Err(BidiError { offset }),
+            }
+        } else if c == PDI {
+            // A PDI closes the innermost isolate, and any embeddings or overrides
+            // within it that are still open.
+            match open.iter().rposition(|(_, isolate)| *isolate) {
+                Some(i) => open.truncate(i),
+                None => return Err(BidiError { offset }),
+            }
+        }
+    }
+    match open.first() {
+        Some(&(offset, _)) => Err(BidiError { offset }),
+        None => Ok(()),
+    }
+}
+
+/// Wraps `text` in Unicode directional isolates, so that it is displayed as a unit
+/// with its own direction that cannot reorder the surrounding text.
+///
+/// Any unbalanced formatting characters in `text` (see [`validate`]) are removed first,
+/// as they could otherwise extend beyond the isolate in some renderers.
+///
+/// # Examples
+///
+/// ```
+/// use pinentry::bidi;
+///
+/// let file = "\u{5e7}\u{5d5}\u{5d1}\u{5e5}.txt";
+/// let description = format!("Enter the passphrase for {}:", bidi::isolate(file));
+/// assert_eq!(
+///     description,
+///     "Enter the passphrase for \u{2068}\u{5e7}\u{5d5}\u{5d1}\u{5e5}.txt\u{2069}:",
+/// );
+/// ```
+pub fn isolate(text: &str) -> String {
+    let mut isolated = String::with_capacity(text.len() + 2 * FSI.len_utf8());
+    isolated.push(FSI);
+    if validate(text).is_ok() {
+        isolated.push_str(text);
+    } else {
+        isolated.extend(text.chars().filter(|c| !is_formatting(*c)));
+    }
+    isolated.push(PDI);
+    isolated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{isolate, validate};
+
+    #[test]
+    fn balanced() {
+        assert_eq!(validate("plain"), Ok(()));
+        assert_eq!(validate("a\u{2067}b\u{202b}c\u{202c}\u{2069}"), Ok(()));
+        assert_eq!(validate("a\u{202e}b").unwrap_err().offset(), 1);
+        assert_eq!(validate("ab\u{2069}").unwrap_err().offset(), 2);
+
+        assert_eq!(isolate("a\u{202e}b"), "\u{2068}ab\u{2069}");
+        assert_eq!(
+            isolate("a\u{2067}b\u{2069}"),
+            "\u{2068}a\u{2067}b\u{2069}\u{2069}"
+        );
+    }
+}
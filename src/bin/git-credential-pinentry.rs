@@ -0,0 +1,55 @@
+//! A `git` credential helper that asks for passwords with `pinentry`.
+//!
+//! This binary is built when the `git-credential` feature is enabled. `git` runs it
+//! for `credential.helper = pinentry`, with the action (`get`, `store`, or `erase`)
+//! as its only argument; see [`pinentry::git_credential`] for how each action is
+//! answered.
+//!
+//! The first `pinentry` binary found on `PATH` is used, unless
+//! `PINENTRY_GIT_CREDENTIAL_BINARY` names a different one.
+
+use std::env;
+use std::io;
+use std::process;
+
+use pinentry::{
+    git_credential::Helper, Error, PassphraseInput, PassphraseProvider, PassphraseRequest,
+};
+
+const USAGE: &str = "Usage: git-credential-pinentry <get|store|erase>";
+
+fn run(action: &str) -> Result<(), String> {
+    let input = match env::var_os("PINENTRY_GIT_CREDENTIAL_BINARY") {
+        Some(binary) => PassphraseInput::with_binary(binary),
+        None => PassphraseInput::with_default_binary(),
+    };
+    // Only `get` needs a `pinentry` binary.
+    let provider = |request: &PassphraseRequest<'_>| match &input {
+        Some(input) => input.passphrase(request),
+        None => Err(Error::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no pinentry binary found",
+        ))),
+    };
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let res = Helper::new(&provider).run(action, &mut stdin.lock(), &mut stdout.lock());
+    res.map_err(|e| e.to_string())
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let action = match (args.next(), args.next()) {
+        (Some(action), None) if action != "-h" && action != "--help" => action,
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(&action) {
+        eprintln!("git-credential-pinentry: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,155 @@
+//! An askpass program that shows its prompts with `pinentry`.
+//!
+//! This binary is built when the `askpass` feature is enabled. It follows the askpass
+//! convention shared by `SSH_ASKPASS`, `SUDO_ASKPASS`, and `GIT_ASKPASS`: it is run
+//! with the prompt as its only argument, and prints the passphrase (followed by a
+//! newline) to stdout, or exits with a non-zero status if the user cancelled.
+//!
+//! OpenSSH also sets `SSH_ASKPASS_PROMPT` to indicate other kinds of prompts:
+//!
+//! - `confirm` shows a confirmation dialog, and exits with a non-zero status unless
+//!   the user confirmed.
+//! - `none` shows a message, without requiring any response.
+//!
+//! The first `pinentry` binary found on `PATH` (or in the other directories searched by
+//! `PassphraseInput::with_default_binary`) is used, unless `PINENTRY_ASKPASS_BINARY`
+//! names a different one. Those directories include the ports and packages prefixes of
+//! the BSDs, so this also works from the minimal `PATH` that `doas` and system
+//! services run with.
+//!
+//! `doas` itself cannot use an askpass program, as it only reads passwords from the
+//! terminal; use `sudo -A` (with `SUDO_ASKPASS`) where a graphical prompt is needed.
+
+use std::env;
+use std::ffi::OsStr;
+use std::io::{self, Write};
+use std::process;
+
+use pinentry::{ConfirmationDialog, Error, MessageDialog, PassphraseInput};
+use secrecy::ExposeSecret;
+
+const USAGE: &str = "Usage: pinentry-askpass [PROMPT]";
+
+fn not_found() -> String {
+    "no pinentry binary found".to_owned()
+}
+
+/// Shows the prompt of the given kind (the value of `SSH_ASKPASS_PROMPT`), with the
+/// given `pinentry` binary or else the default one.
+fn run(prompt: &str, kind: Option<&str>, binary: Option<&OsStr>) -> Result<bool, String> {
+    let error = |e: Error| e.to_string();
+
+    match kind {
+        Some("confirm") => {
+            let dialog = match binary {
+                Some(binary) => ConfirmationDialog::with_binary(binary),
+                None => ConfirmationDialog::with_default_binary(),
+            };
+            match dialog.ok_or_else(not_found)?.confirm(prompt) {
+                Err(Error::Cancelled) | Err(Error::NotConfirmed) => Ok(false),
+                res => res.map_err(error),
+            }
+        }
+        Some("none") => {
+            let dialog = match binary {
+                Some(binary) => MessageDialog::with_binary(binary),
+                None => MessageDialog::with_default_binary(),
+            };
+            dialog
+                .ok_or_else(not_found)?
+                .show_message(prompt)
+                .map(|()| true)
+                .map_err(error)
+        }
+        _ => {
+            let input = match binary {
+                Some(binary) => PassphraseInput::with_binary(binary),
+                None => PassphraseInput::with_default_binary(),
+            };
+            match input
+                .ok_or_else(not_found)?
+                .with_description(prompt)
+                .interact()
+            {
+                Ok(passphrase) => {
+                    let stdout = io::stdout();
+                    let mut stdout = stdout.lock();
+                    stdout
+                        .write_all(passphrase.expose_secret().as_bytes())
+                        .and_then(|()| stdout.write_all(b"\n"))
+                        .and_then(|()| stdout.flush())
+                        .map_err(|e| e.to_string())?;
+                    Ok(true)
+                }
+                Err(Error::Cancelled) => Ok(false),
+                Err(e) => Err(error(e)),
+            }
+        }
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let prompt = args.next().unwrap_or_default();
+    if args.next().is_some() || prompt == "-h" || prompt == "--help" {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+
+    let kind = env::var("SSH_ASKPASS_PROMPT").ok();
+    let binary = env::var_os("PINENTRY_ASKPASS_BINARY");
+    match run(&prompt, kind.as_deref(), binary.as_deref()) {
+        Ok(true) => (),
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("pinentry-askpass: {}", e);
+            process::exit(2);
+        }
+    }
+}
+
+#[cfg(all(test, unix, feature = "test-util"))]
+mod tests {
+    use pinentry::test_util::Harness;
+
+    use super::run;
+
+    #[test]
+    fn kinds() {
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("SETDESC Allow use of key?", &["OK"])
+            .expect("CONFIRM", &["OK"]);
+        let binary = harness.path();
+        assert_eq!(
+            run("Allow use of key?", Some("confirm"), Some(binary.as_ref())),
+            Ok(true)
+        );
+        harness.assert_complete();
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("SETDESC Key added", &["OK"])
+            .expect("MESSAGE", &["OK"]);
+        let binary = harness.path();
+        assert_eq!(
+            run("Key added", Some("none"), Some(binary.as_ref())),
+            Ok(true)
+        );
+        harness.assert_complete();
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("SETDESC Enter passphrase for key:", &["OK"])
+            .expect("GETPIN", &["ERR 83886179 Operation cancelled"]);
+        let binary = harness.path();
+        assert_eq!(
+            run("Enter passphrase for key:", None, Some(binary.as_ref())),
+            Ok(false)
+        );
+        harness.assert_complete();
+    }
+}
@@ -0,0 +1,357 @@
+//! Shows `pinentry` dialogs from the command line.
+//!
+//! This binary is built when the `cli` feature is enabled. Run `pinentry-cli --help`
+//! for usage.
+
+use std::env;
+use std::io::{self, Write};
+use std::process;
+
+use pinentry::{ConfirmationDialog, Error, MessageDialog, PassphraseInput};
+use secrecy::ExposeSecret;
+
+const USAGE: &str = "\
+Usage: pinentry-cli [getpin|confirm|message] [OPTIONS]
+
+Shows a pinentry dialog. By default (getpin), asks for a passphrase and prints it
+followed by a newline.
+
+Options:
+  --binary <PATH>         The pinentry binary to use (default: the first pinentry
+                          found on PATH)
+  --title <TEXT>          The window title
+  --description <TEXT>    The text shown in the dialog
+  --prompt <TEXT>         The text before the passphrase entry (getpin; default:
+                          PIN: if --repeat is given)
+  --repeat <TEXT>         Asks for the passphrase twice, with this text before the
+                          second entry (getpin)
+  --required <TEXT>       Rejects empty passphrases with this error (getpin)
+  --ok <TEXT>             The text of the OK button
+  --cancel <TEXT>         The text of the Cancel button (getpin, confirm)
+  --not-ok <TEXT>         The text of the Not OK button (confirm)
+  --timeout <SECONDS>     Gives up after this many seconds
+  --fd <FD>               Prints the passphrase to this file descriptor (default: 1)
+  -h, --help              Prints this help
+
+Exit status: 0 on success, 1 if the dialog was cancelled or not confirmed, 2 on any
+other error.";
+
+/// The prompt used with `--repeat` if `--prompt` is not given, as the confirmation
+/// prompt is only shown alongside a passphrase prompt.
+const DEFAULT_PROMPT: &str = "PIN:";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    GetPin,
+    Confirm,
+    Message,
+}
+
+#[derive(Default)]
+struct Args {
+    binary: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    prompt: Option<String>,
+    repeat: Option<String>,
+    required: Option<String>,
+    ok: Option<String>,
+    cancel: Option<String>,
+    not_ok: Option<String>,
+    timeout: Option<u16>,
+    fd: Option<u32>,
+}
+
+/// Parses the command-line arguments (without the program name), returning `None` if
+/// the help was requested.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Option<(Mode, Args)>, String> {
+    let mut args = args.into_iter().peekable();
+    let mode = match args.peek().map(String::as_str) {
+        Some("getpin") => Some(Mode::GetPin),
+        Some("confirm") => Some(Mode::Confirm),
+        Some("message") => Some(Mode::Message),
+        _ => None,
+    };
+    if mode.is_some() {
+        args.next();
+    }
+
+    let mut parsed = Args::default();
+    while let Some(flag) = args.next() {
+        if flag == "-h" || flag == "--help" {
+            return Ok(None);
+        }
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{} requires a value", flag))
+        };
+        match flag.as_str() {
+            "--binary" => parsed.binary = Some(value()?),
+            "--title" => parsed.title = Some(value()?),
+            "--description" => parsed.description = Some(value()?),
+            "--prompt" => parsed.prompt = Some(value()?),
+            "--repeat" => parsed.repeat = Some(value()?),
+            "--required" => parsed.required = Some(value()?),
+            "--ok" => parsed.ok = Some(value()?),
+            "--cancel" => parsed.cancel = Some(value()?),
+            "--not-ok" => parsed.not_ok = Some(value()?),
+            "--timeout" => {
+                let timeout = value()?;
+                parsed.timeout = Some(
+                    timeout
+                        .parse()
+                        .map_err(|_| format!("invalid timeout: {}", timeout))?,
+                );
+            }
+            "--fd" => {
+                let fd = value()?;
+                parsed.fd = Some(
+                    fd.parse()
+                        .ok()
+                        .filter(|&fd| is_open(fd))
+                        .ok_or_else(|| format!("invalid fd: {}", fd))?,
+                );
+            }
+            _ => return Err(format!("unexpected argument: {}", flag)),
+        }
+    }
+    Ok(Some((mode.unwrap_or(Mode::GetPin), parsed)))
+}
+
+/// Returns whether the given file descriptor is open in this process.
+#[cfg(unix)]
+fn is_open(fd: u32) -> bool {
+    match libc::c_int::try_from(fd) {
+        // Safety: `F_GETFD` only reads the flags of the descriptor, and fails if it is
+        // not open.
+        Ok(fd) => unsafe { libc::fcntl(fd, libc::F_GETFD) != -1 },
+        Err(_) => false,
+    }
+}
+
+/// Returns whether the given file descriptor can be written to; only stdout is
+/// supported on this platform.
+#[cfg(not(unix))]
+fn is_open(fd: u32) -> bool {
+    fd == 1
+}
+
+/// Writes the passphrase, followed by a newline, to the given file descriptor.
+fn write_secret(fd: Option<u32>, secret: &str) -> io::Result<()> {
+    match fd {
+        None | Some(1) => {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            stdout.write_all(secret.as_bytes())?;
+            stdout.write_all(b"\n")?;
+            stdout.flush()
+        }
+        #[cfg(unix)]
+        Some(fd) => {
+            use std::fs::File;
+            use std::mem::ManuallyDrop;
+            use std::os::unix::io::{FromRawFd, RawFd};
+
+            // Safety: the caller asked us to write to this file descriptor, which
+            // `parse_args` checked is open (and so fits in a `RawFd`), and we do not
+            // close it (so that we never close a descriptor we don't own).
+            let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd as RawFd) });
+            file.write_all(secret.as_bytes())?;
+            file.write_all(b"\n")?;
+            file.flush()
+        }
+        #[cfg(not(unix))]
+        Some(fd) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("cannot write to file descriptor {} on this platform", fd),
+        )),
+    }
+}
+
+fn not_found() -> String {
+    "no pinentry binary found".to_owned()
+}
+
+fn run(mode: Mode, args: &Args) -> Result<bool, String> {
+    let binary = args.binary.as_deref();
+    let error = |e: Error| e.to_string();
+
+    match mode {
+        Mode::GetPin => {
+            let mut input = match binary {
+                Some(binary) => {
+                    PassphraseInput::try_with_binary(binary).map_err(|e| e.to_string())?
+                }
+                None => PassphraseInput::with_default_binary().ok_or_else(not_found)?,
+            };
+            if let Some(title) = &args.title {
+                input.with_title(title);
+            }
+            if let Some(description) = &args.description {
+                input.with_description(description);
+            }
+            match (&args.prompt, &args.repeat) {
+                (Some(prompt), _) => {
+                    input.with_prompt(prompt);
+                }
+                (None, Some(_)) => {
+                    input.with_prompt(DEFAULT_PROMPT);
+                }
+                (None, None) => (),
+            }
+            if let Some(repeat) = &args.repeat {
+                input.with_confirmation_prompt(repeat);
+            }
+            if let Some(required) = &args.required {
+                input.required(required);
+            }
+            if let Some(ok) = &args.ok {
+                input.with_ok(ok);
+            }
+            if let Some(cancel) = &args.cancel {
+                input.with_cancel(cancel);
+            }
+            if let Some(timeout) = args.timeout {
+                input.with_timeout(timeout);
+            }
+            match input.interact() {
+                Ok(passphrase) => {
+                    write_secret(args.fd, passphrase.expose_secret()).map_err(|e| e.to_string())?;
+                    Ok(true)
+                }
+                Err(Error::Cancelled) => Ok(false),
+                Err(e) => Err(error(e)),
+            }
+        }
+        Mode::Confirm => {
+            let mut dialog = match binary {
+                Some(binary) => {
+                    ConfirmationDialog::try_with_binary(binary).map_err(|e| e.to_string())?
+                }
+                None => ConfirmationDialog::with_default_binary().ok_or_else(not_found)?,
+            };
+            if let Some(title) = &args.title {
+                dialog.with_title(title);
+            }
+            if let Some(ok) = &args.ok {
+                dialog.with_ok(ok);
+            }
+            if let Some(cancel) = &args.cancel {
+                dialog.with_cancel(cancel);
+            }
+            if let Some(not_ok) = &args.not_ok {
+                dialog.with_not_ok(not_ok);
+            }
+            if let Some(timeout) = args.timeout {
+                dialog.with_timeout(timeout);
+            }
+            match dialog.confirm(args.description.as_deref().unwrap_or_default()) {
+                Err(Error::Cancelled) | Err(Error::NotConfirmed) => Ok(false),
+                res => res.map_err(error),
+            }
+        }
+        Mode::Message => {
+            let mut dialog = match binary {
+                Some(binary) => {
+                    MessageDialog::try_with_binary(binary).map_err(|e| e.to_string())?
+                }
+                None => MessageDialog::with_default_binary().ok_or_else(not_found)?,
+            };
+            if let Some(title) = &args.title {
+                dialog.with_title(title);
+            }
+            if let Some(ok) = &args.ok {
+                dialog.with_ok(ok);
+            }
+            if let Some(timeout) = args.timeout {
+                dialog.with_timeout(timeout);
+            }
+            dialog
+                .show_message(args.description.as_deref().unwrap_or_default())
+                .map(|()| true)
+                .map_err(error)
+        }
+    }
+}
+
+fn main() {
+    let (mode, args) = match parse_args(env::args().skip(1)) {
+        Ok(Some(parsed)) => parsed,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("pinentry-cli: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    match run(mode, &args) {
+        Ok(true) => (),
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("pinentry-cli: {}", e);
+            process::exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_args, Mode};
+
+    fn parse(args: &[&str]) -> Result<Option<(Mode, super::Args)>, String> {
+        parse_args(args.iter().map(|arg| (*arg).to_owned()))
+    }
+
+    #[test]
+    fn args() {
+        let (mode, args) = parse(&["confirm", "--description", "Sure?", "--timeout", "5"])
+            .unwrap()
+            .unwrap();
+        assert!(mode == Mode::Confirm);
+        assert_eq!(args.description.as_deref(), Some("Sure?"));
+        assert_eq!(args.timeout, Some(5));
+
+        let (mode, _) = parse(&[]).unwrap().unwrap();
+        assert!(mode == Mode::GetPin);
+        assert!(parse(&["--help"]).unwrap().is_none());
+        assert!(parse(&["--title"]).is_err());
+        assert!(parse(&["--timeout", "-1"]).is_err());
+        assert!(parse(&["--frobnicate"]).is_err());
+    }
+
+    #[test]
+    fn fd() {
+        let (_, args) = parse(&["--fd", "1"]).unwrap().unwrap();
+        assert_eq!(args.fd, Some(1));
+
+        for fd in ["-1", "one", "4294967295", "1000000"] {
+            assert_eq!(
+                parse(&["--fd", fd]).err(),
+                Some(format!("invalid fd: {}", fd))
+            );
+        }
+    }
+
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn repeat_without_prompt() {
+        use pinentry::test_util::Harness;
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("SETPROMPT PIN:", &["OK"])
+            .expect("SETREPEAT Again:", &["OK"])
+            .expect_prefix("SETREPEATERROR ", &["OK"])
+            .expect("GETPIN", &["ERR 83886179 Operation cancelled"]);
+
+        let path = harness.path().to_str().unwrap().to_owned();
+        let (mode, args) = parse(&["--binary", &path, "--repeat", "Again:"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(super::run(mode, &args), Ok(false));
+        harness.assert_complete();
+    }
+}
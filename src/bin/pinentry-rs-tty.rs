@@ -0,0 +1,330 @@
+//! A minimal terminal-based `pinentry` binary, for systems without any other.
+//!
+//! This binary is built when the `tty-pinentry` feature is enabled (for example, with
+//! `cargo install pinentry --features tty-pinentry`). It reads passphrases from the
+//! terminal set with `OPTION ttyname` (or else `/dev/tty`) without echoing them, and
+//! asks for confirmations with a `[y/n]` prompt. With the feature enabled, dialogs fall
+//! back to this binary when no `pinentry` binary can be found; see
+//! `PassphraseInput::with_default_binary`.
+//!
+//! Pressing Ctrl-C, or Ctrl-D on an empty line, cancels the dialog. Timeouts are
+//! accepted but ignored, as are the settings that only apply to GUI pinentries.
+//!
+//! This binary only supports Unix.
+
+#[cfg(unix)]
+fn main() -> std::io::Result<()> {
+    unix::main()
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("pinentry-rs-tty: only Unix is supported");
+    std::process::exit(2);
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::io::{self, BufRead, Write};
+
+    use pinentry::encoding::{decode, encode_bytes};
+    use pinentry::zeroize::Zeroizing;
+
+    /// `GPG_ERR_CANCELED`, with the error source set to `pinentry`.
+    const CANCELLED: &str = "ERR 83886179 Operation cancelled";
+    /// `GPG_ERR_NOT_CONFIRMED`, with the error source set to `pinentry`.
+    const NOT_CONFIRMED: &str = "ERR 83886194 Not confirmed";
+    /// `GPG_ERR_ENOTTY`, with the error source set to `pinentry`.
+    const NO_TERMINAL: &str = "ERR 83918950 Inappropriate ioctl for device";
+    /// `GPG_ERR_ASS_UNKNOWN_CMD`, with the error source set to `pinentry`.
+    const UNKNOWN_COMMAND: &str = "ERR 83886355 Unknown command";
+
+    /// The maximum length of the data in a single `D` line, leaving room for the `D `
+    /// prefix and the newline.
+    const MAX_DATA_LEN: usize = 997;
+
+    /// The settings of the current dialog.
+    #[derive(Default)]
+    struct Dialog {
+        tty_name: Option<String>,
+        title: Option<String>,
+        description: Option<String>,
+        error: Option<String>,
+        prompt: Option<String>,
+        repeat: Option<String>,
+        repeat_error: Option<String>,
+        not_ok: bool,
+    }
+
+    impl Dialog {
+        /// Opens the terminal, and shows the title, error, and description.
+        fn show(&mut self) -> io::Result<Tty> {
+            let mut tty = Tty::open(self.tty_name.as_deref().unwrap_or("/dev/tty"))?;
+            writeln!(tty.file)?;
+            // Errors are only shown once, as each is set again before it applies.
+            let error = self.error.take();
+            for text in [&self.title, &error, &self.description]
+                .iter()
+                .filter_map(|text| text.as_deref())
+            {
+                writeln!(tty.file, "{}", text)?;
+            }
+            Ok(tty)
+        }
+
+        /// Asks for a passphrase, returning `None` if the user cancelled.
+        fn get_pin(&mut self) -> io::Result<Option<Zeroizing<Vec<u8>>>> {
+            loop {
+                let mut tty = self.show()?;
+                let prompt = self.prompt.as_deref().unwrap_or("PIN:");
+                let pin = match tty.read_line(prompt, false)? {
+                    Some(pin) => pin,
+                    None => return Ok(None),
+                };
+                let repeat = match &self.repeat {
+                    Some(repeat) => repeat,
+                    None => return Ok(Some(pin)),
+                };
+                match tty.read_line(repeat, false)? {
+                    Some(again) if again == pin => return Ok(Some(pin)),
+                    Some(_) => {
+                        self.error = Some(
+                            (self.repeat_error.as_deref())
+                                .unwrap_or("Passphrases do not match")
+                                .to_owned(),
+                        );
+                    }
+                    None => return Ok(None),
+                }
+            }
+        }
+
+        /// Asks for a confirmation, returning the response to send.
+        fn confirm(&mut self, one_button: bool) -> io::Result<&'static str> {
+            let mut tty = self.show()?;
+            if one_button {
+                return Ok(match tty.read_line("Press Enter to continue", true)? {
+                    Some(_) => "OK",
+                    None => CANCELLED,
+                });
+            }
+            loop {
+                let answer = match tty.read_line("[y/n]", true)? {
+                    Some(answer) => answer,
+                    None => return Ok(CANCELLED),
+                };
+                match answer.to_ascii_lowercase().as_slice() {
+                    b"y" | b"yes" => return Ok("OK"),
+                    b"n" | b"no" if self.not_ok => return Ok(NOT_CONFIRMED),
+                    b"n" | b"no" => return Ok(CANCELLED),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    /// A terminal in non-canonical mode, which is restored when dropped.
+    struct Tty {
+        file: std::fs::File,
+        saved: libc::termios,
+    }
+
+    impl Tty {
+        fn open(tty_name: &str) -> io::Result<Self> {
+            use std::os::unix::io::AsRawFd;
+
+            let file = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(tty_name)?;
+            let fd = file.as_raw_fd();
+            // Safety: `termios` is valid when zeroed, and is filled in by `tcgetattr`.
+            let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+            // Safety: `fd` is open, and `saved` is a valid `termios`.
+            if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // Keystrokes are handled here, so that Ctrl-C cancels the dialog instead of
+            // killing this process (and leaving echo disabled).
+            let mut raw = saved;
+            raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            // Safety: as above.
+            if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Tty { file, saved })
+        }
+
+        /// Reads a line after showing the given prompt, returning `None` if the user
+        /// cancelled.
+        fn read_line(
+            &mut self,
+            prompt: &str,
+            echo: bool,
+        ) -> io::Result<Option<Zeroizing<Vec<u8>>>> {
+            use std::io::Read;
+
+            write!(self.file, "{} ", prompt)?;
+            self.file.flush()?;
+            let mut line = Zeroizing::new(vec![]);
+            let mut byte = [0];
+            let res = loop {
+                if self.file.read(&mut byte)? == 0 {
+                    break None;
+                }
+                match byte[0] {
+                    b'\r' | b'\n' => break Some(line),
+                    // Ctrl-C, or Ctrl-D on an empty line.
+                    0x03 => break None,
+                    0x04 if line.is_empty() => break None,
+                    // Backspace removes a whole UTF-8 character.
+                    0x08 | 0x7f => {
+                        while let Some(b) = line.pop() {
+                            if b & 0xc0 != 0x80 {
+                                break;
+                            }
+                        }
+                        if echo {
+                            self.file.write_all(b"\x08 \x08")?;
+                        }
+                    }
+                    // Ctrl-U clears the line.
+                    0x15 => line.clear(),
+                    b if b < 0x20 => (),
+                    b => {
+                        line.push(b);
+                        if echo {
+                            self.file.write_all(&byte)?;
+                        }
+                    }
+                }
+                self.file.flush()?;
+            };
+            writeln!(self.file)?;
+            Ok(res)
+        }
+    }
+
+    impl Drop for Tty {
+        fn drop(&mut self) {
+            use std::os::unix::io::AsRawFd;
+
+            // Safety: the file is open, and `saved` was filled in by `tcgetattr`.
+            unsafe { libc::tcsetattr(self.file.as_raw_fd(), libc::TCSANOW, &self.saved) };
+        }
+    }
+
+    /// Writes the given data in `D` lines, splitting it so that no line is too long.
+    fn write_data(out: &mut impl Write, data: &[u8]) -> io::Result<()> {
+        let mut line = Zeroizing::new(String::new());
+        for byte in data {
+            let encoded = encode_bytes(&[*byte]);
+            if line.len() + encoded.len() > MAX_DATA_LEN {
+                writeln!(out, "D {}", line.as_str())?;
+                line.clear();
+            }
+            line.push_str(&encoded);
+        }
+        writeln!(out, "D {}", line.as_str())
+    }
+
+    pub(crate) fn main() -> io::Result<()> {
+        let mut dialog = Dialog::default();
+
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        writeln!(out, "OK Pleased to meet you")?;
+        out.flush()?;
+
+        for line in stdin.lock().lines() {
+            let line = line?;
+            let (command, parameters) = match line.split_once(' ') {
+                Some((command, parameters)) => (command, Some(parameters)),
+                None => (line.as_str(), None),
+            };
+            let text = || parameters.map(|p| String::from_utf8_lossy(&decode(p)).into_owned());
+
+            match (command.to_ascii_uppercase().as_str(), parameters) {
+                ("BYE", _) => {
+                    writeln!(out, "OK closing connection")?;
+                    out.flush()?;
+                    return Ok(());
+                }
+                ("GETPIN", _) => match dialog.get_pin() {
+                    Ok(Some(pin)) => {
+                        if dialog.repeat.is_some() {
+                            writeln!(out, "S PIN_REPEATED")?;
+                        }
+                        write_data(&mut out, &pin)?;
+                        writeln!(out, "OK")?;
+                    }
+                    Ok(None) => writeln!(out, "{}", CANCELLED)?,
+                    Err(_) => writeln!(out, "{}", NO_TERMINAL)?,
+                },
+                ("CONFIRM", _) | ("MESSAGE", _) => {
+                    let one_button = command.eq_ignore_ascii_case("MESSAGE")
+                        || parameters.map_or(false, |p| p.trim() == "--one-button");
+                    match dialog.confirm(one_button) {
+                        Ok(response) => writeln!(out, "{}", response)?,
+                        Err(_) => writeln!(out, "{}", NO_TERMINAL)?,
+                    }
+                }
+                ("OPTION", Some(option)) => {
+                    if let Some(tty_name) = option.strip_prefix("ttyname=") {
+                        dialog.tty_name = Some(tty_name.to_owned());
+                    }
+                    writeln!(out, "OK")?
+                }
+                ("SETTITLE", _) => {
+                    dialog.title = text();
+                    writeln!(out, "OK")?
+                }
+                ("SETDESC", _) => {
+                    dialog.description = text();
+                    writeln!(out, "OK")?
+                }
+                ("SETERROR", _) => {
+                    dialog.error = text();
+                    writeln!(out, "OK")?
+                }
+                ("SETPROMPT", _) => {
+                    dialog.prompt = text();
+                    writeln!(out, "OK")?
+                }
+                ("SETREPEAT", _) => {
+                    dialog.repeat = Some(text().unwrap_or_else(|| "Repeat:".to_owned()));
+                    writeln!(out, "OK")?
+                }
+                ("SETREPEATERROR", _) => {
+                    dialog.repeat_error = text();
+                    writeln!(out, "OK")?
+                }
+                ("SETNOTOK", _) => {
+                    dialog.not_ok = parameters.is_some();
+                    writeln!(out, "OK")?
+                }
+                ("RESET", _) => {
+                    dialog = Dialog {
+                        tty_name: dialog.tty_name.take(),
+                        ..Dialog::default()
+                    };
+                    writeln!(out, "OK")?
+                }
+                ("GETINFO", Some("flavor")) => writeln!(out, "D tty\nOK")?,
+                ("GETINFO", Some("version")) => {
+                    writeln!(out, "D {}\nOK", env!("CARGO_PKG_VERSION"))?
+                }
+                ("GETINFO", Some("pid")) => writeln!(out, "D {}\nOK", std::process::id())?,
+                ("GETINFO", _) => writeln!(out, "{}", UNKNOWN_COMMAND)?,
+                ("OPTION", _) | ("NOP", _) => writeln!(out, "OK")?,
+                (command, _) if command.starts_with("SET") => writeln!(out, "OK")?,
+                _ => writeln!(out, "{}", UNKNOWN_COMMAND)?,
+            }
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+//! A minimal, deterministic `pinentry` binary for integration tests.
+//!
+//! This binary is built when the `test-pinentry` feature is enabled. It accepts every
+//! setting, and answers each dialog immediately without showing anything:
+//!
+//! - `GETPIN` returns the value of the `PINENTRY_TEST_PASSPHRASE` environment
+//!   variable (or `test passphrase` if it is unset).
+//! - `CONFIRM` succeeds, unless `PINENTRY_TEST_CONFIRM` is set to `no` (the user
+//!   chose the "not OK" button) or `cancel` (the user cancelled the dialog).
+//! - `MESSAGE` succeeds.
+//!
+//! Setting `PINENTRY_TEST_CONFIRM=cancel` also cancels `GETPIN`.
+
+use std::env;
+use std::io::{self, BufRead, Write};
+
+use pinentry::encoding::encode;
+
+/// `GPG_ERR_CANCELED`, with the error source set to `pinentry`.
+const CANCELLED: &str = "ERR 83886179 Operation cancelled";
+/// `GPG_ERR_NOT_CONFIRMED`, with the error source set to `pinentry`.
+const NOT_CONFIRMED: &str = "ERR 83886194 Not confirmed";
+/// `GPG_ERR_ASS_UNKNOWN_CMD`, with the error source set to `pinentry`.
+const UNKNOWN_COMMAND: &str = "ERR 83886355 Unknown command";
+
+fn main() -> io::Result<()> {
+    let passphrase =
+        env::var("PINENTRY_TEST_PASSPHRASE").unwrap_or_else(|_| "test passphrase".to_owned());
+    let confirm = env::var("PINENTRY_TEST_CONFIRM").unwrap_or_default();
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    writeln!(out, "OK Pleased to meet you")?;
+    out.flush()?;
+
+    for line in stdin.lock().lines() {
+        let line = line?;
+        let (command, parameters) = match line.split_once(' ') {
+            Some((command, parameters)) => (command, Some(parameters)),
+            None => (line.as_str(), None),
+        };
+
+        match (command.to_ascii_uppercase().as_str(), parameters) {
+            ("BYE", _) => {
+                writeln!(out, "OK closing connection")?;
+                out.flush()?;
+                return Ok(());
+            }
+            ("GETPIN", _) if confirm == "cancel" => writeln!(out, "{}", CANCELLED)?,
+            ("GETPIN", _) => writeln!(out, "D {}\nOK", encode(&passphrase))?,
+            ("CONFIRM", _) if confirm == "cancel" => writeln!(out, "{}", CANCELLED)?,
+            ("CONFIRM", _) if confirm == "no" => writeln!(out, "{}", NOT_CONFIRMED)?,
+            ("GETINFO", Some("flavor")) => writeln!(out, "D test\nOK")?,
+            ("GETINFO", Some("version")) => writeln!(out, "D {}\nOK", env!("CARGO_PKG_VERSION"))?,
+            ("GETINFO", Some("pid")) => writeln!(out, "D {}\nOK", std::process::id())?,
+            ("GETINFO", _) => writeln!(out, "{}", UNKNOWN_COMMAND)?,
+            ("CONFIRM", _) | ("MESSAGE", _) | ("OPTION", _) | ("RESET", _) | ("NOP", _) => {
+                writeln!(out, "OK")?
+            }
+            (command, _) if command.starts_with("SET") => writeln!(out, "OK")?,
+            _ => writeln!(out, "{}", UNKNOWN_COMMAND)?,
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
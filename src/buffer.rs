@@ -0,0 +1,164 @@
+use std::fmt;
+
+use secrecy::SecretString;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{constant_time_eq, mlock};
+
+/// A fixed-capacity buffer for receiving a passphrase.
+///
+/// The buffer is allocated once, when it is created, and is never reallocated. Its
+/// entire allocation is wiped when it is cleared or dropped, and (with the `mlock`
+/// feature) is locked into RAM for its whole lifetime.
+///
+/// Passing a `SecretBuffer` to [`PassphraseInput::interact_into`] decodes the
+/// passphrase directly into it, without any intermediate allocations holding the
+/// decoded passphrase.
+///
+/// [`PassphraseInput::interact_into`]: crate::PassphraseInput::interact_into
+pub struct SecretBuffer {
+    buf: Vec<u8>,
+}
+
+impl SecretBuffer {
+    /// Creates an empty buffer that can hold up to `capacity` bytes.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut buf = Vec::with_capacity(capacity);
+        mlock::lock(&mut buf);
+        SecretBuffer { buf }
+    }
+
+    /// Returns the maximum number of bytes that this buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    /// Returns the number of bytes in this buffer.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns true if this buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Wipes the contents of this buffer.
+    pub fn clear(&mut self) {
+        self.buf.zeroize();
+    }
+
+    /// Exposes the contents of this buffer.
+    ///
+    /// Passphrases are valid UTF-8, unless they were received with
+    /// [`Utf8Policy::Raw`](crate::Utf8Policy::Raw).
+    pub fn expose_secret(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Appends a byte to this buffer, returning `false` if it is full.
+    pub(crate) fn push(&mut self, byte: u8) -> bool {
+        if self.buf.len() < self.buf.capacity() {
+            self.buf.push(byte);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Compares the contents of two buffers in constant time.
+impl PartialEq for SecretBuffer {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time_eq(&self.buf, &other.buf)
+    }
+}
+
+impl Eq for SecretBuffer {}
+
+impl fmt::Debug for SecretBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretBuffer([REDACTED])")
+    }
+}
+
+/// Copies the contents of the buffer into a [`SecretString`].
+///
+/// Returns an error if the contents are not valid UTF-8.
+impl TryFrom<&SecretBuffer> for SecretString {
+    type Error = std::str::Utf8Error;
+
+    fn try_from(buf: &SecretBuffer) -> Result<Self, Self::Error> {
+        std::str::from_utf8(&buf.buf).map(SecretString::from)
+    }
+}
+
+/// Copies the contents of the buffer into a [`Zeroizing`] string, which is wiped on
+/// drop.
+///
+/// Returns an error if the contents are not valid UTF-8.
+impl TryFrom<&SecretBuffer> for Zeroizing<String> {
+    type Error = std::str::Utf8Error;
+
+    fn try_from(buf: &SecretBuffer) -> Result<Self, Self::Error> {
+        std::str::from_utf8(&buf.buf).map(|s| Zeroizing::new(s.to_owned()))
+    }
+}
+
+/// Copies the contents of the buffer into a [`Zeroizing`] vector, which is wiped on
+/// drop.
+impl From<&SecretBuffer> for Zeroizing<Vec<u8>> {
+    fn from(buf: &SecretBuffer) -> Self {
+        Zeroizing::new(buf.buf.clone())
+    }
+}
+
+impl Drop for SecretBuffer {
+    fn drop(&mut self) {
+        self.buf.zeroize();
+        mlock::unlock(&mut self.buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::{ExposeSecret, SecretString};
+    use zeroize::Zeroizing;
+
+    use super::SecretBuffer;
+
+    #[test]
+    fn fixed_capacity() {
+        let mut buf = SecretBuffer::with_capacity(4);
+        let capacity = buf.capacity();
+        for byte in b"abcd".iter().cycle().take(capacity) {
+            assert!(buf.push(*byte));
+        }
+        assert!(!buf.push(b'e'));
+        assert_eq!(buf.len(), capacity);
+        assert_eq!(buf.capacity(), capacity);
+
+        buf.clear();
+        assert!(buf.is_empty());
+        assert_eq!(buf.capacity(), capacity);
+        assert_eq!(format!("{:?}", buf), "SecretBuffer([REDACTED])");
+    }
+
+    #[test]
+    fn conversions() {
+        let mut buf = SecretBuffer::with_capacity(8);
+        for byte in "hünter".bytes() {
+            assert!(buf.push(byte));
+        }
+        assert_eq!(
+            SecretString::try_from(&buf).unwrap().expose_secret(),
+            "hünter"
+        );
+        assert_eq!(*Zeroizing::<String>::try_from(&buf).unwrap(), "hünter");
+        assert_eq!(*Zeroizing::<Vec<u8>>::from(&buf), "hünter".as_bytes());
+
+        buf.clear();
+        assert!(buf.push(0xff));
+        assert!(SecretString::try_from(&buf).is_err());
+    }
+}
@@ -0,0 +1,305 @@
+use secrecy::{ExposeSecret, SecretString};
+
+use crate::{Error, Result, SecretBuffer, UnsupportedCharsetError};
+
+/// The character set used by a `pinentry` binary for request parameters and responses.
+///
+/// The Assuan protocol itself is mostly ASCII, but the texts shown in dialogs and the
+/// passphrases returned by `pinentry` binaries are encoded in the character set of the
+/// `pinentry` process's locale. Modern `pinentry` binaries always use UTF-8, but older
+/// ones may use the locale's character set instead.
+///
+/// Requests are always built from UTF-8 strings, and responses are always returned as
+/// UTF-8 strings; this only controls how they are transcoded to and from the
+/// `pinentry` binary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+#[non_exhaustive]
+pub enum Charset {
+    /// UTF-8 (the default), which is passed through unchanged.
+    Utf8,
+    /// ISO-8859-1 (Latin-1).
+    ///
+    /// Characters in requests that cannot be represented in Latin-1 are replaced by `?`.
+    Latin1,
+}
+
+impl Charset {
+    /// Returns the character set of the process locale (from `LC_ALL`, `LC_CTYPE`, or
+    /// `LANG`).
+    ///
+    /// If no locale is set, or the locale is `C` or `POSIX` (which use ASCII), this is
+    /// UTF-8. Returns an error if the locale names any other character set than UTF-8,
+    /// ASCII, or Latin-1 (such as ISO-8859-15 or KOI8-R), or does not name one at all
+    /// (such as `de_DE`, whose character set depends on the system), as transcoding
+    /// with the wrong character set would corrupt passphrases.
+    pub fn from_locale() -> std::result::Result<Self, UnsupportedCharsetError> {
+        ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .map_or(Ok(Charset::Utf8), |locale| Self::from_locale_name(&locale))
+    }
+
+    fn from_locale_name(locale: &str) -> std::result::Result<Self, UnsupportedCharsetError> {
+        let name = locale.split('@').next().unwrap_or_default();
+        let codeset = match name.split_once('.') {
+            Some((_, codeset)) => codeset
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+                .to_ascii_lowercase(),
+            None if name == "C" || name == "POSIX" => return Ok(Charset::Utf8),
+            None => return Err(UnsupportedCharsetError::new(locale)),
+        };
+        match codeset.as_str() {
+            "utf8" | "ascii" | "usascii" | "ansix341968" => Ok(Charset::Utf8),
+            "iso88591" | "latin1" => Ok(Charset::Latin1),
+            _ => Err(UnsupportedCharsetError::new(locale)),
+        }
+    }
+
+    /// Appends an encoded request line to `buf`, transcoded into this character set.
+    pub(crate) fn encode(self, request: &str, buf: &mut Vec<u8>) {
+        match self {
+            Charset::Utf8 => buf.extend_from_slice(request.as_bytes()),
+            Charset::Latin1 => buf.extend(
+                request
+                    .chars()
+                    .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?')),
+            ),
+        }
+    }
+
+    /// Transcodes a response line from this character set into UTF-8, in place.
+    ///
+    /// The line is never reallocated (as it may be locked into memory). Returns false,
+    /// leaving the line unchanged, if the transcoded line would not fit in its capacity.
+    pub(crate) fn decode(self, line: &mut Vec<u8>) -> bool {
+        match self {
+            Charset::Utf8 => true,
+            Charset::Latin1 => {
+                let len = line.len();
+                let decoded_len = len + line.iter().filter(|b| **b >= 0x80).count();
+                if decoded_len == len {
+                    return true;
+                }
+                if decoded_len > line.capacity() {
+                    return false;
+                }
+
+                // Decode from the end, so that each byte is read before it is overwritten.
+                line.resize(decoded_len, 0);
+                let mut j = decoded_len;
+                for i in (0..len).rev() {
+                    let b = line[i];
+                    if b < 0x80 {
+                        j -= 1;
+                        line[j] = b;
+                    } else {
+                        j -= 2;
+                        line[j] = 0xc0 | (b >> 6);
+                        line[j + 1] = 0x80 | (b & 0x3f);
+                    }
+                }
+                debug_assert_eq!(j, 0);
+                true
+            }
+        }
+    }
+}
+
+impl Default for Charset {
+    fn default() -> Self {
+        Charset::Utf8
+    }
+}
+
+/// How a passphrase that is not valid UTF-8 is handled.
+///
+/// `pinentry` binaries should always return UTF-8 (once transcoded from their
+/// [`Charset`]), but some return the raw bytes typed by the user, which may not be.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+#[non_exhaustive]
+pub enum Utf8Policy {
+    /// Invalid UTF-8 is an [`Error::Encoding`] (the default).
+    Strict,
+    /// Each invalid sequence is replaced with U+FFFD REPLACEMENT CHARACTER.
+    Lossy,
+    /// The bytes are returned unchanged by [`PassphraseInput::interact_into`].
+    ///
+    /// Passphrases returned as strings must be valid UTF-8, so this is the same as
+    /// [`Utf8Policy::Strict`] for [`PassphraseInput::interact`].
+    ///
+    /// [`PassphraseInput::interact`]: crate::PassphraseInput::interact
+    /// [`PassphraseInput::interact_into`]: crate::PassphraseInput::interact_into
+    Raw,
+}
+
+impl Utf8Policy {
+    /// Copies the given bytes into a [`SecretString`] according to this policy.
+    pub(crate) fn to_secret_string(self, bytes: &[u8]) -> Result<SecretString> {
+        // These allocate exactly the required length, so the `SecretString` is never
+        // reallocated.
+        let string = match self {
+            Utf8Policy::Strict | Utf8Policy::Raw => String::from(std::str::from_utf8(bytes)?),
+            Utf8Policy::Lossy => {
+                let mut len = 0;
+                lossy_chunks(bytes, |chunk| len += chunk.len());
+                let mut string = String::with_capacity(len);
+                lossy_chunks(bytes, |chunk| string.push_str(chunk));
+                string
+            }
+        };
+        Ok(string.into())
+    }
+
+    /// Applies this policy to the contents of the given buffer, in place.
+    ///
+    /// The buffer is wiped if an error is returned.
+    pub(crate) fn apply(self, buf: &mut SecretBuffer) -> Result<()> {
+        let e = match std::str::from_utf8(buf.expose_secret()) {
+            Ok(_) => return Ok(()),
+            Err(e) => e,
+        };
+        match self {
+            Utf8Policy::Strict => {
+                buf.clear();
+                Err(e.into())
+            }
+            Utf8Policy::Lossy => {
+                let lossy = self.to_secret_string(buf.expose_secret())?;
+                buf.clear();
+                if lossy.expose_secret().bytes().all(|b| buf.push(b)) {
+                    Ok(())
+                } else {
+                    buf.clear();
+                    Err(Error::TooLong)
+                }
+            }
+            Utf8Policy::Raw => Ok(()),
+        }
+    }
+}
+
+/// Calls `f` with each valid chunk of `bytes`, and with U+FFFD REPLACEMENT CHARACTER
+/// in place of each invalid sequence.
+fn lossy_chunks<'b>(mut bytes: &'b [u8], mut f: impl FnMut(&'b str)) {
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => return f(s),
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                f(std::str::from_utf8(valid).unwrap_or_default());
+                f("\u{FFFD}");
+                bytes = &rest[e.error_len().unwrap_or(rest.len())..];
+            }
+        }
+    }
+}
+
+impl Default for Utf8Policy {
+    fn default() -> Self {
+        Utf8Policy::Strict
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+
+    use super::{Charset, Utf8Policy};
+    use crate::{Error, SecretBuffer};
+
+    #[test]
+    fn locale_names() {
+        assert_eq!(Charset::from_locale_name("C"), Ok(Charset::Utf8));
+        assert_eq!(Charset::from_locale_name("POSIX"), Ok(Charset::Utf8));
+        assert_eq!(Charset::from_locale_name("de_DE.UTF-8"), Ok(Charset::Utf8));
+        assert_eq!(
+            Charset::from_locale_name("C.ANSI_X3.4-1968"),
+            Ok(Charset::Utf8)
+        );
+        assert_eq!(
+            Charset::from_locale_name("de_DE.ISO-8859-1"),
+            Ok(Charset::Latin1)
+        );
+        assert_eq!(
+            Charset::from_locale_name("fr_FR.iso88591@euro"),
+            Ok(Charset::Latin1)
+        );
+        for locale in ["de_DE", "en_US.ISO-8859-15", "ru_RU.KOI8-R"] {
+            assert_eq!(
+                Charset::from_locale_name(locale).unwrap_err().locale(),
+                locale
+            );
+        }
+    }
+
+    #[test]
+    fn latin1() {
+        let mut buf = vec![];
+        Charset::Latin1.encode("SETDESC Grüße, 世界\n", &mut buf);
+        assert_eq!(buf, b"SETDESC Gr\xfc\xdfe, ??\n");
+
+        let mut line = Vec::with_capacity(16);
+        line.extend_from_slice(b"D Gr\xfc\xdfe\n");
+        assert!(Charset::Latin1.decode(&mut line));
+        assert_eq!(line, "D Grüße\n".as_bytes());
+
+        let mut line = b"OK\n".to_vec();
+        assert!(Charset::Utf8.decode(&mut line));
+        assert_eq!(line, b"OK\n");
+
+        // Lines that would need to be reallocated are left unchanged.
+        let mut line = b"D \xe9\xe9\xe9\n".to_vec();
+        line.shrink_to_fit();
+        let capacity = line.capacity();
+        assert!(!Charset::Latin1.decode(&mut line));
+        assert_eq!(line, b"D \xe9\xe9\xe9\n");
+        assert_eq!(line.capacity(), capacity);
+    }
+
+    #[test]
+    fn utf8_policy() {
+        let invalid = b"caf\xe9";
+        assert!(matches!(
+            Utf8Policy::Strict.to_secret_string(invalid),
+            Err(Error::Encoding(_))
+        ));
+        assert!(Utf8Policy::Raw.to_secret_string(invalid).is_err());
+        assert_eq!(
+            Utf8Policy::Lossy
+                .to_secret_string(invalid)
+                .unwrap()
+                .expose_secret(),
+            "caf\u{FFFD}"
+        );
+
+        let buffer = |policy: Utf8Policy, capacity| {
+            let mut buf = SecretBuffer::with_capacity(capacity);
+            for b in invalid {
+                buf.push(*b);
+            }
+            policy
+                .apply(&mut buf)
+                .map(|()| buf.expose_secret().to_vec())
+        };
+        assert!(buffer(Utf8Policy::Strict, 8).is_err());
+        assert_eq!(buffer(Utf8Policy::Raw, 8).unwrap(), invalid);
+        assert_eq!(
+            buffer(Utf8Policy::Lossy, 8).unwrap(),
+            "caf\u{FFFD}".as_bytes()
+        );
+        assert!(matches!(buffer(Utf8Policy::Lossy, 4), Err(Error::TooLong)));
+    }
+}
@@ -0,0 +1,212 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use secrecy::ExposeSecret;
+
+use crate::{
+    locale, DefaultText, Error, InvalidSettingError, InvalidSettingReason, PassphraseInput, Result,
+};
+
+/// A dialog for choosing one of a small list of options.
+///
+/// `pinentry` binaries have no list widget, so this lists the numbered choices in the
+/// description of a [`PassphraseInput`], and asks the user to enter the number of their
+/// choice. Any other entry is rejected with an error, and the dialog remains open until
+/// the user either enters a valid number, or selects the "Cancel" button.
+///
+/// The title, buttons, and other settings of the given [`PassphraseInput`] are used as
+/// they are; its description is replaced by the list of choices. Note that most
+/// `pinentry` binaries mask the entry, as they would a passphrase.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{ChoiceDialog, PassphraseInput};
+///
+/// if let Some(input) = PassphraseInput::with_default_binary() {
+///     let keys = ["Work (ed25519)", "Personal (rsa4096)"];
+///     let mut dialog = ChoiceDialog::new(input);
+///     dialog.with_description("Which key do you want to use?");
+///     for key in keys {
+///         dialog.with_choice(key);
+///     }
+///     let key = keys[dialog.choose()?];
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct ChoiceDialog<'a> {
+    input: PassphraseInput<'a>,
+    description: Option<Cow<'a, str>>,
+    choices: Vec<Cow<'a, str>>,
+    invalid_error: Option<Cow<'a, str>>,
+}
+
+impl<'a> ChoiceDialog<'a> {
+    /// Creates a dialog without any choices, shown with the given [`PassphraseInput`].
+    pub fn new(input: PassphraseInput<'a>) -> Self {
+        ChoiceDialog {
+            input,
+            description: None,
+            choices: vec![],
+            invalid_error: None,
+        }
+    }
+
+    /// Sets the text that is shown above the list of choices.
+    pub fn with_description(&mut self, description: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Adds a choice with the given label to the end of the list.
+    pub fn with_choice(&mut self, label: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.choices.push(label.into());
+        self
+    }
+
+    /// Sets the error text that is displayed if the user enters anything other than the
+    /// number of a choice.
+    ///
+    /// By default, this is "Enter a number from 1 to N", or its translation by the
+    /// [`Translator`](crate::Translator) of the [`PassphraseInput`] (see
+    /// [`DefaultText::InvalidChoice`]).
+    pub fn with_invalid_choice_error(&mut self, error: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.invalid_error = Some(error.into());
+        self
+    }
+
+    /// Asks the user to choose, returning the index of the chosen option.
+    ///
+    /// Returns [`Error::Cancelled`] if the user selects the "Cancel" button, and
+    /// [`Error::InvalidSetting`] if no choices have been added.
+    pub fn choose(&self) -> Result<usize> {
+        if self.choices.is_empty() {
+            return Err(Error::InvalidSetting(InvalidSettingError::new(
+                "SETDESC",
+                InvalidSettingReason::NoChoices,
+            )));
+        }
+
+        let mut description = self.description.as_deref().unwrap_or_default().to_owned();
+        if !description.is_empty() {
+            description.push_str("\n\n");
+        }
+        for (i, label) in self.choices.iter().enumerate() {
+            if i > 0 {
+                description.push('\n');
+            }
+            let _ = write!(description, "{}. {}", i + 1, label);
+        }
+        let invalid_error = match &self.invalid_error {
+            Some(error) => error.clone().into_owned(),
+            None => locale::translate(
+                self.input.translator,
+                DefaultText::InvalidChoice {
+                    choices: self.choices.len(),
+                },
+            )
+            .into_owned(),
+        };
+        let validator = |entry: &str| match self.parse(entry) {
+            Some(_) => None,
+            None => Some(invalid_error.clone()),
+        };
+
+        let mut input = self.input.clone();
+        input
+            .with_description(description)
+            .with_validator(&validator);
+        if input.prompt.is_none() {
+            input.with_prompt(locale::translate(
+                self.input.translator,
+                DefaultText::ChoicePrompt,
+            ));
+        }
+        let entry = input.interact()?;
+        Ok(self.parse(entry.expose_secret()).expect("validated"))
+    }
+
+    /// Returns the index of the choice with the given number, if there is one.
+    fn parse(&self, entry: &str) -> Option<usize> {
+        match entry.trim().parse::<usize>() {
+            Ok(n) if (1..=self.choices.len()).contains(&n) => Some(n - 1),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn choose() {
+        use super::ChoiceDialog;
+        use crate::{test_util::Harness, Error, PassphraseInput};
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("SETDESC Which key?%0A%0A1. Work%0A2. Personal", &["OK"])
+            .expect("SETPROMPT Choice:", &["OK"])
+            .expect("GETPIN", &["D 3", "OK"])
+            .expect("SETERROR Enter a number from 1 to 2", &["OK"])
+            .expect("GETPIN", &["D two", "OK"])
+            .expect("SETERROR Enter a number from 1 to 2", &["OK"])
+            .expect("GETPIN", &["D 2 ", "OK"]);
+
+        let mut input = PassphraseInput::with_binary(harness.path()).unwrap();
+        input.with_unix_options(&Harness::unix_options());
+        let mut dialog = ChoiceDialog::new(input);
+        dialog
+            .with_description("Which key?")
+            .with_choice("Work")
+            .with_choice("Personal");
+        assert_eq!(dialog.choose().unwrap(), 1);
+        harness.assert_complete();
+
+        assert!(matches!(
+            ChoiceDialog::new(PassphraseInput::with_binary(harness.path()).unwrap()).choose(),
+            Err(Error::InvalidSetting(_))
+        ));
+    }
+
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn translated() {
+        use super::ChoiceDialog;
+        use crate::{test_util::Harness, DefaultText, PassphraseInput, Translator};
+
+        struct Pirate;
+
+        impl Translator for Pirate {
+            fn translate(&self, text: DefaultText) -> Option<String> {
+                match text {
+                    DefaultText::ChoicePrompt => Some("Yer pick:".to_owned()),
+                    DefaultText::InvalidChoice { choices } => {
+                        Some(format!("Pick 1 to {}, ye scallywag", choices))
+                    }
+                    _ => None,
+                }
+            }
+        }
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("SETDESC 1. Rum%0A2. Grog", &["OK"])
+            .expect("SETPROMPT Yer pick:", &["OK"])
+            .expect("GETPIN", &["D 3", "OK"])
+            .expect("SETERROR Pick 1 to 2, ye scallywag", &["OK"])
+            .expect("GETPIN", &["D 1", "OK"]);
+
+        let mut input = PassphraseInput::with_binary(harness.path()).unwrap();
+        input
+            .with_unix_options(&Harness::unix_options())
+            .with_translator(&Pirate);
+        let mut dialog = ChoiceDialog::new(input);
+        dialog.with_choice("Rum").with_choice("Grog");
+        assert_eq!(dialog.choose().unwrap(), 0);
+        harness.assert_complete();
+    }
+}
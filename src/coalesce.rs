@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use secrecy::{ExposeSecret, SecretString};
+
+use crate::{Error, PassphraseProvider, PassphraseRequest, Result};
+
+/// The outcome of a prompt, shared with every request that waited for it.
+enum Outcome {
+    Passphrase(SecretString),
+    Cancelled,
+    Timeout,
+    NotConfirmed,
+    Failed(String),
+}
+
+impl Outcome {
+    fn new(res: &Result<SecretString>) -> Self {
+        match res {
+            Ok(passphrase) => {
+                Outcome::Passphrase(SecretString::from(passphrase.expose_secret().to_owned()))
+            }
+            Err(Error::Cancelled) => Outcome::Cancelled,
+            Err(Error::Timeout) => Outcome::Timeout,
+            Err(Error::NotConfirmed) => Outcome::NotConfirmed,
+            Err(e) => Outcome::Failed(e.to_string()),
+        }
+    }
+
+    fn to_result(&self) -> Result<SecretString> {
+        match self {
+            Outcome::Passphrase(passphrase) => {
+                Ok(SecretString::from(passphrase.expose_secret().to_owned()))
+            }
+            Outcome::Cancelled => Err(Error::Cancelled),
+            Outcome::Timeout => Err(Error::Timeout),
+            Outcome::NotConfirmed => Err(Error::NotConfirmed),
+            Outcome::Failed(msg) => {
+                Err(Error::Io(io::Error::new(io::ErrorKind::Other, msg.clone())))
+            }
+        }
+    }
+}
+
+/// A prompt that is being shown, which other requests for the same key wait for.
+#[derive(Default)]
+struct Pending {
+    outcome: Mutex<Option<Outcome>>,
+    done: Condvar,
+}
+
+/// A wrapper around a [`PassphraseProvider`] that shows a single prompt for concurrent
+/// requests of the same passphrase.
+///
+/// Requests are identified by a key (such as a cache ID or keygrip). The first request
+/// for a key asks the provider, and any requests for the same key that are made (from
+/// other threads) while its prompt is shown wait for it and share its passphrase,
+/// instead of showing more prompts. Once the prompt is answered, the next request for
+/// the key asks the provider again; the passphrase is not cached.
+///
+/// If the provider fails, the waiting requests fail too. [`Error::Cancelled`],
+/// [`Error::Timeout`], and [`Error::NotConfirmed`] are returned to all of them as-is,
+/// while other errors are returned to the waiting requests as I/O errors with the same
+/// message.
+///
+/// A coalescer can only be shared between threads if its provider can be. Dialogs such
+/// as [`PassphraseInput`] cannot, so the provider should create the dialog for each
+/// prompt instead.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// use pinentry::{Coalescer, PassphraseInput, PassphraseProvider, PassphraseRequest};
+///
+/// let coalescer = Arc::new(Coalescer::new(|request: &PassphraseRequest<'_>| {
+///     PassphraseInput::with_default_binary()
+///         .expect("pinentry is installed")
+///         .passphrase(request)
+/// }));
+/// let workers: Vec<_> = (0..4)
+///     .map(|_| {
+///         let coalescer = coalescer.clone();
+///         thread::spawn(move || {
+///             // Only one dialog is shown, and every worker gets its passphrase.
+///             coalescer.get(
+///                 "FooBar",
+///                 PassphraseRequest::new().with_description("Unlock key FooBar"),
+///             )
+///         })
+///     })
+///     .collect();
+/// for worker in workers {
+///     worker.join().unwrap()?;
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+///
+/// [`PassphraseInput`]: crate::PassphraseInput
+pub struct Coalescer<P> {
+    provider: P,
+    pending: Mutex<HashMap<String, Arc<Pending>>>,
+}
+
+impl<P> fmt::Debug for Coalescer<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Coalescer")
+            .field("pending", &self.lock().keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<P: PassphraseProvider> Coalescer<P> {
+    /// Creates a coalescer that asks the given provider for passphrases.
+    pub fn new(provider: P) -> Self {
+        Coalescer {
+            provider,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Asks the provider for the passphrase with the given key (with the given
+    /// request), or else waits for the prompt that is already being shown for the key.
+    pub fn get(&self, key: &str, request: &PassphraseRequest<'_>) -> Result<SecretString> {
+        let (pending, first) = {
+            let mut pending = self.lock();
+            match pending.get(key) {
+                Some(prompt) => (prompt.clone(), false),
+                None => {
+                    let prompt = Arc::new(Pending::default());
+                    pending.insert(key.to_owned(), prompt.clone());
+                    (prompt, true)
+                }
+            }
+        };
+
+        if first {
+            let mut publish = Publish {
+                pending: &self.pending,
+                key,
+                prompt: pending,
+                outcome: None,
+            };
+            let res = self.provider.passphrase(request);
+            publish.outcome = Some(Outcome::new(&res));
+            res
+        } else {
+            let mut outcome = pending.outcome.lock().unwrap_or_else(|e| e.into_inner());
+            while outcome.is_none() {
+                outcome = pending
+                    .done
+                    .wait(outcome)
+                    .unwrap_or_else(|e| e.into_inner());
+            }
+            outcome.as_ref().expect("checked above").to_result()
+        }
+    }
+
+    /// Returns a provider that gets the passphrase with the given key from this
+    /// coalescer.
+    pub fn provider<'a>(&'a self, key: &'a str) -> impl PassphraseProvider + 'a {
+        move |request: &PassphraseRequest<'_>| self.get(key, request)
+    }
+}
+
+impl<P> Coalescer<P> {
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Arc<Pending>>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Shares the outcome of a prompt with the requests waiting for it when dropped, even
+/// if the provider panics.
+struct Publish<'a> {
+    pending: &'a Mutex<HashMap<String, Arc<Pending>>>,
+    key: &'a str,
+    prompt: Arc<Pending>,
+    outcome: Option<Outcome>,
+}
+
+impl<'a> Drop for Publish<'a> {
+    fn drop(&mut self) {
+        // The prompt is removed before its outcome is shared, so that later requests
+        // show a new prompt instead of waiting for this one.
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(self.key);
+        let outcome = self
+            .outcome
+            .take()
+            .unwrap_or_else(|| Outcome::Failed("the passphrase provider panicked".to_owned()));
+        *self
+            .prompt
+            .outcome
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(outcome);
+        self.prompt.done.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use secrecy::{ExposeSecret, SecretString};
+
+    use super::Coalescer;
+    use crate::{Error, PassphraseRequest};
+
+    #[test]
+    fn coalesce() {
+        let (tx, rx) = mpsc::channel::<bool>();
+        let rx = Mutex::new(rx);
+        let prompts = Arc::new(AtomicUsize::new(0));
+        let coalescer = Arc::new(Coalescer::new({
+            let prompts = prompts.clone();
+            move |_: &PassphraseRequest<'_>| {
+                prompts.fetch_add(1, Ordering::SeqCst);
+                if rx.lock().unwrap().recv().unwrap() {
+                    Ok(SecretString::from("hunter2"))
+                } else {
+                    Err(Error::Cancelled)
+                }
+            }
+        }));
+        let spawn = |n| -> Vec<_> {
+            (0..n)
+                .map(|_| {
+                    let coalescer = coalescer.clone();
+                    thread::spawn(move || coalescer.get("FooBar", &PassphraseRequest::new()))
+                })
+                .collect()
+        };
+
+        let requests = spawn(4);
+        thread::sleep(Duration::from_millis(100));
+        tx.send(true).unwrap();
+        for request in requests {
+            assert_eq!(request.join().unwrap().unwrap().expose_secret(), "hunter2");
+        }
+        assert_eq!(prompts.load(Ordering::SeqCst), 1);
+
+        // Once answered, the next request prompts again, and cancellation is shared.
+        let requests = spawn(3);
+        thread::sleep(Duration::from_millis(100));
+        tx.send(false).unwrap();
+        for request in requests {
+            assert!(matches!(request.join().unwrap(), Err(Error::Cancelled)));
+        }
+        assert_eq!(prompts.load(Ordering::SeqCst), 2);
+        assert!(coalescer.lock().is_empty());
+    }
+}
@@ -0,0 +1,542 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[cfg(unix)]
+use crate::unix;
+
+/// The name of the configuration file.
+const FILE_NAME: &str = "pinentry-rs.toml";
+
+/// The sandboxes that can be named in the `[unix]` table.
+const SANDBOXES: &[&str] = &["bubblewrap", "firejail"];
+
+/// End-user configuration for the dialogs created with `from_config` (such as
+/// [`PassphraseInput::from_config`]).
+///
+/// This lets users tune how every application built with this crate shows dialogs,
+/// without each application needing to expose its own settings. The configuration is
+/// read from a small subset of TOML:
+///
+/// ```toml
+/// # The pinentry binaries to try, in order of preference (default: ["pinentry"]).
+/// binaries = ["pinentry-gnome3", "pinentry-curses"]
+/// # The default timeout in seconds (default: no timeout).
+/// timeout = 60
+///
+/// # Options for spawning pinentry binaries on Unix (see `pinentry::unix::Options`).
+/// [unix]
+/// new_session = true
+/// tty_name = "/dev/pts/3"
+/// tty_type = "xterm"
+/// sandbox = "bubblewrap"
+/// locale = "de_DE.UTF-8"
+/// language = "de:en"
+/// ```
+///
+/// Each key is optional, and corresponds to the method of the same name on
+/// [`unix::Options`] (`new_process_group`, `new_session`, and `clear_locale` take
+/// booleans). The `[unix]` table is ignored on other platforms. Arrays must be written
+/// on a single line. Unknown keys are rejected, so that typos don't go unnoticed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{Config, PassphraseInput};
+///
+/// let config = Config::load()?;
+/// if let Some(mut input) = PassphraseInput::from_config(&config) {
+///     let passphrase = input
+///         .with_description("Enter passphrase for FooBar")
+///         .interact()?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// [`PassphraseInput::from_config`]: crate::PassphraseInput::from_config
+/// [`unix::Options`]: crate::unix::Options
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    binaries: Vec<String>,
+    timeout: Option<u16>,
+    unix: UnixConfig,
+}
+
+/// The settings in the `[unix]` table.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(not(unix), allow(dead_code))]
+struct UnixConfig {
+    new_process_group: bool,
+    new_session: bool,
+    tty_name: Option<String>,
+    tty_type: Option<String>,
+    sandbox: Option<String>,
+    clear_locale: bool,
+    locale: Option<String>,
+    language: Option<String>,
+}
+
+impl Config {
+    /// Returns the path of the user's configuration file.
+    ///
+    /// This is `$XDG_CONFIG_HOME/pinentry-rs.toml`, or `~/.config/pinentry-rs.toml` if
+    /// `XDG_CONFIG_HOME` is unset (`%APPDATA%\pinentry-rs.toml` on Windows). Returns
+    /// `None` if the home directory cannot be determined.
+    pub fn path() -> Option<PathBuf> {
+        let nonempty = |var| env::var_os(var).filter(|value| !value.is_empty());
+
+        #[cfg(windows)]
+        let dir = nonempty("APPDATA").map(PathBuf::from);
+
+        #[cfg(not(windows))]
+        let dir = nonempty("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| nonempty("HOME").map(|home| Path::new(&home).join(".config")));
+
+        dir.map(|dir| dir.join(FILE_NAME))
+    }
+
+    /// Reads the user's configuration file (see [`Config::path`]).
+    ///
+    /// Returns the default configuration if the file does not exist, or if its path
+    /// cannot be determined. Returns an error with [`io::ErrorKind::InvalidData`] if
+    /// the file cannot be parsed.
+    pub fn load() -> io::Result<Self> {
+        match Self::path() {
+            Some(path) => match Self::from_path(path) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+                res => res,
+            },
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Reads the configuration file at the given path.
+    ///
+    /// Returns an error with [`io::ErrorKind::InvalidData`] if the file cannot be
+    /// parsed.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Returns the `pinentry` binaries to try, in order of preference.
+    ///
+    /// This is empty if the configuration doesn't name any, in which case dialogs use
+    /// the binary named `pinentry`.
+    pub fn binaries(&self) -> &[String] {
+        &self.binaries
+    }
+
+    /// Returns the default timeout in seconds, if any.
+    pub fn timeout(&self) -> Option<u16> {
+        self.timeout
+    }
+
+    /// Returns the Unix-specific options for spawning `pinentry` binaries.
+    #[cfg(unix)]
+    pub fn unix_options(&self) -> unix::Options<'_> {
+        let config = &self.unix;
+        let mut options = unix::Options::new();
+        if config.new_process_group {
+            options.new_process_group();
+        }
+        if config.new_session {
+            options.new_session();
+        }
+        if let Some(tty_name) = &config.tty_name {
+            options.tty_name(tty_name);
+        }
+        if let Some(tty_type) = &config.tty_type {
+            options.tty_type(tty_type);
+        }
+        match config.sandbox.as_deref() {
+            Some("bubblewrap") => {
+                options.sandbox(unix::Sandbox::Bubblewrap);
+            }
+            Some("firejail") => {
+                options.sandbox(unix::Sandbox::Firejail);
+            }
+            _ => (),
+        }
+        if config.clear_locale {
+            options.clear_locale();
+        }
+        if let Some(locale) = &config.locale {
+            options.locale(locale);
+        }
+        if let Some(language) = &config.language {
+            options.language(language);
+        }
+        options
+    }
+
+    /// Returns the names of the binaries to try, in order of preference.
+    pub(crate) fn candidates(&self) -> impl Iterator<Item = &str> {
+        let default: &[_] = if self.binaries.is_empty() {
+            &["pinentry"]
+        } else {
+            &[]
+        };
+        self.binaries
+            .iter()
+            .map(String::as_str)
+            .chain(default.iter().copied())
+    }
+}
+
+/// Parses a configuration file.
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Config::default();
+        let mut table = None;
+        let mut seen = vec![];
+
+        for (i, line) in s.lines().enumerate() {
+            let err = |message: String| ConfigError {
+                line: i + 1,
+                message,
+            };
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[') {
+                let (name, rest) = header
+                    .split_once(']')
+                    .ok_or_else(|| err("unterminated table header".to_owned()))?;
+                end_of_line(rest).map_err(err)?;
+                table = match name.trim() {
+                    "unix" => Some("unix"),
+                    name => return Err(err(format!("unknown table: [{}]", name))),
+                };
+                if seen.contains(&(table, "")) {
+                    return Err(err("duplicate table: [unix]".to_owned()));
+                }
+                seen.push((table, ""));
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| err("expected `key = value`".to_owned()))?;
+            let key = key.trim();
+            let (value, rest) = parse_value(value).map_err(err)?;
+            end_of_line(rest).map_err(err)?;
+            if seen.contains(&(table, key)) {
+                return Err(err(format!("duplicate key: {}", key)));
+            }
+
+            let unix = &mut config.unix;
+            match (table, key) {
+                (None, "binaries") => {
+                    config.binaries = value.array(key).map_err(err)?;
+                }
+                (None, "timeout") => {
+                    let timeout = value.integer(key).map_err(err)?;
+                    config.timeout = Some(
+                        u16::try_from(timeout)
+                            .map_err(|_| err(format!("timeout out of range: {}", timeout)))?,
+                    );
+                }
+                (Some("unix"), "new_process_group") => {
+                    unix.new_process_group = value.boolean(key).map_err(err)?;
+                }
+                (Some("unix"), "new_session") => {
+                    unix.new_session = value.boolean(key).map_err(err)?;
+                }
+                (Some("unix"), "tty_name") => {
+                    unix.tty_name = Some(value.string(key).map_err(err)?);
+                }
+                (Some("unix"), "tty_type") => {
+                    unix.tty_type = Some(value.string(key).map_err(err)?);
+                }
+                (Some("unix"), "sandbox") => {
+                    let sandbox = value.string(key).map_err(err)?;
+                    if !SANDBOXES.contains(&sandbox.as_str()) {
+                        return Err(err(format!("unknown sandbox: {:?}", sandbox)));
+                    }
+                    unix.sandbox = Some(sandbox);
+                }
+                (Some("unix"), "clear_locale") => {
+                    unix.clear_locale = value.boolean(key).map_err(err)?;
+                }
+                (Some("unix"), "locale") => {
+                    unix.locale = Some(value.string(key).map_err(err)?);
+                }
+                (Some("unix"), "language") => {
+                    unix.language = Some(value.string(key).map_err(err)?);
+                }
+                _ => return Err(err(format!("unknown key: {}", key))),
+            }
+            seen.push((table, key));
+        }
+
+        Ok(config)
+    }
+}
+
+/// An error encountered while parsing a configuration file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigError {
+    line: usize,
+    message: String,
+}
+
+impl ConfigError {
+    /// Returns the (1-based) number of the line containing the error.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid configuration on line {}: {}",
+            self.line, self.message
+        )
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// A value in a configuration file.
+#[derive(Debug, PartialEq, Eq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<String>),
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+        }
+    }
+
+    fn mismatch(&self, key: &str, expected: &str) -> String {
+        format!("{} must be {}, not {}", key, expected, self.kind())
+    }
+
+    fn string(self, key: &str) -> Result<String, String> {
+        match self {
+            Value::String(s) => Ok(s),
+            _ => Err(self.mismatch(key, "a string")),
+        }
+    }
+
+    fn integer(self, key: &str) -> Result<i64, String> {
+        match self {
+            Value::Integer(i) => Ok(i),
+            _ => Err(self.mismatch(key, "an integer")),
+        }
+    }
+
+    fn boolean(self, key: &str) -> Result<bool, String> {
+        match self {
+            Value::Boolean(b) => Ok(b),
+            _ => Err(self.mismatch(key, "a boolean")),
+        }
+    }
+
+    fn array(self, key: &str) -> Result<Vec<String>, String> {
+        match self {
+            Value::Array(a) => Ok(a),
+            _ => Err(self.mismatch(key, "an array of strings")),
+        }
+    }
+}
+
+/// Checks that nothing but whitespace or a comment follows a value.
+fn end_of_line(rest: &str) -> Result<(), String> {
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(())
+    } else {
+        Err(format!("unexpected trailing characters: {:?}", rest))
+    }
+}
+
+/// Parses a value from the start of `s`, returning it with the rest of `s`.
+fn parse_value(s: &str) -> Result<(Value, &str), String> {
+    let s = s.trim_start();
+    if s.starts_with('"') || s.starts_with('\'') {
+        let (string, rest) = parse_string(s)?;
+        Ok((Value::String(string), rest))
+    } else if let Some(mut rest) = s.strip_prefix('[') {
+        let mut array = vec![];
+        loop {
+            rest = rest.trim_start();
+            if let Some(rest) = rest.strip_prefix(']') {
+                return Ok((Value::Array(array), rest));
+            }
+            let (string, after) = parse_string(rest)?;
+            array.push(string);
+            rest = after.trim_start();
+            match rest.strip_prefix(',') {
+                Some(after) => rest = after,
+                None if rest.starts_with(']') => (),
+                None => return Err("expected `,` or `]` in array".to_owned()),
+            }
+        }
+    } else {
+        let end = s
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '+' || c == '-'))
+            .unwrap_or(s.len());
+        let (token, rest) = s.split_at(end);
+        let value = match token {
+            "true" => Value::Boolean(true),
+            "false" => Value::Boolean(false),
+            _ => Value::Integer(
+                token
+                    .replace('_', "")
+                    .parse()
+                    .map_err(|_| format!("invalid value: {:?}", token))?,
+            ),
+        };
+        Ok((value, rest))
+    }
+}
+
+/// Parses a basic (`"..."`) or literal (`'...'`) string from the start of `s`,
+/// returning it with the rest of `s`.
+fn parse_string(s: &str) -> Result<(String, &str), String> {
+    if let Some(literal) = s.strip_prefix('\'') {
+        return literal
+            .split_once('\'')
+            .map(|(string, rest)| (string.to_owned(), rest))
+            .ok_or_else(|| "unterminated string".to_owned());
+    }
+
+    let mut chars = s
+        .strip_prefix('"')
+        .ok_or_else(|| "expected a string".to_owned())?
+        .char_indices();
+    let mut string = String::new();
+    while let Some((_, c)) = chars.next() {
+        match c {
+            '"' => return Ok((string, chars.as_str())),
+            '\\' => {
+                let escaped = match chars.next().map(|(_, c)| c) {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some(c @ 'u') | Some(c @ 'U') => {
+                        let len = if c == 'u' { 4 } else { 8 };
+                        let hex: String = chars.by_ref().take(len).map(|(_, c)| c).collect();
+                        u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .filter(|_| hex.len() == len)
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| format!("invalid unicode escape: \\{}{}", c, hex))?
+                    }
+                    Some(c) => return Err(format!("invalid escape: \\{}", c)),
+                    None => break,
+                };
+                string.push(escaped);
+            }
+            c => string.push(c),
+        }
+    }
+    Err("unterminated string".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, UnixConfig};
+
+    #[test]
+    fn parse() {
+        let config: Config = r#"
+            # Prefer the GNOME pinentry.
+            binaries = ["pinentry-gnome3", 'pinentry-curses',] # trailing comma
+            timeout = 1_000
+
+            [unix]
+            new_session = true
+            tty_type = "\u0078term"
+            tty_name = "/dev/pts/3"
+            sandbox = "firejail"
+            language = 'pt_BR:pt'
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                binaries: vec!["pinentry-gnome3".to_owned(), "pinentry-curses".to_owned()],
+                timeout: Some(1000),
+                unix: UnixConfig {
+                    new_session: true,
+                    tty_name: Some("/dev/pts/3".to_owned()),
+                    tty_type: Some("xterm".to_owned()),
+                    sandbox: Some("firejail".to_owned()),
+                    language: Some("pt_BR:pt".to_owned()),
+                    ..UnixConfig::default()
+                },
+            }
+        );
+        assert_eq!(
+            config.candidates().collect::<Vec<_>>(),
+            ["pinentry-gnome3", "pinentry-curses"]
+        );
+        assert_eq!(
+            Config::default().candidates().collect::<Vec<_>>(),
+            ["pinentry"]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |s: &str| s.parse::<Config>().unwrap_err().to_string();
+        assert_eq!(
+            error("timeout = 5\n\ntimeout = 6"),
+            "Invalid configuration on line 3: duplicate key: timeout"
+        );
+        assert_eq!(
+            error("binary = \"pinentry\""),
+            "Invalid configuration on line 1: unknown key: binary"
+        );
+        assert_eq!(
+            error("timeout = \"5\""),
+            "Invalid configuration on line 1: timeout must be an integer, not a string"
+        );
+        assert_eq!(
+            error("timeout = 70000"),
+            "Invalid configuration on line 1: timeout out of range: 70000"
+        );
+        assert_eq!(
+            error("[windows]"),
+            "Invalid configuration on line 1: unknown table: [windows]"
+        );
+        assert_eq!(
+            error("[unix]\nsandbox = \"docker\""),
+            "Invalid configuration on line 2: unknown sandbox: \"docker\""
+        );
+        assert_eq!(
+            error("binaries = [\"a\" \"b\"]"),
+            "Invalid configuration on line 1: expected `,` or `]` in array"
+        );
+        assert_eq!(
+            error("timeout = 5 6"),
+            "Invalid configuration on line 1: unexpected trailing characters: \"6\""
+        );
+    }
+}
@@ -0,0 +1,44 @@
+/// Compares two secrets for equality in constant time.
+///
+/// The time taken depends only on the lengths of the secrets, and not on their
+/// contents, so (unlike `==`) it does not reveal how long a matching prefix is. Use
+/// this to compare passphrases, such as when checking that a re-entered passphrase
+/// matches the one entered previously.
+///
+/// # Examples
+///
+/// ```
+/// use pinentry::constant_time_eq;
+/// use secrecy::{ExposeSecret, SecretString};
+///
+/// let first = SecretString::from("correct horse battery staple");
+/// let second = SecretString::from("correct horse battery staple");
+/// assert!(constant_time_eq(
+///     first.expose_secret().as_bytes(),
+///     second.expose_secret().as_bytes(),
+/// ));
+/// ```
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let diff = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+
+    // Safety: `diff` is a valid, aligned local. The volatile read prevents the
+    // compiler from turning the fold above into an early-exit comparison.
+    unsafe { std::ptr::read_volatile(&diff) == 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::constant_time_eq;
+
+    #[test]
+    fn equality() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"hunter2", b"hunter2"));
+        assert!(!constant_time_eq(b"hunter2", b"hunter3"));
+        assert!(!constant_time_eq(b"hunter2", b"hunter22"));
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt;
+use std::sync::Arc;
+
+/// The importance of a diagnostic message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum DiagnosticLevel {
+    /// A noteworthy response from a `pinentry` binary, such as a status line.
+    Info,
+    /// Detailed protocol information, such as comment lines.
+    Debug,
+}
+
+/// Where this crate sends its diagnostic messages.
+///
+/// Diagnostic messages describe the responses received from `pinentry` binaries, and
+/// are redacted in the same way as a [`Transcript`]: they never contain passphrases.
+///
+/// By default, diagnostic messages are sent to the [`log`](https://crates.io/crates/log)
+/// facade. Applications that need to control exactly where protocol details end up can
+/// instead send them to a callback with [`Diagnostics::new`], or drop them entirely
+/// with [`Diagnostics::discard`]. Disabling the `log` feature removes the dependency on
+/// `log` altogether, ensuring that nothing can reach a global logger; the default is
+/// then to discard diagnostic messages.
+///
+/// A `Diagnostics` can be cloned cheaply, and all clones share the same callback.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{Diagnostics, PassphraseInput};
+///
+/// let diagnostics = Diagnostics::new(|level, message| eprintln!("{:?}: {}", level, message));
+/// if let Some(mut input) = PassphraseInput::with_default_binary() {
+///     let passphrase = input
+///         .with_diagnostics(&diagnostics)
+///         .with_description("Enter passphrase for FooBar")
+///         .interact()?;
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+///
+/// [`Transcript`]: crate::Transcript
+#[derive(Clone)]
+pub struct Diagnostics {
+    sink: Sink,
+}
+
+#[derive(Clone)]
+enum Sink {
+    #[cfg(feature = "log")]
+    Log,
+    Callback(Arc<dyn Fn(DiagnosticLevel, fmt::Arguments<'_>) + Send + Sync>),
+    Discard,
+}
+
+impl Diagnostics {
+    /// Sends diagnostic messages to the given callback.
+    pub fn new(f: impl Fn(DiagnosticLevel, fmt::Arguments<'_>) + Send + Sync + 'static) -> Self {
+        Diagnostics {
+            sink: Sink::Callback(Arc::new(f)),
+        }
+    }
+
+    /// Discards all diagnostic messages.
+    pub fn discard() -> Self {
+        Diagnostics {
+            sink: Sink::Discard,
+        }
+    }
+
+    /// Sends diagnostic messages to the `log` facade.
+    #[cfg(feature = "log")]
+    pub fn log() -> Self {
+        Diagnostics { sink: Sink::Log }
+    }
+
+    pub(crate) fn emit(&self, level: DiagnosticLevel, message: fmt::Arguments<'_>) {
+        match &self.sink {
+            #[cfg(feature = "log")]
+            Sink::Log => match level {
+                DiagnosticLevel::Info => log::info!("{}", message),
+                DiagnosticLevel::Debug => log::debug!("{}", message),
+            },
+            Sink::Callback(f) => f(level, message),
+            Sink::Discard => (),
+        }
+    }
+}
+
+/// Uses the `log` facade if the `log` feature is enabled, and otherwise discards all
+/// diagnostic messages.
+impl Default for Diagnostics {
+    fn default() -> Self {
+        #[cfg(feature = "log")]
+        {
+            Self::log()
+        }
+
+        #[cfg(not(feature = "log"))]
+        {
+            Self::discard()
+        }
+    }
+}
+
+impl fmt::Debug for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sink = match self.sink {
+            #[cfg(feature = "log")]
+            Sink::Log => "Log",
+            Sink::Callback(_) => "Callback",
+            Sink::Discard => "Discard",
+        };
+        f.debug_tuple("Diagnostics").field(&sink).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{DiagnosticLevel, Diagnostics};
+
+    #[test]
+    fn callback() {
+        let messages = Arc::new(Mutex::new(vec![]));
+        let diagnostics = {
+            let messages = messages.clone();
+            Diagnostics::new(move |level, message| {
+                messages.lock().unwrap().push((level, message.to_string()))
+            })
+        };
+        diagnostics.emit(DiagnosticLevel::Info, format_args!("< S {}", 1));
+        Diagnostics::discard().emit(DiagnosticLevel::Debug, format_args!("< # {}", 2));
+        assert_eq!(
+            *messages.lock().unwrap(),
+            [(DiagnosticLevel::Info, "< S 1".to_owned())],
+        );
+    }
+}
@@ -0,0 +1,297 @@
+//! Generating memorable passphrases from a wordlist, enabled by the `diceware` feature.
+//!
+//! A [`Diceware`] generator picks each word of a passphrase uniformly at random (with
+//! the operating system's random number generator) from a [`Wordlist`], in the same
+//! way as rolling dice on a [Diceware] list. Lists in the format of the [EFF's
+//! wordlists] (one word per line, after its dice roll) can be loaded with
+//! [`Wordlist::parse`].
+//!
+//! Generators can be used on their own, or with [`PassphraseInput::with_generator`] to
+//! answer the "generate" button of `pinentry` binaries that have one.
+//!
+//! # Wordlists
+//!
+//! The EFF's large wordlist is bundled with this crate, as [`Wordlist::eff_large`]. It
+//! is used by [`Diceware::default`], which also answers the "generate" button of
+//! dialogs that have no generator of their own. Other lists can be loaded with
+//! [`Wordlist::parse`] or [`Wordlist::new`].
+//!
+//! The EFF's large wordlist is © the [Electronic Frontier Foundation], and is
+//! licensed under the [Creative Commons Attribution 3.0 United States] license.
+//!
+//! [Diceware]: https://theworld.com/~reinhold/diceware.html
+//! [EFF's wordlists]: https://www.eff.org/dice
+//! [Electronic Frontier Foundation]: https://www.eff.org/
+//! [Creative Commons Attribution 3.0 United States]: https://creativecommons.org/licenses/by/3.0/us/
+//! [`PassphraseInput::with_generator`]: crate::PassphraseInput::with_generator
+
+use std::fmt;
+use std::io;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Arc, Once};
+
+use secrecy::SecretString;
+use zeroize::Zeroizing;
+
+/// The EFF's large wordlist, with the dice roll before each word.
+const EFF_LARGE_WORDLIST: &str = include_str!("diceware/eff_large_wordlist.txt");
+
+/// The number of words in passphrases, unless set with [`Diceware::with_words`].
+///
+/// With the EFF's large wordlist, this gives passphrases of about 77 bits of entropy.
+const DEFAULT_WORDS: usize = 6;
+
+/// A list of distinct words that passphrases are generated from.
+///
+/// A `Wordlist` can be cloned cheaply, and all clones share the same words.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Wordlist {
+    words: Arc<[String]>,
+}
+
+/// Only the number of words is shown.
+impl fmt::Debug for Wordlist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Wordlist")
+            .field("len", &self.words.len())
+            .finish()
+    }
+}
+
+impl Wordlist {
+    /// Creates a wordlist from the given words.
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidData`] if there are fewer than
+    /// two words, or any word is empty, contains whitespace, or is repeated.
+    pub fn new<I, W>(words: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = W>,
+        W: Into<String>,
+    {
+        let words: Vec<String> = words.into_iter().map(Into::into).collect();
+        let invalid = |msg| Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        if words.len() < 2 || u32::try_from(words.len()).is_err() {
+            return invalid("wordlist must have at least two words");
+        }
+        if words
+            .iter()
+            .any(|word| word.is_empty() || word.contains(char::is_whitespace))
+        {
+            return invalid("wordlist contains an empty word or whitespace");
+        }
+        let mut sorted: Vec<_> = words.iter().collect();
+        sorted.sort_unstable();
+        if sorted.windows(2).any(|pair| pair[0] == pair[1]) {
+            return invalid("wordlist contains a repeated word");
+        }
+        Ok(Wordlist {
+            words: words.into(),
+        })
+    }
+
+    /// Parses a wordlist with one word per line, as in the EFF's wordlists.
+    ///
+    /// Each word may be preceded by its dice roll (or any other number) and whitespace,
+    /// which is ignored. Blank lines are skipped. Returns the same errors as
+    /// [`Wordlist::new`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pinentry::diceware::Wordlist;
+    ///
+    /// let wordlist = Wordlist::parse("11111\tabacus\n11112\tabdomen\n")?;
+    /// assert_eq!(wordlist.len(), 2);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn parse(text: &str) -> io::Result<Self> {
+        Wordlist::new(
+            text.lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty())
+                .map(|line| {
+                    line.trim_start_matches(|c: char| c.is_ascii_digit())
+                        .trim_start()
+                }),
+        )
+    }
+
+    /// Returns the [EFF's large wordlist](https://www.eff.org/dice), of 7,776 words
+    /// (each contributing about 12.9 bits of entropy).
+    ///
+    /// The list is parsed the first time this is called, and shared by every returned
+    /// `Wordlist`.
+    pub fn eff_large() -> Self {
+        static INIT: Once = Once::new();
+        static EFF_LARGE: AtomicPtr<Wordlist> = AtomicPtr::new(ptr::null_mut());
+
+        INIT.call_once(|| {
+            let wordlist =
+                Wordlist::parse(EFF_LARGE_WORDLIST).expect("the EFF's large wordlist is valid");
+            EFF_LARGE.store(Box::into_raw(Box::new(wordlist)), Ordering::Release)
+        });
+        // Safety: the pointer was initialized above from a leaked `Box`, and is never
+        // freed.
+        unsafe { &*EFF_LARGE.load(Ordering::Acquire) }.clone()
+    }
+
+    /// Returns the number of words in this list.
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Returns false, as wordlists always have at least two words.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Returns the entropy (in bits) contributed by each word chosen from this list.
+    pub fn bits_per_word(&self) -> f64 {
+        (self.words.len() as f64).log2()
+    }
+}
+
+/// A generator of passphrases made of random words.
+///
+/// # Examples
+///
+/// ```
+/// use pinentry::diceware::{Diceware, Wordlist};
+/// use pinentry::ExposeSecret;
+///
+/// let mut diceware = Diceware::new(Wordlist::eff_large());
+/// diceware.with_words(7).with_separator("-");
+/// println!("{}", diceware.generate()?.expose_secret());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Diceware {
+    wordlist: Wordlist,
+    words: usize,
+    separator: String,
+}
+
+/// Generates passphrases of six words from the EFF's large wordlist (see
+/// [`Wordlist::eff_large`]), separated by spaces, with about 77 bits of entropy.
+impl Default for Diceware {
+    fn default() -> Self {
+        Diceware::new(Wordlist::eff_large())
+    }
+}
+
+impl Diceware {
+    /// Creates a generator of passphrases of six words from the given list, separated
+    /// by spaces.
+    pub fn new(wordlist: Wordlist) -> Self {
+        Diceware {
+            wordlist,
+            words: DEFAULT_WORDS,
+            separator: " ".to_owned(),
+        }
+    }
+
+    /// Sets the number of words in each passphrase.
+    pub fn with_words(&mut self, words: usize) -> &mut Self {
+        self.words = words;
+        self
+    }
+
+    /// Sets the text placed between the words of each passphrase.
+    pub fn with_separator(&mut self, separator: impl Into<String>) -> &mut Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Returns the entropy (in bits) of the passphrases that this generator produces.
+    pub fn entropy(&self) -> f64 {
+        self.words as f64 * self.wordlist.bits_per_word()
+    }
+
+    /// Generates a passphrase.
+    ///
+    /// Returns an error if the operating system's random number generator fails.
+    pub fn generate(&self) -> io::Result<SecretString> {
+        let max_len = self.wordlist.words.iter().map(String::len).max();
+        let cap = self.words * (max_len.unwrap_or_default() + self.separator.len());
+        // The capacity is reserved up front, so that the buffer is never reallocated
+        // (which would leave unwiped copies of the passphrase behind).
+        let mut passphrase = Zeroizing::new(String::with_capacity(cap));
+        for i in 0..self.words {
+            if i > 0 {
+                passphrase.push_str(&self.separator);
+            }
+            passphrase.push_str(&self.wordlist.words[random_index(self.wordlist.len())?]);
+        }
+        Ok(SecretString::from(std::mem::take(&mut *passphrase)))
+    }
+}
+
+/// Returns a uniformly random index less than `len`, which must fit in a `u32`.
+fn random_index(len: usize) -> io::Result<usize> {
+    let len = len as u32;
+    // Values at or above the largest multiple of `len` are rejected, so that every index
+    // is equally likely.
+    let limit = u32::MAX - u32::MAX % len;
+    loop {
+        let mut bytes = [0; 4];
+        getrandom::getrandom(&mut bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        let value = u32::from_le_bytes(bytes);
+        if value < limit {
+            return Ok((value % len) as usize);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+
+    use super::{random_index, Diceware, Wordlist};
+
+    #[test]
+    fn wordlist() {
+        let wordlist = Wordlist::parse("11111\tabacus\n\n11112 abdomen\nabide\n").unwrap();
+        assert_eq!(wordlist.len(), 3);
+        assert_eq!(wordlist.bits_per_word(), 3f64.log2());
+
+        assert!(Wordlist::new(["abacus"]).is_err());
+        assert!(Wordlist::new(["abacus", "abacus"]).is_err());
+        assert!(Wordlist::new(["abacus", "ab domen"]).is_err());
+        assert!(Wordlist::new(["abacus", ""]).is_err());
+    }
+
+    #[test]
+    fn generate() {
+        let wordlist = Wordlist::new(["abacus", "abdomen"]).unwrap();
+        let mut diceware = Diceware::new(wordlist);
+        diceware.with_words(8).with_separator("-");
+        assert_eq!(diceware.entropy(), 8.0);
+
+        let passphrase = diceware.generate().unwrap();
+        let words: Vec<_> = passphrase.expose_secret().split('-').collect();
+        assert_eq!(words.len(), 8);
+        assert!(words
+            .iter()
+            .all(|word| ["abacus", "abdomen"].contains(word)));
+
+        assert!((0..100).all(|_| random_index(3).unwrap() < 3));
+    }
+
+    #[test]
+    fn eff_large() {
+        let wordlist = Wordlist::eff_large();
+        assert_eq!(wordlist.len(), 7776);
+        assert_eq!(wordlist.words[0], "abacus");
+        assert_eq!(wordlist.words[7775], "zoom");
+        assert_eq!(wordlist, Wordlist::eff_large());
+
+        let passphrase = Diceware::default().generate().unwrap();
+        let words: Vec<_> = passphrase.expose_secret().split(' ').collect();
+        assert_eq!(words.len(), 6);
+        assert!(words
+            .iter()
+            .all(|word| wordlist.words.contains(&word.to_string())));
+    }
+}
@@ -0,0 +1,297 @@
+//! Process-wide caching of `pinentry` binary lookups.
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Mutex, Once};
+
+use crate::error::{InvalidBinaryError, InvalidBinaryReason};
+
+type Cache = Mutex<HashMap<OsString, Result<PathBuf, InvalidBinaryReason>>>;
+
+fn cache() -> &'static Cache {
+    static INIT: Once = Once::new();
+    static CACHE: AtomicPtr<Cache> = AtomicPtr::new(ptr::null_mut());
+
+    INIT.call_once(|| {
+        CACHE.store(
+            Box::into_raw(Box::new(Mutex::new(HashMap::new()))),
+            Ordering::Release,
+        )
+    });
+    // Safety: the pointer was initialized above from a leaked `Box`, and is never
+    // freed.
+    unsafe { &*CACHE.load(Ordering::Acquire) }
+}
+
+/// Finds the given `pinentry` binary.
+///
+/// Bare binary names are searched for in `PATH`, and then (on Unix) in the Nix and
+/// Guix profile directories and (on the BSDs) the ports and packages prefixes, where
+/// `pinentry` is often installed without being on the `PATH` of daemons and services.
+/// On the BSDs, where the ports can install variants such as `pinentry-curses` without
+/// a `pinentry` binary, `pinentry` itself falls back to the first variant that is
+/// found. With the `tty-pinentry` feature, `pinentry` finally falls back to the
+/// `pinentry-rs-tty` binary. These lookups are cached for the
+/// lifetime of the process, until [`clear_binary_cache`] is called. Paths are always
+/// checked directly, because relative paths depend on the current directory.
+pub(crate) fn find(binary_name: &OsStr) -> Result<PathBuf, InvalidBinaryError> {
+    let res = if Path::new(binary_name).parent() != Some(Path::new("")) {
+        lookup(binary_name)
+    } else {
+        let mut cache = cache().lock().unwrap_or_else(|e| e.into_inner());
+        cache
+            .entry(binary_name.to_owned())
+            .or_insert_with(|| lookup(binary_name))
+            .clone()
+    };
+    res.map_err(|reason| InvalidBinaryError::new(binary_name, reason))
+}
+
+/// True on the BSDs, whose ports and packages install into their own prefixes.
+const BSD: bool = cfg!(any(
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly",
+));
+
+/// The `pinentry` variants that `pinentry` falls back to on the BSDs, with those that
+/// need a display first.
+const GRAPHICAL_VARIANTS: &[&str] = &[
+    "pinentry-qt",
+    "pinentry-qt5",
+    "pinentry-gnome3",
+    "pinentry-gtk-2",
+    "pinentry-fltk",
+];
+const TERMINAL_VARIANTS: &[&str] = &["pinentry-curses", "pinentry-tty"];
+
+fn lookup(binary_name: &OsStr) -> Result<PathBuf, InvalidBinaryReason> {
+    search(binary_name)
+        .or_else(|| {
+            if BSD && binary_name == "pinentry" {
+                let graphical = ["DISPLAY", "WAYLAND_DISPLAY"]
+                    .iter()
+                    .any(|var| std::env::var_os(var).map_or(false, |v| !v.is_empty()));
+                pick_variant(|variant| search(OsStr::new(variant)), graphical)
+            } else {
+                None
+            }
+        })
+        .or_else(|| {
+            if cfg!(feature = "tty-pinentry") && binary_name == "pinentry" {
+                tty_pinentry()
+            } else {
+                None
+            }
+        })
+        .ok_or_else(|| diagnose(binary_name))
+}
+
+/// Finds the `pinentry-rs-tty` binary built by the `tty-pinentry` feature, preferring
+/// the one installed alongside the current executable.
+fn tty_pinentry() -> Option<PathBuf> {
+    const NAME: &str = "pinentry-rs-tty";
+
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| {
+            let dir = exe.parent()?.to_owned();
+            which::which_in(NAME, Some(dir), "/").ok()
+        })
+        .or_else(|| search(OsStr::new(NAME)))
+}
+
+/// Searches `PATH`, and then the fallback directories (for bare binary names).
+fn search(binary_name: &OsStr) -> Option<PathBuf> {
+    which::which(binary_name).ok().or_else(|| {
+        if Path::new(binary_name).parent() != Some(Path::new("")) {
+            return None;
+        }
+        let dirs = std::env::join_paths(fallback_dirs()).ok()?;
+        which::which_in(binary_name, Some(dirs), "/").ok()
+    })
+}
+
+/// Returns the first variant that is found, preferring graphical variants if a
+/// display is available, and terminal variants otherwise.
+fn pick_variant(find: impl Fn(&str) -> Option<PathBuf>, graphical: bool) -> Option<PathBuf> {
+    let (first, second) = if graphical {
+        (GRAPHICAL_VARIANTS, TERMINAL_VARIANTS)
+    } else {
+        (TERMINAL_VARIANTS, GRAPHICAL_VARIANTS)
+    };
+    first.iter().chain(second).find_map(|variant| find(variant))
+}
+
+/// Returns the directories that are searched after `PATH`.
+fn fallback_dirs() -> Vec<PathBuf> {
+    if cfg!(unix) {
+        fallback_dirs_for(
+            std::env::var_os("HOME").as_deref().map(Path::new),
+            std::env::var_os("USER").as_deref(),
+            BSD,
+        )
+    } else {
+        vec![]
+    }
+}
+
+/// Returns the Nix and Guix profile directories, followed by the ports and packages
+/// prefixes of the BSDs if `bsd` is true.
+fn fallback_dirs_for(home: Option<&Path>, user: Option<&OsStr>, bsd: bool) -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    if let Some(home) = home.filter(|home| home.is_absolute()) {
+        dirs.push(home.join(".nix-profile/bin"));
+        dirs.push(home.join(".guix-profile/bin"));
+    }
+    if let Some(user) = user.filter(|user| !user.is_empty()) {
+        // Packages installed through the NixOS `users.users.<name>.packages` option.
+        let mut dir = PathBuf::from("/etc/profiles/per-user");
+        dir.push(user);
+        dir.push("bin");
+        dirs.push(dir);
+    }
+    dirs.extend(
+        [
+            // The NixOS (or nix-darwin) system profile.
+            "/run/current-system/sw/bin",
+            // The default profile of multi-user Nix installations.
+            "/nix/var/nix/profiles/default/bin",
+            // The Guix System system profile.
+            "/run/current-system/profile/bin",
+        ]
+        .iter()
+        .map(PathBuf::from),
+    );
+    if bsd {
+        // FreeBSD ports and OpenBSD packages, and then NetBSD's pkgsrc.
+        dirs.push(PathBuf::from("/usr/local/bin"));
+        dirs.push(PathBuf::from("/usr/pkg/bin"));
+    }
+    dirs
+}
+
+/// Determines why `which` rejected the given binary.
+fn diagnose(binary_name: &OsStr) -> InvalidBinaryReason {
+    let path = Path::new(binary_name);
+    let exists = if path.parent() != Some(Path::new("")) {
+        path.exists()
+    } else {
+        std::env::var_os("PATH")
+            .map_or(vec![], |paths| std::env::split_paths(&paths).collect())
+            .into_iter()
+            .chain(fallback_dirs())
+            .any(|dir| dir.join(binary_name).exists())
+    };
+    if exists {
+        InvalidBinaryReason::NotExecutable
+    } else {
+        InvalidBinaryReason::NotFound
+    }
+}
+
+/// Clears the process-wide cache of `pinentry` binary lookups.
+///
+/// Constructors such as [`PassphraseInput::with_default_binary`] only search `PATH`
+/// (and the profile directories of Nix and Guix) the first time they are called for
+/// each binary name, and reuse the result (including the binary not being found)
+/// afterwards. Call this if `PATH` changes, or if a `pinentry` binary may have been
+/// installed or removed.
+///
+/// [`PassphraseInput::with_default_binary`]: crate::PassphraseInput::with_default_binary
+pub fn clear_binary_cache() {
+    cache().lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::path::{Path, PathBuf};
+
+    use super::{diagnose, fallback_dirs_for, pick_variant};
+    use crate::InvalidBinaryReason;
+
+    #[cfg(unix)]
+    #[test]
+    fn diagnosis() {
+        assert_eq!(
+            diagnose(OsStr::new("/etc/passwd")),
+            InvalidBinaryReason::NotExecutable
+        );
+        assert_eq!(
+            diagnose(OsStr::new("/nonexistent/pinentry")),
+            InvalidBinaryReason::NotFound
+        );
+        assert_eq!(
+            diagnose(OsStr::new("pinentry-nonexistent")),
+            InvalidBinaryReason::NotFound
+        );
+    }
+
+    #[test]
+    fn fallback_dirs() {
+        assert_eq!(
+            fallback_dirs_for(
+                Some(Path::new("/home/alice")),
+                Some(OsStr::new("alice")),
+                false
+            ),
+            [
+                "/home/alice/.nix-profile/bin",
+                "/home/alice/.guix-profile/bin",
+                "/etc/profiles/per-user/alice/bin",
+                "/run/current-system/sw/bin",
+                "/nix/var/nix/profiles/default/bin",
+                "/run/current-system/profile/bin",
+            ]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>()
+        );
+        // A relative or unset home directory, or an unset user, is skipped.
+        assert_eq!(
+            fallback_dirs_for(Some(Path::new("alice")), Some(OsStr::new("")), false),
+            fallback_dirs_for(None, None, false)
+        );
+        assert_eq!(fallback_dirs_for(None, None, false).len(), 3);
+
+        let bsd = fallback_dirs_for(None, None, true);
+        assert_eq!(
+            bsd[3..],
+            [
+                PathBuf::from("/usr/local/bin"),
+                PathBuf::from("/usr/pkg/bin")
+            ]
+        );
+    }
+
+    #[test]
+    fn variants() {
+        let installed = |names: &'static [&'static str]| {
+            move |variant: &str| {
+                names
+                    .contains(&variant)
+                    .then(|| PathBuf::from("/usr/local/bin").join(variant))
+            }
+        };
+        let both = installed(&["pinentry-tty", "pinentry-qt5"]);
+        assert_eq!(
+            pick_variant(&both, true),
+            Some(PathBuf::from("/usr/local/bin/pinentry-qt5"))
+        );
+        assert_eq!(
+            pick_variant(&both, false),
+            Some(PathBuf::from("/usr/local/bin/pinentry-tty"))
+        );
+        // A graphical variant is still used without a display if it is the only one.
+        assert_eq!(
+            pick_variant(installed(&["pinentry-gnome3"]), false),
+            Some(PathBuf::from("/usr/local/bin/pinentry-gnome3"))
+        );
+        assert_eq!(pick_variant(installed(&[]), true), None);
+    }
+}
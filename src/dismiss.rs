@@ -0,0 +1,145 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::assuan::Killer;
+
+/// A handle for dismissing a [`ConfirmationDialog`] or [`MessageDialog`] from
+/// application code while it is shown.
+///
+/// This is useful for dialogs that only tell the user to do something that the
+/// application can detect itself, such as touching a security key. Set the handle on
+/// a dialog with its `with_dismiss_handle` method, show the dialog (on another
+/// thread), and call [`DismissHandle::dismiss`] once it is no longer needed. The
+/// `pinentry` process is then killed, and the dialog returns [`Error::Dismissed`].
+///
+/// A handle is dismissed once, and stays dismissed: a dialog that is shown with an
+/// already dismissed handle is closed as soon as it is shown. Create a new handle for
+/// each dialog.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::thread;
+///
+/// use pinentry::{DismissHandle, MessageDialog};
+///
+/// # fn wait_for_touch() {}
+/// let handle = DismissHandle::new();
+/// let prompt = {
+///     let handle = handle.clone();
+///     thread::spawn(move || {
+///         MessageDialog::with_default_binary()
+///             .map(|mut dialog| {
+///                 dialog
+///                     .with_dismiss_handle(&handle)
+///                     .show_message("Touch your security key")
+///             })
+///     })
+/// };
+///
+/// wait_for_touch();
+/// handle.dismiss();
+/// match prompt.join().unwrap() {
+///     // The user closed the dialog before touching the key.
+///     Some(Ok(())) => (),
+///     Some(Err(pinentry::Error::Dismissed)) | None => (),
+///     Some(Err(e)) => return Err(e),
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+///
+/// [`ConfirmationDialog`]: crate::ConfirmationDialog
+/// [`MessageDialog`]: crate::MessageDialog
+/// [`Error::Dismissed`]: crate::Error::Dismissed
+#[derive(Clone, Default)]
+pub struct DismissHandle {
+    inner: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    dismissed: bool,
+    /// The shown prompts, with whether each has been dismissed.
+    shown: Vec<(u64, Killer, bool)>,
+    next_id: u64,
+}
+
+impl fmt::Debug for DismissHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DismissHandle")
+            .field("dismissed", &self.is_dismissed())
+            .finish()
+    }
+}
+
+impl DismissHandle {
+    /// Creates a handle that has not been dismissed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Dismisses the dialog that this handle is set on.
+    ///
+    /// Returns true if a dialog was being shown, and false if it had not yet been
+    /// shown (in which case it is closed as soon as it is) or had already finished.
+    pub fn dismiss(&self) -> bool {
+        let mut state = self.state();
+        state.dismissed = true;
+        let mut dismissed = false;
+        for (_, killer, killed) in &mut state.shown {
+            if !*killed && killer.kill().is_ok() {
+                *killed = true;
+                dismissed = true;
+            }
+        }
+        dismissed
+    }
+
+    /// Returns true if this handle has been dismissed.
+    pub fn is_dismissed(&self) -> bool {
+        self.state().dismissed
+    }
+
+    /// Registers a prompt that is being shown, until the returned guard is dropped.
+    pub(crate) fn shown(&self, killer: Killer) -> Shown {
+        let mut state = self.state();
+        let id = state.next_id;
+        state.next_id += 1;
+        let killed = state.dismissed && killer.kill().is_ok();
+        state.shown.push((id, killer, killed));
+        Shown {
+            handle: self.clone(),
+            id,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A prompt that is being shown with a [`DismissHandle`].
+pub(crate) struct Shown {
+    handle: DismissHandle,
+    id: u64,
+}
+
+impl Shown {
+    /// Returns true if the prompt's `pinentry` process was killed by the handle.
+    pub(crate) fn was_dismissed(&self) -> bool {
+        self.handle
+            .state()
+            .shown
+            .iter()
+            .any(|(id, _, killed)| *id == self.id && *killed)
+    }
+}
+
+impl Drop for Shown {
+    fn drop(&mut self) {
+        self.handle
+            .state()
+            .shown
+            .retain(|(id, _, _)| *id != self.id);
+    }
+}
@@ -0,0 +1,168 @@
+//! Percent-encoding of Assuan request parameters and data lines.
+//!
+//! Assuan lines cannot contain line endings, so request parameters and the data in `D`
+//! response lines are percent-encoded: `%` and every ASCII control character (the bytes
+//! below 0x20, such as CR and LF, and DEL) are replaced by their `%XX` escapes (for
+//! example, `%25`, `%0D`, and `%0A`), and a trailing backslash (which Assuan treats as
+//! a line continuation) is replaced by `%5C`. All other characters are passed through
+//! unchanged.
+//!
+//! These are the same functions that this crate uses to talk to `pinentry` binaries.
+//! Values that are not valid UTF-8 (such as Unix paths) can be encoded with
+//! [`encode_bytes`], which additionally percent-encodes each invalid byte.
+//!
+//! For every string `s`, decoding its encoding returns exactly the bytes of `s`:
+//!
+//! ```
+//! use pinentry::encoding::{decode, encode};
+//!
+//! for s in ["plain", "100%", "two\r\nlines", "trailing\\", "%41"] {
+//!     assert_eq!(decode(&encode(s)), s.as_bytes());
+//! }
+//! ```
+
+use percent_encoding::percent_decode_str;
+
+/// Returns the length in bytes of the encoding of `s`.
+pub fn encoded_len(s: &str) -> usize {
+    let escaped_backslash = if s.ends_with('\\') { 2 } else { 0 };
+    s.chars()
+        .map(|c| if needs_escape(c) { 3 } else { c.len_utf8() })
+        .sum::<usize>()
+        + escaped_backslash
+}
+
+/// Percent-encodes `s` for use as a request parameter or in a data line.
+pub fn encode(s: &str) -> String {
+    let mut buf = String::with_capacity(encoded_len(s));
+    encode_into(s, &mut buf);
+    buf
+}
+
+/// Appends the encoding of `s` to `buf`.
+pub(crate) fn encode_into(s: &str, buf: &mut String) {
+    let (s, trailing_backslash) = match s.strip_suffix('\\') {
+        Some(s) => (s, true),
+        None => (s, false),
+    };
+    for c in s.chars() {
+        if needs_escape(c) {
+            buf.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            buf.push(c);
+        }
+    }
+    if trailing_backslash {
+        buf.push_str("%5C");
+    }
+}
+
+/// Returns true if `c` is percent-encoded wherever it appears.
+fn needs_escape(c: char) -> bool {
+    c == '%' || c.is_ascii_control()
+}
+
+/// Percent-encodes `bytes` for use as a request parameter, where any bytes that are not
+/// part of a valid UTF-8 sequence are also percent-encoded.
+pub fn encode_bytes(bytes: &[u8]) -> String {
+    let mut buf = String::with_capacity(bytes.len());
+    encode_bytes_into(bytes, &mut buf);
+    buf
+}
+
+/// Appends the encoding of `bytes` to `buf`.
+pub(crate) fn encode_bytes_into(mut bytes: &[u8], buf: &mut String) {
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => return encode_into(s, buf),
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                encode_into(std::str::from_utf8(valid).unwrap_or_default(), buf);
+                let invalid = e.error_len().unwrap_or(rest.len());
+                for b in &rest[..invalid] {
+                    buf.push_str(&format!("%{:02X}", b));
+                }
+                bytes = &rest[invalid..];
+            }
+        }
+    }
+}
+
+/// Decodes a percent-encoded request parameter or data line.
+///
+/// Every `%XX` escape (where `XX` is a pair of hexadecimal digits) is decoded, whether
+/// or not [`encode`] would have produced it, and any other `%` is passed through
+/// unchanged. The result may not be valid UTF-8.
+pub fn decode(s: &str) -> Vec<u8> {
+    decode_iter(s).collect()
+}
+
+/// Decodes a percent-encoded request parameter or data line, byte by byte.
+pub(crate) fn decode_iter(s: &str) -> impl Iterator<Item = u8> + '_ {
+    percent_decode_str(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, encode_bytes, encoded_len};
+
+    #[test]
+    fn roundtrip() {
+        let mut cases: Vec<String> = ["", "\\", "\\\\", "%", "%%0A", "a\\b", "\r\n\r"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        cases.push((0..=255u8).map(char::from).collect());
+
+        // A deterministic pseudo-random selection of strings over an alphabet that
+        // exercises every escape.
+        let alphabet = ['a', '%', '\r', '\n', '\\', '0', 'A', 'é', '\u{1F510}'];
+        let mut state = 0x2545_f491_u32;
+        for len in 0..64 {
+            cases.push(
+                (0..len)
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 17;
+                        state ^= state << 5;
+                        alphabet[state as usize % alphabet.len()]
+                    })
+                    .collect(),
+            );
+        }
+
+        for s in &cases {
+            let encoded = encode(s);
+            assert_eq!(encoded.len(), encoded_len(s));
+            assert!(!encoded.contains(|c: char| c.is_ascii_control()));
+            assert!(!encoded.ends_with('\\'));
+            assert_eq!(decode(&encoded), s.as_bytes());
+        }
+    }
+
+    #[test]
+    fn control_characters() {
+        assert_eq!(encode("a\tb\x1b[0m"), "a%09b%1B[0m");
+        assert_eq!(encode("\0\x7f~"), "%00%7F~");
+        assert_eq!(encode("100% \u{80}"), "100%25 \u{80}");
+        assert_eq!(encode_bytes(b"\x01\xff"), "%01%FF");
+    }
+
+    #[test]
+    fn roundtrip_bytes() {
+        for bytes in [
+            &b"/dev/pts/3"[..],
+            b"/dev/\xff\xfe",
+            b"caf\xc3\xa9 %\n",
+            b"\xe2\x82",
+            b"trailing\\",
+            b"\xc3\xa9\\\x80",
+        ] {
+            let encoded = encode_bytes(bytes);
+            assert!(!encoded.contains(['\r', '\n']));
+            assert!(!encoded.ends_with('\\'));
+            assert_eq!(decode(&encoded), bytes);
+        }
+        assert_eq!(encode_bytes(b"/dev/\xff"), "/dev/%FF");
+    }
+}
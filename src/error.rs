@@ -1,8 +1,110 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{fmt, io};
 
+mod codes;
+
 pub(crate) const GPG_ERR_TIMEOUT: u16 = 62;
 pub(crate) const GPG_ERR_CANCELED: u16 = 99;
 pub(crate) const GPG_ERR_NOT_CONFIRMED: u16 = 114;
+pub(crate) const GPG_ERR_ENOTTY: u16 = 32870;
+
+/// The bit position of the error source within a full GPG error value.
+const GPG_ERR_SOURCE_SHIFT: u32 = 24;
+/// The mask for the error source, after shifting.
+const GPG_ERR_SOURCE_MASK: u32 = 127;
+/// The mask for the error code within a full GPG error value.
+const GPG_ERR_CODE_MASK: u32 = 65535;
+
+/// Diagnostics that indicate a terminal-based pinentry could not access a terminal,
+/// each given as the substrings that must all appear.
+const NO_TERMINAL_DIAGNOSTICS: &[&[&str]] = &[
+    &["inappropriate ioctl"],
+    &["no controlling terminal"],
+    &["not a tty"],
+    // The locations that `pinentry-curses` reports when it cannot open the terminal.
+    &["open_tty_for_read"],
+    &["open_tty_for_write"],
+    // Opening `/dev/tty` without a controlling terminal fails with `ENXIO`.
+    &["/dev/tty", "no such device or address"],
+];
+
+/// Diagnostics that indicate a GUI pinentry could not connect to a display.
+const NO_DISPLAY_DIAGNOSTICS: &[&str] = &[
+    "cannot open display",
+    "can't open display",
+    "could not connect to display",
+    "unable to open display",
+    "no display",
+];
+
+/// The component that a GPG error originated from.
+///
+/// See https://github.com/gpg/libgpg-error/blob/master/src/err-sources.h.in for the
+/// full list of error sources.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorSource {
+    /// The error source was not specified.
+    Unknown,
+    /// The error originated from `libgcrypt`.
+    Gcrypt,
+    /// The error originated from GnuPG.
+    Gpg,
+    /// The error originated from GpgSM.
+    Gpgsm,
+    /// The error originated from `gpg-agent`.
+    GpgAgent,
+    /// The error originated from the `pinentry` binary.
+    Pinentry,
+    /// The error originated from the smartcard daemon.
+    Scd,
+    /// The error originated from GPGME.
+    Gpgme,
+    /// The error originated from `libassuan`.
+    Assuan,
+    /// The error originated from a user-defined component.
+    User(u8),
+    /// Any other error source.
+    Other(u8),
+}
+
+impl ErrorSource {
+    fn from_value(value: u8) -> Self {
+        match value {
+            0 => ErrorSource::Unknown,
+            1 => ErrorSource::Gcrypt,
+            2 => ErrorSource::Gpg,
+            3 => ErrorSource::Gpgsm,
+            4 => ErrorSource::GpgAgent,
+            5 => ErrorSource::Pinentry,
+            6 => ErrorSource::Scd,
+            7 => ErrorSource::Gpgme,
+            15 => ErrorSource::Assuan,
+            32..=35 => ErrorSource::User(value - 31),
+            _ => ErrorSource::Other(value),
+        }
+    }
+}
+
+impl fmt::Display for ErrorSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorSource::Unknown => write!(f, "Unspecified source"),
+            ErrorSource::Gcrypt => write!(f, "gcrypt"),
+            ErrorSource::Gpg => write!(f, "GnuPG"),
+            ErrorSource::Gpgsm => write!(f, "GpgSM"),
+            ErrorSource::GpgAgent => write!(f, "GPG Agent"),
+            ErrorSource::Pinentry => write!(f, "Pinentry"),
+            ErrorSource::Scd => write!(f, "SCD"),
+            ErrorSource::Gpgme => write!(f, "GPGME"),
+            ErrorSource::Assuan => write!(f, "Assuan"),
+            ErrorSource::User(n) => write!(f, "User defined source {}", n),
+            ErrorSource::Other(n) => write!(f, "Source {}", n),
+        }
+    }
+}
 
 /// An uncommon or unexpected GPG error.
 ///
@@ -17,49 +119,551 @@ pub struct GpgError {
     /// mapping from error code to GPG error type.
     code: u16,
 
+    /// The component that the error originated from.
+    source: ErrorSource,
+
     /// A description of the error, if available.
     ///
     /// See https://github.com/gpg/libgpg-error/blob/master/src/err-codes.h.in for the
     /// likely descriptions.
     description: Option<String>,
+
+    /// Any diagnostics that the `pinentry` binary printed to stderr.
+    stderr: Option<String>,
+
+    /// The command that failed, if known.
+    command: Option<String>,
 }
 
 impl fmt::Display for GpgError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Code {}", self.code)?;
-        if let Some(desc) = &self.description {
+        if let Some(desc) = self
+            .description
+            .as_deref()
+            .or_else(|| codes::description(self.code))
+        {
             write!(f, ": {}", desc)?;
         }
+        if self.source != ErrorSource::Unknown {
+            write!(f, " <{}>", self.source)?;
+        }
+        if let Some(command) = &self.command {
+            write!(f, " (in response to {})", command)?;
+        }
         Ok(())
     }
 }
 
+impl std::error::Error for GpgError {}
+
 impl GpgError {
     pub(super) fn new(code: u16, description: Option<String>) -> Self {
-        GpgError { code, description }
+        GpgError {
+            code,
+            source: ErrorSource::Unknown,
+            description,
+            stderr: None,
+            command: None,
+        }
+    }
+
+    pub(crate) fn with_source(self, source: ErrorSource) -> Self {
+        GpgError { source, ..self }
+    }
+
+    pub(crate) fn with_stderr(self, stderr: Option<String>) -> Self {
+        GpgError { stderr, ..self }
     }
 
     /// Returns the GPG code for this error.
     pub fn code(&self) -> u16 {
         self.code
     }
+
+    /// Returns the component that this error originated from.
+    pub fn error_source(&self) -> ErrorSource {
+        self.source
+    }
+
+    /// Returns the description of this error, if the `pinentry` binary provided one.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Returns the canonical `libgpg-error` description of this error's code, if known.
+    pub fn canonical_description(&self) -> Option<&'static str> {
+        codes::description(self.code)
+    }
+
+    /// Returns the command that failed, if known.
+    ///
+    /// This never includes the command's parameters, except for the name of the option
+    /// being set by an `OPTION` command (for example, `OPTION ttyname`).
+    pub fn command(&self) -> Option<&str> {
+        self.command.as_deref()
+    }
+
+    /// Returns any diagnostics that the `pinentry` binary printed to stderr.
+    ///
+    /// GUI pinentries often explain failures here (for example, that they could not
+    /// connect to a display).
+    pub fn stderr(&self) -> Option<&str> {
+        self.stderr.as_deref()
+    }
+}
+
+/// The maximum number of characters of an offending response line that we keep.
+const MAX_PROTOCOL_LINE_LEN: usize = 64;
+
+/// A response from the `pinentry` binary that could not be parsed.
+#[derive(Debug)]
+pub struct ProtocolError {
+    /// The command that was being executed, or `None` if we were waiting for the
+    /// initial greeting.
+    command: Option<String>,
+
+    /// A redacted and truncated copy of the offending response line.
+    line: String,
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.command {
+            Some(command) => write!(f, "Invalid response to {}", command)?,
+            None => write!(f, "Invalid greeting")?,
+        }
+        write!(f, ": {:?}", self.line)
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl ProtocolError {
+    pub(crate) fn new(command: Option<&str>, line: &str) -> Self {
+        let line = line.trim_end_matches(&['\r', '\n'][..]);
+        let line = if line == "D" || line.starts_with("D ") {
+            // Data lines may contain secrets.
+            "D [REDACTED]".to_owned()
+        } else {
+            match line.char_indices().nth(MAX_PROTOCOL_LINE_LEN) {
+                Some((i, _)) => format!("{}...", &line[..i]),
+                None => line.to_owned(),
+            }
+        };
+
+        ProtocolError {
+            command: command.map(String::from),
+            line,
+        }
+    }
+
+    /// Returns the command that was being executed, or `None` if the error occurred
+    /// while waiting for the initial greeting.
+    pub fn command(&self) -> Option<&str> {
+        self.command.as_deref()
+    }
+
+    /// Returns a copy of the offending response line.
+    ///
+    /// Data lines (which may contain secrets) are redacted, and long lines are
+    /// truncated.
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+}
+
+/// An error returned when a binary does not greet us as a `pinentry` binary would (for
+/// example, because it is some other program, or a wrapper script that prints other
+/// output first).
+#[derive(Debug)]
+pub struct NotAPinentryError {
+    binary: PathBuf,
+    greeting: ProtocolError,
+}
+
+impl fmt::Display for NotAPinentryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Not a pinentry binary: {} (its first line was {:?})",
+            self.binary.display(),
+            self.greeting.line(),
+        )
+    }
+}
+
+impl std::error::Error for NotAPinentryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.greeting)
+    }
+}
+
+impl NotAPinentryError {
+    pub(crate) fn new(binary: &Path, greeting: ProtocolError) -> Self {
+        NotAPinentryError {
+            binary: binary.to_owned(),
+            greeting,
+        }
+    }
+
+    /// Returns the path to the binary.
+    pub fn binary(&self) -> &Path {
+        &self.binary
+    }
+
+    /// Returns a redacted and truncated copy of the line that the binary printed
+    /// instead of a greeting.
+    pub fn greeting(&self) -> &str {
+        self.greeting.line()
+    }
+}
+
+/// The reason a `pinentry` binary was unable to show a dialog.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnavailableReason {
+    /// A terminal-based pinentry (such as `pinentry-curses`) could not access a
+    /// terminal.
+    ///
+    /// Applications should suggest that the user sets `GPG_TTY` (for example with
+    /// `export GPG_TTY=$(tty)`), or installs a GUI pinentry.
+    NoTerminal,
+    /// A GUI pinentry could not connect to a display.
+    ///
+    /// Applications should suggest that the user checks that `DISPLAY` or
+    /// `WAYLAND_DISPLAY` is set, or installs a terminal-based pinentry such as
+    /// `pinentry-curses`.
+    NoDisplay,
+}
+
+impl UnavailableReason {
+    /// Detects the reason from the given `pinentry` diagnostics.
+    pub(crate) fn detect(diagnostics: &str) -> Option<Self> {
+        let diagnostics = diagnostics.to_lowercase();
+        if NO_DISPLAY_DIAGNOSTICS
+            .iter()
+            .any(|d| diagnostics.contains(d))
+        {
+            Some(UnavailableReason::NoDisplay)
+        } else if NO_TERMINAL_DIAGNOSTICS
+            .iter()
+            .any(|d| d.iter().all(|d| diagnostics.contains(d)))
+        {
+            Some(UnavailableReason::NoTerminal)
+        } else {
+            None
+        }
+    }
+
+    /// Detects the reason from the given GPG error.
+    pub(crate) fn from_gpg(e: &GpgError) -> Option<Self> {
+        if e.code == GPG_ERR_ENOTTY {
+            Some(UnavailableReason::NoTerminal)
+        } else {
+            e.description
+                .as_deref()
+                .and_then(Self::detect)
+                .or_else(|| e.stderr.as_deref().and_then(Self::detect))
+        }
+    }
+}
+
+/// A `pinentry` binary was unable to show a dialog, because it had no terminal or
+/// display to show it on.
+#[derive(Debug)]
+pub struct UnavailableError {
+    reason: UnavailableReason,
+    details: Option<String>,
+}
+
+impl fmt::Display for UnavailableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.reason {
+            UnavailableReason::NoTerminal => write!(f, "pinentry could not access a terminal")?,
+            UnavailableReason::NoDisplay => write!(f, "pinentry could not connect to a display")?,
+        }
+        if let Some(details) = &self.details {
+            write!(f, " ({})", details)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for UnavailableError {}
+
+impl UnavailableError {
+    pub(crate) fn new(reason: UnavailableReason, details: Option<String>) -> Self {
+        UnavailableError { reason, details }
+    }
+
+    /// Returns the reason the `pinentry` binary was unable to show a dialog.
+    pub fn reason(&self) -> UnavailableReason {
+        self.reason
+    }
+
+    /// Returns the diagnostics reported by the `pinentry` binary, if any.
+    pub fn details(&self) -> Option<&str> {
+        self.details.as_deref()
+    }
+}
+
+/// The reason a `pinentry` binary could not be used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InvalidBinaryReason {
+    /// The path does not exist, or the binary name cannot be found in `PATH`.
+    NotFound,
+    /// The binary exists, but is not an executable file.
+    NotExecutable,
+}
+
+/// An error returned when a `pinentry` binary cannot be found, or is not executable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidBinaryError {
+    binary: OsString,
+    reason: InvalidBinaryReason,
+}
+
+impl fmt::Display for InvalidBinaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let binary = Path::new(&self.binary).display();
+        match self.reason {
+            InvalidBinaryReason::NotFound => write!(f, "pinentry binary not found: {}", binary),
+            InvalidBinaryReason::NotExecutable => {
+                write!(f, "pinentry binary is not executable: {}", binary)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvalidBinaryError {}
+
+impl InvalidBinaryError {
+    pub(crate) fn new(binary: &OsStr, reason: InvalidBinaryReason) -> Self {
+        InvalidBinaryError {
+            binary: binary.to_owned(),
+            reason,
+        }
+    }
+
+    /// Returns the path to, or name of, the binary that could not be used.
+    pub fn binary(&self) -> &OsStr {
+        &self.binary
+    }
+
+    /// Returns the reason the binary could not be used.
+    pub fn reason(&self) -> InvalidBinaryReason {
+        self.reason
+    }
+}
+
+/// The reason a dialog's settings were rejected before spawning a `pinentry` binary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InvalidSettingReason {
+    /// The text contains a NUL character, at which `pinentry` binaries would truncate
+    /// it.
+    ContainsNul,
+    /// The text is too long to be sent in a single Assuan request (which is limited to
+    /// 1000 bytes, after percent-encoding).
+    TooLong,
+    /// The timeout is zero, which `pinentry` binaries treat as no timeout at all.
+    ZeroTimeout,
+    /// A confirmation prompt was set without a passphrase prompt, so the two entry
+    /// widgets would be indistinguishable.
+    ConfirmationWithoutPrompt,
+    /// A [`ChoiceDialog`] was shown without any choices.
+    ///
+    /// [`ChoiceDialog`]: crate::ChoiceDialog
+    NoChoices,
+}
+
+/// An error returned when a dialog's settings are invalid.
+///
+/// Dialogs check their settings before spawning a `pinentry` binary, so these errors
+/// never leave a `pinentry` process running.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidSettingError {
+    command: &'static str,
+    reason: InvalidSettingReason,
+}
+
+impl fmt::Display for InvalidSettingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.reason {
+            InvalidSettingReason::ContainsNul => {
+                write!(f, "Invalid setting for {}: contains NUL", self.command)
+            }
+            InvalidSettingReason::TooLong => {
+                write!(f, "Invalid setting for {}: too long", self.command)
+            }
+            InvalidSettingReason::ZeroTimeout => write!(f, "Invalid timeout: must be non-zero"),
+            InvalidSettingReason::ConfirmationWithoutPrompt => {
+                write!(f, "Invalid setting for {}: requires a prompt", self.command)
+            }
+            InvalidSettingReason::NoChoices => {
+                write!(f, "Invalid setting for {}: no choices", self.command)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvalidSettingError {}
+
+impl InvalidSettingError {
+    pub(crate) fn new(command: &'static str, reason: InvalidSettingReason) -> Self {
+        InvalidSettingError { command, reason }
+    }
+
+    /// Returns the Assuan command (such as `SETDESC`) that would have sent the invalid
+    /// setting.
+    pub fn command(&self) -> &str {
+        self.command
+    }
+
+    /// Returns the reason the setting was rejected.
+    pub fn reason(&self) -> InvalidSettingReason {
+        self.reason
+    }
+}
+
+/// An error returned when every source in a [`FallbackChain`] failed.
+///
+/// [`FallbackChain`]: crate::FallbackChain
+#[derive(Debug)]
+pub struct FallbackError {
+    attempts: Vec<(String, Error)>,
+}
+
+impl fmt::Display for FallbackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No passphrase source succeeded")?;
+        for (i, (label, e)) in self.attempts.iter().enumerate() {
+            write!(f, "{} {}: {}", if i == 0 { ":" } else { ";" }, label, e)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for FallbackError {}
+
+impl FallbackError {
+    pub(crate) fn new(attempts: Vec<(String, Error)>) -> Self {
+        FallbackError { attempts }
+    }
+
+    /// Returns the label of each source that was tried, in order, with the error that
+    /// it returned.
+    pub fn attempts(&self) -> &[(String, Error)] {
+        &self.attempts
+    }
+}
+
+/// An error returned when a prompt was not shown because a [`RateLimiter`]'s limit
+/// has been reached.
+///
+/// [`RateLimiter`]: crate::RateLimiter
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RateLimitedError {
+    retry_after: Option<Duration>,
+}
+
+impl fmt::Display for RateLimitedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.retry_after {
+            Some(retry_after) => write!(
+                f,
+                "Too many prompts: retry in {:.1}s",
+                retry_after.as_secs_f64()
+            ),
+            None => write!(f, "Too many prompts: no prompts are allowed"),
+        }
+    }
+}
+
+impl std::error::Error for RateLimitedError {}
+
+impl RateLimitedError {
+    pub(crate) fn new(retry_after: Option<Duration>) -> Self {
+        RateLimitedError { retry_after }
+    }
+
+    /// Returns how long until a prompt may be shown again, or `None` if the limiter
+    /// allows no prompts at all.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+}
+
+/// An error returned when the process locale uses a character set that [`Charset`]
+/// does not support.
+///
+/// [`Charset`]: crate::Charset
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnsupportedCharsetError {
+    locale: String,
+}
+
+impl fmt::Display for UnsupportedCharsetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unsupported character set in locale {:?}", self.locale)
+    }
+}
+
+impl std::error::Error for UnsupportedCharsetError {}
+
+impl UnsupportedCharsetError {
+    pub(crate) fn new(locale: &str) -> Self {
+        UnsupportedCharsetError {
+            locale: locale.to_owned(),
+        }
+    }
+
+    /// Returns the name of the locale.
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
 }
 
 /// Errors that may be returned while interacting with `pinentry` binaries.
+///
+/// New variants may be added in future releases; the `is_*` predicates below can be
+/// used to check for common cases without matching on every variant.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// The user cancelled the operation.
     Cancelled,
     /// Operation timed out waiting for the user to respond.
     Timeout,
+    /// The user selected the "Not OK" button of a confirmation dialog.
+    NotConfirmed,
+    /// The dialog was closed with a [`DismissHandle`](crate::DismissHandle).
+    Dismissed,
 
     /// An I/O error occurred while communicating with the `pinentry` binary.
     Io(io::Error),
     /// An uncommon or unexpected GPG error.
     Gpg(GpgError),
+    /// The `pinentry` binary sent a response that could not be parsed.
+    Protocol(ProtocolError),
+    /// The binary did not send the greeting of a `pinentry` binary.
+    NotAPinentry(NotAPinentryError),
+    /// The `pinentry` binary had no terminal or display to show a dialog on.
+    Unavailable(UnavailableError),
+    /// The dialog's settings are invalid, so no `pinentry` binary was spawned.
+    InvalidSetting(InvalidSettingError),
+    /// Every source in a [`FallbackChain`](crate::FallbackChain) failed.
+    Exhausted(FallbackError),
+    /// The prompt was not shown, because a [`RateLimiter`](crate::RateLimiter)'s limit
+    /// has been reached.
+    RateLimited(RateLimitedError),
 
     /// The user's input doesn't decode to valid UTF-8.
     Encoding(std::str::Utf8Error),
+    /// The user's input exceeds the configured maximum length.
+    TooLong,
 }
 
 impl fmt::Display for Error {
@@ -67,19 +671,73 @@ impl fmt::Display for Error {
         match self {
             Error::Timeout => write!(f, "Operation timed out"),
             Error::Cancelled => write!(f, "Operation cancelled"),
+            Error::NotConfirmed => write!(f, "Not confirmed"),
+            Error::Dismissed => write!(f, "Dialog dismissed"),
+            Error::TooLong => write!(f, "Input exceeds the maximum length"),
             Error::Gpg(e) => e.fmt(f),
+            Error::Protocol(e) => e.fmt(f),
+            Error::NotAPinentry(e) => e.fmt(f),
+            Error::Unavailable(e) => e.fmt(f),
+            Error::InvalidSetting(e) => e.fmt(f),
+            Error::Exhausted(e) => e.fmt(f),
+            Error::RateLimited(e) => e.fmt(f),
             Error::Io(e) => e.fmt(f),
             Error::Encoding(e) => e.fmt(f),
         }
     }
 }
 
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Cancelled
+            | Error::Timeout
+            | Error::NotConfirmed
+            | Error::Dismissed
+            | Error::TooLong => None,
+            Error::Io(e) => Some(e),
+            Error::Gpg(e) => Some(e),
+            Error::Protocol(e) => Some(e),
+            Error::NotAPinentry(e) => Some(e),
+            Error::Unavailable(e) => Some(e),
+            Error::InvalidSetting(e) => Some(e),
+            Error::Exhausted(e) => Some(e),
+            Error::RateLimited(e) => Some(e),
+            Error::Encoding(e) => Some(e),
+        }
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self {
         Error::Io(e)
     }
 }
 
+/// Converts into an [`io::Error`] with the closest matching [`io::ErrorKind`].
+///
+/// The original error is preserved as the inner error, and can be recovered with
+/// [`io::Error::into_inner`] and downcasting. [`Error::Cancelled`] is deliberately not
+/// mapped to [`io::ErrorKind::Interrupted`], because many I/O loops automatically retry
+/// interrupted operations, which would prompt the user again.
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        let kind = match e {
+            Error::Io(e) => return e,
+            Error::Cancelled => io::ErrorKind::Other,
+            Error::Timeout => io::ErrorKind::TimedOut,
+            Error::NotConfirmed => io::ErrorKind::PermissionDenied,
+            Error::Unavailable(_) | Error::Exhausted(_) => io::ErrorKind::NotFound,
+            Error::InvalidSetting(_) => io::ErrorKind::InvalidInput,
+            Error::Protocol(_) | Error::NotAPinentry(_) | Error::Encoding(_) | Error::TooLong => {
+                io::ErrorKind::InvalidData
+            }
+            Error::Gpg(_) | Error::Dismissed | Error::RateLimited(_) => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
 impl From<std::str::Utf8Error> for Error {
     fn from(e: std::str::Utf8Error) -> Self {
         Error::Encoding(e)
@@ -87,11 +745,141 @@ impl From<std::str::Utf8Error> for Error {
 }
 
 impl Error {
-    pub(crate) fn from_parts(code: u16, description: Option<String>) -> Self {
+    /// Returns true if the user cancelled the operation.
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, Error::Cancelled)
+    }
+
+    /// Returns true if the operation timed out waiting for the user to respond.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Error::Timeout)
+    }
+
+    /// Returns true if the user selected the "Not OK" button of a confirmation dialog.
+    pub fn is_not_confirmed(&self) -> bool {
+        matches!(self, Error::NotConfirmed)
+    }
+
+    /// Returns the GPG error code, if this is an uncommon or unexpected GPG error.
+    pub fn gpg_code(&self) -> Option<u16> {
+        match self {
+            Error::Gpg(e) => Some(e.code()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn from_parts(value: u32, description: Option<String>) -> Self {
+        let code = (value & GPG_ERR_CODE_MASK) as u16;
+        let source = ((value >> GPG_ERR_SOURCE_SHIFT) & GPG_ERR_SOURCE_MASK) as u8;
+
         match code {
             GPG_ERR_TIMEOUT => Error::Timeout,
             GPG_ERR_CANCELED => Error::Cancelled,
-            _ => Error::Gpg(GpgError::new(code, description)),
+            GPG_ERR_NOT_CONFIRMED => Error::NotConfirmed,
+            _ => Error::Gpg(
+                GpgError::new(code, description).with_source(ErrorSource::from_value(source)),
+            ),
+        }
+    }
+
+    /// Records the command that this error occurred in response to.
+    pub(crate) fn in_response_to(self, command: &str) -> Self {
+        match self {
+            Error::Gpg(e) => Error::Gpg(GpgError {
+                command: Some(command.to_owned()),
+                ..e
+            }),
+            e => e,
+        }
+    }
+
+    /// Returns true if this error was caused by the `pinentry` binary exiting
+    /// unexpectedly.
+    pub(crate) fn is_exited(&self) -> bool {
+        matches!(
+            self,
+            Error::Io(e) if matches!(
+                e.kind(),
+                io::ErrorKind::BrokenPipe | io::ErrorKind::UnexpectedEof
+            )
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, ErrorSource, GpgError, ProtocolError, UnavailableReason, GPG_ERR_ENOTTY};
+
+    #[test]
+    fn protocol_error_redaction() {
+        let e = ProtocolError::new(Some("GETPIN"), "D hunter2%\n");
+        assert_eq!(e.command(), Some("GETPIN"));
+        assert_eq!(e.line(), "D [REDACTED]");
+
+        let e = ProtocolError::new(None, "Hello there\r\n");
+        assert_eq!(e.command(), None);
+        assert_eq!(e.line(), "Hello there");
+
+        let e = ProtocolError::new(None, &"x".repeat(100));
+        assert_eq!(e.line(), format!("{}...", "x".repeat(64)));
+    }
+
+    #[test]
+    fn unavailable_detection() {
+        assert_eq!(
+            UnavailableReason::detect("Gtk-WARNING **: cannot open display: :0"),
+            Some(UnavailableReason::NoDisplay)
+        );
+        assert_eq!(
+            UnavailableReason::from_gpg(&GpgError::new(GPG_ERR_ENOTTY, None)),
+            Some(UnavailableReason::NoTerminal)
+        );
+        assert_eq!(
+            UnavailableReason::from_gpg(&GpgError::new(
+                257,
+                Some("Inappropriate ioctl for device".into())
+            )),
+            Some(UnavailableReason::NoTerminal)
+        );
+        assert_eq!(UnavailableReason::from_gpg(&GpgError::new(257, None)), None);
+
+        assert_eq!(
+            UnavailableReason::detect("can't open /dev/tty: No such device or address"),
+            Some(UnavailableReason::NoTerminal)
+        );
+        // Other errors that merely mention the terminal are not reclassified.
+        assert_eq!(
+            UnavailableReason::detect("can't open /dev/tty: Permission denied"),
+            None
+        );
+    }
+
+    #[test]
+    fn error_source() {
+        match Error::from_parts(83918950, Some("Inappropriate ioctl for device".into())) {
+            Error::Gpg(e) => {
+                assert_eq!(e.code(), GPG_ERR_ENOTTY);
+                assert_eq!(e.error_source(), ErrorSource::Pinentry);
+                assert_eq!(
+                    e.to_string(),
+                    "Code 32870: Inappropriate ioctl for device <Pinentry>"
+                );
+            }
+            e => panic!("Unexpected error: {:?}", e),
         }
+        assert!(Error::from_parts(83886179, None).is_cancelled());
+    }
+
+    #[test]
+    fn canonical_description() {
+        assert_eq!(
+            GpgError::new(275, None).to_string(),
+            "Code 275: Unknown IPC command"
+        );
+        assert_eq!(
+            GpgError::new(275, Some("Unknown command".into())).to_string(),
+            "Code 275: Unknown command"
+        );
+        assert_eq!(GpgError::new(272, None).to_string(), "Code 272");
     }
 }
@@ -0,0 +1,114 @@
+use std::process::ExitStatus;
+
+use crate::{assuan, PromptOutcome, Result};
+
+/// An event in the lifecycle of a prompt.
+///
+/// Set on a dialog with its `with_event_hook` method, these events can be forwarded by
+/// embedding agents to their own clients (similar to the `PINENTRY_LAUNCHED` status
+/// message of `gpg-agent`).
+///
+/// When a dialog is shown in a [`PinentrySession`], the `pinentry` process belongs to
+/// the session, so only [`LifecycleEvent::Configured`], [`LifecycleEvent::Prompted`],
+/// and [`LifecycleEvent::ResultReceived`] are emitted.
+///
+/// [`PinentrySession`]: crate::PinentrySession
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LifecycleEvent<'e> {
+    /// The `pinentry` binary was launched.
+    Launched {
+        /// The process ID of the `pinentry` process.
+        pid: u32,
+        /// The flavor of the `pinentry` binary (for example, `curses` or `gtk2`), if it
+        /// reports one.
+        flavor: Option<&'e str>,
+    },
+    /// The dialog's settings were sent to the `pinentry` binary.
+    Configured,
+    /// The prompt (for example, `GETPIN`) was issued.
+    Prompted,
+    /// The result of the prompt was received.
+    ResultReceived {
+        /// How the prompt finished.
+        outcome: PromptOutcome,
+    },
+    /// The `pinentry` process was shut down.
+    Reaped {
+        /// The process ID of the `pinentry` process.
+        pid: u32,
+        /// The exit status of the `pinentry` process, if it exited cleanly.
+        status: Option<ExitStatus>,
+    },
+}
+
+/// A function that is called with each [`LifecycleEvent`] of a prompt.
+pub(crate) type EventHook<'a> = Option<&'a dyn Fn(&LifecycleEvent<'_>)>;
+
+pub(crate) fn emit(hook: EventHook<'_>, event: LifecycleEvent<'_>) {
+    if let Some(hook) = hook {
+        hook(&event);
+    }
+}
+
+/// The functions that suspend and resume the caller's terminal UI around a prompt.
+pub(crate) type TerminalHooks<'a> = Option<(&'a dyn Fn(), &'a dyn Fn())>;
+
+/// Resumes the caller's terminal UI when dropped.
+pub(crate) struct Suspended<'a> {
+    resume: Option<&'a dyn Fn()>,
+}
+
+impl<'a> Drop for Suspended<'a> {
+    fn drop(&mut self) {
+        if let Some(resume) = self.resume {
+            resume();
+        }
+    }
+}
+
+/// Suspends the caller's terminal UI until the returned guard is dropped, even if the
+/// prompt fails or panics.
+pub(crate) fn suspend(hooks: TerminalHooks<'_>) -> Suspended<'_> {
+    Suspended {
+        resume: hooks.map(|(suspend, resume)| {
+            suspend();
+            resume
+        }),
+    }
+}
+
+/// Runs `f` with a `pinentry` process owned by a dialog, and then shuts it down.
+pub(crate) fn run<T>(
+    hook: EventHook<'_>,
+    mut pinentry: assuan::Connection,
+    f: impl FnOnce(&mut assuan::Connection) -> Result<T>,
+) -> Result<T> {
+    let pid = pinentry.pid();
+    if let Some(hook) = hook {
+        let flavor = pinentry.flavor();
+        hook(&LifecycleEvent::Launched {
+            pid,
+            flavor: flavor.as_deref(),
+        });
+    }
+
+    let res = f(&mut pinentry);
+
+    let status = pinentry.shutdown();
+    emit(hook, LifecycleEvent::Reaped { pid, status });
+    res
+}
+
+/// Issues the prompt `f`.
+pub(crate) fn prompt<T>(hook: EventHook<'_>, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    emit(hook, LifecycleEvent::Prompted);
+    let res = f();
+    emit(
+        hook,
+        LifecycleEvent::ResultReceived {
+            outcome: PromptOutcome::of(&res),
+        },
+    );
+    res
+}
@@ -0,0 +1,247 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+
+use secrecy::SecretString;
+
+use crate::{Error, FallbackError, PassphraseInput, PassphraseProvider, PassphraseRequest, Result};
+
+/// A source of passphrases in a [`FallbackChain`].
+enum Source<'a> {
+    Pinentry(Box<PassphraseInput<'a>>),
+    EnvVar(&'a str),
+    Provider(Box<dyn PassphraseProvider + 'a>),
+}
+
+/// An ordered list of sources of passphrases, which are tried in turn until one of them
+/// returns a passphrase.
+///
+/// Each source is given a label, which is used to report why it failed. If the user
+/// cancels a prompt (so that a source returns [`Error::Cancelled`]), the chain stops
+/// there rather than prompting again with the next source. If every source fails, the
+/// chain returns [`Error::Exhausted`] with the error from each source.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{FallbackChain, PassphraseInput, PassphraseRequest};
+///
+/// # fn keyring_lookup(_: &PassphraseRequest<'_>) -> pinentry::Result<pinentry::SecretString> { unimplemented!() }
+/// let mut chain = FallbackChain::new();
+/// chain.with_provider("keyring", keyring_lookup);
+/// if let Some(input) = PassphraseInput::with_default_binary() {
+///     chain.with_pinentry(input);
+/// }
+/// chain.with_env_var("FOOBAR_PASSPHRASE");
+///
+/// let passphrase = chain.interact(PassphraseRequest::new().with_description("Unlock FooBar"))?;
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+#[derive(Default)]
+pub struct FallbackChain<'a> {
+    sources: Vec<(Cow<'a, str>, Source<'a>)>,
+}
+
+impl<'a> fmt::Debug for FallbackChain<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FallbackChain")
+            .field(
+                "sources",
+                &self
+                    .sources
+                    .iter()
+                    .map(|(label, _)| label)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl<'a> FallbackChain<'a> {
+    /// Creates an empty chain.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a source (labelled `pinentry`) that shows the given dialog.
+    ///
+    /// This source fails without showing the dialog if the dialog reports that it
+    /// cannot interact (see [`PassphraseInput::can_interact`]).
+    pub fn with_pinentry(&mut self, input: PassphraseInput<'a>) -> &mut Self {
+        self.sources
+            .push((Cow::Borrowed("pinentry"), Source::Pinentry(Box::new(input))));
+        self
+    }
+
+    /// Adds a source (labelled with the variable's name) that reads the passphrase from
+    /// the given environment variable.
+    ///
+    /// This source fails if the variable is unset, empty, or not valid UTF-8.
+    pub fn with_env_var(&mut self, var: &'a str) -> &mut Self {
+        self.sources.push((Cow::Borrowed(var), Source::EnvVar(var)));
+        self
+    }
+
+    /// Adds a source with the given label that asks the given provider (such as a
+    /// closure that looks the passphrase up in a keyring).
+    pub fn with_provider(
+        &mut self,
+        label: impl Into<Cow<'a, str>>,
+        provider: impl PassphraseProvider + 'a,
+    ) -> &mut Self {
+        self.sources
+            .push((label.into(), Source::Provider(Box::new(provider))));
+        self
+    }
+
+    /// Tries each source in turn, and returns the first passphrase that one of them
+    /// returns.
+    pub fn interact(&self, request: &PassphraseRequest<'_>) -> Result<SecretString> {
+        let mut attempts = vec![];
+        for (label, source) in &self.sources {
+            let res = match source {
+                Source::Pinentry(input) if input.can_interact() => input.passphrase(request),
+                Source::Pinentry(_) => Err(Error::Io(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "cannot show a dialog",
+                ))),
+                Source::EnvVar(var) => env_passphrase(var),
+                Source::Provider(provider) => provider.passphrase(request),
+            };
+            match res {
+                Ok(passphrase) => return Ok(passphrase),
+                Err(Error::Cancelled) => return Err(Error::Cancelled),
+                Err(e) => attempts.push((label.to_string(), e)),
+            }
+        }
+        Err(Error::Exhausted(FallbackError::new(attempts)))
+    }
+}
+
+impl<'a> PassphraseProvider for FallbackChain<'a> {
+    fn passphrase(&self, request: &PassphraseRequest<'_>) -> Result<SecretString> {
+        self.interact(request)
+    }
+}
+
+/// Reads a passphrase from the given environment variable.
+fn env_passphrase(var: &str) -> Result<SecretString> {
+    match std::env::var_os(var) {
+        Some(value) if !value.is_empty() => {
+            value.into_string().map(SecretString::from).map_err(|_| {
+                Error::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "not valid UTF-8",
+                ))
+            })
+        }
+        _ => Err(Error::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "not set",
+        ))),
+    }
+}
+
+/// A [`PassphraseProvider`] that shows a [`PassphraseInput`] when a `pinentry` binary
+/// is available, and otherwise prompts on the terminal with [`dialoguer::Password`].
+///
+/// The terminal is used if no dialog was given (for example, because
+/// [`PassphraseInput::with_default_binary`] returned `None`), or if the dialog reports
+/// that it cannot interact (see [`PassphraseInput::can_interact`]). The description and
+/// any error of the request are printed to stderr before the terminal prompt.
+///
+/// This is enabled by the `dialoguer` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{DialoguerFallback, PassphraseInput, PassphraseProvider, PassphraseRequest};
+///
+/// let provider = DialoguerFallback::new(PassphraseInput::with_default_binary());
+/// let passphrase =
+///     provider.passphrase(PassphraseRequest::new().with_description("Unlock key FooBar"))?;
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+///
+/// [`dialoguer::Password`]: https://docs.rs/dialoguer/0.10/dialoguer/struct.Password.html
+#[cfg(feature = "dialoguer")]
+#[derive(Clone, Debug)]
+pub struct DialoguerFallback<'a> {
+    input: Option<PassphraseInput<'a>>,
+}
+
+#[cfg(feature = "dialoguer")]
+impl<'a> DialoguerFallback<'a> {
+    /// Creates a provider that shows the given dialog if possible, and otherwise
+    /// prompts on the terminal.
+    pub fn new(input: Option<PassphraseInput<'a>>) -> Self {
+        DialoguerFallback { input }
+    }
+}
+
+#[cfg(feature = "dialoguer")]
+impl<'a> PassphraseProvider for DialoguerFallback<'a> {
+    fn passphrase(&self, request: &PassphraseRequest<'_>) -> Result<SecretString> {
+        match &self.input {
+            Some(input) if input.can_interact() => input.passphrase(request),
+            _ => terminal_passphrase(request),
+        }
+    }
+}
+
+/// Prompts for a passphrase on the terminal with `dialoguer`.
+#[cfg(feature = "dialoguer")]
+fn terminal_passphrase(request: &PassphraseRequest<'_>) -> Result<SecretString> {
+    let term = dialoguer::console::Term::stderr();
+    for text in [request.description(), request.error()].iter().flatten() {
+        term.write_line(text)?;
+    }
+
+    let mut password = dialoguer::Password::new();
+    password.with_prompt(request.prompt().unwrap_or("Passphrase"));
+    if let Some(confirmation_prompt) = request.confirmation_prompt() {
+        password.with_confirmation(
+            confirmation_prompt,
+            &*crate::DefaultText::PassphraseMismatch.builtin(),
+        );
+    }
+    Ok(SecretString::from(password.interact()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::{ExposeSecret, SecretString};
+
+    use super::FallbackChain;
+    use crate::{Error, PassphraseRequest};
+
+    #[test]
+    fn chain() {
+        let request = PassphraseRequest::new();
+        let failing = |_: &PassphraseRequest<'_>| Err(Error::Timeout);
+        let cancelled = |_: &PassphraseRequest<'_>| Err(Error::Cancelled);
+        let working = |_: &PassphraseRequest<'_>| Ok(SecretString::from("hunter2"));
+
+        let mut chain = FallbackChain::new();
+        chain
+            .with_provider("failing", failing)
+            .with_env_var("PINENTRY_RS_TEST_UNSET_PASSPHRASE");
+        match chain.interact(&request) {
+            Err(Error::Exhausted(e)) => {
+                let labels: Vec<_> = e.attempts().iter().map(|(label, _)| label).collect();
+                assert_eq!(labels, ["failing", "PINENTRY_RS_TEST_UNSET_PASSPHRASE"]);
+                assert!(matches!(e.attempts()[0].1, Error::Timeout));
+            }
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+
+        chain.with_provider("working", working);
+        assert_eq!(chain.interact(&request).unwrap().expose_secret(), "hunter2");
+
+        let mut chain = FallbackChain::new();
+        chain
+            .with_provider("cancelled", cancelled)
+            .with_provider("working", working);
+        assert!(matches!(chain.interact(&request), Err(Error::Cancelled)));
+    }
+}
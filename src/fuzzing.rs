@@ -0,0 +1,39 @@
+//! Direct access to the Assuan protocol layer, for fuzz targets and property tests.
+//!
+//! This module is enabled by the `fuzzing` feature, and is not covered by semver
+//! guarantees: it may change in any release.
+//!
+//! # Examples
+//!
+//! A `cargo fuzz` target for the response parser:
+//!
+//! ```ignore
+//! #![no_main]
+//! use libfuzzer_sys::fuzz_target;
+//!
+//! fuzz_target!(|line: &str| {
+//!     let _ = pinentry::fuzzing::parse_response(line);
+//! });
+//! ```
+
+pub use crate::assuan::Response;
+
+use crate::Result;
+
+/// Parses a single response line from a `pinentry` binary, including its line ending.
+///
+/// Returns `None` if the line is not a valid response.
+pub fn parse_response(line: &str) -> Option<Response<'_>> {
+    crate::assuan::parse_response(line)
+}
+
+/// Encodes a request, percent-encoding its parameters and appending a line ending.
+///
+/// Unlike the requests sent to `pinentry` binaries, the returned string is not wiped
+/// when it is dropped.
+///
+/// Returns an error if the encoded request would be longer than the 1000 bytes that
+/// Assuan allows.
+pub fn encode_request(command: &str, parameters: Option<&str>) -> Result<String> {
+    crate::assuan::encode_request(command, parameters).map(|request| request.to_string())
+}
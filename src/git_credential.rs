@@ -0,0 +1,238 @@
+//! The `git` credential helper protocol, answered with passphrase prompts.
+//!
+//! `git` runs credential helpers with an action (`get`, `store`, or `erase`) as their
+//! argument, and writes the attributes of the credential (such as `protocol=https`
+//! and `host=example.com`) to their stdin, one `key=value` per line. For `get`, the
+//! helper prints the attributes that it knows to stdout in the same format. See
+//! [`gitcredentials(7)`] for details.
+//!
+//! [`Helper`] answers `get` by asking a [`PassphraseProvider`] (usually a
+//! [`PassphraseInput`]) for the password. It does not remember passwords, so it ignores
+//! `store` and `erase`; to avoid prompting for every operation, configure a caching
+//! helper (such as `git credential-cache`) before it:
+//!
+//! ```text
+//! [credential]
+//!     helper = cache
+//!     helper = pinentry
+//! ```
+//!
+//! The `git-credential-pinentry` binary (built with the `git-credential` feature) runs
+//! a `Helper` with the first `pinentry` binary found on `PATH`.
+//!
+//! [`gitcredentials(7)`]: https://git-scm.com/docs/gitcredentials
+//! [`PassphraseInput`]: crate::PassphraseInput
+
+use std::io::{self, BufRead, Write};
+
+use secrecy::ExposeSecret;
+
+use crate::{Error, PassphraseProvider, PassphraseRequest, Result};
+
+/// The attributes of a credential, as sent by `git` to a credential helper.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Credential {
+    attributes: Vec<(String, String)>,
+}
+
+impl Credential {
+    /// Reads a credential description from the given reader, up to a blank line or the
+    /// end of input.
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidData`] if a line is not of the
+    /// form `key=value`.
+    pub fn read(reader: &mut impl BufRead) -> io::Result<Self> {
+        let mut attributes = vec![];
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            if line.is_empty() {
+                break;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "expected key=value"))?;
+            attributes.push((key.to_owned(), value.to_owned()));
+        }
+        Ok(Credential { attributes })
+    }
+
+    /// Returns the value of the given attribute, if it was sent.
+    ///
+    /// If the attribute was sent more than once, the last value is returned (as `git`
+    /// itself does).
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the protocol over which the credential will be used (such as `https`).
+    pub fn protocol(&self) -> Option<&str> {
+        self.get("protocol")
+    }
+
+    /// Returns the remote hostname (including the port, if one was specified).
+    pub fn host(&self) -> Option<&str> {
+        self.get("host")
+    }
+
+    /// Returns the path of the remote repository, if `credential.useHttpPath` is set.
+    pub fn path(&self) -> Option<&str> {
+        self.get("path")
+    }
+
+    /// Returns the username, if `git` already knows it.
+    pub fn username(&self) -> Option<&str> {
+        self.get("username")
+    }
+
+    /// Returns a URL identifying the credential (such as
+    /// `https://user@example.com/repo.git`), to show to the user.
+    pub fn url(&self) -> String {
+        let mut url = String::new();
+        if let Some(protocol) = self.protocol() {
+            url.push_str(protocol);
+            url.push_str("://");
+        }
+        if let Some(username) = self.username() {
+            url.push_str(username);
+            url.push('@');
+        }
+        url.push_str(self.host().unwrap_or_default());
+        if let Some(path) = self.path() {
+            url.push('/');
+            url.push_str(path);
+        }
+        url
+    }
+}
+
+/// A `git` credential helper that asks a [`PassphraseProvider`] for passwords.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{git_credential::Helper, PassphraseInput};
+///
+/// if let Some(input) = PassphraseInput::with_default_binary() {
+///     let action = std::env::args().nth(1).unwrap_or_default();
+///     Helper::new(&input).run(&action, &mut std::io::stdin().lock(), &mut std::io::stdout())?;
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+pub struct Helper<'a> {
+    provider: &'a dyn PassphraseProvider,
+}
+
+impl<'a> Helper<'a> {
+    /// Creates a credential helper that asks the given provider for passwords.
+    pub fn new(provider: &'a dyn PassphraseProvider) -> Self {
+        Helper { provider }
+    }
+
+    /// Performs the given action, reading the credential description from `input` and
+    /// writing any response to `output`.
+    ///
+    /// For `get`, the user is asked for the password of the credential. If they cancel
+    /// the prompt, `quit=1` is printed so that `git` does not fall back to prompting on
+    /// the terminal. If `git` already knows the password, nothing is printed. Other
+    /// actions are ignored.
+    ///
+    /// Returns [`Error::Io`] if reading or writing fails, or if the password contains a
+    /// line ending or NUL character (which the protocol cannot represent).
+    pub fn run(
+        &self,
+        action: &str,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> Result<()> {
+        let credential = Credential::read(input)?;
+        if action != "get" || credential.get("password").is_some() {
+            return Ok(());
+        }
+
+        let description = match credential.username() {
+            Some(_) => format!("Enter the password for {}", credential.url()),
+            None => format!("Enter the password or token for {}", credential.url()),
+        };
+        let res = self.provider.passphrase(
+            PassphraseRequest::new()
+                .with_description(description)
+                .with_prompt("Password:"),
+        );
+        match res {
+            Ok(password) => {
+                let password = password.expose_secret();
+                if password.contains(&['\n', '\r', '\0'][..]) {
+                    return Err(Error::Io(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "password contains a line ending or NUL character",
+                    )));
+                }
+                output.write_all(b"password=")?;
+                output.write_all(password.as_bytes())?;
+                output.write_all(b"\n")?;
+            }
+            Err(Error::Cancelled) => output.write_all(b"quit=1\n")?,
+            Err(e) => return Err(e),
+        }
+        output.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::SecretString;
+
+    use super::{Credential, Helper};
+    use crate::{Error, PassphraseRequest};
+
+    const INPUT: &[u8] = b"protocol=https\nhost=example.com\nusername=alice\n\nignored=1\n";
+
+    #[test]
+    fn read() {
+        let credential = Credential::read(&mut &*INPUT).unwrap();
+        assert_eq!(credential.host(), Some("example.com"));
+        assert_eq!(credential.path(), None);
+        assert_eq!(credential.get("ignored"), None);
+        assert_eq!(credential.url(), "https://alice@example.com");
+        assert!(Credential::read(&mut &b"protocol\n"[..]).is_err());
+    }
+
+    #[test]
+    fn get() {
+        let provider = |request: &PassphraseRequest<'_>| {
+            assert_eq!(
+                request.description(),
+                Some("Enter the password for https://alice@example.com")
+            );
+            Ok(SecretString::from("hunter2"))
+        };
+        let mut output = vec![];
+        Helper::new(&provider)
+            .run("get", &mut &*INPUT, &mut output)
+            .unwrap();
+        assert_eq!(output, b"password=hunter2\n");
+
+        let mut output = vec![];
+        Helper::new(&provider)
+            .run("store", &mut &*INPUT, &mut output)
+            .unwrap();
+        assert!(output.is_empty());
+
+        let cancelled = |_: &PassphraseRequest<'_>| Err(Error::Cancelled);
+        let mut output = vec![];
+        Helper::new(&cancelled)
+            .run("get", &mut &*INPUT, &mut output)
+            .unwrap();
+        assert_eq!(output, b"quit=1\n");
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt;
+
+/// A version of a `pinentry` binary, as reported by `GETINFO version`.
+///
+/// Versions are compared by their major, minor, and patch numbers, in that order.
+/// Pre-release and build suffixes (such as the `-beta42` of `1.1.1-beta42`) are
+/// ignored.
+///
+/// # Examples
+///
+/// ```
+/// use pinentry::PinentryVersion;
+///
+/// let version = PinentryVersion::parse("1.2.1").unwrap();
+/// assert!(version >= PinentryVersion::new(0, 9, 7));
+/// assert!(version.at_least(1, 2, 0));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PinentryVersion {
+    major: u32,
+    minor: u32,
+    patch: u32,
+}
+
+impl PinentryVersion {
+    /// Creates a version from its components.
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        PinentryVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parses a version of the form `major[.minor[.patch]][suffix]`, where any missing
+    /// component is zero.
+    ///
+    /// Returns `None` if the string does not start with a number.
+    pub fn parse(version: &str) -> Option<Self> {
+        let end = version
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(version.len());
+        let mut components = version[..end].split('.').map(|c| c.parse::<u32>());
+        let major = components.next()?.ok()?;
+        let mut next = || match components.next() {
+            Some(Ok(component)) => Some(component),
+            Some(Err(_)) => None,
+            None => Some(0),
+        };
+        let minor = next()?;
+        let patch = next()?;
+        Some(PinentryVersion::new(major, minor, patch))
+    }
+
+    /// Returns the major version.
+    pub fn major(&self) -> u32 {
+        self.major
+    }
+
+    /// Returns the minor version.
+    pub fn minor(&self) -> u32 {
+        self.minor
+    }
+
+    /// Returns the patch version.
+    pub fn patch(&self) -> u32 {
+        self.patch
+    }
+
+    /// Returns true if this version is the given version or later.
+    pub fn at_least(&self, major: u32, minor: u32, patch: u32) -> bool {
+        *self >= PinentryVersion::new(major, minor, patch)
+    }
+}
+
+impl fmt::Display for PinentryVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// What a `pinentry` binary reports about itself.
+///
+/// This is obtained from a [`PinentrySession`] with [`PinentrySession::info`], and can
+/// be used to only use features that the binary supports.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{PassphraseInput, PinentrySession};
+///
+/// if let Some(mut session) = PinentrySession::with_default_binary()? {
+///     // Repeating the passphrase needs pinentry 0.9.7 or later.
+///     let repeat = session.info().version_at_least(0, 9, 7);
+///     if let Some(mut input) = PassphraseInput::with_default_binary() {
+///         input.with_description("Choose a passphrase for FooBar");
+///         if repeat {
+///             input.with_confirmation("Repeat:", "Passphrases do not match");
+///         }
+///         let passphrase = input.interact_in(&mut session)?;
+///     }
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+///
+/// [`PinentrySession`]: crate::PinentrySession
+/// [`PinentrySession::info`]: crate::PinentrySession::info
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PinentryInfo {
+    flavor: Option<String>,
+    version: Option<PinentryVersion>,
+}
+
+impl PinentryInfo {
+    pub(crate) fn new(flavor: Option<String>, version: Option<&str>) -> Self {
+        PinentryInfo {
+            flavor,
+            version: version.and_then(PinentryVersion::parse),
+        }
+    }
+
+    /// Returns the flavor of the `pinentry` binary (for example, `curses` or `gtk2`), if
+    /// it reports one.
+    ///
+    /// Binaries that fall back to a terminal when there is no display report both
+    /// flavors (for example, `gtk2:curses`).
+    pub fn flavor(&self) -> Option<&str> {
+        self.flavor.as_deref()
+    }
+
+    /// Returns the version of the `pinentry` binary, if it reports one that can be
+    /// parsed.
+    pub fn version(&self) -> Option<PinentryVersion> {
+        self.version
+    }
+
+    /// Returns true if the `pinentry` binary reports the given version or later.
+    ///
+    /// Returns false if the binary does not report a version.
+    pub fn version_at_least(&self, major: u32, minor: u32, patch: u32) -> bool {
+        self.version
+            .map_or(false, |version| version.at_least(major, minor, patch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PinentryInfo, PinentryVersion};
+
+    #[test]
+    fn version() {
+        assert_eq!(
+            PinentryVersion::parse("1.2.1"),
+            Some(PinentryVersion::new(1, 2, 1))
+        );
+        assert_eq!(
+            PinentryVersion::parse("1.1.1-beta42"),
+            Some(PinentryVersion::new(1, 1, 1))
+        );
+        assert_eq!(
+            PinentryVersion::parse("2"),
+            Some(PinentryVersion::new(2, 0, 0))
+        );
+        assert_eq!(PinentryVersion::parse("unknown"), None);
+        assert_eq!(PinentryVersion::parse("1..2"), None);
+
+        assert!(PinentryVersion::new(0, 10, 0) > PinentryVersion::new(0, 9, 7));
+        assert!(PinentryVersion::new(0, 9, 7).at_least(0, 9, 7));
+        assert!(!PinentryVersion::new(0, 9, 6).at_least(0, 9, 7));
+        assert_eq!(PinentryVersion::new(1, 2, 1).to_string(), "1.2.1");
+
+        assert!(PinentryInfo::new(None, Some("1.0.0")).version_at_least(0, 9, 7));
+        assert!(!PinentryInfo::new(None, Some("unknown")).version_at_least(0, 0, 0));
+    }
+}
@@ -0,0 +1,177 @@
+//! Deriving keys from passphrases, enabled by the `kdf` feature.
+//!
+//! Most applications that ask for a passphrase only need it to derive an encryption
+//! key. [`PassphraseInput::interact_derive_key`] asks for the passphrase and passes it
+//! straight to a [`Kdf`], so that only the derived key is returned and the passphrase
+//! itself is wiped before the call returns.
+//!
+//! [`PassphraseInput::interact_derive_key`]: crate::PassphraseInput::interact_derive_key
+
+use std::fmt;
+use std::io;
+
+/// The capacity of the buffer that passphrases are decoded into by
+/// [`PassphraseInput::interact_derive_key`], unless a maximum length is set.
+///
+/// Lines from `pinentry` binaries are at most 1000 bytes long, so this is enough for
+/// any passphrase (even once transcoded into UTF-8).
+///
+/// [`PassphraseInput::interact_derive_key`]: crate::PassphraseInput::interact_derive_key
+pub(crate) const DEFAULT_MAX_LENGTH: usize = 2048;
+
+/// A password-based key derivation function, with its cost parameters.
+///
+/// The parameters (and the salt given when deriving a key) must be stored alongside
+/// whatever the key encrypts, so that the same key can be derived again.
+///
+/// # Examples
+///
+/// ```
+/// use pinentry::kdf::Kdf;
+///
+/// // 64 MiB of memory, 3 iterations, and 1 lane.
+/// let kdf = Kdf::argon2id(64 * 1024, 3, 1);
+///
+/// let mut key = [0; 32];
+/// kdf.derive(b"hunter2", b"per-file salt", &mut key)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Kdf {
+    inner: Inner,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Inner {
+    Argon2id {
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    },
+    Scrypt {
+        log_n: u8,
+        r: u32,
+        p: u32,
+    },
+}
+
+impl fmt::Debug for Kdf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+/// A [`Kdf`] whose parameters have been checked, ready to derive a key.
+pub(crate) enum Prepared {
+    Argon2id(argon2::Argon2<'static>),
+    Scrypt(scrypt::Params),
+}
+
+impl Kdf {
+    /// Argon2id (version 0x13), with the given memory size (in KiB), number of
+    /// iterations, and degree of parallelism.
+    pub fn argon2id(m_cost: u32, t_cost: u32, p_cost: u32) -> Self {
+        Kdf {
+            inner: Inner::Argon2id {
+                m_cost,
+                t_cost,
+                p_cost,
+            },
+        }
+    }
+
+    /// scrypt, with the given cost (as a power of two), block size, and degree of
+    /// parallelism.
+    pub fn scrypt(log_n: u8, r: u32, p: u32) -> Self {
+        Kdf {
+            inner: Inner::Scrypt { log_n, r, p },
+        }
+    }
+
+    /// Derives a key from `passphrase` and `salt`, filling `key`.
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidInput`] if the parameters are
+    /// invalid, the salt is too short (Argon2id needs at least 8 bytes), or `key` has
+    /// an unsupported length.
+    pub fn derive(&self, passphrase: &[u8], salt: &[u8], key: &mut [u8]) -> io::Result<()> {
+        self.prepare(salt, key.len())?.derive(passphrase, salt, key)
+    }
+
+    /// Checks the parameters, so that they can be rejected before the user is asked for
+    /// a passphrase.
+    pub(crate) fn prepare(&self, salt: &[u8], key_len: usize) -> io::Result<Prepared> {
+        match self.inner {
+            Inner::Argon2id {
+                m_cost,
+                t_cost,
+                p_cost,
+            } => {
+                if salt.len() < argon2::MIN_SALT_LEN {
+                    return Err(invalid("salt is too short"));
+                }
+                let params =
+                    argon2::Params::new(m_cost, t_cost, p_cost, Some(key_len)).map_err(invalid)?;
+                Ok(Prepared::Argon2id(argon2::Argon2::new(
+                    argon2::Algorithm::Argon2id,
+                    argon2::Version::V0x13,
+                    params,
+                )))
+            }
+            Inner::Scrypt { log_n, r, p } => {
+                let params = scrypt::Params::new(log_n, r, p, key_len).map_err(invalid)?;
+                Ok(Prepared::Scrypt(params))
+            }
+        }
+    }
+}
+
+impl Prepared {
+    pub(crate) fn derive(&self, passphrase: &[u8], salt: &[u8], key: &mut [u8]) -> io::Result<()> {
+        match self {
+            Prepared::Argon2id(argon2) => argon2
+                .hash_password_into(passphrase, salt, key)
+                .map_err(invalid),
+            Prepared::Scrypt(params) => {
+                scrypt::scrypt(passphrase, salt, params, key).map_err(invalid)
+            }
+        }
+    }
+}
+
+fn invalid(e: impl fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Kdf;
+
+    #[test]
+    fn derive() {
+        for kdf in [Kdf::argon2id(64, 1, 1), Kdf::scrypt(4, 8, 1)] {
+            let mut key = [0; 32];
+            kdf.derive(b"hunter2", b"saltsaltsalt", &mut key).unwrap();
+            let mut again = [0; 32];
+            kdf.derive(b"hunter2", b"saltsaltsalt", &mut again).unwrap();
+            assert_eq!(key, again);
+            kdf.derive(b"hunter2", b"peppersalt", &mut again).unwrap();
+            assert_ne!(key, again);
+            kdf.derive(b"hunter3", b"saltsaltsalt", &mut again).unwrap();
+            assert_ne!(key, again);
+        }
+
+        let mut key = [0; 32];
+        assert!(Kdf::argon2id(64, 1, 1)
+            .derive(b"hunter2", b"salt", &mut key)
+            .is_err());
+        assert!(Kdf::argon2id(64, 0, 1)
+            .derive(b"hunter2", b"saltsaltsalt", &mut key)
+            .is_err());
+        assert!(Kdf::scrypt(4, 0, 1)
+            .derive(b"hunter2", b"saltsaltsalt", &mut key)
+            .is_err());
+        assert!(Kdf::argon2id(64, 1, 1)
+            .derive(b"hunter2", b"saltsaltsalt", &mut [])
+            .is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! Answering SSH `keyboard-interactive` (and PAM conversation) prompts with dialogs.
+
+use std::borrow::Cow;
+
+use secrecy::{ExposeSecret, SecretString};
+use zeroize::Zeroizing;
+
+use crate::{ConfirmationDialog, PassphraseInput, Result};
+
+/// An adapter that walks the user through a round of `keyboard-interactive`
+/// questions.
+///
+/// SSH servers (and PAM modules) ask a list of questions, each with a prompt and a flag
+/// saying whether the answer should be echoed as it is typed. `pinentry` binaries can
+/// only read hidden input, so:
+///
+/// - Questions whose answers are not echoed (such as passwords) are asked with the
+///   passphrase dialog.
+/// - Questions whose answers are echoed (such as usernames) are also asked with the
+///   passphrase dialog, and the answer is then shown in the confirmation dialog so
+///   that the user can check it. If the user does not confirm it, the question is
+///   asked again.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{keyboard_interactive::KeyboardInteractive, ConfirmationDialog, PassphraseInput};
+///
+/// if let (Some(input), Some(confirmation)) = (
+///     PassphraseInput::with_default_binary(),
+///     ConfirmationDialog::with_default_binary(),
+/// ) {
+///     let answers = KeyboardInteractive::new(input, confirmation).respond(
+///         "example.com",
+///         "Two-factor authentication is required.",
+///         &[("Password: ", false), ("Verification code: ", true)],
+///     )?;
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct KeyboardInteractive<'a> {
+    input: PassphraseInput<'a>,
+    confirmation: ConfirmationDialog<'a>,
+}
+
+impl<'a> KeyboardInteractive<'a> {
+    /// Creates an adapter that asks each question with the given passphrase dialog, and
+    /// checks echoed answers with the given confirmation dialog.
+    ///
+    /// The texts that [`KeyboardInteractive::respond`] sets replace any that are set on
+    /// the dialogs; anything else (such as timeouts and platform-specific options) is
+    /// kept.
+    pub fn new(input: PassphraseInput<'a>, confirmation: ConfirmationDialog<'a>) -> Self {
+        KeyboardInteractive {
+            input,
+            confirmation,
+        }
+    }
+
+    /// Asks the given questions (each a prompt, and whether its answer would normally
+    /// be echoed) in order, and returns their answers in the same order.
+    ///
+    /// The name (such as the server's hostname) is shown as the window title and the
+    /// instruction as the description of each dialog, unless they are empty. If there
+    /// are no questions, nothing is shown.
+    ///
+    /// Returns [`Error::Cancelled`] as soon as the user cancels any question, without
+    /// asking the remaining ones.
+    ///
+    /// [`Error::Cancelled`]: crate::Error::Cancelled
+    pub fn respond(
+        &self,
+        name: &str,
+        instruction: &str,
+        questions: &[(&str, bool)],
+    ) -> Result<Vec<SecretString>> {
+        let mut input = self.input.clone();
+        let mut confirmation = self.confirmation.clone();
+        if !name.is_empty() {
+            input.with_title(name.to_owned());
+            confirmation.with_title(name.to_owned());
+        }
+        if !instruction.is_empty() {
+            input.with_description(instruction.to_owned());
+        }
+
+        questions
+            .iter()
+            .map(|(prompt, echo)| {
+                input.with_prompt(Cow::Owned(prompt.trim_end().to_owned()));
+                loop {
+                    let answer = input.interact()?;
+                    if !echo || confirmation.confirm(&confirm_query(prompt, &answer))? {
+                        return Ok(answer);
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the query that shows an echoed answer, so that the user can check it.
+fn confirm_query(prompt: &str, answer: &SecretString) -> Zeroizing<String> {
+    Zeroizing::new(format!(
+        "{}\n\n{}\n\nIs this correct?",
+        prompt.trim_end(),
+        answer.expose_secret()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::SecretString;
+
+    use super::confirm_query;
+
+    #[test]
+    fn query() {
+        assert_eq!(
+            *confirm_query("Username: ", &SecretString::from("alice")),
+            "Username:\n\nalice\n\nIs this correct?"
+        );
+    }
+
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn respond() {
+        use secrecy::ExposeSecret;
+
+        use super::KeyboardInteractive;
+        use crate::{test_util::Harness, ConfirmationDialog, PassphraseInput};
+
+        let mut input_harness = Harness::new();
+        input_harness
+            .expect_options()
+            .expect("SETTITLE example.com", &["OK"])
+            .expect_prefix("SETPROMPT ", &["OK"])
+            .expect("GETPIN", &["D 123456", "OK"]);
+        let mut confirmation_harness = Harness::new();
+        confirmation_harness
+            .expect_options()
+            .expect("SETTITLE example.com", &["OK"])
+            .expect(
+                "SETDESC Verification code:%0A%0A123456%0A%0AIs this correct?",
+                &["OK"],
+            )
+            .expect("CONFIRM", &["OK"]);
+
+        let options = Harness::unix_options();
+        let mut input = PassphraseInput::with_binary(input_harness.path()).unwrap();
+        input.with_unix_options(&options);
+        let mut confirmation =
+            ConfirmationDialog::with_binary(confirmation_harness.path()).unwrap();
+        confirmation.with_unix_options(&options);
+
+        let answers = KeyboardInteractive::new(input, confirmation)
+            .respond(
+                "example.com",
+                "",
+                &[("Password: ", false), ("Verification code: ", true)],
+            )
+            .unwrap();
+        let answers: Vec<_> = answers.iter().map(|a| a.expose_secret()).collect();
+        assert_eq!(answers, ["123456", "123456"]);
+        confirmation_harness.assert_complete();
+        assert_eq!(
+            input_harness
+                .received()
+                .iter()
+                .filter(|line| line.starts_with("SETPROMPT"))
+                .collect::<Vec<_>>(),
+            ["SETPROMPT Password:", "SETPROMPT Verification code:"],
+        );
+    }
+}
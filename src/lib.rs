@@ -6,8 +6,7 @@
 //! ## Request passphrase or PIN
 //!
 //! ```no_run
-//! use pinentry::PassphraseInput;
-//! use secrecy::SecretString;
+//! use pinentry::{PassphraseInput, SecretString};
 //!
 //! let passphrase = if let Some(mut input) = PassphraseInput::with_default_binary() {
 //!     // pinentry binary is available!
@@ -48,41 +47,293 @@
 //! };
 //! # Ok::<(), pinentry::Error>(())
 //! ```
+//!
+//! # Feature flags
+//!
+//! - `log` (enabled by default): Sends diagnostic messages to the
+//!   [`log`](https://crates.io/crates/log) facade, unless another [`Diagnostics`] sink
+//!   is configured. When disabled, diagnostic messages are discarded by default.
+//! - `tracing`: Records a [`tracing`](https://crates.io/crates/tracing) span for each
+//!   request sent to `pinentry` binaries, with the command name, duration, and kind of
+//!   response (but never any request parameters or response data).
+//! - `fuzzing`: Enables the `fuzzing` module, which exposes the response parser and
+//!   request encoder (with [`arbitrary`](https://crates.io/crates/arbitrary)
+//!   implementations) for fuzz targets and property tests. It is not covered by semver
+//!   guarantees.
+//! - `diceware`: Enables the `diceware` module, which generates memorable passphrases
+//!   from a wordlist (the EFF's large wordlist is bundled), and
+//!   `PassphraseInput::with_generator`, which shows them with the "generate" button of
+//!   `pinentry` binaries that have one.
+//! - `kdf`: Enables the `kdf` module, and `PassphraseInput::interact_derive_key`,
+//!   which derives a key from the entered passphrase with Argon2id or scrypt and
+//!   returns only the key.
+//! - `askpass`: Builds the `pinentry-askpass` binary, which can be used as
+//!   `SSH_ASKPASS`, `SUDO_ASKPASS`, or `GIT_ASKPASS` to show their prompts with
+//!   `pinentry`.
+//! - `git-credential`: Builds the `git-credential-pinentry` binary, a `git` credential
+//!   helper (see [`git_credential`]) that asks for passwords with `pinentry`.
+//! - `kernel-keyring` (Linux only): Enables the `linux_keyring` module, a cache of
+//!   passphrases in the kernel keyring, so that short-lived programs need not prompt
+//!   every time they run.
+//! - `dialoguer`: Enables `DialoguerFallback`, which prompts on the terminal with
+//!   [`dialoguer`](https://crates.io/crates/dialoguer) when no `pinentry` binary is
+//!   available.
+//! - `serde`: Enables the `settings` module, with serializable settings for each kind
+//!   of dialog (via [`serde`](https://crates.io/crates/serde)).
+//! - `cli`: Builds the `pinentry-cli` binary, which shows dialogs from the command
+//!   line (run `pinentry-cli --help` for usage).
+//! - `test-pinentry`: Builds the `pinentry-test` binary, a minimal and deterministic
+//!   `pinentry` for integration tests (see its documentation for how it answers each
+//!   dialog).
+//! - `tty-pinentry`: Builds the `pinentry-rs-tty` binary, a minimal terminal-based
+//!   `pinentry` (Unix only), and makes dialogs created with `with_default_binary` fall
+//!   back to it when no `pinentry` binary can be found (see
+//!   [`PassphraseInput::with_default_binary`]).
+//! - `test-util`: Enables the `test_util` module, with a scripted fake `pinentry`
+//!   binary (Unix only) and golden transcript assertions for testing code that shows
+//!   dialogs.
+//! - `nom` (enabled by default): Parses responses from `pinentry` binaries with the
+//!   [`nom`](https://crates.io/crates/nom) crate. When disabled, a small hand-written
+//!   parser that accepts exactly the same responses is used instead.
+//! - `mlock`: Locks the memory used to read responses from `pinentry` binaries (which
+//!   contain passphrases) into RAM, and excludes it from core dumps where the platform
+//!   supports it.
 
 // Catch documentation errors caused by code changes.
 #![deny(rustdoc::broken_intra_doc_links)]
 #![deny(missing_docs)]
 
-use secrecy::SecretString;
+use std::borrow::Cow;
+use std::cell::Cell;
 use std::ffi::OsStr;
+use std::fmt;
 use std::path::PathBuf;
+use std::process::Child;
+use std::time::Duration;
 
+#[cfg(unix)]
+pub mod agent;
 mod assuan;
+pub mod bidi;
+mod buffer;
+mod charset;
+mod choice;
+mod coalesce;
+mod config;
+mod ct;
+mod diagnostics;
+#[cfg(feature = "diceware")]
+pub mod diceware;
+mod discovery;
+mod dismiss;
+pub mod encoding;
 mod error;
+mod events;
+mod fallback;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod git_credential;
+mod info;
+#[cfg(feature = "kdf")]
+pub mod kdf;
+pub mod keyboard_interactive;
+#[cfg(all(target_os = "linux", feature = "kernel-keyring"))]
+pub mod linux_keyring;
+mod locale;
+mod metrics;
+mod mlock;
+pub mod policy;
+mod provider;
+mod rate_limit;
+mod session;
+#[cfg(feature = "serde")]
+pub mod settings;
+#[cfg(unix)]
+mod signals;
+pub mod ssh_agent;
+mod supervisor;
+mod trace;
+mod transcript;
+
+#[cfg(feature = "test-util")]
+pub mod test_util;
+
+#[cfg(target_os = "macos")]
+pub mod macos;
+#[cfg(target_os = "linux")]
+pub mod plymouth;
+#[cfg(target_os = "linux")]
+pub mod portal;
+#[cfg(unix)]
+pub mod unix;
+#[cfg(windows)]
+pub mod windows;
 
-pub use error::{Error, GpgError};
+/// The version of [`secrecy`](https://crates.io/crates/secrecy) used in this crate's
+/// public API.
+///
+/// Applications that depend on a different (incompatible) version of `secrecy` can use
+/// this re-export to work with the passphrases returned by dialogs, or convert a
+/// [`SecretBuffer`] into the container they need (such as [`zeroize::Zeroizing`]).
+pub use secrecy;
+pub use secrecy::{ExposeSecret, SecretString};
+/// The version of [`zeroize`](https://crates.io/crates/zeroize) used in this crate's
+/// public API.
+pub use zeroize;
+
+pub use buffer::SecretBuffer;
+pub use charset::{Charset, Utf8Policy};
+pub use choice::ChoiceDialog;
+pub use coalesce::Coalescer;
+pub use config::{Config, ConfigError};
+pub use ct::constant_time_eq;
+pub use diagnostics::{DiagnosticLevel, Diagnostics};
+pub use discovery::clear_binary_cache;
+pub use dismiss::DismissHandle;
+pub use error::{
+    Error, ErrorSource, FallbackError, GpgError, InvalidBinaryError, InvalidBinaryReason,
+    InvalidSettingError, InvalidSettingReason, NotAPinentryError, ProtocolError, RateLimitedError,
+    UnavailableError, UnavailableReason, UnsupportedCharsetError,
+};
+pub use events::LifecycleEvent;
+#[cfg(feature = "dialoguer")]
+pub use fallback::DialoguerFallback;
+pub use fallback::FallbackChain;
+pub use info::{PinentryInfo, PinentryVersion};
+pub use locale::{DefaultText, Translator};
+pub use metrics::{PromptKind, PromptMetrics, PromptOutcome};
+pub use provider::{PassphraseProvider, PassphraseRequest};
+pub use rate_limit::RateLimiter;
+pub use session::PinentrySession;
+pub use supervisor::{ReapedPrompt, Supervisor};
+pub use transcript::Transcript;
 
 /// Result type for the `pinentry` crate.
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Checks that a dialog's timeout can be sent to the `pinentry` binary.
+fn check_timeout(timeout: Option<u16>) -> Result<()> {
+    match timeout {
+        Some(0) => Err(Error::InvalidSetting(InvalidSettingError::new(
+            "SETTIMEOUT",
+            InvalidSettingReason::ZeroTimeout,
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// A placeholder for callbacks and other opaque references in `Debug` output.
+struct Opaque;
+
+impl fmt::Debug for Opaque {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("..")
+    }
+}
+
 /// A dialog for requesting a passphrase from the user.
+#[derive(Clone)]
 pub struct PassphraseInput<'a> {
     binary: PathBuf,
-    required: Option<&'a str>,
-    title: Option<&'a str>,
-    description: Option<&'a str>,
-    error: Option<&'a str>,
-    prompt: Option<&'a str>,
-    confirmation: Option<(&'a str, &'a str)>,
-    ok: Option<&'a str>,
-    cancel: Option<&'a str>,
+    required: Option<Cow<'a, str>>,
+    title: Option<Cow<'a, str>>,
+    description: Option<Cow<'a, str>>,
+    error: Option<Cow<'a, str>>,
+    prompt: Option<Cow<'a, str>>,
+    confirmation: Option<(Cow<'a, str>, Option<Cow<'a, str>>)>,
+    ok: Option<Cow<'a, str>>,
+    cancel: Option<Cow<'a, str>>,
     timeout: Option<u16>,
+    localized_defaults: bool,
+    translator: Option<&'a dyn Translator>,
+    charset: Charset,
+    on_spawn: Option<&'a dyn Fn(&Child)>,
+    supervisor: Option<(&'a Supervisor, &'a str)>,
+    response_timeout: Option<Duration>,
+    skip_preamble: bool,
+    transcript: Option<&'a Transcript>,
+    diagnostics: Option<&'a Diagnostics>,
+    metrics: Option<&'a dyn PromptMetrics>,
+    rate_limiter: Option<&'a RateLimiter>,
+    on_event: events::EventHook<'a>,
+    terminal_hooks: events::TerminalHooks<'a>,
+    respawn_on_crash: bool,
+    max_length: Option<usize>,
+    utf8_policy: Utf8Policy,
+    policy: Option<&'a policy::PassphrasePolicy<'a>>,
+    validator: Option<&'a dyn Fn(&str) -> Option<String>>,
+    #[cfg(feature = "diceware")]
+    generator: Option<(&'a diceware::Diceware, Cow<'a, str>)>,
+    #[cfg(target_os = "linux")]
+    plymouth: bool,
+    #[cfg(unix)]
+    cancel_on_interrupt: bool,
+    #[cfg(unix)]
+    unix: unix::Options<'a>,
+    #[cfg(target_os = "macos")]
+    macos: macos::Options<'a>,
+    #[cfg(windows)]
+    windows: windows::Options,
+}
+
+/// Callbacks, translators, and other hooks are shown only as placeholders, and
+/// supervised dialogs show only their label.
+impl<'a> fmt::Debug for PassphraseInput<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("PassphraseInput");
+        debug
+            .field("binary", &self.binary)
+            .field("required", &self.required)
+            .field("title", &self.title)
+            .field("description", &self.description)
+            .field("error", &self.error)
+            .field("prompt", &self.prompt)
+            .field("confirmation", &self.confirmation)
+            .field("ok", &self.ok)
+            .field("cancel", &self.cancel)
+            .field("timeout", &self.timeout)
+            .field("localized_defaults", &self.localized_defaults)
+            .field("translator", &self.translator.map(|_| Opaque))
+            .field("charset", &self.charset)
+            .field("on_spawn", &self.on_spawn.map(|_| Opaque))
+            .field("supervisor", &self.supervisor.map(|(_, label)| label))
+            .field("response_timeout", &self.response_timeout)
+            .field("skip_preamble", &self.skip_preamble)
+            .field("transcript", &self.transcript.map(|_| Opaque))
+            .field("diagnostics", &self.diagnostics)
+            .field("metrics", &self.metrics.map(|_| Opaque))
+            .field("rate_limiter", &self.rate_limiter)
+            .field("on_event", &self.on_event.map(|_| Opaque))
+            .field("terminal_hooks", &self.terminal_hooks.map(|_| Opaque))
+            .field("respawn_on_crash", &self.respawn_on_crash)
+            .field("max_length", &self.max_length)
+            .field("utf8_policy", &self.utf8_policy)
+            .field("policy", &self.policy)
+            .field("validator", &self.validator.map(|_| Opaque));
+        #[cfg(feature = "diceware")]
+        debug.field("generator", &self.generator);
+        #[cfg(target_os = "linux")]
+        debug.field("plymouth", &self.plymouth);
+        #[cfg(unix)]
+        debug
+            .field("cancel_on_interrupt", &self.cancel_on_interrupt)
+            .field("unix", &self.unix);
+        #[cfg(target_os = "macos")]
+        debug.field("macos", &self.macos);
+        #[cfg(windows)]
+        debug.field("windows", &self.windows);
+        debug.finish()
+    }
 }
 
 impl<'a> PassphraseInput<'a> {
     /// Creates a new PassphraseInput using the binary named `pinentry`.
     ///
-    /// Returns `None` if `pinentry` cannot be found in `PATH`.
+    /// Returns `None` if `pinentry` cannot be found in `PATH` (or, on Unix, in the Nix
+    /// and Guix profile directories). With the `tty-pinentry` feature, the
+    /// `pinentry-rs-tty` binary is used if it is installed and `pinentry` is not. The
+    /// result of the search is cached for the lifetime of the process; see
+    /// [`clear_binary_cache`].
     pub fn with_default_binary() -> Option<Self> {
         Self::with_binary("pinentry")
     }
@@ -95,20 +346,97 @@ impl<'a> PassphraseInput<'a> {
     /// - A binary name was provided that cannot be found in `PATH`.
     /// - The binary is found but is not executable.
     pub fn with_binary<T: AsRef<OsStr>>(binary_name: T) -> Option<Self> {
-        which::which(binary_name)
-            .ok()
-            .map(|binary| PassphraseInput {
-                binary,
-                required: None,
-                title: None,
-                description: None,
-                error: None,
-                prompt: None,
-                confirmation: None,
-                ok: None,
-                cancel: None,
-                timeout: None,
-            })
+        Self::try_with_binary(binary_name).ok()
+    }
+
+    /// Creates a new PassphraseInput using the given path to, or name of, a `pinentry`
+    /// binary, returning the reason if it cannot be used.
+    ///
+    /// This makes the same checks as [`PassphraseInput::with_binary`].
+    pub fn try_with_binary<T: AsRef<OsStr>>(
+        binary_name: T,
+    ) -> std::result::Result<Self, InvalidBinaryError> {
+        discovery::find(binary_name.as_ref()).map(Self::with_binary_unchecked)
+    }
+
+    /// Creates a new PassphraseInput using the given path to a `pinentry` binary, without
+    /// checking that it exists or is executable.
+    ///
+    /// This is useful if the path has already been validated, or if it is only valid
+    /// in the environment that the binary is spawned in (for example, inside a
+    /// [sandbox](unix::Sandbox)). If the binary cannot be spawned, the dialog returns
+    /// [`Error::Io`].
+    pub fn with_binary_unchecked<T: Into<PathBuf>>(binary: T) -> Self {
+        PassphraseInput {
+            binary: binary.into(),
+            required: None,
+            title: None,
+            description: None,
+            error: None,
+            prompt: None,
+            confirmation: None,
+            ok: None,
+            cancel: None,
+            timeout: None,
+            localized_defaults: false,
+            translator: None,
+            charset: Charset::Utf8,
+            on_spawn: None,
+            supervisor: None,
+            response_timeout: None,
+            skip_preamble: false,
+            transcript: None,
+            diagnostics: None,
+            metrics: None,
+            rate_limiter: None,
+            on_event: None,
+            terminal_hooks: None,
+            respawn_on_crash: false,
+            max_length: None,
+            utf8_policy: Utf8Policy::Strict,
+            policy: None,
+            validator: None,
+            #[cfg(feature = "diceware")]
+            generator: None,
+            #[cfg(target_os = "linux")]
+            plymouth: true,
+            #[cfg(unix)]
+            cancel_on_interrupt: false,
+            #[cfg(unix)]
+            unix: unix::Options::default(),
+            #[cfg(target_os = "macos")]
+            macos: macos::Options::default(),
+            #[cfg(windows)]
+            windows: windows::Options::default(),
+        }
+    }
+
+    /// Creates a new PassphraseInput from the given end-user configuration (usually read
+    /// with [`Config::load`]).
+    ///
+    /// The first of the configured binaries that can be found is used, and the
+    /// configured timeout and Unix-specific options are applied. Returns `None` if none
+    /// of the configured binaries can be found.
+    pub fn from_config(config: &'a Config) -> Option<Self> {
+        let mut input = config.candidates().find_map(Self::with_binary)?;
+        if let Some(timeout) = config.timeout() {
+            input.with_timeout(timeout);
+        }
+        #[cfg(unix)]
+        input.with_unix_options(&config.unix_options());
+        Some(input)
+    }
+
+    /// Creates a new PassphraseInput using the binary named `pinentry`, configured from the
+    /// conventional environment variables in the same way that `gpg-agent` launches
+    /// `pinentry` binaries (see [`unix::Options::from_env`]).
+    ///
+    /// Returns `None` if `pinentry` cannot be found in `PATH`.
+    #[cfg(unix)]
+    pub fn from_env() -> Option<Self> {
+        let mut input = Self::with_default_binary()?;
+        input.with_unix_options(&unix::Options::from_env());
+        Some(input)
     }
 
     /// Prevents the user from submitting an empty passphrase.
@@ -116,8 +444,71 @@ impl<'a> PassphraseInput<'a> {
     /// The provided error text will be displayed if the user submits an empty passphrase.
     /// The dialog will remain open until the user either submits a non-empty passphrase,
     /// or selects the "Cancel" button.
-    pub fn required(&mut self, empty_error: &'a str) -> &mut Self {
-        self.required = Some(empty_error);
+    pub fn required(&mut self, empty_error: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.required = Some(empty_error.into());
+        self
+    }
+
+    /// Prevents the user from submitting a passphrase that breaks the given policy.
+    ///
+    /// If the user submits such a passphrase, the way in which it breaks the policy is
+    /// displayed as an error, and the dialog remains open until the user either submits
+    /// a passphrase that satisfies the policy, or selects the "Cancel" button. With
+    /// [`PassphraseInput::with_confirmation`], the policy is only checked after both
+    /// entries match.
+    pub fn with_policy(&mut self, policy: &'a policy::PassphrasePolicy<'a>) -> &mut Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Rejects entries for which the given validator returns an error to display, in
+    /// the same way as a policy.
+    pub(crate) fn with_validator(
+        &mut self,
+        validator: &'a dyn Fn(&str) -> Option<String>,
+    ) -> &mut Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    /// Sets the maximum length (in bytes, when encoded as UTF-8) of the passphrase.
+    ///
+    /// If the `pinentry` binary returns a longer passphrase, it is wiped from memory and
+    /// [`Error::TooLong`] is returned. This bounds the memory allocated for a
+    /// buggy or malicious `pinentry` binary.
+    ///
+    /// [`PassphraseInput::interact_into`] is instead bounded by the capacity of the
+    /// buffer it is given.
+    pub fn with_max_length(&mut self, max_length: usize) -> &mut Self {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    /// Sets how a passphrase that is not valid UTF-8 is handled (by default,
+    /// [`Utf8Policy::Strict`]).
+    ///
+    /// With [`Utf8Policy::Lossy`], a passphrase that the user has already typed is
+    /// returned (with its invalid sequences replaced) instead of being discarded.
+    pub fn with_utf8_policy(&mut self, utf8_policy: Utf8Policy) -> &mut Self {
+        self.utf8_policy = utf8_policy;
+        self
+    }
+
+    /// Shows a button with the given label that fills in a passphrase from the given
+    /// generator.
+    ///
+    /// Only some `pinentry` binaries (such as `pinentry-qt`) show this button; others
+    /// ignore it. The user can still edit the generated passphrase before submitting it.
+    /// Pass [`Diceware::default`](diceware::Diceware::default) to generate passphrases
+    /// from the EFF's large wordlist, which is also used if a `pinentry` binary asks
+    /// for a generated passphrase when no generator is set.
+    #[cfg(feature = "diceware")]
+    pub fn with_generator(
+        &mut self,
+        generator: &'a diceware::Diceware,
+        label: impl Into<Cow<'a, str>>,
+    ) -> &mut Self {
+        self.generator = Some((generator, label.into()));
         self
     }
 
@@ -125,14 +516,14 @@ impl<'a> PassphraseInput<'a> {
     ///
     /// When using this feature you should take care that the window is still identifiable
     /// as the pinentry.
-    pub fn with_title(&mut self, title: &'a str) -> &mut Self {
-        self.title = Some(title);
+    pub fn with_title(&mut self, title: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.title = Some(title.into());
         self
     }
 
     /// Sets the descriptive text to display.
-    pub fn with_description(&mut self, description: &'a str) -> &mut Self {
-        self.description = Some(description);
+    pub fn with_description(&mut self, description: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.description = Some(description.into());
         self
     }
 
@@ -140,8 +531,8 @@ impl<'a> PassphraseInput<'a> {
     ///
     /// This is used to display an error message, for example on a second interaction if
     /// the first passphrase was invalid.
-    pub fn with_error(&mut self, error: &'a str) -> &mut Self {
-        self.error = Some(error);
+    pub fn with_error(&mut self, error: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.error = Some(error.into());
         self
     }
 
@@ -154,8 +545,8 @@ impl<'a> PassphraseInput<'a> {
     /// pinentry is used. Modern versions underline the next character after the
     /// underscore and use the first such underlined character as a keyboard accelerator.
     /// Use a double underscore to escape an underscore.
-    pub fn with_prompt(&mut self, prompt: &'a str) -> &mut Self {
-        self.prompt = Some(prompt);
+    pub fn with_prompt(&mut self, prompt: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.prompt = Some(prompt.into());
         self
     }
 
@@ -170,10 +561,23 @@ impl<'a> PassphraseInput<'a> {
     /// Use a double underscore to escape an underscore.
     pub fn with_confirmation(
         &mut self,
-        confirmation_prompt: &'a str,
-        mismatch_error: &'a str,
+        confirmation_prompt: impl Into<Cow<'a, str>>,
+        mismatch_error: impl Into<Cow<'a, str>>,
+    ) -> &mut Self {
+        self.confirmation = Some((confirmation_prompt.into(), Some(mismatch_error.into())));
+        self
+    }
+
+    /// Enables confirmation prompting, with a built-in error message in the language of
+    /// the process locale (see [`DefaultText::builtin`]) if the passphrases do not match.
+    ///
+    /// When asking for a passphrase or PIN, this sets the text just before the widget for
+    /// the passphrase confirmation entry.
+    pub fn with_confirmation_prompt(
+        &mut self,
+        confirmation_prompt: impl Into<Cow<'a, str>>,
     ) -> &mut Self {
-        self.confirmation = Some((confirmation_prompt, mismatch_error));
+        self.confirmation = Some((confirmation_prompt.into(), None));
         self
     }
 
@@ -183,8 +587,8 @@ impl<'a> PassphraseInput<'a> {
     /// pinentry is used. Modern versions underline the next character after the
     /// underscore and use the first such underlined character as a keyboard accelerator.
     /// Use a double underscore to escape an underscore.
-    pub fn with_ok(&mut self, ok: &'a str) -> &mut Self {
-        self.ok = Some(ok);
+    pub fn with_ok(&mut self, ok: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.ok = Some(ok.into());
         self
     }
 
@@ -195,8 +599,8 @@ impl<'a> PassphraseInput<'a> {
     /// pinentry is used. Modern versions underline the next character after the
     /// underscore and use the first such underlined character as a keyboard accelerator.
     /// Use a double underscore to escape an underscore.
-    pub fn with_cancel(&mut self, cancel: &'a str) -> &mut Self {
-        self.cancel = Some(cancel);
+    pub fn with_cancel(&mut self, cancel: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.cancel = Some(cancel.into());
         self
     }
 
@@ -206,64 +610,702 @@ impl<'a> PassphraseInput<'a> {
         self
     }
 
+    /// Uses built-in translations of the button texts that have not been set, in the
+    /// language of the process locale (from `LANGUAGE`, `LC_ALL`, `LC_MESSAGES`, or
+    /// `LANG`).
+    ///
+    /// By default, the `pinentry` binary chooses the texts of any buttons that have not
+    /// been set.
+    pub fn with_localized_defaults(&mut self) -> &mut Self {
+        self.localized_defaults = true;
+        self
+    }
+
+    /// Looks up the texts that this crate provides (see [`DefaultText`]) with the given
+    /// [`Translator`] instead of using the built-in translations.
+    pub fn with_translator(&mut self, translator: &'a dyn Translator) -> &mut Self {
+        self.translator = Some(translator);
+        self
+    }
+
+    /// Sets the character set used by the `pinentry` binary (by default, UTF-8).
+    ///
+    /// Use [`Charset::from_locale`] for older `pinentry` binaries that use the character
+    /// set of the process locale.
+    pub fn with_charset(&mut self, charset: Charset) -> &mut Self {
+        self.charset = charset;
+        self
+    }
+
+    /// Sets a function that will be called with the `pinentry` process each time it is
+    /// spawned.
+    ///
+    /// This can be used by supervising applications to obtain the process ID (via
+    /// [`Child::id`]), for example to place the process in a cgroup or to report it
+    /// in their own status interfaces.
+    pub fn with_spawn_hook(&mut self, on_spawn: &'a dyn Fn(&Child)) -> &mut Self {
+        self.on_spawn = Some(on_spawn);
+        self
+    }
+
+    /// Places this dialog under the supervision of the given [`Supervisor`].
+    ///
+    /// The `label` identifies this dialog in [`Supervisor::take_reaped`] if it is killed
+    /// for exceeding the supervisor's budget.
+    pub fn with_supervisor(&mut self, supervisor: &'a Supervisor, label: &'a str) -> &mut Self {
+        self.supervisor = Some((supervisor, label));
+        self
+    }
+
+    /// Sets how long to wait for each response from the `pinentry` binary, after which
+    /// it is assumed to be wedged: its process is killed, and [`Error::Timeout`] is
+    /// returned.
+    ///
+    /// The prompt itself waits for the user, so it is only bounded if a timeout is also
+    /// set with `with_timeout`, in which case it is allowed that much longer. Use a
+    /// [`Supervisor`] to bound the whole dialog instead.
+    pub fn with_response_timeout(&mut self, response_timeout: Duration) -> &mut Self {
+        self.response_timeout = Some(response_timeout);
+        self
+    }
+
+    /// Skips up to 32 lines that are not Assuan responses (such as banners or blank
+    /// lines printed by a wrapper script) before the `pinentry` binary's greeting.
+    ///
+    /// By default, the first line must be the greeting, so that a binary that is not a
+    /// `pinentry` fails immediately instead of being waited on.
+    pub fn skip_preamble(&mut self) -> &mut Self {
+        self.skip_preamble = true;
+        self
+    }
+
+    /// Records the requests and responses exchanged with the `pinentry` binary in the
+    /// given [`Transcript`].
+    pub fn with_transcript(&mut self, transcript: &'a Transcript) -> &mut Self {
+        self.transcript = Some(transcript);
+        self
+    }
+
+    /// Sends this dialog's diagnostic messages to the given [`Diagnostics`] sink,
+    /// instead of the default.
+    pub fn with_diagnostics(&mut self, diagnostics: &'a Diagnostics) -> &mut Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    /// Reports the outcome and latency of each prompt shown by this dialog to the given
+    /// [`PromptMetrics`].
+    pub fn with_metrics(&mut self, metrics: &'a dyn PromptMetrics) -> &mut Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Counts each prompt shown by this dialog against the given [`RateLimiter`], which
+    /// may be shared with other dialogs.
+    ///
+    /// Once its limit is reached, [`Error::RateLimited`] is returned (or the prompt
+    /// waits, if the limiter queues prompts) instead of showing the dialog.
+    pub fn with_rate_limiter(&mut self, limiter: &'a RateLimiter) -> &mut Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Sets a function that will be called with each [`LifecycleEvent`] of the prompts
+    /// shown by this dialog.
+    pub fn with_event_hook(&mut self, on_event: &'a dyn Fn(&LifecycleEvent<'_>)) -> &mut Self {
+        self.on_event = Some(on_event);
+        self
+    }
+
+    /// Sets functions that suspend and resume the caller's terminal UI, for
+    /// applications that draw on the terminal that a `curses` or `tty` `pinentry` would
+    /// use.
+    ///
+    /// `suspend` is called before each prompt, and `resume` is called after it has
+    /// finished (once any `pinentry` process owned by the dialog has exited), including
+    /// when the prompt fails. They are called even if the `pinentry` binary shows the
+    /// dialog elsewhere, as this cannot be known beforehand.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # fn leave_raw_mode() {}
+    /// # fn enter_raw_mode() {}
+    /// # let mut dialog = pinentry::PassphraseInput::with_default_binary().unwrap();
+    /// // With crossterm, for example, these would leave and re-enter raw mode and the
+    /// // alternate screen.
+    /// dialog.with_terminal_hooks(&leave_raw_mode, &enter_raw_mode);
+    /// ```
+    pub fn with_terminal_hooks(
+        &mut self,
+        suspend: &'a dyn Fn(),
+        resume: &'a dyn Fn(),
+    ) -> &mut Self {
+        self.terminal_hooks = Some((suspend, resume));
+        self
+    }
+
+    /// Cancels the prompt when the user presses Ctrl-C or Ctrl-Z, instead of the
+    /// signal interrupting or stopping this process.
+    ///
+    /// While the prompt is shown, `SIGINT` and `SIGTSTP` are caught, and the `pinentry`
+    /// process is killed so that the prompt returns as if the user had cancelled it.
+    /// This avoids both leaving an orphaned dialog behind and exiting while a `curses`
+    /// `pinentry` still has the terminal. The previous signal handlers are restored
+    /// once the prompt has finished.
+    #[cfg(unix)]
+    pub fn cancel_on_interrupt(&mut self) -> &mut Self {
+        self.cancel_on_interrupt = true;
+        self
+    }
+
+    /// Sets the Unix-specific options for spawning the `pinentry` binary.
+    #[cfg(unix)]
+    pub fn with_unix_options(&mut self, options: &unix::Options<'a>) -> &mut Self {
+        self.unix = options.clone();
+        self
+    }
+
+    /// Sets the macOS-specific options for presenting the dialog.
+    #[cfg(target_os = "macos")]
+    pub fn with_macos_options(&mut self, options: &macos::Options<'a>) -> &mut Self {
+        self.macos = options.clone();
+        self
+    }
+
+    /// Sets the Windows-specific options for spawning the `pinentry` binary.
+    #[cfg(windows)]
+    pub fn with_windows_options(&mut self, options: &windows::Options) -> &mut Self {
+        self.windows = options.clone();
+        self
+    }
+
+    /// Respawns the `pinentry` binary and retries once if it exits unexpectedly before
+    /// asking for the passphrase.
+    ///
+    /// Some GUI pinentries occasionally crash after being configured. With this enabled,
+    /// a fresh `pinentry` process is spawned and configured from scratch before giving
+    /// up. If the `pinentry` process exits once it has been asked for the passphrase
+    /// (for example, while the user is typing), the error is returned instead, so that
+    /// the user is never silently prompted again.
+    ///
+    /// This has no effect on [`PassphraseInput::interact_in`], as the `pinentry` process
+    /// belongs to the session, which cannot be used once its process has exited.
+    pub fn respawn_on_crash(&mut self) -> &mut Self {
+        self.respawn_on_crash = true;
+        self
+    }
+
+    /// Always uses the `pinentry` binary, even while Plymouth is running.
+    ///
+    /// By default, while Plymouth is running (see [`plymouth::is_running`]), dialogs
+    /// without a confirmation prompt ask for the passphrase through Plymouth instead of
+    /// spawning the `pinentry` binary. Plymouth shows only the error (if any) and the
+    /// description (or else the prompt) of the dialog.
+    #[cfg(target_os = "linux")]
+    pub fn without_plymouth(&mut self) -> &mut Self {
+        self.plymouth = false;
+        self
+    }
+
     /// Asks for a passphrase or PIN.
     pub fn interact(&self) -> Result<SecretString> {
-        let mut pinentry = assuan::Connection::open(&self.binary)?;
+        self.validate()?;
+        rate_limit::acquire(self.rate_limiter)?;
+        let _suspended = events::suspend(self.terminal_hooks);
+        metrics::measure(self.metrics, PromptKind::Passphrase, || {
+            let prompted = Cell::new(false);
+            match self.interact_once(&prompted) {
+                Err(e) if self.respawn_on_crash && e.is_exited() && !prompted.get() => {
+                    self.interact_once(&prompted)
+                }
+                res => res,
+            }
+        })
+    }
 
-        if let Some(title) = &self.title {
-            pinentry.send_request("SETTITLE", Some(title))?;
-        }
-        if let Some(desc) = &self.description {
-            pinentry.send_request("SETDESC", Some(desc))?;
+    /// Asks for a passphrase or PIN, decoding it directly into `buf`.
+    ///
+    /// Any previous contents of `buf` are wiped. If the passphrase does not fit in
+    /// `buf`, it is wiped from `buf` and [`Error::TooLong`] is returned.
+    ///
+    /// This avoids the intermediate allocations made by [`PassphraseInput::interact`],
+    /// so the decoded passphrase is only ever held in memory controlled by the caller.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pinentry::{PassphraseInput, SecretBuffer};
+    ///
+    /// let mut passphrase = SecretBuffer::with_capacity(256);
+    /// if let Some(mut input) = PassphraseInput::with_default_binary() {
+    ///     input
+    ///         .with_description("Enter passphrase for FooBar")
+    ///         .interact_into(&mut passphrase)?;
+    /// }
+    /// # Ok::<(), pinentry::Error>(())
+    /// ```
+    pub fn interact_into(&self, buf: &mut SecretBuffer) -> Result<()> {
+        self.validate()?;
+        rate_limit::acquire(self.rate_limiter)?;
+        let _suspended = events::suspend(self.terminal_hooks);
+        metrics::measure(self.metrics, PromptKind::Passphrase, || {
+            let prompted = Cell::new(false);
+            match self.interact_into_once(buf, &prompted) {
+                Err(e) if self.respawn_on_crash && e.is_exited() && !prompted.get() => {
+                    self.interact_into_once(buf, &prompted)
+                }
+                res => res,
+            }
+        })
+    }
+
+    /// Asks for a passphrase or PIN, and derives a key from it with the given KDF and
+    /// salt, filling `key`.
+    ///
+    /// The passphrase is decoded into a buffer that is wiped as soon as the key has been
+    /// derived, so only the key ever leaves this crate. The buffer holds up to the
+    /// maximum length set with [`PassphraseInput::with_max_length`] (or 2048 bytes by
+    /// default), and [`Error::TooLong`] is returned for longer passphrases.
+    ///
+    /// The KDF parameters are checked before the dialog is shown, and an I/O error of
+    /// kind [`std::io::ErrorKind::InvalidInput`] is returned if they are invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pinentry::{kdf::Kdf, PassphraseInput};
+    ///
+    /// let mut key = [0; 32];
+    /// if let Some(mut input) = PassphraseInput::with_default_binary() {
+    ///     input
+    ///         .with_description("Enter passphrase for FooBar")
+    ///         .interact_derive_key(&Kdf::argon2id(64 * 1024, 3, 1), b"per-file salt", &mut key)?;
+    /// }
+    /// # Ok::<(), pinentry::Error>(())
+    /// ```
+    #[cfg(feature = "kdf")]
+    pub fn interact_derive_key(&self, kdf: &kdf::Kdf, salt: &[u8], key: &mut [u8]) -> Result<()> {
+        let prepared = kdf.prepare(salt, key.len())?;
+        let mut passphrase =
+            SecretBuffer::with_capacity(self.max_length.unwrap_or(kdf::DEFAULT_MAX_LENGTH));
+        self.interact_into(&mut passphrase)?;
+        prepared.derive(passphrase.expose_secret(), salt, key)?;
+        Ok(())
+    }
+
+    /// Asks for a passphrase or PIN, using the `pinentry` process of the given session.
+    ///
+    /// See [`PinentrySession`] for details.
+    pub fn interact_in(&self, session: &mut PinentrySession) -> Result<SecretString> {
+        self.validate()?;
+        rate_limit::acquire(self.rate_limiter)?;
+        let _suspended = events::suspend(self.terminal_hooks);
+        metrics::measure(self.metrics, PromptKind::Passphrase, || {
+            let pinentry = session.start();
+            self.configure(pinentry)?;
+            self.get_pin(pinentry)
+        })
+    }
+
+    /// Asks for a passphrase or PIN with a new `pinentry` process, setting `prompted`
+    /// once it has been asked for the passphrase.
+    fn interact_once(&self, prompted: &Cell<bool>) -> Result<SecretString> {
+        #[cfg(target_os = "linux")]
+        if self.uses_plymouth() {
+            let passphrase = self.plymouth_passphrase()?;
+            return self.utf8_policy.to_secret_string(&passphrase);
         }
-        if let Some(error) = &self.error {
-            pinentry.send_request("SETERROR", Some(error))?;
+
+        events::run(self.on_event, self.open()?, |pinentry| {
+            self.configure(pinentry)?;
+            let res = self.get_pin(pinentry);
+            prompted.set(pinentry.prompted());
+            res
+        })
+    }
+
+    /// Asks for a passphrase or PIN with a new `pinentry` process in the same way as
+    /// `interact_once`, decoding it directly into `buf`.
+    fn interact_into_once(&self, buf: &mut SecretBuffer, prompted: &Cell<bool>) -> Result<()> {
+        #[cfg(target_os = "linux")]
+        if self.uses_plymouth() {
+            buf.clear();
+            let passphrase = self.plymouth_passphrase()?;
+            if !passphrase.iter().all(|&b| buf.push(b)) {
+                buf.clear();
+                return Err(Error::TooLong);
+            }
+            return self.utf8_policy.apply(buf);
         }
-        if let Some(prompt) = &self.prompt {
-            pinentry.send_request("SETPROMPT", Some(prompt))?;
+
+        events::run(self.on_event, self.open()?, |pinentry| {
+            self.configure(pinentry)?;
+            let res = self.get_pin_into(pinentry, buf);
+            prompted.set(pinentry.prompted());
+            res
+        })
+    }
+
+    fn get_pin_into(
+        &self,
+        pinentry: &mut assuan::Connection,
+        buf: &mut SecretBuffer,
+    ) -> Result<()> {
+        #[cfg(feature = "diceware")]
+        pinentry.set_genpin(
+            self.generator
+                .as_ref()
+                .map(|(generator, _)| (*generator).clone()),
+        );
+        loop {
+            events::prompt(self.on_event, || {
+                pinentry.send_request_into("GETPIN", None, buf)
+            })?;
+            self.utf8_policy.apply(buf)?;
+            match self.rejection(buf.expose_secret()) {
+                Some(error) => {
+                    // SETERROR is cleared by GETPIN, so we reset it on each loop.
+                    pinentry.send_request("SETERROR", Some(&error))?;
+                }
+                None => return Ok(()),
+            }
         }
-        if let Some(ok) = &self.ok {
-            pinentry.send_request("SETOK", Some(ok))?;
+    }
+
+    fn get_pin(&self, pinentry: &mut assuan::Connection) -> Result<SecretString> {
+        pinentry.set_max_data_len(self.max_length);
+        pinentry.set_utf8_policy(self.utf8_policy);
+        #[cfg(feature = "diceware")]
+        pinentry.set_genpin(
+            self.generator
+                .as_ref()
+                .map(|(generator, _)| (*generator).clone()),
+        );
+        loop {
+            let passphrase =
+                events::prompt(self.on_event, || pinentry.send_request("GETPIN", None))?
+                    // If the user provides an empty passphrase, GETPIN returns no data.
+                    .unwrap_or_else(|| String::new().into());
+            match self.rejection(passphrase.expose_secret().as_bytes()) {
+                Some(error) => {
+                    // SETERROR is cleared by GETPIN, so we reset it on each loop.
+                    pinentry.send_request("SETERROR", Some(&error))?;
+                }
+                None => return Ok(passphrase),
+            }
         }
-        if let Some(cancel) = &self.cancel {
-            pinentry.send_request("SETCANCEL", Some(cancel))?;
+    }
+
+    /// Returns the error to display if the given passphrase is empty but required,
+    /// breaks the policy, or is rejected by the validator.
+    fn rejection(&self, passphrase: &[u8]) -> Option<Cow<'_, str>> {
+        if let (Some(empty_error), true) = (self.required.as_deref(), passphrase.is_empty()) {
+            return Some(Cow::Borrowed(empty_error));
         }
-        if let Some((confirmation_prompt, mismatch_error)) = &self.confirmation {
-            pinentry.send_request("SETREPEAT", Some(confirmation_prompt))?;
-            pinentry.send_request("SETREPEATERROR", Some(mismatch_error))?;
+        if self.policy.is_none() && self.validator.is_none() {
+            return None;
         }
-        if let Some(timeout) = self.timeout {
-            pinentry.send_request("SETTIMEOUT", Some(&format!("{}", timeout)))?;
+        let decoded;
+        let passphrase = match std::str::from_utf8(passphrase) {
+            Ok(passphrase) => passphrase,
+            // Passphrases kept raw with `Utf8Policy::Raw` are checked as if decoded lossily.
+            Err(_) => {
+                decoded = zeroize::Zeroizing::new(String::from_utf8_lossy(passphrase).into_owned());
+                decoded.as_str()
+            }
+        };
+        if let Some(Err(violation)) = self.policy.map(|policy| policy.check(passphrase)) {
+            return Some(locale::translate(
+                self.translator,
+                DefaultText::PolicyViolation(violation),
+            ));
         }
+        self.validator
+            .and_then(|validator| validator(passphrase))
+            .map(Cow::Owned)
+    }
+
+    /// Returns true if this dialog will be shown through Plymouth.
+    #[cfg(target_os = "linux")]
+    fn uses_plymouth(&self) -> bool {
+        self.plymouth && self.confirmation.is_none() && plymouth::is_running()
+    }
 
+    /// Asks for the passphrase through Plymouth.
+    #[cfg(target_os = "linux")]
+    fn plymouth_passphrase(&self) -> Result<zeroize::Zeroizing<Vec<u8>>> {
+        let text = self.description.as_deref().or(self.prompt.as_deref());
+        let mut error = self.error.as_deref().map(Cow::Borrowed);
         loop {
-            match (pinentry.send_request("GETPIN", None)?, self.required) {
-                // If the user provides an empty passphrase, GETPIN returns no data.
-                (None, None) => return Ok(String::new().into()),
-                (Some(passphrase), _) => return Ok(passphrase),
-                (_, Some(empty_error)) => {
-                    // SETERROR is cleared by GETPIN, so we reset it on each loop.
-                    pinentry.send_request("SETERROR", Some(empty_error))?;
-                }
+            let prompt = (error.as_deref().into_iter())
+                .chain(text)
+                .collect::<Vec<_>>()
+                .join(" ");
+            let passphrase = plymouth::ask_password_bytes(&prompt, self.timeout)?;
+            if matches!(self.max_length, Some(max_length) if passphrase.len() > max_length) {
+                return Err(Error::TooLong);
             }
+            match self.rejection(&passphrase) {
+                Some(rejection) => error = Some(rejection),
+                None => return Ok(passphrase),
+            }
+        }
+    }
+
+    /// Spawns the `pinentry` binary.
+    fn open(&self) -> Result<assuan::Connection> {
+        let mut pinentry = assuan::Connection::open(
+            &self.binary,
+            #[cfg(unix)]
+            &self.unix,
+            #[cfg(target_os = "macos")]
+            &self.macos,
+            #[cfg(windows)]
+            &self.windows,
+            self.on_spawn,
+            self.transcript,
+            self.diagnostics,
+            self.response_timeout,
+            self.skip_preamble,
+        )?;
+        pinentry.set_charset(self.charset);
+        if let Some((supervisor, label)) = self.supervisor {
+            pinentry.supervise(supervisor, label);
+        }
+        Ok(pinentry)
+    }
+
+    /// Checks this dialog's settings, without spawning anything.
+    ///
+    /// Returns [`Error::InvalidSetting`] if any text contains a NUL character or is too
+    /// long to send to the `pinentry` binary, if the timeout is zero, or if a
+    /// confirmation prompt is set without a prompt. [`PassphraseInput::interact`] (and
+    /// its variants) make the same checks before spawning the `pinentry` binary.
+    pub fn validate(&self) -> Result<()> {
+        if self.confirmation.is_some() && self.prompt.is_none() {
+            return Err(Error::InvalidSetting(InvalidSettingError::new(
+                "SETREPEAT",
+                InvalidSettingReason::ConfirmationWithoutPrompt,
+            )));
+        }
+        check_timeout(self.timeout)?;
+        let timeout = self.timeout.map(|timeout| format!("{}", timeout));
+        assuan::check_settings(&self.settings(&timeout, &self.defaults()))
+            .and_then(|()| assuan::check_settings(&[("SETERROR", self.required.as_deref())]))
+            .map_err(Error::InvalidSetting)
+    }
+
+    /// Returns true if this dialog is likely to be shown: the `pinentry` binary still
+    /// exists and is executable, and there is somewhere for it to show the dialog: on
+    /// Unix, a display or terminal, and on Windows, an interactive session (see
+    /// `windows::current_session`).
+    ///
+    /// This is a cheap check that doesn't spawn anything, so applications can fall
+    /// back to another way of prompting before committing to a dialog that would fail.
+    /// A `true` result does not guarantee that the `pinentry` binary will be able to
+    /// use the display or terminal (for example, `pinentry-gnome3` also needs a session
+    /// bus).
+    ///
+    /// On Linux, this also returns true if the dialog will be shown through Plymouth
+    /// (see [`PassphraseInput::without_plymouth`]).
+    pub fn can_interact(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        if self.uses_plymouth() {
+            return true;
+        }
+
+        if discovery::find(self.binary.as_os_str()).is_err() {
+            return false;
+        }
+
+        #[cfg(unix)]
+        {
+            self.unix.can_display()
+        }
+
+        #[cfg(windows)]
+        {
+            self.windows.can_display()
+        }
+
+        #[cfg(not(any(unix, windows)))]
+        {
+            true
+        }
+    }
+
+    /// Returns the exact sequence of Assuan requests that [`PassphraseInput::interact`]
+    /// would send, without spawning anything.
+    ///
+    /// This assumes that the user enters a non-empty passphrase. This is useful for
+    /// snapshot tests, and for debugging configuration without a display.
+    pub fn dry_run(&self) -> Vec<String> {
+        let timeout = self.timeout.map(|timeout| format!("{}", timeout));
+        assuan::dry_run(
+            #[cfg(unix)]
+            &self.unix,
+            #[cfg(target_os = "macos")]
+            &self.macos,
+            #[cfg(windows)]
+            &self.windows,
+            self.on_event.is_some(),
+            &self.settings(&timeout, &self.defaults()),
+            "GETPIN",
+        )
+    }
+
+    fn configure(&self, pinentry: &mut assuan::Connection) -> Result<()> {
+        let timeout = self.timeout.map(|timeout| format!("{}", timeout));
+        let defaults = self.defaults();
+        #[cfg(unix)]
+        pinentry.set_cancel_on_interrupt(self.cancel_on_interrupt);
+        pinentry.apply_settings(&self.settings(&timeout, &defaults))?;
+        events::emit(self.on_event, LifecycleEvent::Configured);
+        Ok(())
+    }
+
+    fn defaults(&self) -> locale::Defaults {
+        let localized = |set: Option<&str>, text| match set {
+            None if self.localized_defaults => Some(locale::translate(self.translator, text)),
+            _ => None,
+        };
+        locale::Defaults {
+            ok: localized(self.ok.as_deref(), DefaultText::Ok),
+            cancel: localized(self.cancel.as_deref(), DefaultText::Cancel),
+            passphrase_mismatch: match &self.confirmation {
+                Some((_, None)) => Some(locale::translate(
+                    self.translator,
+                    DefaultText::PassphraseMismatch,
+                )),
+                _ => None,
+            },
+        }
+    }
+
+    fn settings<'s>(
+        &'s self,
+        timeout: &'s Option<String>,
+        defaults: &'s locale::Defaults,
+    ) -> [(&'static str, Option<&'s str>); 10] {
+        [
+            ("SETTITLE", self.title.as_deref()),
+            ("SETDESC", self.description.as_deref()),
+            ("SETERROR", self.error.as_deref()),
+            ("SETPROMPT", self.prompt.as_deref()),
+            ("SETOK", self.ok.as_deref().or(defaults.ok.as_deref())),
+            (
+                "SETCANCEL",
+                self.cancel.as_deref().or(defaults.cancel.as_deref()),
+            ),
+            (
+                "SETREPEAT",
+                self.confirmation.as_ref().map(|(prompt, _)| &**prompt),
+            ),
+            (
+                "SETREPEATERROR",
+                self.confirmation.as_ref().and_then(|(_, error)| {
+                    error.as_deref().or(defaults.passphrase_mismatch.as_deref())
+                }),
+            ),
+            ("SETTIMEOUT", timeout.as_deref()),
+            ("SETGENPIN", self.generator_label()),
+        ]
+    }
+
+    /// Returns the label of the button that generates a passphrase, if there is one.
+    fn generator_label(&self) -> Option<&str> {
+        #[cfg(feature = "diceware")]
+        {
+            self.generator.as_ref().map(|(_, label)| &**label)
+        }
+
+        #[cfg(not(feature = "diceware"))]
+        {
+            None
         }
     }
 }
 
 /// A dialog for requesting a confirmation from the user.
+#[derive(Clone)]
 pub struct ConfirmationDialog<'a> {
     binary: PathBuf,
-    title: Option<&'a str>,
-    ok: Option<&'a str>,
-    cancel: Option<&'a str>,
-    not_ok: Option<&'a str>,
+    title: Option<Cow<'a, str>>,
+    ok: Option<Cow<'a, str>>,
+    cancel: Option<Cow<'a, str>>,
+    not_ok: Option<Cow<'a, str>>,
     timeout: Option<u16>,
+    localized_defaults: bool,
+    translator: Option<&'a dyn Translator>,
+    charset: Charset,
+    on_spawn: Option<&'a dyn Fn(&Child)>,
+    supervisor: Option<(&'a Supervisor, &'a str)>,
+    response_timeout: Option<Duration>,
+    skip_preamble: bool,
+    transcript: Option<&'a Transcript>,
+    diagnostics: Option<&'a Diagnostics>,
+    metrics: Option<&'a dyn PromptMetrics>,
+    rate_limiter: Option<&'a RateLimiter>,
+    on_event: events::EventHook<'a>,
+    terminal_hooks: events::TerminalHooks<'a>,
+    dismiss: Option<&'a DismissHandle>,
+    #[cfg(unix)]
+    cancel_on_interrupt: bool,
+    #[cfg(unix)]
+    unix: unix::Options<'a>,
+    #[cfg(target_os = "macos")]
+    macos: macos::Options<'a>,
+    #[cfg(windows)]
+    windows: windows::Options,
+}
+
+/// Callbacks, translators, and other hooks are shown only as placeholders, and
+/// supervised dialogs show only their label.
+impl<'a> fmt::Debug for ConfirmationDialog<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("ConfirmationDialog");
+        debug
+            .field("binary", &self.binary)
+            .field("title", &self.title)
+            .field("ok", &self.ok)
+            .field("cancel", &self.cancel)
+            .field("not_ok", &self.not_ok)
+            .field("timeout", &self.timeout)
+            .field("localized_defaults", &self.localized_defaults)
+            .field("translator", &self.translator.map(|_| Opaque))
+            .field("charset", &self.charset)
+            .field("on_spawn", &self.on_spawn.map(|_| Opaque))
+            .field("supervisor", &self.supervisor.map(|(_, label)| label))
+            .field("response_timeout", &self.response_timeout)
+            .field("skip_preamble", &self.skip_preamble)
+            .field("transcript", &self.transcript.map(|_| Opaque))
+            .field("diagnostics", &self.diagnostics)
+            .field("metrics", &self.metrics.map(|_| Opaque))
+            .field("rate_limiter", &self.rate_limiter)
+            .field("on_event", &self.on_event.map(|_| Opaque))
+            .field("terminal_hooks", &self.terminal_hooks.map(|_| Opaque))
+            .field("dismiss", &self.dismiss);
+        #[cfg(unix)]
+        debug
+            .field("cancel_on_interrupt", &self.cancel_on_interrupt)
+            .field("unix", &self.unix);
+        #[cfg(target_os = "macos")]
+        debug.field("macos", &self.macos);
+        #[cfg(windows)]
+        debug.field("windows", &self.windows);
+        debug.finish()
+    }
 }
 
 impl<'a> ConfirmationDialog<'a> {
     /// Creates a new ConfirmationDialog using the binary named `pinentry`.
     ///
-    /// Returns `None` if `pinentry` cannot be found in `PATH`.
+    /// Returns `None` if `pinentry` cannot be found in `PATH` (or, on Unix, in the Nix
+    /// and Guix profile directories). With the `tty-pinentry` feature, the
+    /// `pinentry-rs-tty` binary is used if it is installed and `pinentry` is not. The
+    /// result of the search is cached for the lifetime of the process; see
+    /// [`clear_binary_cache`].
     pub fn with_default_binary() -> Option<Self> {
         Self::with_binary("pinentry")
     }
@@ -276,24 +1318,93 @@ impl<'a> ConfirmationDialog<'a> {
     /// - A binary name was provided that cannot be found in `PATH`.
     /// - The binary is found but is not executable.
     pub fn with_binary<T: AsRef<OsStr>>(binary_name: T) -> Option<Self> {
-        which::which(binary_name)
-            .ok()
-            .map(|binary| ConfirmationDialog {
-                binary,
-                title: None,
-                ok: None,
-                cancel: None,
-                not_ok: None,
-                timeout: None,
-            })
+        Self::try_with_binary(binary_name).ok()
+    }
+
+    /// Creates a new ConfirmationDialog using the given path to, or name of, a `pinentry`
+    /// binary, returning the reason if it cannot be used.
+    ///
+    /// This makes the same checks as [`ConfirmationDialog::with_binary`].
+    pub fn try_with_binary<T: AsRef<OsStr>>(
+        binary_name: T,
+    ) -> std::result::Result<Self, InvalidBinaryError> {
+        discovery::find(binary_name.as_ref()).map(Self::with_binary_unchecked)
+    }
+
+    /// Creates a new ConfirmationDialog using the given path to a `pinentry` binary, without
+    /// checking that it exists or is executable.
+    ///
+    /// This is useful if the path has already been validated, or if it is only valid
+    /// in the environment that the binary is spawned in (for example, inside a
+    /// [sandbox](unix::Sandbox)). If the binary cannot be spawned, the dialog returns
+    /// [`Error::Io`].
+    pub fn with_binary_unchecked<T: Into<PathBuf>>(binary: T) -> Self {
+        ConfirmationDialog {
+            binary: binary.into(),
+            title: None,
+            ok: None,
+            cancel: None,
+            not_ok: None,
+            timeout: None,
+            localized_defaults: false,
+            translator: None,
+            charset: Charset::Utf8,
+            on_spawn: None,
+            supervisor: None,
+            response_timeout: None,
+            skip_preamble: false,
+            transcript: None,
+            diagnostics: None,
+            metrics: None,
+            rate_limiter: None,
+            on_event: None,
+            terminal_hooks: None,
+            dismiss: None,
+            #[cfg(unix)]
+            cancel_on_interrupt: false,
+            #[cfg(unix)]
+            unix: unix::Options::default(),
+            #[cfg(target_os = "macos")]
+            macos: macos::Options::default(),
+            #[cfg(windows)]
+            windows: windows::Options::default(),
+        }
+    }
+
+    /// Creates a new ConfirmationDialog from the given end-user configuration (usually read
+    /// with [`Config::load`]).
+    ///
+    /// The first of the configured binaries that can be found is used, and the
+    /// configured timeout and Unix-specific options are applied. Returns `None` if none
+    /// of the configured binaries can be found.
+    pub fn from_config(config: &'a Config) -> Option<Self> {
+        let mut dialog = config.candidates().find_map(Self::with_binary)?;
+        if let Some(timeout) = config.timeout() {
+            dialog.with_timeout(timeout);
+        }
+        #[cfg(unix)]
+        dialog.with_unix_options(&config.unix_options());
+        Some(dialog)
+    }
+
+    /// Creates a new ConfirmationDialog using the binary named `pinentry`, configured from the
+    /// conventional environment variables in the same way that `gpg-agent` launches
+    /// `pinentry` binaries (see [`unix::Options::from_env`]).
+    ///
+    /// Returns `None` if `pinentry` cannot be found in `PATH`.
+    #[cfg(unix)]
+    pub fn from_env() -> Option<Self> {
+        let mut dialog = Self::with_default_binary()?;
+        dialog.with_unix_options(&unix::Options::from_env());
+        Some(dialog)
     }
 
     /// Sets the window title.
     ///
     /// When using this feature you should take care that the window is still identifiable
     /// as the pinentry.
-    pub fn with_title(&mut self, title: &'a str) -> &mut Self {
-        self.title = Some(title);
+    pub fn with_title(&mut self, title: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.title = Some(title.into());
         self
     }
 
@@ -303,8 +1414,8 @@ impl<'a> ConfirmationDialog<'a> {
     /// pinentry is used. Modern versions underline the next character after the
     /// underscore and use the first such underlined character as a keyboard accelerator.
     /// Use a double underscore to escape an underscore.
-    pub fn with_ok(&mut self, ok: &'a str) -> &mut Self {
-        self.ok = Some(ok);
+    pub fn with_ok(&mut self, ok: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.ok = Some(ok.into());
         self
     }
 
@@ -315,8 +1426,8 @@ impl<'a> ConfirmationDialog<'a> {
     /// pinentry is used. Modern versions underline the next character after the
     /// underscore and use the first such underlined character as a keyboard accelerator.
     /// Use a double underscore to escape an underscore.
-    pub fn with_cancel(&mut self, cancel: &'a str) -> &mut Self {
-        self.cancel = Some(cancel);
+    pub fn with_cancel(&mut self, cancel: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.cancel = Some(cancel.into());
         self
     }
 
@@ -329,8 +1440,8 @@ impl<'a> ConfirmationDialog<'a> {
     /// pinentry is used. Modern versions underline the next character after the
     /// underscore and use the first such underlined character as a keyboard accelerator.
     /// Use a double underscore to escape an underscore.
-    pub fn with_not_ok(&mut self, not_ok: &'a str) -> &mut Self {
-        self.not_ok = Some(not_ok);
+    pub fn with_not_ok(&mut self, not_ok: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.not_ok = Some(not_ok.into());
         self
     }
 
@@ -340,9 +1451,161 @@ impl<'a> ConfirmationDialog<'a> {
         self
     }
 
-    /// Asks for confirmation.
+    /// Uses built-in translations of the button texts that have not been set, in the
+    /// language of the process locale (from `LANGUAGE`, `LC_ALL`, `LC_MESSAGES`, or
+    /// `LANG`).
     ///
-    /// Returns:
+    /// See [`PassphraseInput::with_localized_defaults`] for details.
+    pub fn with_localized_defaults(&mut self) -> &mut Self {
+        self.localized_defaults = true;
+        self
+    }
+
+    /// Looks up the texts that this crate provides (see [`DefaultText`]) with the given
+    /// [`Translator`] instead of using the built-in translations.
+    pub fn with_translator(&mut self, translator: &'a dyn Translator) -> &mut Self {
+        self.translator = Some(translator);
+        self
+    }
+
+    /// Sets the character set used by the `pinentry` binary (by default, UTF-8).
+    ///
+    /// See [`PassphraseInput::with_charset`] for details.
+    pub fn with_charset(&mut self, charset: Charset) -> &mut Self {
+        self.charset = charset;
+        self
+    }
+
+    /// Sets a function that will be called with the `pinentry` process each time it is
+    /// spawned.
+    ///
+    /// See [`PassphraseInput::with_spawn_hook`] for details.
+    pub fn with_spawn_hook(&mut self, on_spawn: &'a dyn Fn(&Child)) -> &mut Self {
+        self.on_spawn = Some(on_spawn);
+        self
+    }
+
+    /// Places this dialog under the supervision of the given [`Supervisor`].
+    ///
+    /// See [`PassphraseInput::with_supervisor`] for details.
+    pub fn with_supervisor(&mut self, supervisor: &'a Supervisor, label: &'a str) -> &mut Self {
+        self.supervisor = Some((supervisor, label));
+        self
+    }
+
+    /// Sets how long to wait for each response from the `pinentry` binary, after which
+    /// it is assumed to be wedged: its process is killed, and [`Error::Timeout`] is
+    /// returned.
+    ///
+    /// See [`PassphraseInput::with_response_timeout`] for details.
+    pub fn with_response_timeout(&mut self, response_timeout: Duration) -> &mut Self {
+        self.response_timeout = Some(response_timeout);
+        self
+    }
+
+    /// Skips up to 32 lines that are not Assuan responses (such as banners or blank
+    /// lines printed by a wrapper script) before the `pinentry` binary's greeting.
+    ///
+    /// See [`PassphraseInput::skip_preamble`] for details.
+    pub fn skip_preamble(&mut self) -> &mut Self {
+        self.skip_preamble = true;
+        self
+    }
+
+    /// Records the requests and responses exchanged with the `pinentry` binary in the
+    /// given [`Transcript`].
+    pub fn with_transcript(&mut self, transcript: &'a Transcript) -> &mut Self {
+        self.transcript = Some(transcript);
+        self
+    }
+
+    /// Sends this dialog's diagnostic messages to the given [`Diagnostics`] sink,
+    /// instead of the default.
+    pub fn with_diagnostics(&mut self, diagnostics: &'a Diagnostics) -> &mut Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    /// Reports the outcome and latency of each prompt shown by this dialog to the given
+    /// [`PromptMetrics`].
+    pub fn with_metrics(&mut self, metrics: &'a dyn PromptMetrics) -> &mut Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Counts each prompt shown by this dialog against the given [`RateLimiter`], which
+    /// may be shared with other dialogs.
+    ///
+    /// See [`PassphraseInput::with_rate_limiter`] for details.
+    pub fn with_rate_limiter(&mut self, limiter: &'a RateLimiter) -> &mut Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Sets a function that will be called with each [`LifecycleEvent`] of the prompts
+    /// shown by this dialog.
+    pub fn with_event_hook(&mut self, on_event: &'a dyn Fn(&LifecycleEvent<'_>)) -> &mut Self {
+        self.on_event = Some(on_event);
+        self
+    }
+
+    /// Sets functions that suspend and resume the caller's terminal UI, for
+    /// applications that draw on the terminal that a `curses` or `tty` `pinentry` would
+    /// use.
+    ///
+    /// See [`PassphraseInput::with_terminal_hooks`] for details.
+    pub fn with_terminal_hooks(
+        &mut self,
+        suspend: &'a dyn Fn(),
+        resume: &'a dyn Fn(),
+    ) -> &mut Self {
+        self.terminal_hooks = Some((suspend, resume));
+        self
+    }
+
+    /// Sets a handle with which the application can close this dialog while it is
+    /// shown, after which [`Error::Dismissed`] is returned.
+    ///
+    /// See [`DismissHandle`] for details.
+    pub fn with_dismiss_handle(&mut self, handle: &'a DismissHandle) -> &mut Self {
+        self.dismiss = Some(handle);
+        self
+    }
+
+    /// Cancels the prompt when the user presses Ctrl-C or Ctrl-Z, instead of the
+    /// signal interrupting or stopping this process.
+    ///
+    /// See [`PassphraseInput::cancel_on_interrupt`] for details.
+    #[cfg(unix)]
+    pub fn cancel_on_interrupt(&mut self) -> &mut Self {
+        self.cancel_on_interrupt = true;
+        self
+    }
+
+    /// Sets the Unix-specific options for spawning the `pinentry` binary.
+    #[cfg(unix)]
+    pub fn with_unix_options(&mut self, options: &unix::Options<'a>) -> &mut Self {
+        self.unix = options.clone();
+        self
+    }
+
+    /// Sets the macOS-specific options for presenting the dialog.
+    #[cfg(target_os = "macos")]
+    pub fn with_macos_options(&mut self, options: &macos::Options<'a>) -> &mut Self {
+        self.macos = options.clone();
+        self
+    }
+
+    /// Sets the Windows-specific options for spawning the `pinentry` binary.
+    #[cfg(windows)]
+    pub fn with_windows_options(&mut self, options: &windows::Options) -> &mut Self {
+        self.windows = options.clone();
+        self
+    }
+
+    /// Asks for confirmation.
+    ///
+    /// Returns:
     /// - `Ok(true)` if the "OK" button is selected.
     /// - `Ok(false)` if:
     ///   - the "Cancel" button is selected and the "Not OK" button is disabled.
@@ -350,45 +1613,245 @@ impl<'a> ConfirmationDialog<'a> {
     /// - `Err(Error::Cancelled)` if the "Cancel" button is selected and the "Not OK"
     ///   button is enabled.
     pub fn confirm(&self, query: &str) -> Result<bool> {
-        let mut pinentry = assuan::Connection::open(&self.binary)?;
+        self.check(query)?;
+        rate_limit::acquire(self.rate_limiter)?;
+        let _suspended = events::suspend(self.terminal_hooks);
+        metrics::measure(self.metrics, PromptKind::Confirmation, || {
+            self.confirm_once(query)
+        })
+    }
+
+    fn confirm_once(&self, query: &str) -> Result<bool> {
+        let mut pinentry = assuan::Connection::open(
+            &self.binary,
+            #[cfg(unix)]
+            &self.unix,
+            #[cfg(target_os = "macos")]
+            &self.macos,
+            #[cfg(windows)]
+            &self.windows,
+            self.on_spawn,
+            self.transcript,
+            self.diagnostics,
+            self.response_timeout,
+            self.skip_preamble,
+        )?;
+        pinentry.set_charset(self.charset);
+        if let Some((supervisor, label)) = self.supervisor {
+            pinentry.supervise(supervisor, label);
+        }
+
+        events::run(self.on_event, pinentry, |pinentry| {
+            self.run(pinentry, query)
+        })
+    }
 
-        pinentry.send_request("SETDESC", Some(query))?;
-        if let Some(ok) = &self.ok {
-            pinentry.send_request("SETOK", Some(ok))?;
+    /// Asks for confirmation, using the `pinentry` process of the given session.
+    ///
+    /// Returns the same results as [`ConfirmationDialog::confirm`]. See
+    /// [`PinentrySession`] for details.
+    pub fn confirm_in(&self, session: &mut PinentrySession, query: &str) -> Result<bool> {
+        self.check(query)?;
+        rate_limit::acquire(self.rate_limiter)?;
+        let _suspended = events::suspend(self.terminal_hooks);
+        metrics::measure(self.metrics, PromptKind::Confirmation, || {
+            self.run(session.start(), query)
+        })
+    }
+
+    /// Checks this dialog's settings, without spawning anything.
+    ///
+    /// Returns [`Error::InvalidSetting`] if any text contains a NUL character or is too
+    /// long to send to the `pinentry` binary, or if the timeout is zero.
+    /// [`ConfirmationDialog::confirm`] (and its variants) make the same checks, including of the
+    /// query, before spawning the `pinentry` binary.
+    pub fn validate(&self) -> Result<()> {
+        self.check("")
+    }
+
+    fn check(&self, query: &str) -> Result<()> {
+        check_timeout(self.timeout)?;
+        let timeout = self.timeout.map(|timeout| format!("{}", timeout));
+        assuan::check_settings(&self.settings(query, &timeout, &self.defaults()))
+            .map_err(Error::InvalidSetting)
+    }
+
+    /// Returns true if this dialog is likely to be shown: the `pinentry` binary still
+    /// exists and is executable, and there is somewhere for it to show the dialog: on
+    /// Unix, a display or terminal, and on Windows, an interactive session (see
+    /// `windows::current_session`).
+    ///
+    /// This is a cheap check that doesn't spawn anything, so applications can fall
+    /// back to another way of prompting before committing to a dialog that would fail.
+    /// A `true` result does not guarantee that the `pinentry` binary will be able to
+    /// use the display or terminal (for example, `pinentry-gnome3` also needs a session
+    /// bus).
+    pub fn can_interact(&self) -> bool {
+        if discovery::find(self.binary.as_os_str()).is_err() {
+            return false;
         }
-        if let Some(cancel) = &self.cancel {
-            pinentry.send_request("SETCANCEL", Some(cancel))?;
+
+        #[cfg(unix)]
+        {
+            self.unix.can_display()
         }
-        if let Some(not_ok) = &self.not_ok {
-            pinentry.send_request("SETNOTOK", Some(not_ok))?;
+
+        #[cfg(windows)]
+        {
+            self.windows.can_display()
         }
-        if let Some(timeout) = self.timeout {
-            pinentry.send_request("SETTIMEOUT", Some(&format!("{}", timeout)))?;
+
+        #[cfg(not(any(unix, windows)))]
+        {
+            true
+        }
+    }
+
+    /// Returns the exact sequence of Assuan requests that
+    /// [`ConfirmationDialog::confirm`] would send, without spawning anything.
+    ///
+    /// This is useful for snapshot tests, and for debugging configuration without a
+    /// display.
+    pub fn dry_run(&self, query: &str) -> Vec<String> {
+        let timeout = self.timeout.map(|timeout| format!("{}", timeout));
+        assuan::dry_run(
+            #[cfg(unix)]
+            &self.unix,
+            #[cfg(target_os = "macos")]
+            &self.macos,
+            #[cfg(windows)]
+            &self.windows,
+            self.on_event.is_some(),
+            &self.settings(query, &timeout, &self.defaults()),
+            "CONFIRM",
+        )
+    }
+
+    fn defaults(&self) -> locale::Defaults {
+        let localized = |set: Option<&str>, text| match set {
+            None if self.localized_defaults => Some(locale::translate(self.translator, text)),
+            _ => None,
+        };
+        locale::Defaults {
+            ok: localized(self.ok.as_deref(), DefaultText::Ok),
+            cancel: localized(self.cancel.as_deref(), DefaultText::Cancel),
+            ..Default::default()
         }
+    }
+
+    fn settings<'s>(
+        &'s self,
+        query: &'s str,
+        timeout: &'s Option<String>,
+        defaults: &'s locale::Defaults,
+    ) -> [(&'static str, Option<&'s str>); 6] {
+        [
+            ("SETTITLE", self.title.as_deref()),
+            ("SETDESC", Some(query)),
+            ("SETOK", self.ok.as_deref().or(defaults.ok.as_deref())),
+            (
+                "SETCANCEL",
+                self.cancel.as_deref().or(defaults.cancel.as_deref()),
+            ),
+            ("SETNOTOK", self.not_ok.as_deref()),
+            ("SETTIMEOUT", timeout.as_deref()),
+        ]
+    }
 
-        pinentry
-            .send_request("CONFIRM", None)
+    fn run(&self, pinentry: &mut assuan::Connection, query: &str) -> Result<bool> {
+        let timeout = self.timeout.map(|timeout| format!("{}", timeout));
+        let defaults = self.defaults();
+        #[cfg(unix)]
+        pinentry.set_cancel_on_interrupt(self.cancel_on_interrupt);
+        pinentry.set_dismiss_handle(self.dismiss.cloned());
+        pinentry.apply_settings(&self.settings(query, &timeout, &defaults))?;
+        events::emit(self.on_event, LifecycleEvent::Configured);
+
+        events::prompt(self.on_event, || pinentry.send_request("CONFIRM", None))
             .map(|_| true)
             .or_else(|e| match (&e, self.not_ok.is_some()) {
                 (Error::Cancelled, false) => Ok(false),
-                (Error::Gpg(gpg), true) if gpg.code() == error::GPG_ERR_NOT_CONFIRMED => Ok(false),
+                (Error::NotConfirmed, true) => Ok(false),
                 _ => Err(e),
             })
     }
 }
 
 /// A dialog for showing a message to the user.
+#[derive(Clone)]
 pub struct MessageDialog<'a> {
     binary: PathBuf,
-    title: Option<&'a str>,
-    ok: Option<&'a str>,
+    title: Option<Cow<'a, str>>,
+    ok: Option<Cow<'a, str>>,
     timeout: Option<u16>,
+    localized_defaults: bool,
+    translator: Option<&'a dyn Translator>,
+    charset: Charset,
+    on_spawn: Option<&'a dyn Fn(&Child)>,
+    supervisor: Option<(&'a Supervisor, &'a str)>,
+    response_timeout: Option<Duration>,
+    skip_preamble: bool,
+    transcript: Option<&'a Transcript>,
+    diagnostics: Option<&'a Diagnostics>,
+    metrics: Option<&'a dyn PromptMetrics>,
+    rate_limiter: Option<&'a RateLimiter>,
+    on_event: events::EventHook<'a>,
+    terminal_hooks: events::TerminalHooks<'a>,
+    dismiss: Option<&'a DismissHandle>,
+    #[cfg(unix)]
+    cancel_on_interrupt: bool,
+    #[cfg(unix)]
+    unix: unix::Options<'a>,
+    #[cfg(target_os = "macos")]
+    macos: macos::Options<'a>,
+    #[cfg(windows)]
+    windows: windows::Options,
+}
+
+/// Callbacks, translators, and other hooks are shown only as placeholders, and
+/// supervised dialogs show only their label.
+impl<'a> fmt::Debug for MessageDialog<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("MessageDialog");
+        debug
+            .field("binary", &self.binary)
+            .field("title", &self.title)
+            .field("ok", &self.ok)
+            .field("timeout", &self.timeout)
+            .field("localized_defaults", &self.localized_defaults)
+            .field("translator", &self.translator.map(|_| Opaque))
+            .field("charset", &self.charset)
+            .field("on_spawn", &self.on_spawn.map(|_| Opaque))
+            .field("supervisor", &self.supervisor.map(|(_, label)| label))
+            .field("response_timeout", &self.response_timeout)
+            .field("skip_preamble", &self.skip_preamble)
+            .field("transcript", &self.transcript.map(|_| Opaque))
+            .field("diagnostics", &self.diagnostics)
+            .field("metrics", &self.metrics.map(|_| Opaque))
+            .field("rate_limiter", &self.rate_limiter)
+            .field("on_event", &self.on_event.map(|_| Opaque))
+            .field("terminal_hooks", &self.terminal_hooks.map(|_| Opaque))
+            .field("dismiss", &self.dismiss);
+        #[cfg(unix)]
+        debug
+            .field("cancel_on_interrupt", &self.cancel_on_interrupt)
+            .field("unix", &self.unix);
+        #[cfg(target_os = "macos")]
+        debug.field("macos", &self.macos);
+        #[cfg(windows)]
+        debug.field("windows", &self.windows);
+        debug.finish()
+    }
 }
 
 impl<'a> MessageDialog<'a> {
     /// Creates a new MessageDialog using the binary named `pinentry`.
     ///
-    /// Returns `None` if `pinentry` cannot be found in `PATH`.
+    /// Returns `None` if `pinentry` cannot be found in `PATH` (or, on Unix, in the Nix
+    /// and Guix profile directories). With the `tty-pinentry` feature, the
+    /// `pinentry-rs-tty` binary is used if it is installed and `pinentry` is not. The
+    /// result of the search is cached for the lifetime of the process; see
+    /// [`clear_binary_cache`].
     pub fn with_default_binary() -> Option<Self> {
         Self::with_binary("pinentry")
     }
@@ -401,20 +1864,91 @@ impl<'a> MessageDialog<'a> {
     /// - A binary name was provided that cannot be found in `PATH`.
     /// - The binary is found but is not executable.
     pub fn with_binary<T: AsRef<OsStr>>(binary_name: T) -> Option<Self> {
-        which::which(binary_name).ok().map(|binary| MessageDialog {
-            binary,
+        Self::try_with_binary(binary_name).ok()
+    }
+
+    /// Creates a new MessageDialog using the given path to, or name of, a `pinentry`
+    /// binary, returning the reason if it cannot be used.
+    ///
+    /// This makes the same checks as [`MessageDialog::with_binary`].
+    pub fn try_with_binary<T: AsRef<OsStr>>(
+        binary_name: T,
+    ) -> std::result::Result<Self, InvalidBinaryError> {
+        discovery::find(binary_name.as_ref()).map(Self::with_binary_unchecked)
+    }
+
+    /// Creates a new MessageDialog using the given path to a `pinentry` binary, without
+    /// checking that it exists or is executable.
+    ///
+    /// This is useful if the path has already been validated, or if it is only valid
+    /// in the environment that the binary is spawned in (for example, inside a
+    /// [sandbox](unix::Sandbox)). If the binary cannot be spawned, the dialog returns
+    /// [`Error::Io`].
+    pub fn with_binary_unchecked<T: Into<PathBuf>>(binary: T) -> Self {
+        MessageDialog {
+            binary: binary.into(),
             title: None,
             ok: None,
             timeout: None,
-        })
+            localized_defaults: false,
+            translator: None,
+            charset: Charset::Utf8,
+            on_spawn: None,
+            supervisor: None,
+            response_timeout: None,
+            skip_preamble: false,
+            transcript: None,
+            diagnostics: None,
+            metrics: None,
+            rate_limiter: None,
+            on_event: None,
+            terminal_hooks: None,
+            dismiss: None,
+            #[cfg(unix)]
+            cancel_on_interrupt: false,
+            #[cfg(unix)]
+            unix: unix::Options::default(),
+            #[cfg(target_os = "macos")]
+            macos: macos::Options::default(),
+            #[cfg(windows)]
+            windows: windows::Options::default(),
+        }
+    }
+
+    /// Creates a new MessageDialog from the given end-user configuration (usually read
+    /// with [`Config::load`]).
+    ///
+    /// The first of the configured binaries that can be found is used, and the
+    /// configured timeout and Unix-specific options are applied. Returns `None` if none
+    /// of the configured binaries can be found.
+    pub fn from_config(config: &'a Config) -> Option<Self> {
+        let mut dialog = config.candidates().find_map(Self::with_binary)?;
+        if let Some(timeout) = config.timeout() {
+            dialog.with_timeout(timeout);
+        }
+        #[cfg(unix)]
+        dialog.with_unix_options(&config.unix_options());
+        Some(dialog)
+    }
+
+    /// Creates a new MessageDialog using the binary named `pinentry`, configured from the
+    /// conventional environment variables in the same way that `gpg-agent` launches
+    /// `pinentry` binaries (see [`unix::Options::from_env`]).
+    ///
+    /// Returns `None` if `pinentry` cannot be found in `PATH`.
+    #[cfg(unix)]
+    pub fn from_env() -> Option<Self> {
+        let mut dialog = Self::with_default_binary()?;
+        dialog.with_unix_options(&unix::Options::from_env());
+        Some(dialog)
     }
 
     /// Sets the window title.
     ///
     /// When using this feature you should take care that the window is still identifiable
     /// as the pinentry.
-    pub fn with_title(&mut self, title: &'a str) -> &mut Self {
-        self.title = Some(title);
+    pub fn with_title(&mut self, title: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.title = Some(title.into());
         self
     }
 
@@ -424,8 +1958,8 @@ impl<'a> MessageDialog<'a> {
     /// pinentry is used. Modern versions underline the next character after the
     /// underscore and use the first such underlined character as a keyboard accelerator.
     /// Use a double underscore to escape an underscore.
-    pub fn with_ok(&mut self, ok: &'a str) -> &mut Self {
-        self.ok = Some(ok);
+    pub fn with_ok(&mut self, ok: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.ok = Some(ok.into());
         self
     }
 
@@ -435,18 +1969,744 @@ impl<'a> MessageDialog<'a> {
         self
     }
 
+    /// Uses built-in translations of the button texts that have not been set, in the
+    /// language of the process locale (from `LANGUAGE`, `LC_ALL`, `LC_MESSAGES`, or
+    /// `LANG`).
+    ///
+    /// See [`PassphraseInput::with_localized_defaults`] for details.
+    pub fn with_localized_defaults(&mut self) -> &mut Self {
+        self.localized_defaults = true;
+        self
+    }
+
+    /// Looks up the texts that this crate provides (see [`DefaultText`]) with the given
+    /// [`Translator`] instead of using the built-in translations.
+    pub fn with_translator(&mut self, translator: &'a dyn Translator) -> &mut Self {
+        self.translator = Some(translator);
+        self
+    }
+
+    /// Sets the character set used by the `pinentry` binary (by default, UTF-8).
+    ///
+    /// See [`PassphraseInput::with_charset`] for details.
+    pub fn with_charset(&mut self, charset: Charset) -> &mut Self {
+        self.charset = charset;
+        self
+    }
+
+    /// Sets a function that will be called with the `pinentry` process each time it is
+    /// spawned.
+    ///
+    /// See [`PassphraseInput::with_spawn_hook`] for details.
+    pub fn with_spawn_hook(&mut self, on_spawn: &'a dyn Fn(&Child)) -> &mut Self {
+        self.on_spawn = Some(on_spawn);
+        self
+    }
+
+    /// Places this dialog under the supervision of the given [`Supervisor`].
+    ///
+    /// See [`PassphraseInput::with_supervisor`] for details.
+    pub fn with_supervisor(&mut self, supervisor: &'a Supervisor, label: &'a str) -> &mut Self {
+        self.supervisor = Some((supervisor, label));
+        self
+    }
+
+    /// Sets how long to wait for each response from the `pinentry` binary, after which
+    /// it is assumed to be wedged: its process is killed, and [`Error::Timeout`] is
+    /// returned.
+    ///
+    /// See [`PassphraseInput::with_response_timeout`] for details.
+    pub fn with_response_timeout(&mut self, response_timeout: Duration) -> &mut Self {
+        self.response_timeout = Some(response_timeout);
+        self
+    }
+
+    /// Skips up to 32 lines that are not Assuan responses (such as banners or blank
+    /// lines printed by a wrapper script) before the `pinentry` binary's greeting.
+    ///
+    /// See [`PassphraseInput::skip_preamble`] for details.
+    pub fn skip_preamble(&mut self) -> &mut Self {
+        self.skip_preamble = true;
+        self
+    }
+
+    /// Records the requests and responses exchanged with the `pinentry` binary in the
+    /// given [`Transcript`].
+    pub fn with_transcript(&mut self, transcript: &'a Transcript) -> &mut Self {
+        self.transcript = Some(transcript);
+        self
+    }
+
+    /// Sends this dialog's diagnostic messages to the given [`Diagnostics`] sink,
+    /// instead of the default.
+    pub fn with_diagnostics(&mut self, diagnostics: &'a Diagnostics) -> &mut Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    /// Reports the outcome and latency of each prompt shown by this dialog to the given
+    /// [`PromptMetrics`].
+    pub fn with_metrics(&mut self, metrics: &'a dyn PromptMetrics) -> &mut Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Counts each prompt shown by this dialog against the given [`RateLimiter`], which
+    /// may be shared with other dialogs.
+    ///
+    /// See [`PassphraseInput::with_rate_limiter`] for details.
+    pub fn with_rate_limiter(&mut self, limiter: &'a RateLimiter) -> &mut Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Sets a function that will be called with each [`LifecycleEvent`] of the prompts
+    /// shown by this dialog.
+    pub fn with_event_hook(&mut self, on_event: &'a dyn Fn(&LifecycleEvent<'_>)) -> &mut Self {
+        self.on_event = Some(on_event);
+        self
+    }
+
+    /// Sets functions that suspend and resume the caller's terminal UI, for
+    /// applications that draw on the terminal that a `curses` or `tty` `pinentry` would
+    /// use.
+    ///
+    /// See [`PassphraseInput::with_terminal_hooks`] for details.
+    pub fn with_terminal_hooks(
+        &mut self,
+        suspend: &'a dyn Fn(),
+        resume: &'a dyn Fn(),
+    ) -> &mut Self {
+        self.terminal_hooks = Some((suspend, resume));
+        self
+    }
+
+    /// Sets a handle with which the application can close this dialog while it is
+    /// shown, after which [`Error::Dismissed`] is returned.
+    ///
+    /// See [`DismissHandle`] for details.
+    pub fn with_dismiss_handle(&mut self, handle: &'a DismissHandle) -> &mut Self {
+        self.dismiss = Some(handle);
+        self
+    }
+
+    /// Cancels the prompt when the user presses Ctrl-C or Ctrl-Z, instead of the
+    /// signal interrupting or stopping this process.
+    ///
+    /// See [`PassphraseInput::cancel_on_interrupt`] for details.
+    #[cfg(unix)]
+    pub fn cancel_on_interrupt(&mut self) -> &mut Self {
+        self.cancel_on_interrupt = true;
+        self
+    }
+
+    /// Sets the Unix-specific options for spawning the `pinentry` binary.
+    #[cfg(unix)]
+    pub fn with_unix_options(&mut self, options: &unix::Options<'a>) -> &mut Self {
+        self.unix = options.clone();
+        self
+    }
+
+    /// Sets the macOS-specific options for presenting the dialog.
+    #[cfg(target_os = "macos")]
+    pub fn with_macos_options(&mut self, options: &macos::Options<'a>) -> &mut Self {
+        self.macos = options.clone();
+        self
+    }
+
+    /// Sets the Windows-specific options for spawning the `pinentry` binary.
+    #[cfg(windows)]
+    pub fn with_windows_options(&mut self, options: &windows::Options) -> &mut Self {
+        self.windows = options.clone();
+        self
+    }
+
     /// Shows a message.
     pub fn show_message(&self, message: &str) -> Result<()> {
-        let mut pinentry = assuan::Connection::open(&self.binary)?;
+        self.check(message)?;
+        rate_limit::acquire(self.rate_limiter)?;
+        let _suspended = events::suspend(self.terminal_hooks);
+        metrics::measure(self.metrics, PromptKind::Message, || {
+            self.show_message_once(message)
+        })
+    }
 
-        pinentry.send_request("SETDESC", Some(message))?;
-        if let Some(ok) = &self.ok {
-            pinentry.send_request("SETOK", Some(ok))?;
+    fn show_message_once(&self, message: &str) -> Result<()> {
+        let mut pinentry = assuan::Connection::open(
+            &self.binary,
+            #[cfg(unix)]
+            &self.unix,
+            #[cfg(target_os = "macos")]
+            &self.macos,
+            #[cfg(windows)]
+            &self.windows,
+            self.on_spawn,
+            self.transcript,
+            self.diagnostics,
+            self.response_timeout,
+            self.skip_preamble,
+        )?;
+        pinentry.set_charset(self.charset);
+        if let Some((supervisor, label)) = self.supervisor {
+            pinentry.supervise(supervisor, label);
         }
-        if let Some(timeout) = self.timeout {
-            pinentry.send_request("SETTIMEOUT", Some(&format!("{}", timeout)))?;
+
+        events::run(self.on_event, pinentry, |pinentry| {
+            self.run(pinentry, message)
+        })
+    }
+
+    /// Shows a message, using the `pinentry` process of the given session.
+    ///
+    /// See [`PinentrySession`] for details.
+    pub fn show_message_in(&self, session: &mut PinentrySession, message: &str) -> Result<()> {
+        self.check(message)?;
+        rate_limit::acquire(self.rate_limiter)?;
+        let _suspended = events::suspend(self.terminal_hooks);
+        metrics::measure(self.metrics, PromptKind::Message, || {
+            self.run(session.start(), message)
+        })
+    }
+
+    /// Checks this dialog's settings, without spawning anything.
+    ///
+    /// Returns [`Error::InvalidSetting`] if any text contains a NUL character or is too
+    /// long to send to the `pinentry` binary, or if the timeout is zero.
+    /// [`MessageDialog::show_message`] (and its variants) make the same checks, including of the
+    /// message, before spawning the `pinentry` binary.
+    pub fn validate(&self) -> Result<()> {
+        self.check("")
+    }
+
+    fn check(&self, message: &str) -> Result<()> {
+        check_timeout(self.timeout)?;
+        let timeout = self.timeout.map(|timeout| format!("{}", timeout));
+        assuan::check_settings(&self.settings(message, &timeout, &self.defaults()))
+            .map_err(Error::InvalidSetting)
+    }
+
+    /// Returns true if this dialog is likely to be shown: the `pinentry` binary still
+    /// exists and is executable, and there is somewhere for it to show the dialog: on
+    /// Unix, a display or terminal, and on Windows, an interactive session (see
+    /// `windows::current_session`).
+    ///
+    /// This is a cheap check that doesn't spawn anything, so applications can fall
+    /// back to another way of prompting before committing to a dialog that would fail.
+    /// A `true` result does not guarantee that the `pinentry` binary will be able to
+    /// use the display or terminal (for example, `pinentry-gnome3` also needs a session
+    /// bus).
+    pub fn can_interact(&self) -> bool {
+        if discovery::find(self.binary.as_os_str()).is_err() {
+            return false;
+        }
+
+        #[cfg(unix)]
+        {
+            self.unix.can_display()
+        }
+
+        #[cfg(windows)]
+        {
+            self.windows.can_display()
+        }
+
+        #[cfg(not(any(unix, windows)))]
+        {
+            true
         }
+    }
+
+    /// Returns the exact sequence of Assuan requests that
+    /// [`MessageDialog::show_message`] would send, without spawning anything.
+    ///
+    /// This is useful for snapshot tests, and for debugging configuration without a
+    /// display.
+    pub fn dry_run(&self, message: &str) -> Vec<String> {
+        let timeout = self.timeout.map(|timeout| format!("{}", timeout));
+        assuan::dry_run(
+            #[cfg(unix)]
+            &self.unix,
+            #[cfg(target_os = "macos")]
+            &self.macos,
+            #[cfg(windows)]
+            &self.windows,
+            self.on_event.is_some(),
+            &self.settings(message, &timeout, &self.defaults()),
+            "MESSAGE",
+        )
+    }
+
+    fn defaults(&self) -> locale::Defaults {
+        locale::Defaults {
+            ok: match self.ok {
+                None if self.localized_defaults => {
+                    Some(locale::translate(self.translator, DefaultText::Ok))
+                }
+                _ => None,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn settings<'s>(
+        &'s self,
+        message: &'s str,
+        timeout: &'s Option<String>,
+        defaults: &'s locale::Defaults,
+    ) -> [(&'static str, Option<&'s str>); 4] {
+        [
+            ("SETTITLE", self.title.as_deref()),
+            ("SETDESC", Some(message)),
+            ("SETOK", self.ok.as_deref().or(defaults.ok.as_deref())),
+            ("SETTIMEOUT", timeout.as_deref()),
+        ]
+    }
+
+    fn run(&self, pinentry: &mut assuan::Connection, message: &str) -> Result<()> {
+        let timeout = self.timeout.map(|timeout| format!("{}", timeout));
+        let defaults = self.defaults();
+        #[cfg(unix)]
+        pinentry.set_cancel_on_interrupt(self.cancel_on_interrupt);
+        pinentry.set_dismiss_handle(self.dismiss.cloned());
+        pinentry.apply_settings(&self.settings(message, &timeout, &defaults))?;
+        events::emit(self.on_event, LifecycleEvent::Configured);
+
+        events::prompt(self.on_event, || pinentry.send_request("MESSAGE", None)).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DefaultText, PassphraseInput, Translator};
+
+    #[cfg(unix)]
+    #[test]
+    fn dry_run() {
+        let mut input = PassphraseInput::with_binary("/bin/sh").unwrap();
+        input
+            .with_unix_options(
+                crate::unix::Options::new()
+                    .tty_name("/dev/tty")
+                    .tty_type("dumb"),
+            )
+            .with_description(format!("Enter {}% of your passphrase", 100))
+            .with_timeout(30);
+        assert_eq!(
+            input.dry_run(),
+            [
+                "OPTION ttyname=/dev/tty",
+                "OPTION ttytype=dumb",
+                "SETDESC Enter 100%25 of your passphrase",
+                "SETTIMEOUT 30",
+                "GETPIN",
+                "BYE",
+            ],
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn titles() {
+        use super::{ConfirmationDialog, MessageDialog};
+
+        let options = crate::unix::Options::new()
+            .tty_name("/dev/tty")
+            .tty_type("dumb")
+            .clone();
+        let mut dialog = ConfirmationDialog::with_binary("/bin/sh").unwrap();
+        dialog.with_unix_options(&options).with_title("FooBar");
+        assert_eq!(dialog.dry_run("Continue?")[2], "SETTITLE FooBar");
+        let mut dialog = MessageDialog::with_binary("/bin/sh").unwrap();
+        dialog.with_unix_options(&options).with_title("FooBar");
+        assert_eq!(dialog.dry_run("Done")[2], "SETTITLE FooBar");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn translated_defaults() {
+        struct Shouty;
+
+        impl Translator for Shouty {
+            fn translate(&self, text: DefaultText) -> Option<String> {
+                match text {
+                    DefaultText::Cancel => None,
+                    _ => Some(text.builtin().to_uppercase()),
+                }
+            }
+        }
+
+        let mut input = PassphraseInput::with_binary("/bin/sh").unwrap();
+        input
+            .with_unix_options(crate::unix::Options::new().tty_type("dumb"))
+            .with_translator(&Shouty)
+            .with_localized_defaults()
+            .with_ok("Unlock")
+            .with_confirmation_prompt("Again:");
+        let requests = input.dry_run();
+        assert_eq!(requests[2], "SETOK Unlock");
+        assert_eq!(
+            requests[3],
+            format!("SETCANCEL {}", DefaultText::Cancel.builtin())
+        );
+        assert_eq!(requests[4], "SETREPEAT Again:");
+        assert_eq!(
+            requests[5],
+            format!(
+                "SETREPEATERROR {}",
+                DefaultText::PassphraseMismatch.builtin().to_uppercase(),
+            ),
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn template() {
+        let on_spawn = |_: &std::process::Child| ();
+        let mut template = PassphraseInput::with_binary("/bin/sh").unwrap();
+        template
+            .with_unix_options(crate::unix::Options::new().tty_type("dumb"))
+            .with_spawn_hook(&on_spawn)
+            .with_title("FooBar");
+
+        let mut input = template.clone();
+        input.with_description("Enter passphrase for FooBar");
+        assert_eq!(template.dry_run().len() + 1, input.dry_run().len());
+
+        let debug = format!("{:?}", input);
+        assert!(debug.contains(r#"description: Some("Enter passphrase for FooBar")"#));
+        assert!(debug.contains("on_spawn: Some(..)"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_settings() {
+        use super::{ConfirmationDialog, Error, InvalidSettingReason};
+
+        let reason = |res: crate::Result<()>| match res {
+            Err(Error::InvalidSetting(e)) => Some((e.command().to_owned(), e.reason())),
+            _ => None,
+        };
+
+        let mut input = PassphraseInput::with_binary("/bin/sh").unwrap();
+        assert!(input.validate().is_ok());
+        input.with_confirmation_prompt("Again:");
+        assert_eq!(
+            reason(input.validate()),
+            Some((
+                "SETREPEAT".to_owned(),
+                InvalidSettingReason::ConfirmationWithoutPrompt
+            ))
+        );
+        input.with_prompt("Passphrase:").with_title("Foo\0Bar");
+        // This fails before spawning `/bin/sh`, which would never respond.
+        assert_eq!(
+            reason(input.interact().map(|_| ())),
+            Some(("SETTITLE".to_owned(), InvalidSettingReason::ContainsNul))
+        );
+
+        let mut dialog = ConfirmationDialog::with_binary("/bin/sh").unwrap();
+        dialog.with_timeout(0);
+        assert_eq!(
+            reason(dialog.validate()),
+            Some(("SETTIMEOUT".to_owned(), InvalidSettingReason::ZeroTimeout))
+        );
+        dialog.with_timeout(30);
+        assert_eq!(
+            reason(dialog.confirm(&"%".repeat(400)).map(|_| ())),
+            Some(("SETDESC".to_owned(), InvalidSettingReason::TooLong))
+        );
+    }
+
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn policy() {
+        use secrecy::ExposeSecret;
+
+        use crate::{policy::PassphrasePolicy, test_util::Harness};
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("GETPIN", &["OK"])
+            .expect("SETERROR Passphrase is required", &["OK"])
+            .expect("GETPIN", &["D hunter2", "OK"])
+            .expect(
+                "SETERROR Passphrase must be at least 8 characters long",
+                &["OK"],
+            )
+            .expect("GETPIN", &["D correct horse", "OK"]);
+
+        let mut policy = PassphrasePolicy::new();
+        policy.with_min_length(8);
+        let mut input = PassphraseInput::with_binary(harness.path()).unwrap();
+        input
+            .with_unix_options(&Harness::unix_options())
+            .required("Passphrase is required")
+            .with_policy(&policy);
+        assert_eq!(input.interact().unwrap().expose_secret(), "correct horse");
+        harness.assert_complete();
+    }
+
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn dismiss() {
+        use std::thread;
+        use std::time::Duration;
+
+        use crate::{test_util::Harness, DismissHandle, Error, MessageDialog};
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("SETDESC Touch your security key", &["OK"])
+            .expect("MESSAGE", &[]);
+
+        let handle = DismissHandle::new();
+        let dismisser = {
+            let handle = handle.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                handle.dismiss();
+            })
+        };
+        let mut dialog = MessageDialog::with_binary(harness.path()).unwrap();
+        dialog
+            .with_unix_options(&Harness::unix_options())
+            .with_dismiss_handle(&handle);
+        assert!(matches!(
+            dialog.show_message("Touch your security key"),
+            Err(Error::Dismissed)
+        ));
+        dismisser.join().unwrap();
+
+        // A dialog shown with a dismissed handle is closed straight away.
+        assert!(matches!(
+            dialog.show_message("Touch your security key"),
+            Err(Error::Dismissed)
+        ));
+    }
+
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn cancel_on_interrupt() {
+        use std::thread;
+        use std::time::Duration;
+
+        use crate::{signals, test_util::Harness, Error};
+
+        let mut harness = Harness::new();
+        harness.expect_options().expect("GETPIN", &[]);
+
+        let interrupt = thread::spawn(|| {
+            while !signals::is_installed() {
+                thread::sleep(Duration::from_millis(10));
+            }
+            signals::simulate();
+        });
+        let mut input = PassphraseInput::with_binary(harness.path()).unwrap();
+        input
+            .with_unix_options(&Harness::unix_options())
+            .cancel_on_interrupt();
+        assert!(matches!(input.interact(), Err(Error::Cancelled)));
+        interrupt.join().unwrap();
+        assert!(!signals::is_installed());
+    }
+
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn terminal_hooks() {
+        use std::cell::RefCell;
+
+        use crate::{test_util::Harness, Child, Error};
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("GETPIN", &["ERR 83886179 Operation cancelled"]);
+
+        let calls = RefCell::new(vec![]);
+        let suspend = || calls.borrow_mut().push("suspend");
+        let resume = || calls.borrow_mut().push("resume");
+        let on_spawn = |_: &Child| calls.borrow_mut().push("spawn");
+        let mut input = PassphraseInput::with_binary(harness.path()).unwrap();
+        input
+            .with_unix_options(&Harness::unix_options())
+            .with_spawn_hook(&on_spawn)
+            .with_terminal_hooks(&suspend, &resume);
+        assert!(matches!(input.interact(), Err(Error::Cancelled)));
+        harness.assert_complete();
+        assert_eq!(*calls.borrow(), ["suspend", "spawn", "resume"]);
+    }
+
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn posix_spawn() {
+        use std::cell::Cell;
+
+        use secrecy::ExposeSecret;
+
+        use crate::{test_util::Harness, Child};
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("GETPIN", &["D hunter2", "OK"]);
+
+        let mut options = Harness::unix_options();
+        options.posix_spawn().new_session();
+        let mut input = PassphraseInput::with_binary(harness.path()).unwrap();
+        input.with_unix_options(&options);
+        assert_eq!(input.interact().unwrap().expose_secret(), "hunter2");
+        harness.assert_complete();
+
+        // The spawn hook is still called with the `Child`.
+        let spawned = Cell::new(false);
+        let on_spawn = |_: &Child| spawned.set(true);
+        input.with_spawn_hook(&on_spawn);
+        assert_eq!(input.interact().unwrap().expose_secret(), "hunter2");
+        assert!(spawned.get());
+    }
+
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn rate_limiter() {
+        use std::time::Duration;
+
+        use secrecy::ExposeSecret;
+
+        use crate::{test_util::Harness, Error, RateLimiter};
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("GETPIN", &["D hunter2", "OK"]);
+
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+        let mut input = PassphraseInput::with_binary(harness.path()).unwrap();
+        input
+            .with_unix_options(&Harness::unix_options())
+            .with_rate_limiter(&limiter);
+        assert_eq!(input.interact().unwrap().expose_secret(), "hunter2");
+        // The second prompt is refused without spawning the binary again.
+        assert!(matches!(input.interact(), Err(Error::RateLimited(_))));
+        harness.assert_complete();
+    }
+
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn no_respawn_after_getpin() {
+        use crate::test_util::Harness;
+
+        let mut harness = Harness::new();
+        harness.expect_options().expect("GETPIN", &[]).exit();
+
+        let mut input = PassphraseInput::with_binary(harness.path()).unwrap();
+        input
+            .with_unix_options(&Harness::unix_options())
+            .respawn_on_crash();
+        // The user may already have been typing, so they are not prompted again.
+        assert!(input.interact().unwrap_err().is_exited());
+        harness.assert_complete();
+    }
+
+    #[cfg(all(unix, feature = "kdf", feature = "test-util"))]
+    #[test]
+    fn derive_key() {
+        use crate::{kdf::Kdf, test_util::Harness};
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("GETPIN", &["D hunter2", "OK"]);
+
+        let kdf = Kdf::scrypt(4, 8, 1);
+        let mut input = PassphraseInput::with_binary(harness.path()).unwrap();
+        input.with_unix_options(&Harness::unix_options());
+        let mut key = [0; 32];
+        input
+            .interact_derive_key(&kdf, b"saltsaltsalt", &mut key)
+            .unwrap();
+        harness.assert_complete();
+
+        let mut expected = [0; 32];
+        kdf.derive(b"hunter2", b"saltsaltsalt", &mut expected)
+            .unwrap();
+        assert_eq!(key, expected);
+
+        // Invalid parameters are rejected without showing the dialog.
+        assert!(input
+            .interact_derive_key(&Kdf::argon2id(64, 1, 1), b"salt", &mut key)
+            .is_err());
+    }
+
+    #[cfg(all(unix, feature = "diceware", feature = "test-util"))]
+    #[test]
+    fn generator() {
+        use secrecy::ExposeSecret;
+
+        use crate::{
+            diceware::{Diceware, Wordlist},
+            test_util::Harness,
+            Transcript,
+        };
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("SETGENPIN _Generate", &["OK"])
+            .expect("GETPIN", &["INQUIRE GENPIN"])
+            .expect_prefix("D abacus", &[])
+            .expect("END", &["D abacus abacus abacus", "OK"]);
+
+        let mut diceware = Diceware::new(Wordlist::new(["abacus", "abacuses"]).unwrap());
+        diceware.with_words(3);
+        let transcript = Transcript::new();
+        let mut input = PassphraseInput::with_binary(harness.path()).unwrap();
+        input
+            .with_unix_options(&Harness::unix_options())
+            .with_transcript(&transcript)
+            .with_generator(&diceware, "_Generate");
+        assert_eq!(
+            input.interact().unwrap().expose_secret(),
+            "abacus abacus abacus"
+        );
+        harness.assert_complete();
+        assert!(transcript.lines().contains(&"> D [REDACTED]".to_owned()));
+    }
+
+    #[cfg(all(unix, feature = "diceware", feature = "test-util"))]
+    #[test]
+    fn default_generator() {
+        use crate::test_util::Harness;
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("GETPIN", &["INQUIRE GENPIN"])
+            .expect_prefix("D ", &[])
+            .expect("END", &["D generated", "OK"]);
+
+        let mut input = PassphraseInput::with_binary(harness.path()).unwrap();
+        input.with_unix_options(&Harness::unix_options());
+        assert!(input.interact().is_ok());
+        harness.assert_complete();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn can_interact() {
+        let mut input = PassphraseInput::with_binary_unchecked("/nonexistent/pinentry");
+        assert!(!input.can_interact());
 
-        pinentry.send_request("MESSAGE", None).map(|_| ())
+        input = PassphraseInput::with_binary("/bin/sh").unwrap();
+        // `/dev/null` is not a terminal.
+        input.with_unix_options(crate::unix::Options::new().tty_name("/dev/null"));
+        let has_display = ["DISPLAY", "WAYLAND_DISPLAY"]
+            .iter()
+            .any(|var| std::env::var_os(var).map_or(false, |v| !v.is_empty()));
+        assert_eq!(
+            input.can_interact(),
+            cfg!(target_os = "macos") || has_display
+        );
     }
 }
@@ -0,0 +1,334 @@
+//! A cache of passphrases in the Linux kernel keyring, enabled by the `kernel-keyring`
+//! feature.
+//!
+//! Passphrases are stored as `user` keys, which live only in kernel memory: they are
+//! never written to disk or swap, and disappear when they time out, when the keyring
+//! is cleared, or (for the session keyring) when the login session ends. This lets
+//! short-lived command-line tools avoid prompting on every invocation, without running
+//! an agent.
+
+use std::ffi::CString;
+use std::io;
+
+use secrecy::{ExposeSecret, SecretString};
+use zeroize::Zeroizing;
+
+use crate::{PassphraseProvider, PassphraseRequest, Result};
+
+const KEY_SPEC_THREAD_KEYRING: i32 = -1;
+const KEY_SPEC_SESSION_KEYRING: i32 = -3;
+const KEY_SPEC_USER_KEYRING: i32 = -4;
+
+const KEYCTL_SETPERM: libc::c_long = 5;
+const KEYCTL_LINK: libc::c_long = 8;
+const KEYCTL_UNLINK: libc::c_long = 9;
+const KEYCTL_SEARCH: libc::c_long = 10;
+const KEYCTL_READ: libc::c_long = 11;
+const KEYCTL_SET_TIMEOUT: libc::c_long = 15;
+const KEYCTL_INVALIDATE: libc::c_long = 21;
+
+/// Keys found by `KEYCTL_SEARCH` are not linked into any other keyring.
+const NO_DESTINATION: libc::c_long = 0;
+
+/// The possessor may do anything with the key.
+const KEY_POS_ALL: u32 = 0x3f00_0000;
+/// Other processes of the same user may view, read, search for, and time out the key.
+const KEY_USR_ACCESS: u32 = 0x0001_0000 | 0x0002_0000 | 0x0008_0000 | 0x0020_0000;
+
+/// The prefix of the description of every key stored by a [`KeyringCache`].
+const DESCRIPTION_PREFIX: &str = "pinentry:";
+
+/// A kernel keyring that a [`KeyringCache`] stores passphrases in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Keyring {
+    /// The session keyring, which is shared by the processes of a login session (or
+    /// of a `keyctl session`), and discarded when the session ends.
+    Session,
+    /// The user keyring, which is shared by every process of the user, and kept until
+    /// the user's last process exits.
+    User,
+}
+
+impl Keyring {
+    fn id(self) -> i32 {
+        match self {
+            Keyring::Session => KEY_SPEC_SESSION_KEYRING,
+            Keyring::User => KEY_SPEC_USER_KEYRING,
+        }
+    }
+}
+
+/// A cache of passphrases in a Linux kernel keyring.
+///
+/// Each passphrase is stored under a key chosen by the application (such as the name
+/// or fingerprint of what the passphrase unlocks), as a `user` key described as
+/// `pinentry:<key>`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{linux_keyring::{Keyring, KeyringCache}, PassphraseInput, PassphraseRequest};
+///
+/// if let Some(input) = PassphraseInput::with_default_binary() {
+///     let mut cache = KeyringCache::new(Keyring::Session);
+///     cache.with_timeout(15 * 60);
+///     // Only prompts if the passphrase was not entered in the last 15 minutes.
+///     let passphrase = cache.get_or_prompt(
+///         "foobar/vault",
+///         &input,
+///         PassphraseRequest::new().with_description("Unlock FooBar"),
+///     )?;
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct KeyringCache {
+    keyring: Keyring,
+    timeout: Option<u32>,
+}
+
+impl KeyringCache {
+    /// Creates a cache in the given keyring, whose passphrases do not time out.
+    pub fn new(keyring: Keyring) -> Self {
+        KeyringCache {
+            keyring,
+            timeout: None,
+        }
+    }
+
+    /// Sets the time (in seconds) after which passphrases stored from now on are
+    /// removed from the keyring.
+    ///
+    /// The timeout is set before a passphrase is linked into the keyring, so it is
+    /// never there without one.
+    pub fn with_timeout(&mut self, timeout: u32) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Returns the passphrase stored under the given key, if any.
+    ///
+    /// Passphrases that have timed out are not returned.
+    pub fn get(&self, key: &str) -> io::Result<Option<SecretString>> {
+        let serial = match self.search(key)? {
+            Some(serial) => serial,
+            None => return Ok(None),
+        };
+
+        let mut buf = Zeroizing::new(vec![]);
+        loop {
+            // Safety: `buf` is valid for `buf.len()` bytes.
+            let len = match cvt(unsafe {
+                libc::syscall(
+                    libc::SYS_keyctl,
+                    KEYCTL_READ,
+                    serial,
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                )
+            }) {
+                Ok(len) => len as usize,
+                Err(e) if is_missing(&e) => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            if len <= buf.len() {
+                buf.truncate(len);
+                break;
+            }
+            // The key is larger than the buffer, so nothing was read.
+            buf = Zeroizing::new(vec![0; len]);
+        }
+
+        let passphrase = String::from_utf8(std::mem::take(&mut *buf))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Some(SecretString::from(passphrase)))
+    }
+
+    /// Stores the given passphrase under the given key, replacing any passphrase that
+    /// was stored under it before.
+    pub fn insert(&self, key: &str, passphrase: &SecretString) -> io::Result<()> {
+        let description = description(key)?;
+        let payload = passphrase.expose_secret().as_bytes();
+        // The key is created in the thread keyring (which no other thread can search),
+        // and only linked into the cache's keyring once its permissions and timeout
+        // are set.
+        // Safety: the strings are NUL-terminated, and `payload` is valid for
+        // `payload.len()` bytes.
+        let serial = cvt(unsafe {
+            libc::syscall(
+                libc::SYS_add_key,
+                b"user\0".as_ptr(),
+                description.as_ptr(),
+                payload.as_ptr(),
+                payload.len(),
+                KEY_SPEC_THREAD_KEYRING as libc::c_long,
+            )
+        })?;
+
+        let res = self.configure_and_link(serial);
+        // Safety: `KEYCTL_UNLINK` has no memory-safety preconditions.
+        let _ = cvt(unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                KEYCTL_UNLINK,
+                serial,
+                KEY_SPEC_THREAD_KEYRING as libc::c_long,
+            )
+        });
+        res
+    }
+
+    /// Sets the permissions and timeout of a newly created key, and then links it into
+    /// the cache's keyring (displacing any key stored under the same key).
+    fn configure_and_link(&self, serial: libc::c_long) -> io::Result<()> {
+        if self.keyring == Keyring::User {
+            // Keys are only usable by the processes that possess them by default, which
+            // other processes of the user do not for keys in the user keyring.
+            // Safety: `KEYCTL_SETPERM` has no memory-safety preconditions.
+            cvt(unsafe {
+                libc::syscall(
+                    libc::SYS_keyctl,
+                    KEYCTL_SETPERM,
+                    serial,
+                    (KEY_POS_ALL | KEY_USR_ACCESS) as libc::c_long,
+                )
+            })?;
+        }
+        if let Some(timeout) = self.timeout {
+            // Safety: `KEYCTL_SET_TIMEOUT` has no memory-safety preconditions.
+            cvt(unsafe {
+                libc::syscall(
+                    libc::SYS_keyctl,
+                    KEYCTL_SET_TIMEOUT,
+                    serial,
+                    timeout as libc::c_long,
+                )
+            })?;
+        }
+        // Safety: `KEYCTL_LINK` has no memory-safety preconditions.
+        cvt(unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                KEYCTL_LINK,
+                serial,
+                self.keyring.id() as libc::c_long,
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Removes the passphrase stored under the given key, if any.
+    pub fn remove(&self, key: &str) -> io::Result<()> {
+        if let Some(serial) = self.search(key)? {
+            // Safety: `KEYCTL_INVALIDATE` has no memory-safety preconditions.
+            match cvt(unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_INVALIDATE, serial) }) {
+                Err(e) if !is_missing(&e) => return Err(e),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the passphrase stored under the given key, or else asks the given
+    /// provider for it and stores it under the key.
+    ///
+    /// A passphrase that could not be stored is still returned.
+    pub fn get_or_prompt(
+        &self,
+        key: &str,
+        provider: &dyn PassphraseProvider,
+        request: &PassphraseRequest<'_>,
+    ) -> Result<SecretString> {
+        if let Some(passphrase) = self.get(key)? {
+            return Ok(passphrase);
+        }
+        let passphrase = provider.passphrase(request)?;
+        let _ = self.insert(key, &passphrase);
+        Ok(passphrase)
+    }
+
+    /// Returns a [`PassphraseProvider`] that returns the passphrase stored under the
+    /// given key, or an error of kind [`io::ErrorKind::NotFound`] if there is none.
+    ///
+    /// This can be a source in a [`FallbackChain`](crate::FallbackChain), ahead of the
+    /// sources that prompt.
+    pub fn provider<'a>(&'a self, key: &'a str) -> impl PassphraseProvider + 'a {
+        move |_: &PassphraseRequest<'_>| -> Result<SecretString> {
+            match self.get(key)? {
+                Some(passphrase) => Ok(passphrase),
+                None => Err(io::Error::new(io::ErrorKind::NotFound, "not in the keyring").into()),
+            }
+        }
+    }
+
+    /// Returns the serial number of the key stored under the given key, if any.
+    fn search(&self, key: &str) -> io::Result<Option<libc::c_long>> {
+        let description = description(key)?;
+        // Safety: the strings are NUL-terminated.
+        match cvt(unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                KEYCTL_SEARCH,
+                self.keyring.id() as libc::c_long,
+                b"user\0".as_ptr(),
+                description.as_ptr(),
+                NO_DESTINATION,
+            )
+        }) {
+            Ok(serial) => Ok(Some(serial)),
+            Err(e) if is_missing(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn description(key: &str) -> io::Result<CString> {
+    CString::new(format!("{}{}", DESCRIPTION_PREFIX, key))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Returns true if the error means that the key does not exist (any more).
+fn is_missing(e: &io::Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(libc::ENOKEY) | Some(libc::EKEYEXPIRED) | Some(libc::EKEYREVOKED)
+    )
+}
+
+fn cvt(ret: libc::c_long) -> io::Result<libc::c_long> {
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::{ExposeSecret, SecretString};
+
+    use super::{Keyring, KeyringCache};
+    use crate::PassphraseRequest;
+
+    #[test]
+    fn roundtrip() {
+        let key = format!("test/{}", std::process::id());
+        let cache = KeyringCache::new(Keyring::Session);
+        match cache.get(&key) {
+            // The kernel keyring is unavailable (or blocked by seccomp, as in many
+            // containers).
+            Err(e) if matches!(e.raw_os_error(), Some(libc::ENOSYS) | Some(libc::EPERM)) => return,
+            res => assert!(res.unwrap().is_none()),
+        }
+
+        let prompt = |_: &PassphraseRequest<'_>| Ok(SecretString::from("hunter2"));
+        let passphrase = cache
+            .get_or_prompt(&key, &prompt, &PassphraseRequest::new())
+            .unwrap();
+        assert_eq!(passphrase.expose_secret(), "hunter2");
+        assert_eq!(cache.get(&key).unwrap().unwrap().expose_secret(), "hunter2");
+
+        cache.remove(&key).unwrap();
+        assert!(cache.get(&key).unwrap().is_none());
+    }
+}
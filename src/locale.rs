@@ -0,0 +1,311 @@
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Once;
+
+use crate::policy::PolicyViolation;
+
+/// A string that this crate shows in dialogs when the application doesn't provide one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DefaultText {
+    /// The text for the button signalling confirmation.
+    Ok,
+    /// The text for the button signalling cancellation.
+    Cancel,
+    /// The error shown when the confirmation of a new passphrase does not match.
+    PassphraseMismatch,
+    /// The prompt of a [`ChoiceDialog`](crate::ChoiceDialog).
+    ChoicePrompt,
+    /// The error shown by a [`ChoiceDialog`](crate::ChoiceDialog) when the user enters
+    /// anything other than the number of a choice.
+    InvalidChoice {
+        /// The number of choices.
+        choices: usize,
+    },
+    /// The error shown when a new passphrase breaks the
+    /// [`PassphrasePolicy`](crate::policy::PassphrasePolicy) of the dialog.
+    PolicyViolation(PolicyViolation),
+}
+
+/// The built-in translations of the [`DefaultText`]s for a single language.
+struct Translation {
+    language: &'static str,
+    ok: &'static str,
+    cancel: &'static str,
+    passphrase_mismatch: &'static str,
+}
+
+/// Built-in translations. English must come first, as it is the fallback.
+const CATALOG: &[Translation] = &[
+    Translation {
+        language: "en",
+        ok: "_OK",
+        cancel: "_Cancel",
+        passphrase_mismatch: "Passphrases do not match",
+    },
+    Translation {
+        language: "cs",
+        ok: "_OK",
+        cancel: "_Zrušit",
+        passphrase_mismatch: "Hesla se neshodují",
+    },
+    Translation {
+        language: "da",
+        ok: "_OK",
+        cancel: "_Annuller",
+        passphrase_mismatch: "Adgangsfraserne stemmer ikke overens",
+    },
+    Translation {
+        language: "de",
+        ok: "_OK",
+        cancel: "_Abbrechen",
+        passphrase_mismatch: "Die Passphrasen stimmen nicht überein",
+    },
+    Translation {
+        language: "es",
+        ok: "_Aceptar",
+        cancel: "_Cancelar",
+        passphrase_mismatch: "Las frases de contraseña no coinciden",
+    },
+    Translation {
+        language: "fi",
+        ok: "_OK",
+        cancel: "_Peruuta",
+        passphrase_mismatch: "Salalauseet eivät täsmää",
+    },
+    Translation {
+        language: "fr",
+        ok: "_OK",
+        cancel: "_Annuler",
+        passphrase_mismatch: "Les phrases secrètes ne correspondent pas",
+    },
+    Translation {
+        language: "it",
+        ok: "_OK",
+        cancel: "_Annulla",
+        passphrase_mismatch: "Le passphrase non corrispondono",
+    },
+    Translation {
+        language: "ja",
+        ok: "OK(_O)",
+        cancel: "キャンセル(_C)",
+        passphrase_mismatch: "パスフレーズが一致しません",
+    },
+    Translation {
+        language: "nb",
+        ok: "_OK",
+        cancel: "_Avbryt",
+        passphrase_mismatch: "Passordfrasene er ikke like",
+    },
+    Translation {
+        language: "nl",
+        ok: "_OK",
+        cancel: "_Annuleren",
+        passphrase_mismatch: "Wachtwoordzinnen komen niet overeen",
+    },
+    Translation {
+        language: "pl",
+        ok: "_OK",
+        cancel: "_Anuluj",
+        passphrase_mismatch: "Hasła nie są zgodne",
+    },
+    Translation {
+        language: "pt",
+        ok: "_OK",
+        cancel: "_Cancelar",
+        passphrase_mismatch: "As frases secretas não coincidem",
+    },
+    Translation {
+        language: "ru",
+        ok: "_OK",
+        cancel: "_Отмена",
+        passphrase_mismatch: "Фразы-пароли не совпадают",
+    },
+    Translation {
+        language: "sv",
+        ok: "_OK",
+        cancel: "_Avbryt",
+        passphrase_mismatch: "Lösenfraserna stämmer inte överens",
+    },
+    Translation {
+        language: "tr",
+        ok: "_Tamam",
+        cancel: "_İptal",
+        passphrase_mismatch: "Parolalar eşleşmiyor",
+    },
+    Translation {
+        language: "uk",
+        ok: "_Гаразд",
+        cancel: "_Скасувати",
+        passphrase_mismatch: "Фрази-паролі не збігаються",
+    },
+    Translation {
+        language: "zh",
+        ok: "确定(_O)",
+        cancel: "取消(_C)",
+        passphrase_mismatch: "密码不匹配",
+    },
+];
+
+impl DefaultText {
+    /// Returns the built-in translation of this text in the language of the process
+    /// locale (from `LANGUAGE`, `LC_ALL`, `LC_MESSAGES`, or `LANG`), falling back to
+    /// English if there is no built-in translation for that language.
+    ///
+    /// The texts of [`ChoiceDialog`](crate::ChoiceDialog)s and policy violations are
+    /// only built in in English; use a [`Translator`] to show them in other languages.
+    pub fn builtin(self) -> Cow<'static, str> {
+        let translation = &CATALOG[language()];
+        match self {
+            DefaultText::Ok => translation.ok.into(),
+            DefaultText::Cancel => translation.cancel.into(),
+            DefaultText::PassphraseMismatch => translation.passphrase_mismatch.into(),
+            DefaultText::ChoicePrompt => "Choice:".into(),
+            DefaultText::InvalidChoice { choices } => {
+                format!("Enter a number from 1 to {}", choices).into()
+            }
+            DefaultText::PolicyViolation(violation) => violation.to_string().into(),
+        }
+    }
+}
+
+/// A source of translations for the strings that this crate shows in dialogs.
+///
+/// Applications that use an i18n framework (such as Fluent or gettext) can implement
+/// this trait to look up each [`DefaultText`] in their own translation catalogs.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{DefaultText, PassphraseInput, Translator};
+///
+/// struct Pirate;
+///
+/// impl Translator for Pirate {
+///     fn translate(&self, text: DefaultText) -> Option<String> {
+///         match text {
+///             DefaultText::Ok => Some("_Aye".to_owned()),
+///             DefaultText::Cancel => Some("_Belay that".to_owned()),
+///             _ => None,
+///         }
+///     }
+/// }
+///
+/// if let Some(mut input) = PassphraseInput::with_default_binary() {
+///     let passphrase = input
+///         .with_translator(&Pirate)
+///         .with_localized_defaults()
+///         .with_description("Enter passphrase for FooBar")
+///         .interact()?;
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+pub trait Translator {
+    /// Returns the translation of the given text, or `None` to use the built-in
+    /// translation ([`DefaultText::builtin`]).
+    fn translate(&self, text: DefaultText) -> Option<String>;
+}
+
+/// The strings that this crate provides for a single dialog.
+#[derive(Default)]
+pub(crate) struct Defaults {
+    pub(crate) ok: Option<Cow<'static, str>>,
+    pub(crate) cancel: Option<Cow<'static, str>>,
+    pub(crate) passphrase_mismatch: Option<Cow<'static, str>>,
+}
+
+/// Returns the given text from the given translator, falling back to the built-in
+/// translation.
+pub(crate) fn translate(
+    translator: Option<&dyn Translator>,
+    text: DefaultText,
+) -> Cow<'static, str> {
+    translator
+        .and_then(|translator| translator.translate(text))
+        .map(Cow::Owned)
+        .unwrap_or_else(|| text.builtin())
+}
+
+/// Returns the index in [`CATALOG`] of the process locale's language, which is resolved
+/// once per process.
+fn language() -> usize {
+    static INIT: Once = Once::new();
+    static LANGUAGE: AtomicUsize = AtomicUsize::new(0);
+
+    INIT.call_once(|| {
+        let vars = ["LANGUAGE", "LC_ALL", "LC_MESSAGES", "LANG"];
+        let index = vars
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| find(&value))
+            .unwrap_or(0);
+        LANGUAGE.store(index, Ordering::Release)
+    });
+    LANGUAGE.load(Ordering::Acquire)
+}
+
+/// Finds the first language in a locale name (such as `de_DE.UTF-8`) or a
+/// colon-separated `LANGUAGE` list (such as `pt_BR:pt:en`) that has a built-in
+/// translation.
+fn find(locale: &str) -> Option<usize> {
+    locale
+        .split(':')
+        .map(|locale| {
+            locale
+                .split(|c| c == '_' || c == '.' || c == '@' || c == '-')
+                .next()
+                .unwrap_or_default()
+        })
+        .map(|language| if language == "no" { "nb" } else { language })
+        .find_map(|language| CATALOG.iter().position(|t| t.language == language))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find, CATALOG};
+
+    #[test]
+    fn locale_names() {
+        assert_eq!(find("C"), None);
+        assert_eq!(find("POSIX"), None);
+        assert_eq!(find("de_DE.UTF-8").map(|i| CATALOG[i].language), Some("de"));
+        assert_eq!(find("fr_FR@euro").map(|i| CATALOG[i].language), Some("fr"));
+        assert_eq!(find("xx:pt_BR:en").map(|i| CATALOG[i].language), Some("pt"));
+        assert_eq!(find("no_NO").map(|i| CATALOG[i].language), Some("nb"));
+        assert_eq!(find("en_US.UTF-8"), Some(0));
+    }
+
+    #[test]
+    fn policy_violations() {
+        use super::{translate, DefaultText, Translator};
+        use crate::policy::PolicyViolation;
+
+        struct Terse;
+
+        impl Translator for Terse {
+            fn translate(&self, text: DefaultText) -> Option<String> {
+                match text {
+                    DefaultText::PolicyViolation(PolicyViolation::TooShort { min_length }) => {
+                        Some(format!("Min. {} chars", min_length))
+                    }
+                    _ => None,
+                }
+            }
+        }
+
+        let too_short = DefaultText::PolicyViolation(PolicyViolation::TooShort { min_length: 8 });
+        assert_eq!(
+            translate(None, too_short),
+            "Passphrase must be at least 8 characters long"
+        );
+        assert_eq!(translate(Some(&Terse), too_short), "Min. 8 chars");
+        assert_eq!(
+            translate(
+                Some(&Terse),
+                DefaultText::PolicyViolation(PolicyViolation::Breached)
+            ),
+            "Passphrase has appeared in a data breach"
+        );
+    }
+}
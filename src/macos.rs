@@ -0,0 +1,152 @@
+//! macOS-specific options for presenting `pinentry-mac` dialogs.
+//!
+//! These are applied in addition to the [`unix::Options`](crate::unix::Options) of a
+//! dialog.
+//!
+//! # Keychain and Touch ID
+//!
+//! [`Options::cache_key`] and [`Options::save_by_default`] control the "Save in
+//! Keychain" checkbox of `pinentry-mac`, so that applications can offer to skip the
+//! dialog next time. `pinentry-mac` has no option that makes a saved passphrase
+//! require Touch ID, so none is offered here: whether reading it back prompts the user
+//! is decided by `pinentry-mac` and the access settings of the user's keychain.
+
+use std::borrow::Cow;
+
+use crate::encoding;
+
+/// Checks the "Save in Keychain" checkbox by default.
+const USE_PASSPHRASE_CACHE: &str = "USE_PASSPHRASE_CACHE";
+
+/// macOS-specific options for presenting a `pinentry` dialog.
+///
+/// These target `pinentry-mac`, and are ignored by `pinentry` binaries that do not
+/// support them.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{macos, PassphraseInput};
+///
+/// if let Some(mut input) = PassphraseInput::with_default_binary() {
+///     input
+///         .with_macos_options(macos::Options::new().app_name("FooBar").no_activate())
+///         .with_description("Enter passphrase for FooBar")
+///         .interact()?;
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Options<'a> {
+    app_name: Option<Cow<'a, str>>,
+    no_activate: bool,
+    cache_key: Option<Cow<'a, str>>,
+    save_by_default: bool,
+}
+
+impl<'a> Options<'a> {
+    /// Creates a new set of options with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the name of the application that is asking for the passphrase.
+    ///
+    /// `pinentry-mac` shows its dialogs under its own name, so this is used as the
+    /// window title of dialogs that do not set one (such as with
+    /// [`PassphraseInput::with_title`]).
+    ///
+    /// [`PassphraseInput::with_title`]: crate::PassphraseInput::with_title
+    pub fn app_name(&mut self, app_name: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.app_name = Some(app_name.into());
+        self
+    }
+
+    /// Shows the dialog without activating `pinentry-mac` (via `OPTION no-grab`).
+    ///
+    /// By default, `pinentry-mac` activates itself when it shows a dialog, taking focus
+    /// from the frontmost application. Background tools that prompt while the user is
+    /// working elsewhere can use this to avoid interrupting their typing.
+    pub fn no_activate(&mut self) -> &mut Self {
+        self.no_activate = true;
+        self
+    }
+
+    /// Allows the passphrase to be saved in the user's keychain under the given key
+    /// (via `SETKEYINFO` and `OPTION allow-external-password-cache`).
+    ///
+    /// `pinentry-mac` then shows a "Save in Keychain" checkbox, and if the user has
+    /// saved a passphrase for the key before, returns it without showing a dialog. The
+    /// key should identify what the passphrase unlocks, and be stable across runs (for
+    /// example, the keygrip of a secret key, or an application-specific name). By
+    /// default, passphrases are never saved.
+    pub fn cache_key(&mut self, key: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.cache_key = Some(key.into());
+        self
+    }
+
+    /// Checks the "Save in Keychain" checkbox by default (via `USE_PASSPHRASE_CACHE`).
+    ///
+    /// This has no effect unless [`Options::cache_key`] is set.
+    pub fn save_by_default(&mut self) -> &mut Self {
+        self.save_by_default = true;
+        self
+    }
+
+    /// Returns the environment variables to set for the `pinentry` process.
+    pub(crate) fn env(&self) -> Vec<(&'static str, &'static str)> {
+        let mut env = vec![];
+        if self.cache_key.is_some() && self.save_by_default {
+            env.push((USE_PASSPHRASE_CACHE, "1"));
+        }
+        env
+    }
+
+    /// Returns the encoded requests to send to every newly spawned `pinentry` binary.
+    pub(crate) fn requests(&self) -> Vec<String> {
+        let mut requests = vec![];
+        if self.no_activate {
+            requests.push("OPTION no-grab".to_owned());
+        }
+        if let Some(key) = &self.cache_key {
+            requests.push("OPTION allow-external-password-cache".to_owned());
+            requests.push(format!("SETKEYINFO {}", encoding::encode(key)));
+        }
+        if let Some(app_name) = &self.app_name {
+            requests.push(format!("SETTITLE {}", encoding::encode(app_name)));
+        }
+        requests
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+
+    #[test]
+    fn requests() {
+        assert!(Options::new().requests().is_empty());
+        assert_eq!(
+            Options::new()
+                .app_name("FooBar")
+                .no_activate()
+                .cache_key("foobar/vault")
+                .requests(),
+            [
+                "OPTION no-grab",
+                "OPTION allow-external-password-cache",
+                "SETKEYINFO foobar/vault",
+                "SETTITLE FooBar",
+            ],
+        );
+    }
+
+    #[test]
+    fn env() {
+        let mut options = Options::new();
+        options.save_by_default();
+        assert!(options.env().is_empty());
+        options.cache_key("foobar/vault");
+        assert_eq!(options.env(), [("USE_PASSPHRASE_CACHE", "1")]);
+    }
+}
@@ -0,0 +1,96 @@
+use std::time::{Duration, Instant};
+
+use crate::{Error, Result};
+
+/// The kind of dialog that a prompt was shown with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PromptKind {
+    /// A [`PassphraseInput`](crate::PassphraseInput).
+    Passphrase,
+    /// A [`ConfirmationDialog`](crate::ConfirmationDialog).
+    Confirmation,
+    /// A [`MessageDialog`](crate::MessageDialog).
+    Message,
+}
+
+/// How a prompt finished.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PromptOutcome {
+    /// The user responded to the prompt. This includes declining a confirmation.
+    Completed,
+    /// The user cancelled the prompt.
+    Cancelled,
+    /// The prompt timed out.
+    TimedOut,
+    /// The prompt was closed with a [`DismissHandle`](crate::DismissHandle).
+    Dismissed,
+    /// The prompt failed. Contains the GPG error code, if the `pinentry` binary
+    /// returned an uncommon or unexpected one.
+    Failed(Option<u16>),
+}
+
+impl PromptOutcome {
+    pub(crate) fn of<T>(res: &Result<T>) -> Self {
+        match res {
+            Ok(_) | Err(Error::NotConfirmed) => PromptOutcome::Completed,
+            Err(Error::Cancelled) => PromptOutcome::Cancelled,
+            Err(Error::Timeout) => PromptOutcome::TimedOut,
+            Err(Error::Dismissed) => PromptOutcome::Dismissed,
+            Err(e) => PromptOutcome::Failed(e.gpg_code()),
+        }
+    }
+}
+
+/// Callbacks for collecting metrics about prompts.
+///
+/// Set on a dialog with its `with_metrics` method, this can be used to feed the outcome
+/// and latency of every prompt into a metrics system such as Prometheus or StatsD. All
+/// methods have empty default implementations.
+///
+/// # Examples
+///
+/// ```
+/// use pinentry::{PromptKind, PromptMetrics, PromptOutcome};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::time::Duration;
+///
+/// #[derive(Default)]
+/// struct Counters {
+///     cancelled: AtomicUsize,
+/// }
+///
+/// impl PromptMetrics for Counters {
+///     fn prompt_finished(&self, _: PromptKind, outcome: PromptOutcome, _: Duration) {
+///         if outcome == PromptOutcome::Cancelled {
+///             self.cancelled.fetch_add(1, Ordering::Relaxed);
+///         }
+///     }
+/// }
+/// ```
+pub trait PromptMetrics {
+    /// Called when a prompt is started, before the `pinentry` binary is spawned.
+    fn prompt_started(&self, _kind: PromptKind) {}
+
+    /// Called when a prompt finishes, with its outcome and how long it took.
+    fn prompt_finished(&self, _kind: PromptKind, _outcome: PromptOutcome, _latency: Duration) {}
+}
+
+/// Runs the prompt `f`, reporting it to `metrics` if set.
+pub(crate) fn measure<T>(
+    metrics: Option<&dyn PromptMetrics>,
+    kind: PromptKind,
+    f: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    match metrics {
+        Some(metrics) => {
+            metrics.prompt_started(kind);
+            let start = Instant::now();
+            let res = f();
+            metrics.prompt_finished(kind, PromptOutcome::of(&res), start.elapsed());
+            res
+        }
+        None => f(),
+    }
+}
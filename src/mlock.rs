@@ -0,0 +1,31 @@
+//! Best-effort locking of memory that may hold secrets, enabled by the `mlock` feature.
+//!
+//! Locked memory is kept out of swap (via `mlock` or `VirtualLock`), and excluded from
+//! core dumps where the platform supports it. Locking can fail (for example, if it
+//! would exceed `RLIMIT_MEMLOCK`), in which case the memory is used unlocked.
+
+/// Locks the entire allocation of `buf` (including any spare capacity).
+#[cfg_attr(not(feature = "mlock"), allow(unused_variables, clippy::ptr_arg))]
+pub(crate) fn lock(buf: &mut Vec<u8>) {
+    #[cfg(feature = "mlock")]
+    {
+        if buf.capacity() > 0 {
+            // Safety: the pointer and length describe the allocation owned by `buf`.
+            unsafe { memsec::mlock(buf.as_mut_ptr(), buf.capacity()) };
+        }
+    }
+}
+
+/// Unlocks the entire allocation of `buf` (including any spare capacity).
+///
+/// This must be called before `buf` is reallocated or dropped.
+#[cfg_attr(not(feature = "mlock"), allow(unused_variables, clippy::ptr_arg))]
+pub(crate) fn unlock(buf: &mut Vec<u8>) {
+    #[cfg(feature = "mlock")]
+    {
+        if buf.capacity() > 0 {
+            // Safety: the pointer and length describe the allocation owned by `buf`.
+            unsafe { memsec::munlock(buf.as_mut_ptr(), buf.capacity()) };
+        }
+    }
+}
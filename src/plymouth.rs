@@ -0,0 +1,173 @@
+//! Prompting through Plymouth, the boot splash screen, during early boot.
+//!
+//! While Plymouth is running (for example, in an initramfs that is unlocking an
+//! encrypted disk), it owns the display and the console, so `pinentry` binaries cannot
+//! be shown. Instead, passphrases are requested from Plymouth's daemon with its
+//! ask-for-password protocol, in the same way as `systemd-ask-password` does.
+//!
+//! [`PassphraseInput::interact`] does this automatically whenever [`is_running`] returns
+//! true, unless [`PassphraseInput::without_plymouth`] is set.
+//!
+//! [`PassphraseInput::interact`]: crate::PassphraseInput::interact
+//! [`PassphraseInput::without_plymouth`]: crate::PassphraseInput::without_plymouth
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use secrecy::SecretString;
+use zeroize::Zeroizing;
+
+use crate::unix::connect_abstract;
+use crate::{Error, Result};
+
+/// The name of the abstract socket that Plymouth's daemon listens on.
+const SOCKET_NAME: &[u8] = b"/org/freedesktop/plymouthd";
+
+/// Checks that the daemon is responding.
+const REQUEST_PING: u8 = b'P';
+/// Requests a password, with the prompt that follows.
+const REQUEST_PASSWORD: u8 = b'*';
+/// The request's argument is a length-prefixed string.
+const ARGUMENT_STRING: u8 = 0x02;
+/// The request succeeded.
+const RESPONSE_ACK: u8 = 0x06;
+/// The user entered a password, which follows.
+const RESPONSE_ANSWER: u8 = 0x02;
+/// The user did not enter a password (for example, because they pressed Escape).
+const RESPONSE_NO_ANSWER: u8 = 0x05;
+
+/// The longest answer that is accepted, as in `systemd-ask-password` (which reads
+/// responses into a buffer of `LINE_MAX` bytes).
+const MAX_ANSWER_LEN: usize = 2048 - 5;
+/// How long to wait for the daemon to answer a ping.
+const PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Returns true if Plymouth's daemon is running.
+///
+/// This connects to the daemon's socket and pings it, so that a daemon that has exited
+/// (or a stale file left behind by one) is not mistaken for a running one.
+pub fn is_running() -> bool {
+    ping().is_ok()
+}
+
+/// Pings the daemon, returning an error unless it acknowledges the ping.
+fn ping() -> io::Result<()> {
+    let mut stream = connect_abstract(SOCKET_NAME)?;
+    stream.set_read_timeout(Some(PING_TIMEOUT))?;
+    stream.write_all(&[REQUEST_PING, 0])?;
+    let mut response = [0];
+    stream.read_exact(&mut response)?;
+    if response[0] == RESPONSE_ACK {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected response from Plymouth",
+        ))
+    }
+}
+
+/// Asks for a password through Plymouth, with the given prompt.
+///
+/// Returns [`Error::Cancelled`] if the user did not enter a password, and an I/O error
+/// if Plymouth is not running or the prompt is longer than 254 bytes.
+pub fn ask_password(prompt: &str) -> Result<SecretString> {
+    let password = ask_password_bytes(prompt, None)?;
+    let password = std::str::from_utf8(&password)?;
+    Ok(SecretString::from(password.to_owned()))
+}
+
+/// Asks for a password through Plymouth, returning `Error::Timeout` if no answer is
+/// received within the given number of seconds.
+pub(crate) fn ask_password_bytes(prompt: &str, timeout: Option<u16>) -> Result<Zeroizing<Vec<u8>>> {
+    let mut stream = connect_abstract(SOCKET_NAME)?;
+    stream.set_read_timeout(timeout.map(|timeout| Duration::from_secs(timeout.into())))?;
+    stream.write_all(&request(prompt)?)?;
+    match read_response(&mut stream) {
+        Err(Error::Io(e))
+            if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+        {
+            Err(Error::Timeout)
+        }
+        res => res,
+    }
+}
+
+/// Returns the request that asks for a password with the given prompt.
+fn request(prompt: &str) -> io::Result<Vec<u8>> {
+    // The length includes the trailing NUL.
+    let len = u8::try_from(prompt.len() + 1)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "prompt is too long"))?;
+    if prompt.contains('\0') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "prompt contains a NUL character",
+        ));
+    }
+    let mut request = vec![REQUEST_PASSWORD, ARGUMENT_STRING, len];
+    request.extend_from_slice(prompt.as_bytes());
+    request.push(0);
+    Ok(request)
+}
+
+/// Reads the response to a password request.
+fn read_response(reader: &mut impl Read) -> Result<Zeroizing<Vec<u8>>> {
+    let mut kind = [0];
+    reader.read_exact(&mut kind)?;
+    match kind[0] {
+        RESPONSE_ANSWER => (),
+        RESPONSE_NO_ANSWER => return Err(Error::Cancelled),
+        _ => {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected response from Plymouth",
+            )))
+        }
+    }
+
+    // The length is sent in the daemon's native byte order.
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_ne_bytes(len) as usize;
+    if len > MAX_ANSWER_LEN {
+        return Err(Error::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "answer from Plymouth is too long",
+        )));
+    }
+    let mut answer = Zeroizing::new(vec![0; len]);
+    reader.read_exact(&mut answer)?;
+    // The answer is a list of NUL-terminated passwords, of which only the first is
+    // the user's answer.
+    let end = answer.iter().position(|&b| b == 0).unwrap_or(answer.len());
+    answer.truncate(end);
+    Ok(answer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_response, request};
+    use crate::Error;
+
+    #[test]
+    fn protocol() {
+        assert_eq!(request("Disk:").unwrap(), b"*\x02\x06Disk:\0");
+        assert!(request(&"a".repeat(255)).is_err());
+
+        let response = |len: u32, answer: &[u8]| {
+            let mut response = vec![0x02];
+            response.extend_from_slice(&len.to_ne_bytes());
+            response.extend_from_slice(answer);
+            response
+        };
+        let answer = read_response(&mut &response(8, b"hunter2\0")[..]).unwrap();
+        assert_eq!(&answer[..], b"hunter2");
+        assert!(matches!(
+            read_response(&mut &b"\x05"[..]),
+            Err(Error::Cancelled)
+        ));
+        assert!(read_response(&mut &response(8, b"hunt")[..]).is_err());
+        // Lengths are capped before anything is allocated for the answer.
+        assert!(read_response(&mut &response(u32::MAX, b"hunter2\0")[..]).is_err());
+    }
+}
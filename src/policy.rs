@@ -0,0 +1,449 @@
+//! Rules that new passphrases must satisfy.
+//!
+//! A [`PassphrasePolicy`] checks a passphrase against a minimum length, a minimum
+//! number of character classes, a minimum estimated entropy, a list of dictionary
+//! words, and an offline [`BreachFilter`] of passphrases known to have been leaked.
+//!
+//! [`PassphraseInput::with_policy`] enforces a policy inside the prompt: if the user
+//! enters a passphrase that breaks it, the dialog stays open and shows why, until the
+//! user enters one that satisfies it or selects the "Cancel" button.
+//!
+//! [`PassphraseInput::with_policy`]: crate::PassphraseInput::with_policy
+
+use std::fmt;
+
+use zeroize::Zeroizing;
+
+/// The number of symbols in each class of ASCII characters, used to estimate entropy.
+const LOWERCASE_SYMBOLS: u32 = 26;
+const UPPERCASE_SYMBOLS: u32 = 26;
+const DIGIT_SYMBOLS: u32 = 10;
+const PUNCTUATION_SYMBOLS: u32 = 33;
+/// A conservative number of symbols for characters outside ASCII, which are usually
+/// drawn from the letters of a single script.
+const OTHER_SYMBOLS: u32 = 100;
+
+/// The ways in which a passphrase can break a [`PassphrasePolicy`].
+///
+/// The [`Display`](fmt::Display) implementation gives a message in English suitable
+/// for showing to the user, which [`PassphraseInput::with_policy`] shows as the
+/// dialog's error unless the dialog's [`Translator`] translates it (see
+/// [`DefaultText::PolicyViolation`]).
+///
+/// [`PassphraseInput::with_policy`]: crate::PassphraseInput::with_policy
+/// [`Translator`]: crate::Translator
+/// [`DefaultText::PolicyViolation`]: crate::DefaultText::PolicyViolation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PolicyViolation {
+    /// The passphrase has fewer characters than the given minimum.
+    TooShort {
+        /// The minimum number of characters.
+        min_length: usize,
+    },
+    /// The passphrase uses fewer character classes (lowercase letters, uppercase
+    /// letters, digits, and symbols) than the given minimum.
+    TooFewClasses {
+        /// The minimum number of character classes.
+        min_classes: usize,
+    },
+    /// The estimated entropy of the passphrase is lower than the given minimum.
+    TooPredictable {
+        /// The minimum estimated entropy, in bits.
+        min_entropy: u32,
+    },
+    /// The passphrase is a dictionary word, possibly with digits or symbols before or
+    /// after it.
+    DictionaryWord,
+    /// The passphrase is in the [`BreachFilter`].
+    Breached,
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyViolation::TooShort { min_length } => write!(
+                f,
+                "Passphrase must be at least {} characters long",
+                min_length
+            ),
+            PolicyViolation::TooFewClasses { min_classes } => write!(
+                f,
+                "Passphrase must use at least {} of: lowercase letters, uppercase letters, digits, and symbols",
+                min_classes
+            ),
+            PolicyViolation::TooPredictable { .. } => {
+                write!(f, "Passphrase is too easy to guess")
+            }
+            PolicyViolation::DictionaryWord => write!(f, "Passphrase is a dictionary word"),
+            PolicyViolation::Breached => {
+                write!(f, "Passphrase has appeared in a data breach")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+/// A set of rules that new passphrases must satisfy.
+///
+/// An empty policy accepts every passphrase, and each rule is checked in the order of
+/// its setter below.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{policy::PassphrasePolicy, PassphraseInput};
+///
+/// let mut policy = PassphrasePolicy::new();
+/// policy
+///     .with_min_length(12)
+///     .with_min_classes(2)
+///     .with_dictionary(&["password", "letmein", "qwerty"]);
+///
+/// if let Some(mut input) = PassphraseInput::with_default_binary() {
+///     let passphrase = input
+///         .with_description("Choose a passphrase for FooBar")
+///         .with_confirmation("Repeat:", "Passphrases do not match")
+///         .with_policy(&policy)
+///         .interact()?;
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PassphrasePolicy<'a> {
+    min_length: usize,
+    min_classes: usize,
+    min_entropy: u32,
+    dictionary: &'a [&'a str],
+    breach_filter: Option<&'a BreachFilter>,
+}
+
+impl<'a> PassphrasePolicy<'a> {
+    /// Creates a policy that accepts every passphrase.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the minimum number of characters (not bytes) in a passphrase.
+    pub fn with_min_length(&mut self, min_length: usize) -> &mut Self {
+        self.min_length = min_length;
+        self
+    }
+
+    /// Sets the minimum number of character classes that a passphrase must use, out of
+    /// lowercase letters, uppercase letters, digits, and symbols (anything else).
+    pub fn with_min_classes(&mut self, min_classes: usize) -> &mut Self {
+        self.min_classes = min_classes;
+        self
+    }
+
+    /// Sets the minimum entropy (in bits) of a passphrase, as estimated by
+    /// [`estimate_entropy`].
+    pub fn with_min_entropy(&mut self, min_entropy: u32) -> &mut Self {
+        self.min_entropy = min_entropy;
+        self
+    }
+
+    /// Rejects passphrases that are one of the given words, ignoring case and any
+    /// digits or symbols before or after the word (so that `Password1!` is rejected if
+    /// `password` is in the list).
+    pub fn with_dictionary(&mut self, dictionary: &'a [&'a str]) -> &mut Self {
+        self.dictionary = dictionary;
+        self
+    }
+
+    /// Rejects passphrases that are in the given filter of breached passphrases.
+    ///
+    /// As the filter is probabilistic, a small fraction of passphrases that were never
+    /// breached are also rejected.
+    pub fn with_breach_filter(&mut self, breach_filter: &'a BreachFilter) -> &mut Self {
+        self.breach_filter = Some(breach_filter);
+        self
+    }
+
+    /// Checks the given passphrase against this policy, returning the first rule that
+    /// it breaks.
+    pub fn check(&self, passphrase: &str) -> Result<(), PolicyViolation> {
+        if passphrase.chars().count() < self.min_length {
+            return Err(PolicyViolation::TooShort {
+                min_length: self.min_length,
+            });
+        }
+        if classes(passphrase).count() < self.min_classes {
+            return Err(PolicyViolation::TooFewClasses {
+                min_classes: self.min_classes,
+            });
+        }
+        if estimate_entropy(passphrase) < f64::from(self.min_entropy) {
+            return Err(PolicyViolation::TooPredictable {
+                min_entropy: self.min_entropy,
+            });
+        }
+        if !self.dictionary.is_empty() {
+            let word = Zeroizing::new(
+                passphrase
+                    .trim_matches(|c: char| !c.is_alphabetic())
+                    .to_lowercase(),
+            );
+            if self
+                .dictionary
+                .iter()
+                .any(|entry| entry.to_lowercase() == *word)
+            {
+                return Err(PolicyViolation::DictionaryWord);
+            }
+        }
+        if matches!(self.breach_filter, Some(filter) if filter.contains(passphrase)) {
+            return Err(PolicyViolation::Breached);
+        }
+        Ok(())
+    }
+}
+
+/// The character classes of a passphrase.
+#[derive(Clone, Copy, Debug, Default)]
+struct Classes {
+    lowercase: bool,
+    uppercase: bool,
+    digit: bool,
+    punctuation: bool,
+    other: bool,
+}
+
+impl Classes {
+    /// Returns the number of classes used, where characters outside ASCII count as
+    /// symbols.
+    fn count(&self) -> usize {
+        [
+            self.lowercase,
+            self.uppercase,
+            self.digit,
+            self.punctuation || self.other,
+        ]
+        .iter()
+        .filter(|&&used| used)
+        .count()
+    }
+
+    /// Returns the number of symbols in the classes used.
+    fn symbols(&self) -> u32 {
+        [
+            (self.lowercase, LOWERCASE_SYMBOLS),
+            (self.uppercase, UPPERCASE_SYMBOLS),
+            (self.digit, DIGIT_SYMBOLS),
+            (self.punctuation, PUNCTUATION_SYMBOLS),
+            (self.other, OTHER_SYMBOLS),
+        ]
+        .iter()
+        .filter(|(used, _)| *used)
+        .map(|(_, symbols)| symbols)
+        .sum()
+    }
+}
+
+fn classes(passphrase: &str) -> Classes {
+    let mut classes = Classes::default();
+    for c in passphrase.chars() {
+        match c {
+            'a'..='z' => classes.lowercase = true,
+            'A'..='Z' => classes.uppercase = true,
+            '0'..='9' => classes.digit = true,
+            _ if c.is_ascii() => classes.punctuation = true,
+            _ => classes.other = true,
+        }
+    }
+    classes
+}
+
+/// Returns a rough estimate of the entropy of a passphrase, in bits.
+///
+/// The estimate assumes that each character was chosen at random from the character
+/// classes that the passphrase uses, except that a character repeating the one before
+/// it adds nothing. Passphrases chosen by people are far less random than this, so the
+/// estimate is an upper bound; combine it with a dictionary or breach filter to catch
+/// common passphrases.
+pub fn estimate_entropy(passphrase: &str) -> f64 {
+    let symbols = classes(passphrase).symbols();
+    if symbols == 0 {
+        return 0.0;
+    }
+    let mut previous = None;
+    let mut chars = 0;
+    for c in passphrase.chars() {
+        if previous != Some(c) {
+            chars += 1;
+        }
+        previous = Some(c);
+    }
+    f64::from(chars) * f64::from(symbols).log2()
+}
+
+/// A Bloom filter of breached passphrases, which can be checked offline.
+///
+/// The filter is a bit array in which each passphrase sets a fixed number of bits,
+/// chosen by hashing it. Checking a passphrase never has false negatives, but has
+/// false positives at a rate that depends on the size of the filter and the number of
+/// passphrases in it.
+///
+/// Filters are usually built ahead of time with [`BreachFilter::insert`] (for example,
+/// from a corpus of leaked passwords), stored with [`BreachFilter::as_bytes`], and
+/// shipped with the application, which loads them with [`BreachFilter::from_bytes`].
+/// The hash function is part of the format, so filters must be built with this type.
+///
+/// # Examples
+///
+/// ```
+/// use pinentry::policy::{BreachFilter, PassphrasePolicy, PolicyViolation};
+///
+/// let mut filter = BreachFilter::new(1024, 7);
+/// filter.insert("correct horse battery staple");
+///
+/// let mut policy = PassphrasePolicy::new();
+/// policy.with_breach_filter(&filter);
+/// assert_eq!(
+///     policy.check("correct horse battery staple"),
+///     Err(PolicyViolation::Breached),
+/// );
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct BreachFilter {
+    bits: Vec<u8>,
+    hashes: u32,
+}
+
+/// Only the size of the filter is shown.
+impl fmt::Debug for BreachFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BreachFilter")
+            .field("len", &self.bits.len())
+            .field("hashes", &self.hashes)
+            .finish()
+    }
+}
+
+impl BreachFilter {
+    /// Creates an empty filter of the given number of bytes, in which each passphrase
+    /// sets the given number of bits.
+    ///
+    /// For a false-positive rate of `p` with `n` passphrases, use about
+    /// `-1.44 * log2(p) * n / 8` bytes and `-log2(p)` bits per passphrase.
+    pub fn new(len: usize, hashes: u32) -> Self {
+        BreachFilter::from_bytes(vec![0; len], hashes)
+    }
+
+    /// Loads a filter from the bytes returned by [`BreachFilter::as_bytes`], in which
+    /// each passphrase sets the given number of bits.
+    pub fn from_bytes(bits: Vec<u8>, hashes: u32) -> Self {
+        BreachFilter { bits, hashes }
+    }
+
+    /// Returns the bytes of this filter.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+
+    /// Adds the given passphrase to this filter.
+    pub fn insert(&mut self, passphrase: &str) {
+        if self.bits.is_empty() {
+            return;
+        }
+        for bit in self.bits(passphrase) {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Returns true if the given passphrase is probably in this filter, and false if it
+    /// is definitely not.
+    ///
+    /// An empty filter contains nothing.
+    pub fn contains(&self, passphrase: &str) -> bool {
+        !self.bits.is_empty()
+            && self
+                .bits(passphrase)
+                .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Returns the bits set by the given passphrase, with double hashing.
+    ///
+    /// The filter must not be empty.
+    fn bits(&self, passphrase: &str) -> impl Iterator<Item = usize> {
+        let len = (self.bits.len() as u64) * 8;
+        let h1 = fnv1a(0xcbf2_9ce4_8422_2325, passphrase.as_bytes());
+        // The second hash must be odd, so that it cannot be a multiple of `len`.
+        let h2 = fnv1a(h1, passphrase.as_bytes()) | 1;
+        (0..u64::from(self.hashes))
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+}
+
+/// The 64-bit FNV-1a hash of `data`, starting from the given state.
+fn fnv1a(state: u64, data: &[u8]) -> u64 {
+    data.iter().fold(state, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate_entropy, BreachFilter, PassphrasePolicy, PolicyViolation};
+
+    #[test]
+    fn check() {
+        let mut filter = BreachFilter::new(64, 5);
+        filter.insert("Tr0ub4dor&3");
+        let mut policy = PassphrasePolicy::new();
+        assert_eq!(policy.check(""), Ok(()));
+
+        policy
+            .with_min_length(8)
+            .with_min_classes(2)
+            .with_min_entropy(40)
+            .with_dictionary(&["password"])
+            .with_breach_filter(&filter);
+        assert_eq!(
+            policy.check("hunter2"),
+            Err(PolicyViolation::TooShort { min_length: 8 })
+        );
+        // Length is counted in characters, not bytes.
+        assert_eq!(
+            policy.check("ééééééé"),
+            Err(PolicyViolation::TooShort { min_length: 8 })
+        );
+        assert_eq!(
+            policy.check("abcdefgh"),
+            Err(PolicyViolation::TooFewClasses { min_classes: 2 })
+        );
+        assert_eq!(
+            policy.check("aaaaaaaa1"),
+            Err(PolicyViolation::TooPredictable { min_entropy: 40 })
+        );
+        assert_eq!(
+            policy.check("PASSWORD123!"),
+            Err(PolicyViolation::DictionaryWord)
+        );
+        assert_eq!(policy.check("Tr0ub4dor&3"), Err(PolicyViolation::Breached));
+        assert_eq!(policy.check("Tr0ub4dor&4"), Ok(()));
+    }
+
+    #[test]
+    fn entropy() {
+        assert_eq!(estimate_entropy(""), 0.0);
+        assert_eq!(estimate_entropy("0123"), 4.0 * 10f64.log2());
+        assert_eq!(estimate_entropy("aaaa"), estimate_entropy("a"));
+        assert!(estimate_entropy("aB3!") > estimate_entropy("abcd"));
+    }
+
+    #[test]
+    fn breach_filter() {
+        let mut filter = BreachFilter::new(128, 7);
+        assert!(!filter.contains("hunter2"));
+        filter.insert("hunter2");
+        assert!(filter.contains("hunter2"));
+        assert!(!filter.contains("hunter3"));
+
+        let loaded = BreachFilter::from_bytes(filter.as_bytes().to_vec(), 7);
+        assert!(loaded.contains("hunter2"));
+        assert!(!BreachFilter::new(0, 7).contains("hunter2"));
+    }
+}
@@ -0,0 +1,330 @@
+//! Retrieving secrets through the XDG Desktop Portal.
+//!
+//! Sandboxed applications (such as Flatpak apps) often cannot spawn a `pinentry`
+//! binary, and on Wayland compositors an X11 `pinentry` may be unable to show its
+//! dialog at all. The portal's Secret interface works in both cases: it gives each
+//! application a secret of its own, which the desktop's secret service (such as GNOME
+//! Keyring) keeps for it, asking the user to unlock their keyring first if needed.
+//! Applications can derive the keys that protect their data from this secret (for
+//! example, with the `kdf` module), instead of asking the user for a passphrase.
+//!
+//! The portal is reached over the D-Bus session bus, with a minimal client that only
+//! implements what the Secret interface needs. No portal asks the user for an
+//! arbitrary passphrase, so dialogs still need a `pinentry` binary.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use pinentry::portal;
+//!
+//! if portal::is_available() {
+//!     let secret = portal::retrieve_secret()?;
+//!     // Derive the application's keys from `secret.expose_secret()`.
+//! }
+//! # Ok::<(), pinentry::Error>(())
+//! ```
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use zeroize::Zeroizing;
+
+use crate::{Error, Result, SecretBuffer};
+
+mod dbus;
+
+use dbus::{Call, Connection};
+
+/// The portal's bus name.
+const DESTINATION: &str = "org.freedesktop.portal.Desktop";
+/// The object that implements the portal's interfaces.
+const PATH: &str = "/org/freedesktop/portal/desktop";
+const SECRET_INTERFACE: &str = "org.freedesktop.portal.Secret";
+const REQUEST_INTERFACE: &str = "org.freedesktop.portal.Request";
+const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+/// The request succeeded.
+const RESPONSE_SUCCESS: u32 = 0;
+/// The user cancelled the request.
+const RESPONSE_CANCELLED: u32 = 1;
+
+/// The longest secret that is accepted. Secret services generate secrets of 64 bytes.
+const MAX_SECRET_LEN: usize = 1024;
+
+/// Returns true if the portal's Secret interface is available.
+///
+/// This connects to the session bus and asks the portal for the interface's version,
+/// so that a bus without a portal (or with a portal that has no secret service behind
+/// it) is not mistaken for a working one.
+pub fn is_available() -> bool {
+    version().is_ok()
+}
+
+/// Returns the version of the portal's Secret interface.
+fn version() -> io::Result<u32> {
+    let mut bus = Connection::session()?;
+    let mut call = Call::new(DESTINATION, PATH, PROPERTIES_INTERFACE, "Get");
+    call.signature = "ss";
+    call.body.string(SECRET_INTERFACE);
+    call.body.string("version");
+    let reply = bus.call(call)?;
+    let mut reply = reply.body("v")?;
+    match reply.signature()? {
+        "u" => reply.u32(),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected version from the portal",
+        )),
+    }
+}
+
+/// Retrieves the calling application's secret from the portal.
+///
+/// The secret is the same each time that the same application retrieves it (the
+/// portal identifies sandboxed applications by their app ID). Whether applications
+/// that are not sandboxed share a secret depends on the secret service.
+///
+/// The user may be asked to unlock their keyring first. Returns [`Error::Cancelled`]
+/// if they dismiss that prompt, and an I/O error if the portal is not available or
+/// fails to retrieve the secret.
+pub fn retrieve_secret() -> Result<SecretBuffer> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    // Tokens only need to be unique among this connection's requests.
+    let token = format!(
+        "pinentry_{}_{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+    );
+    retrieve(&mut Connection::session()?, &token)
+}
+
+/// Retrieves the secret with a request that has the given token.
+fn retrieve(bus: &mut Connection, token: &str) -> Result<SecretBuffer> {
+    // The portal may respond before it replies to the call, so the response is
+    // subscribed to first.
+    let handle = request_path(bus.unique_name(), token);
+    bus.add_match(&format!(
+        "type='signal',interface='{}',member='Response',path='{}'",
+        REQUEST_INTERFACE, handle,
+    ))?;
+
+    let (read, write) = pipe()?;
+    let fds = [write.as_raw_fd()];
+    let mut call = Call::new(DESTINATION, PATH, SECRET_INTERFACE, "RetrieveSecret");
+    call.signature = "ha{sv}";
+    call.fds = &fds;
+    // The index of the file descriptor.
+    call.body.u32(0);
+    call.body.array(8, |options| {
+        options.structure();
+        options.string("handle_token");
+        options.signature("s");
+        options.string(token);
+    });
+    let reply = bus.call(call)?;
+    drop(write);
+    if reply.body("o")?.string()? != handle {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected request from the portal",
+        )
+        .into());
+    }
+
+    // The secret service writes the secret and then closes the pipe (or closes it
+    // without writing anything if the request fails), before the portal responds.
+    let secret = read_secret(read)?;
+    let response = bus.wait_signal(&handle, REQUEST_INTERFACE, "Response")?;
+    match response.body("ua{sv}")?.u32()? {
+        RESPONSE_SUCCESS => Ok(secret),
+        RESPONSE_CANCELLED => Err(Error::Cancelled),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Other,
+            "the portal failed to retrieve the secret",
+        )
+        .into()),
+    }
+}
+
+/// Returns the object path of the request with the given token, made by the connection
+/// with the given unique name.
+fn request_path(unique_name: &str, token: &str) -> String {
+    // The sender is the unique name without its leading `:`, with its dots replaced by
+    // underscores.
+    let sender = unique_name.trim_start_matches(':').replace('.', "_");
+    format!("{}/request/{}/{}", PATH, sender, token)
+}
+
+/// Reads the secret from the pipe that the portal writes it to, until it is closed.
+fn read_secret(mut pipe: impl Read) -> Result<SecretBuffer> {
+    let mut secret = SecretBuffer::with_capacity(MAX_SECRET_LEN);
+    let mut chunk = Zeroizing::new([0; 64]);
+    loop {
+        let n = match pipe.read(&mut chunk[..]) {
+            Ok(0) => return Ok(secret),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for &byte in &chunk[..n] {
+            if !secret.push(byte) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "secret from the portal is too long",
+                )
+                .into());
+            }
+        }
+    }
+}
+
+/// Creates a pipe, returning its read and write ends.
+fn pipe() -> io::Result<(File, File)> {
+    let mut fds = [0; 2];
+    // Safety: `fds` is valid for writes of two descriptors.
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Safety: both descriptors were just created by `pipe2`, and nothing else owns them.
+    Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Read, Write};
+    use std::mem;
+    use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+
+    use super::dbus::{
+        encode, field, read_line, read_message, Connection, Writer, FIELD_INTERFACE, FIELD_MEMBER,
+        FIELD_PATH, FIELD_REPLY_SERIAL, FIELD_SIGNATURE, METHOD_RETURN, SIGNAL,
+    };
+    use super::{read_secret, request_path, retrieve, MAX_SECRET_LEN};
+    use crate::Error;
+
+    /// Receives a byte from `stream`, with the file descriptor that accompanies it.
+    fn recv_fd(stream: &UnixStream) -> (u8, RawFd) {
+        let mut byte = 0u8;
+        let mut iov = libc::iovec {
+            iov_base: &mut byte as *mut u8 as *mut libc::c_void,
+            iov_len: 1,
+        };
+        let mut control = [0u64; 8];
+        // Safety: `msghdr` is valid when zeroed.
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+        // Safety: `msg` and the buffers that it points to are valid.
+        assert_eq!(unsafe { libc::recvmsg(stream.as_raw_fd(), &mut msg, 0) }, 1);
+        // Safety: the kernel filled in a control message with one descriptor.
+        let fd = unsafe { *(libc::CMSG_DATA(libc::CMSG_FIRSTHDR(&msg)) as *const RawFd) };
+        (byte, fd)
+    }
+
+    /// Replies to the call with the given serial number.
+    fn reply(bus: &mut UnixStream, serial: u32, signature: &str, body: Writer) {
+        let message = encode(METHOD_RETURN, serial, &body, |fields| {
+            field(fields, FIELD_REPLY_SERIAL, "u", |w| w.u32(serial));
+            if !signature.is_empty() {
+                field(fields, FIELD_SIGNATURE, "g", |w| w.signature(signature));
+            }
+        });
+        bus.write_all(&message).unwrap();
+    }
+
+    /// Runs a fake session bus with a portal that answers `RetrieveSecret` with the
+    /// given response and secret, returning a connection to it.
+    fn fake_portal(response: u32, secret: &'static [u8]) -> Connection {
+        let (client, mut bus) = UnixStream::pair().unwrap();
+        thread::spawn(move || {
+            assert!(read_line(&mut bus).unwrap().starts_with("\0AUTH EXTERNAL "));
+            bus.write_all(b"OK 0123456789abcdef\r\n").unwrap();
+            assert_eq!(read_line(&mut bus).unwrap(), "NEGOTIATE_UNIX_FD");
+            bus.write_all(b"AGREE_UNIX_FD\r\n").unwrap();
+            assert_eq!(read_line(&mut bus).unwrap(), "BEGIN");
+
+            // `Hello`
+            read_message(&mut bus).unwrap();
+            let mut name = Writer::default();
+            name.string(":1.42");
+            reply(&mut bus, 1, "s", name);
+            // `AddMatch`
+            read_message(&mut bus).unwrap();
+            reply(&mut bus, 2, "", Writer::default());
+
+            // `RetrieveSecret`
+            let (first, fd) = recv_fd(&bus);
+            read_message(&mut (&[first][..]).chain(&mut bus)).unwrap();
+            // Safety: `fd` was just received, and nothing else owns it.
+            let mut pipe = unsafe { File::from_raw_fd(fd) };
+            pipe.write_all(secret).unwrap();
+            drop(pipe);
+            let handle = request_path(":1.42", "pinentry_test");
+            let mut path = Writer::default();
+            path.string(&handle);
+            reply(&mut bus, 3, "o", path);
+
+            let mut body = Writer::default();
+            body.u32(response);
+            body.array(8, |_| ());
+            let signal = encode(SIGNAL, 2, &body, |fields| {
+                field(fields, FIELD_PATH, "o", |w| w.string(&handle));
+                field(fields, FIELD_INTERFACE, "s", |w| {
+                    w.string("org.freedesktop.portal.Request")
+                });
+                field(fields, FIELD_MEMBER, "s", |w| w.string("Response"));
+                field(fields, FIELD_SIGNATURE, "g", |w| w.signature("ua{sv}"));
+            });
+            bus.write_all(&signal).unwrap();
+        });
+        Connection::new(client).unwrap()
+    }
+
+    #[test]
+    fn retrieve_secret() {
+        let mut bus = fake_portal(0, b"application secret");
+        assert_eq!(bus.unique_name(), ":1.42");
+        let secret = retrieve(&mut bus, "pinentry_test").unwrap();
+        assert_eq!(secret.expose_secret(), b"application secret");
+
+        let mut bus = fake_portal(1, b"");
+        assert!(matches!(
+            retrieve(&mut bus, "pinentry_test"),
+            Err(Error::Cancelled)
+        ));
+        let mut bus = fake_portal(2, b"");
+        assert!(matches!(
+            retrieve(&mut bus, "pinentry_test"),
+            Err(Error::Io(_))
+        ));
+
+        // Secrets are capped at the size of the buffer that they are read into.
+        assert!(matches!(
+            read_secret(&vec![0; MAX_SECRET_LEN + 1][..]),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn no_bus() {
+        let (client, bus) = UnixStream::pair().unwrap();
+        drop(bus);
+        assert!(Connection::new(client).is_err());
+    }
+
+    #[test]
+    fn request_paths() {
+        assert_eq!(
+            request_path(":1.42", "pinentry_1_0"),
+            "/org/freedesktop/portal/desktop/request/1_42/pinentry_1_0",
+        );
+    }
+}
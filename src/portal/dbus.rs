@@ -0,0 +1,715 @@
+//! A minimal D-Bus client, with just enough of the protocol to make requests to the
+//! XDG Desktop Portal: method calls with basic arguments and file descriptors, and
+//! the signals that carry the portal's responses.
+
+use std::collections::VecDeque;
+use std::env;
+use std::ffi::{c_void, OsString};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::ptr;
+
+use percent_encoding::percent_decode;
+
+use crate::unix::connect_abstract;
+
+/// A method call.
+const METHOD_CALL: u8 = 1;
+/// A reply to a method call, with its return values.
+pub(super) const METHOD_RETURN: u8 = 2;
+/// A reply to a method call, with the error that it failed with.
+pub(super) const ERROR: u8 = 3;
+/// A signal.
+pub(super) const SIGNAL: u8 = 4;
+
+/// The object that a call is made on, or that a signal is emitted by.
+pub(super) const FIELD_PATH: u8 = 1;
+/// The interface of the method or signal.
+pub(super) const FIELD_INTERFACE: u8 = 2;
+/// The name of the method or signal.
+pub(super) const FIELD_MEMBER: u8 = 3;
+/// The name of the error in an error reply.
+pub(super) const FIELD_ERROR_NAME: u8 = 4;
+/// The serial number of the call that a message replies to.
+pub(super) const FIELD_REPLY_SERIAL: u8 = 5;
+/// The connection that a message is sent to.
+const FIELD_DESTINATION: u8 = 6;
+/// The signature of the message body.
+pub(super) const FIELD_SIGNATURE: u8 = 8;
+/// The number of file descriptors that accompany a message.
+const FIELD_UNIX_FDS: u8 = 9;
+
+/// The longest message header or body that is accepted. The specification allows
+/// messages of up to 128 MiB, but replies from the portal are tiny.
+const MAX_MESSAGE_LEN: usize = 1 << 20;
+/// The longest line that is accepted while authenticating.
+const MAX_LINE_LEN: usize = 512;
+
+/// The message bus itself.
+const BUS_NAME: &str = "org.freedesktop.DBus";
+const BUS_PATH: &str = "/org/freedesktop/DBus";
+
+/// A connection to a message bus.
+pub(super) struct Connection {
+    stream: UnixStream,
+    serial: u32,
+    unique_name: String,
+    /// Signals that were received while waiting for a reply.
+    pending: VecDeque<Message>,
+}
+
+impl Connection {
+    /// Connects to the session bus.
+    pub(super) fn session() -> io::Result<Self> {
+        Connection::new(connect_session()?)
+    }
+
+    /// Authenticates as the current user on a connection to a message bus, and
+    /// registers with the bus.
+    pub(super) fn new(mut stream: UnixStream) -> io::Result<Self> {
+        authenticate(&mut stream)?;
+        let mut conn = Connection {
+            stream,
+            serial: 0,
+            unique_name: String::new(),
+            pending: VecDeque::new(),
+        };
+        let reply = conn.call(Call::new(BUS_NAME, BUS_PATH, BUS_NAME, "Hello"))?;
+        conn.unique_name = reply.body("s")?.string()?.to_owned();
+        Ok(conn)
+    }
+
+    /// Returns the name that the bus assigned to this connection.
+    pub(super) fn unique_name(&self) -> &str {
+        &self.unique_name
+    }
+
+    /// Asks the bus to send this connection the signals that match the given rule.
+    pub(super) fn add_match(&mut self, rule: &str) -> io::Result<()> {
+        let mut call = Call::new(BUS_NAME, BUS_PATH, BUS_NAME, "AddMatch");
+        call.signature = "s";
+        call.body.string(rule);
+        self.call(call).map(|_| ())
+    }
+
+    /// Calls a method, and waits for its reply.
+    ///
+    /// Returns an error if the method fails.
+    pub(super) fn call(&mut self, call: Call<'_>) -> io::Result<Message> {
+        self.serial += 1;
+        let serial = self.serial;
+        self.send(&call.encode(serial), call.fds)?;
+        loop {
+            let message = read_message(&mut self.stream)?;
+            if message.reply_serial != Some(serial) {
+                // A signal that is received first may be the response to this call.
+                if message.kind == SIGNAL {
+                    self.pending.push_back(message);
+                }
+                continue;
+            }
+            return match message.kind {
+                METHOD_RETURN => Ok(message),
+                ERROR => Err(message.error()),
+                _ => Err(invalid("unexpected reply from the message bus")),
+            };
+        }
+    }
+
+    /// Waits for a signal from the given object.
+    pub(super) fn wait_signal(
+        &mut self,
+        path: &str,
+        interface: &str,
+        member: &str,
+    ) -> io::Result<Message> {
+        if let Some(i) = self
+            .pending
+            .iter()
+            .position(|message| message.is_signal(path, interface, member))
+        {
+            return Ok(self.pending.remove(i).expect("index is in bounds"));
+        }
+        loop {
+            let message = read_message(&mut self.stream)?;
+            if message.is_signal(path, interface, member) {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Sends a message, with the given file descriptors.
+    fn send(&mut self, message: &[u8], fds: &[RawFd]) -> io::Result<()> {
+        if fds.is_empty() {
+            return self.stream.write_all(message);
+        }
+
+        let fds_len = mem::size_of_val(fds) as u32;
+        // Safety: `CMSG_SPACE` has no preconditions.
+        let space = unsafe { libc::CMSG_SPACE(fds_len) } as usize;
+        // Control messages must be aligned like `cmsghdr`.
+        let mut control = vec![0u64; (space + 7) / 8];
+        let mut iov = libc::iovec {
+            iov_base: message.as_ptr() as *mut c_void,
+            iov_len: message.len(),
+        };
+        // Safety: `msghdr` is valid when zeroed.
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut c_void;
+        msg.msg_controllen = space as _;
+        // Safety: `control` has room for one control message with `fds`.
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(fds_len) as _;
+            ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg) as *mut RawFd, fds.len());
+        }
+        let sent = loop {
+            // Safety: `msg` and the buffers that it points to are valid.
+            let ret = unsafe { libc::sendmsg(self.stream.as_raw_fd(), &msg, libc::MSG_NOSIGNAL) };
+            if ret >= 0 {
+                break ret as usize;
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        };
+        // The file descriptors were sent with the first part of the message.
+        self.stream.write_all(&message[sent..])
+    }
+}
+
+/// A method call to be sent.
+pub(super) struct Call<'a> {
+    destination: &'a str,
+    path: &'a str,
+    interface: &'a str,
+    member: &'a str,
+    /// The signature of the arguments in `body`.
+    pub(super) signature: &'a str,
+    /// The arguments.
+    pub(super) body: Writer,
+    /// The file descriptors that the arguments refer to (by index).
+    pub(super) fds: &'a [RawFd],
+}
+
+impl<'a> Call<'a> {
+    /// Creates a call of a method without arguments.
+    pub(super) fn new(
+        destination: &'a str,
+        path: &'a str,
+        interface: &'a str,
+        member: &'a str,
+    ) -> Self {
+        Call {
+            destination,
+            path,
+            interface,
+            member,
+            signature: "",
+            body: Writer::default(),
+            fds: &[],
+        }
+    }
+
+    /// Returns the encoded message for this call.
+    fn encode(&self, serial: u32) -> Vec<u8> {
+        encode(METHOD_CALL, serial, &self.body, |fields| {
+            field(fields, FIELD_PATH, "o", |w| w.string(self.path));
+            field(fields, FIELD_INTERFACE, "s", |w| w.string(self.interface));
+            field(fields, FIELD_MEMBER, "s", |w| w.string(self.member));
+            field(fields, FIELD_DESTINATION, "s", |w| {
+                w.string(self.destination)
+            });
+            if !self.signature.is_empty() {
+                field(fields, FIELD_SIGNATURE, "g", |w| {
+                    w.signature(self.signature)
+                });
+            }
+            if !self.fds.is_empty() {
+                field(fields, FIELD_UNIX_FDS, "u", |w| {
+                    w.u32(self.fds.len() as u32)
+                });
+            }
+        })
+    }
+}
+
+/// Returns an encoded message with the given body, and the header fields written by
+/// `fields` (with [`field`]).
+pub(super) fn encode(
+    kind: u8,
+    serial: u32,
+    body: &Writer,
+    fields: impl FnOnce(&mut Writer),
+) -> Vec<u8> {
+    let mut message = Writer::default();
+    message.u8(b'l');
+    message.u8(kind);
+    // No flags are set.
+    message.u8(0);
+    // The major protocol version.
+    message.u8(1);
+    message.u32(body.buf.len() as u32);
+    message.u32(serial);
+    message.array(8, fields);
+    // The body starts at the next multiple of 8 bytes.
+    message.align(8);
+    message.buf.extend_from_slice(&body.buf);
+    message.buf
+}
+
+/// Writes a header field with a value of the given (single, basic) type.
+pub(super) fn field(w: &mut Writer, code: u8, signature: &str, value: impl FnOnce(&mut Writer)) {
+    w.structure();
+    w.u8(code);
+    w.signature(signature);
+    value(w);
+}
+
+/// Marshals values in little-endian byte order.
+#[derive(Default)]
+pub(super) struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn align(&mut self, alignment: usize) {
+        while self.buf.len() % alignment != 0 {
+            self.buf.push(0);
+        }
+    }
+
+    pub(super) fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub(super) fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a string or an object path.
+    pub(super) fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    pub(super) fn signature(&mut self, value: &str) {
+        self.u8(value.len() as u8);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    /// Writes an array, whose elements (aligned to `alignment`) are written by
+    /// `elements`.
+    pub(super) fn array(&mut self, alignment: usize, elements: impl FnOnce(&mut Self)) {
+        self.u32(0);
+        let len_pos = self.buf.len() - 4;
+        // The padding before the first element is not part of the array's length.
+        self.align(alignment);
+        let start = self.buf.len();
+        elements(self);
+        let len = (self.buf.len() - start) as u32;
+        self.buf[len_pos..len_pos + 4].copy_from_slice(&len.to_le_bytes());
+    }
+
+    /// Starts a struct or dictionary entry.
+    pub(super) fn structure(&mut self) {
+        self.align(8);
+    }
+}
+
+/// Unmarshals values from a message.
+pub(super) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn align(&mut self, alignment: usize) -> io::Result<()> {
+        let pos = (self.pos + alignment - 1) / alignment * alignment;
+        if pos > self.buf.len() {
+            return Err(invalid("truncated message"));
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .buf
+            .get(self.pos..)
+            .and_then(|rest| rest.get(..len))
+            .ok_or_else(|| invalid("truncated message"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub(super) fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(super) fn u32(&mut self) -> io::Result<u32> {
+        self.align(4)?;
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    /// Reads a string or an object path.
+    pub(super) fn string(&mut self) -> io::Result<&'a str> {
+        let len = self.u32()? as usize;
+        self.nul_terminated(len)
+    }
+
+    pub(super) fn signature(&mut self) -> io::Result<&'a str> {
+        let len = self.u8()?.into();
+        self.nul_terminated(len)
+    }
+
+    fn nul_terminated(&mut self, len: usize) -> io::Result<&'a str> {
+        let bytes = self.bytes(
+            len.checked_add(1)
+                .ok_or_else(|| invalid("string is too long"))?,
+        )?;
+        if bytes[len] != 0 {
+            return Err(invalid("string is not NUL-terminated"));
+        }
+        std::str::from_utf8(&bytes[..len]).map_err(|_| invalid("string is not valid UTF-8"))
+    }
+}
+
+/// A received message.
+pub(super) struct Message {
+    kind: u8,
+    big_endian: bool,
+    reply_serial: Option<u32>,
+    path: Option<String>,
+    interface: Option<String>,
+    member: Option<String>,
+    error_name: Option<String>,
+    signature: String,
+    body: Vec<u8>,
+}
+
+impl Message {
+    /// Parses a message from its header (including the padding after its header
+    /// fields) and its body.
+    fn parse(header: &[u8], body: Vec<u8>) -> io::Result<Self> {
+        let mut message = Message {
+            kind: header[1],
+            big_endian: header[0] == b'B',
+            reply_serial: None,
+            path: None,
+            interface: None,
+            member: None,
+            error_name: None,
+            signature: String::new(),
+            body,
+        };
+        let mut reader = Reader {
+            buf: header,
+            pos: 12,
+            big_endian: message.big_endian,
+        };
+        let len = reader.u32()? as usize;
+        reader.align(8)?;
+        let end = reader.pos + len;
+        while reader.pos < end {
+            reader.align(8)?;
+            let code = reader.u8()?;
+            let value = match reader.signature()? {
+                "s" | "o" => Some(reader.string()?.to_owned()),
+                "g" => Some(reader.signature()?.to_owned()),
+                "u" => {
+                    let value = reader.u32()?;
+                    if code == FIELD_REPLY_SERIAL {
+                        message.reply_serial = Some(value);
+                    }
+                    None
+                }
+                "y" => {
+                    reader.u8()?;
+                    None
+                }
+                _ => return Err(invalid("unsupported header field")),
+            };
+            match code {
+                FIELD_PATH => message.path = value,
+                FIELD_INTERFACE => message.interface = value,
+                FIELD_MEMBER => message.member = value,
+                FIELD_ERROR_NAME => message.error_name = value,
+                FIELD_SIGNATURE => message.signature = value.unwrap_or_default(),
+                _ => (),
+            }
+        }
+        Ok(message)
+    }
+
+    /// Returns a reader for the body of this message, if its arguments have the given
+    /// signature.
+    pub(super) fn body(&self, signature: &str) -> io::Result<Reader<'_>> {
+        if self.signature != signature {
+            return Err(invalid("unexpected arguments in message"));
+        }
+        Ok(Reader {
+            buf: &self.body,
+            pos: 0,
+            big_endian: self.big_endian,
+        })
+    }
+
+    fn is_signal(&self, path: &str, interface: &str, member: &str) -> bool {
+        self.kind == SIGNAL
+            && self.path.as_deref() == Some(path)
+            && self.interface.as_deref() == Some(interface)
+            && self.member.as_deref() == Some(member)
+    }
+
+    /// Returns the error of an error reply.
+    fn error(&self) -> io::Error {
+        let name = self.error_name.as_deref().unwrap_or("unknown error");
+        // Error replies usually have a message as their first argument.
+        let mut reader = Reader {
+            buf: &self.body,
+            pos: 0,
+            big_endian: self.big_endian,
+        };
+        match reader.string() {
+            Ok(description) if self.signature.starts_with('s') => {
+                io::Error::new(io::ErrorKind::Other, format!("{}: {}", name, description))
+            }
+            _ => io::Error::new(io::ErrorKind::Other, name.to_owned()),
+        }
+    }
+}
+
+/// Reads a message.
+pub(super) fn read_message(reader: &mut impl Read) -> io::Result<Message> {
+    let mut fixed = [0; 16];
+    reader.read_exact(&mut fixed)?;
+    let big_endian = match fixed[0] {
+        b'l' => false,
+        b'B' => true,
+        _ => return Err(invalid("invalid message")),
+    };
+    let len_at = |i: usize| {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&fixed[i..i + 4]);
+        let len = if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        };
+        len as usize
+    };
+    let (body_len, fields_len) = (len_at(4), len_at(12));
+    if body_len > MAX_MESSAGE_LEN || fields_len > MAX_MESSAGE_LEN {
+        return Err(invalid("message is too long"));
+    }
+
+    // The header is padded to a multiple of 8 bytes.
+    let mut header = vec![0; (16 + fields_len + 7) / 8 * 8];
+    header[..16].copy_from_slice(&fixed);
+    reader.read_exact(&mut header[16..])?;
+    let mut body = vec![0; body_len];
+    reader.read_exact(&mut body)?;
+    Message::parse(&header, body)
+}
+
+/// Authenticates as the current user, with the `EXTERNAL` mechanism (which checks
+/// the credentials of the socket's peer), and enables passing file descriptors.
+fn authenticate(stream: &mut UnixStream) -> io::Result<()> {
+    // Safety: `getuid` has no preconditions.
+    let uid = unsafe { libc::getuid() }.to_string();
+    let uid: String = uid.bytes().map(|b| format!("{:02x}", b)).collect();
+    // Clients start by sending a NUL byte, with which credentials may be passed.
+    stream.write_all(format!("\0AUTH EXTERNAL {}\r\n", uid).as_bytes())?;
+    if !read_line(stream)?.starts_with("OK ") {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "the message bus rejected the connection",
+        ));
+    }
+    stream.write_all(b"NEGOTIATE_UNIX_FD\r\n")?;
+    if read_line(stream)? != "AGREE_UNIX_FD" {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the message bus cannot pass file descriptors",
+        ));
+    }
+    stream.write_all(b"BEGIN\r\n")
+}
+
+/// Reads a line of the authentication protocol, without its line ending.
+///
+/// Lines are read a byte at a time, so that nothing after them is consumed.
+pub(super) fn read_line(reader: &mut impl Read) -> io::Result<String> {
+    let mut line = Vec::new();
+    while !line.ends_with(b"\r\n") {
+        if line.len() > MAX_LINE_LEN {
+            return Err(invalid("line is too long"));
+        }
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        line.push(byte[0]);
+    }
+    line.truncate(line.len() - 2);
+    String::from_utf8(line).map_err(|_| invalid("line is not valid UTF-8"))
+}
+
+/// The address of a message bus.
+#[derive(Debug, PartialEq)]
+enum Address {
+    /// A Unix socket at the given path.
+    Path(PathBuf),
+    /// An abstract Unix socket with the given name.
+    Abstract(Vec<u8>),
+}
+
+/// Connects to the session bus.
+fn connect_session() -> io::Result<UnixStream> {
+    let addresses = match env::var_os("DBUS_SESSION_BUS_ADDRESS") {
+        Some(addresses) => addresses,
+        // The address used by `dbus-daemon` and `dbus-broker` under systemd.
+        None => match env::var_os("XDG_RUNTIME_DIR") {
+            Some(runtime_dir) => {
+                return UnixStream::connect(PathBuf::from(runtime_dir).join("bus"))
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no session bus address is set",
+                ))
+            }
+        },
+    };
+
+    let mut error = io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "the session bus address has no Unix socket",
+    );
+    for address in parse_addresses(&addresses.into_vec()) {
+        let res = match address {
+            Address::Path(path) => UnixStream::connect(path),
+            Address::Abstract(name) => connect_abstract(&name),
+        };
+        match res {
+            Ok(stream) => return Ok(stream),
+            Err(e) => error = e,
+        }
+    }
+    Err(error)
+}
+
+/// Returns the Unix socket addresses in a list of server addresses, in order.
+fn parse_addresses(addresses: &[u8]) -> Vec<Address> {
+    addresses
+        .split(|&b| b == b';')
+        .filter_map(|address| {
+            let params = address.strip_prefix(b"unix:")?;
+            params.split(|&b| b == b',').find_map(|param| {
+                let i = param.iter().position(|&b| b == b'=')?;
+                let value: Vec<u8> = percent_decode(&param[i + 1..]).collect();
+                match &param[..i] {
+                    b"path" => Some(Address::Path(OsString::from_vec(value).into())),
+                    b"abstract" => Some(Address::Abstract(value)),
+                    _ => None,
+                }
+            })
+        })
+        .collect()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{
+        encode, field, parse_addresses, read_message, Address, Call, Writer, FIELD_INTERFACE,
+        FIELD_MEMBER, FIELD_PATH, FIELD_REPLY_SERIAL, FIELD_SIGNATURE, METHOD_RETURN, SIGNAL,
+    };
+
+    #[test]
+    fn marshalling() {
+        let call = Call::new(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "Hello",
+        );
+        // The fixed header, then each header field padded to a multiple of 8 bytes.
+        let mut expected =
+            b"l\x01\x00\x01\x00\x00\x00\x00\x01\x00\x00\x00\x6d\x00\x00\x00".to_vec();
+        expected
+            .extend_from_slice(b"\x01\x01o\x00\x15\x00\x00\x00/org/freedesktop/DBus\x00\x00\x00");
+        expected.extend_from_slice(
+            b"\x02\x01s\x00\x14\x00\x00\x00org.freedesktop.DBus\x00\x00\x00\x00",
+        );
+        expected.extend_from_slice(b"\x03\x01s\x00\x05\x00\x00\x00Hello\x00\x00\x00");
+        expected.extend_from_slice(
+            b"\x06\x01s\x00\x14\x00\x00\x00org.freedesktop.DBus\x00\x00\x00\x00",
+        );
+        assert_eq!(call.encode(1), expected);
+
+        let mut body = Writer::default();
+        body.string(":1.42");
+        let reply = encode(METHOD_RETURN, 7, &body, |fields| {
+            field(fields, FIELD_REPLY_SERIAL, "u", |w| w.u32(1));
+            field(fields, FIELD_SIGNATURE, "g", |w| w.signature("s"));
+        });
+        let reply = read_message(&mut &reply[..]).unwrap();
+        assert_eq!(reply.reply_serial, Some(1));
+        assert_eq!(reply.body("s").unwrap().string().unwrap(), ":1.42");
+        assert!(reply.body("u").is_err());
+
+        let signal = encode(SIGNAL, 8, &Writer::default(), |fields| {
+            field(fields, FIELD_PATH, "o", |w| w.string("/a"));
+            field(fields, FIELD_INTERFACE, "s", |w| w.string("b.c"));
+            field(fields, FIELD_MEMBER, "s", |w| w.string("D"));
+        });
+        // Truncated messages are rejected.
+        assert!(read_message(&mut &signal[..signal.len() - 1]).is_err());
+        let signal = read_message(&mut &signal[..]).unwrap();
+        assert!(signal.is_signal("/a", "b.c", "D"));
+        assert!(!signal.is_signal("/a", "b.c", "E"));
+    }
+
+    #[test]
+    fn addresses() {
+        assert_eq!(
+            parse_addresses(b"unix:path=/run/user/1000/bus"),
+            [Address::Path(PathBuf::from("/run/user/1000/bus"))],
+        );
+        assert_eq!(
+            parse_addresses(
+                b"tcp:host=localhost,port=1234;unix:abstract=/tmp/dbus-%41b,guid=1234;\
+                  unix:guid=5678,path=/run/flatpak/bus"
+            ),
+            [
+                Address::Abstract(b"/tmp/dbus-Ab".to_vec()),
+                Address::Path(PathBuf::from("/run/flatpak/bus")),
+            ],
+        );
+        assert!(parse_addresses(b"unix:tmpdir=/tmp").is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+use std::borrow::Cow;
+
+use secrecy::SecretString;
+
+use crate::{PassphraseInput, Result};
+
+/// A request for a passphrase, made through a [`PassphraseProvider`].
+///
+/// Every field is optional, and providers may ignore any of them (a provider that
+/// cannot ask twice, for example, may ignore the confirmation prompt).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PassphraseRequest<'a> {
+    description: Option<Cow<'a, str>>,
+    prompt: Option<Cow<'a, str>>,
+    error: Option<Cow<'a, str>>,
+    confirmation_prompt: Option<Cow<'a, str>>,
+}
+
+impl<'a> PassphraseRequest<'a> {
+    /// Creates an empty request.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the text describing what the passphrase is for (such as the name of the
+    /// key that it unlocks).
+    pub fn with_description(&mut self, description: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Sets the text just before the passphrase entry.
+    pub fn with_prompt(&mut self, prompt: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.prompt = Some(prompt.into());
+        self
+    }
+
+    /// Sets an error to show from a previous attempt (such as an incorrect
+    /// passphrase).
+    pub fn with_error(&mut self, error: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.error = Some(error.into());
+        self
+    }
+
+    /// Asks for a new passphrase, which the user enters twice; the given text is shown
+    /// just before the second entry.
+    pub fn with_confirmation_prompt(
+        &mut self,
+        confirmation_prompt: impl Into<Cow<'a, str>>,
+    ) -> &mut Self {
+        self.confirmation_prompt = Some(confirmation_prompt.into());
+        self
+    }
+
+    /// Returns the text describing what the passphrase is for, if set.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Returns the text to show just before the passphrase entry, if set.
+    pub fn prompt(&self) -> Option<&str> {
+        self.prompt.as_deref()
+    }
+
+    /// Returns the error to show from a previous attempt, if set.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Returns the text to show just before the confirmation entry, if this is a
+    /// request for a new passphrase.
+    pub fn confirmation_prompt(&self) -> Option<&str> {
+        self.confirmation_prompt.as_deref()
+    }
+}
+
+/// Something that can ask the user for a passphrase.
+///
+/// Libraries that need passphrases (for example, to decrypt a key) can accept any
+/// `PassphraseProvider`, instead of a concrete dialog type. It is implemented by
+/// [`PassphraseInput`], and by closures with the same signature as
+/// [`PassphraseProvider::passphrase`], so applications can also plug in their own
+/// prompts (or a non-interactive source of passphrases, in tests).
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{PassphraseInput, PassphraseProvider, PassphraseRequest, SecretString};
+///
+/// fn unlock(provider: &dyn PassphraseProvider) -> pinentry::Result<SecretString> {
+///     provider.passphrase(PassphraseRequest::new().with_description("Unlock key FooBar"))
+/// }
+///
+/// if let Some(input) = PassphraseInput::with_default_binary() {
+///     unlock(&input)?;
+/// }
+/// unlock(&|_: &PassphraseRequest<'_>| Ok(SecretString::from("hunter2")))?;
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+pub trait PassphraseProvider {
+    /// Asks the user for a passphrase.
+    ///
+    /// Returns [`Error::Cancelled`] if the user declined to enter one.
+    ///
+    /// [`Error::Cancelled`]: crate::Error::Cancelled
+    fn passphrase(&self, request: &PassphraseRequest<'_>) -> Result<SecretString>;
+}
+
+impl<F> PassphraseProvider for F
+where
+    F: Fn(&PassphraseRequest<'_>) -> Result<SecretString>,
+{
+    fn passphrase(&self, request: &PassphraseRequest<'_>) -> Result<SecretString> {
+        self(request)
+    }
+}
+
+/// Shows this dialog with the texts of the request in place of its own. Texts that the
+/// request does not set are left as configured on the dialog.
+impl<'a> PassphraseProvider for PassphraseInput<'a> {
+    fn passphrase(&self, request: &PassphraseRequest<'_>) -> Result<SecretString> {
+        let owned = |text: &str| Cow::Owned(text.to_owned());
+
+        let mut input = self.clone();
+        if let Some(description) = request.description() {
+            input.with_description(owned(description));
+        }
+        if let Some(prompt) = request.prompt() {
+            input.with_prompt(owned(prompt));
+        }
+        if let Some(error) = request.error() {
+            input.with_error(owned(error));
+        }
+        if let Some(confirmation_prompt) = request.confirmation_prompt() {
+            input.with_confirmation_prompt(owned(confirmation_prompt));
+        }
+        input.interact()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::{ExposeSecret, SecretString};
+
+    use super::{PassphraseProvider, PassphraseRequest};
+    use crate::Error;
+
+    #[test]
+    fn closure() {
+        let provider = |request: &PassphraseRequest<'_>| match request.error() {
+            None => Ok(SecretString::from("hunter2")),
+            Some(_) => Err(Error::Cancelled),
+        };
+        let provider: &dyn PassphraseProvider = &provider;
+
+        let mut request = PassphraseRequest::new();
+        request.with_description("Unlock key FooBar");
+        assert_eq!(
+            provider.passphrase(&request).unwrap().expose_secret(),
+            "hunter2"
+        );
+        request.with_error("Bad passphrase");
+        assert!(matches!(
+            provider.passphrase(&request),
+            Err(Error::Cancelled)
+        ));
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{Error, RateLimitedError, Result};
+
+/// A limit on how many prompts may be shown in a period of time.
+///
+/// Set on one or more dialogs with their `with_rate_limiter` methods, this stops a
+/// misbehaving client or a retry loop from showing the user a stream of dialogs. Each
+/// prompt counts once when it is shown, whether or not the user answers it.
+///
+/// Once the limit is reached, further prompts fail with [`Error::RateLimited`] until
+/// the oldest prompt in the window has expired, or (with [`RateLimiter::queue`]) wait
+/// until then before being shown.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use pinentry::{PassphraseInput, RateLimiter};
+///
+/// // At most 3 dialogs every 30 seconds.
+/// let limiter = RateLimiter::new(3, Duration::from_secs(30));
+/// if let Some(mut input) = PassphraseInput::with_default_binary() {
+///     input
+///         .with_description("Enter passphrase for FooBar")
+///         .with_rate_limiter(&limiter);
+///     match input.interact() {
+///         Err(pinentry::Error::RateLimited(e)) => {
+///             eprintln!("Too many attempts, try again in {:?}", e.retry_after());
+///         }
+///         res => {
+///             let passphrase = res?;
+///         }
+///     }
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct RateLimiter {
+    max_prompts: u32,
+    window: Duration,
+    queue: bool,
+    /// The times at which the prompts in the current window were shown, oldest first.
+    shown: Mutex<VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter that allows at most `max_prompts` prompts in any period of the
+    /// given length.
+    pub fn new(max_prompts: u32, window: Duration) -> Self {
+        RateLimiter {
+            max_prompts,
+            window,
+            queue: false,
+            shown: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Waits until a prompt may be shown once the limit is reached, instead of
+    /// returning [`Error::RateLimited`].
+    pub fn queue(&mut self) -> &mut Self {
+        self.queue = true;
+        self
+    }
+
+    /// Counts a prompt against the limit.
+    ///
+    /// Dialogs do this themselves before each prompt. It can also be called before
+    /// showing prompts of any other kind (such as with a [`PassphraseProvider`]), so
+    /// that they share the same limit.
+    ///
+    /// Returns [`Error::RateLimited`] if the limit has been reached, unless this limiter
+    /// queues prompts, in which case this blocks until the prompt may be shown.
+    ///
+    /// [`PassphraseProvider`]: crate::PassphraseProvider
+    pub fn acquire(&self) -> Result<()> {
+        loop {
+            let retry_after = {
+                let mut shown = self.shown.lock().unwrap_or_else(|e| e.into_inner());
+                let now = Instant::now();
+                while matches!(shown.front(), Some(&at) if now.duration_since(at) >= self.window) {
+                    shown.pop_front();
+                }
+                if shown.len() < self.max_prompts as usize {
+                    shown.push_back(now);
+                    return Ok(());
+                }
+                match shown.front() {
+                    Some(&oldest) => self.window - now.duration_since(oldest),
+                    // No prompts are allowed at all.
+                    None => return Err(Error::RateLimited(RateLimitedError::new(None))),
+                }
+            };
+            if !self.queue {
+                return Err(Error::RateLimited(RateLimitedError::new(Some(retry_after))));
+            }
+            thread::sleep(retry_after);
+        }
+    }
+}
+
+/// Counts a prompt against the given limiter, if any.
+pub(crate) fn acquire(limiter: Option<&RateLimiter>) -> Result<()> {
+    limiter.map_or(Ok(()), RateLimiter::acquire)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::RateLimiter;
+    use crate::Error;
+
+    #[test]
+    fn limit() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        assert!(limiter.acquire().is_ok());
+        assert!(limiter.acquire().is_ok());
+        match limiter.acquire() {
+            Err(Error::RateLimited(e)) => {
+                let retry_after = e.retry_after().unwrap();
+                assert!(retry_after > Duration::from_secs(59));
+                assert!(retry_after <= Duration::from_secs(60));
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        assert!(matches!(
+            RateLimiter::new(0, Duration::from_secs(60)).acquire(),
+            Err(Error::RateLimited(e)) if e.retry_after().is_none()
+        ));
+    }
+
+    #[test]
+    fn queue() {
+        let mut limiter = RateLimiter::new(1, Duration::from_millis(100));
+        limiter.queue();
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
@@ -0,0 +1,304 @@
+use std::ffi::OsStr;
+use std::time::Duration;
+
+use crate::{
+    assuan, discovery, Charset, Diagnostics, PinentryInfo, Result, Transcript, Utf8Policy,
+};
+
+#[cfg(target_os = "macos")]
+use crate::macos;
+#[cfg(unix)]
+use crate::unix;
+#[cfg(windows)]
+use crate::windows;
+
+/// A `pinentry` process that is reused across many dialogs.
+///
+/// Each dialog normally spawns and configures its own `pinentry` process. Long-running
+/// programs that show many dialogs can instead open a single `PinentrySession`, and
+/// show each dialog in it with [`PassphraseInput::interact_in`],
+/// [`ConfirmationDialog::confirm_in`], or [`MessageDialog::show_message_in`].
+///
+/// Only the settings that differ from those of the previous dialog are sent to the
+/// `pinentry` process. If a dialog leaves unset something that the previous dialog set,
+/// the `pinentry` process is reset first, so that no settings leak from one dialog to
+/// the next.
+///
+/// When a dialog is shown in a session, the session's binary and spawn options are
+/// used instead of the dialog's (in particular, the dialog's spawn hook, supervisor,
+/// transcript, diagnostics sink, character set, and platform-specific options are
+/// ignored).
+///
+/// If the `pinentry` process exits (which is reported as an [`Error::Io`] of kind
+/// [`BrokenPipe`] or [`UnexpectedEof`]), the session can no longer be used, and a new
+/// one should be opened.
+///
+/// The `pinentry` process is shut down when the session is dropped.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{ConfirmationDialog, PassphraseInput, PinentrySession};
+///
+/// if let Some(mut session) = PinentrySession::with_default_binary()? {
+///     if let Some(mut input) = PassphraseInput::with_default_binary() {
+///         let passphrase = input
+///             .with_description("Enter passphrase for FooBar")
+///             .interact_in(&mut session)?;
+///     }
+///     if let Some(mut dialog) = ConfirmationDialog::with_default_binary() {
+///         dialog.confirm_in(&mut session, "Allow use of key FooBar?")?;
+///     }
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+///
+/// [`PassphraseInput::interact_in`]: crate::PassphraseInput::interact_in
+/// [`ConfirmationDialog::confirm_in`]: crate::ConfirmationDialog::confirm_in
+/// [`MessageDialog::show_message_in`]: crate::MessageDialog::show_message_in
+/// [`Error::Io`]: crate::Error::Io
+/// [`BrokenPipe`]: std::io::ErrorKind::BrokenPipe
+/// [`UnexpectedEof`]: std::io::ErrorKind::UnexpectedEof
+pub struct PinentrySession {
+    pinentry: assuan::Connection,
+}
+
+impl PinentrySession {
+    /// Opens a new session using the binary named `pinentry`.
+    ///
+    /// Returns `Ok(None)` if `pinentry` cannot be found in `PATH`.
+    pub fn with_default_binary() -> Result<Option<Self>> {
+        Self::with_binary("pinentry")
+    }
+
+    /// Opens a new session using the given path to, or name of, a `pinentry` binary.
+    ///
+    /// Returns `Ok(None)` if:
+    /// - A path was provided that does not exist.
+    /// - A binary name was provided that cannot be found in `PATH`.
+    /// - The binary is found but is not executable.
+    pub fn with_binary<T: AsRef<OsStr>>(binary_name: T) -> Result<Option<Self>> {
+        Self::open(
+            binary_name,
+            #[cfg(unix)]
+            &unix::Options::default(),
+            #[cfg(target_os = "macos")]
+            &macos::Options::default(),
+            #[cfg(windows)]
+            &windows::Options::default(),
+        )
+    }
+
+    /// Opens a new session using the given path to, or name of, a `pinentry` binary,
+    /// spawned with the given Unix-specific options.
+    ///
+    /// Returns `Ok(None)` in the same cases as [`PinentrySession::with_binary`].
+    #[cfg(unix)]
+    pub fn with_binary_and_unix_options<T: AsRef<OsStr>>(
+        binary_name: T,
+        options: &unix::Options<'_>,
+    ) -> Result<Option<Self>> {
+        Self::open(
+            binary_name,
+            options,
+            #[cfg(target_os = "macos")]
+            &macos::Options::default(),
+        )
+    }
+
+    /// Opens a new session using the given path to, or name of, a `pinentry` binary,
+    /// spawned with the given Unix-specific options and presented with the given
+    /// macOS-specific options.
+    ///
+    /// Returns `Ok(None)` in the same cases as [`PinentrySession::with_binary`].
+    #[cfg(target_os = "macos")]
+    pub fn with_binary_and_macos_options<T: AsRef<OsStr>>(
+        binary_name: T,
+        unix: &unix::Options<'_>,
+        macos: &macos::Options<'_>,
+    ) -> Result<Option<Self>> {
+        Self::open(binary_name, unix, macos)
+    }
+
+    /// Opens a new session using the given path to, or name of, a `pinentry` binary,
+    /// spawned with the given Windows-specific options.
+    ///
+    /// Returns `Ok(None)` in the same cases as [`PinentrySession::with_binary`].
+    #[cfg(windows)]
+    pub fn with_binary_and_windows_options<T: AsRef<OsStr>>(
+        binary_name: T,
+        options: &windows::Options,
+    ) -> Result<Option<Self>> {
+        Self::open(binary_name, options)
+    }
+
+    fn open<T: AsRef<OsStr>>(
+        binary_name: T,
+        #[cfg(unix)] unix: &unix::Options<'_>,
+        #[cfg(target_os = "macos")] macos: &macos::Options<'_>,
+        #[cfg(windows)] windows: &windows::Options,
+    ) -> Result<Option<Self>> {
+        match discovery::find(binary_name.as_ref()) {
+            Ok(binary) => assuan::Connection::open(
+                &binary,
+                #[cfg(unix)]
+                unix,
+                #[cfg(target_os = "macos")]
+                macos,
+                #[cfg(windows)]
+                windows,
+                None,
+                None,
+                None,
+                None,
+                false,
+            )
+            .map(|pinentry| Some(PinentrySession { pinentry })),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Records the requests and responses exchanged with the `pinentry` process from now
+    /// on in the given [`Transcript`].
+    ///
+    /// When a dialog is shown in this session, its own transcript (if any) is ignored.
+    pub fn record(&mut self, transcript: &Transcript) {
+        self.pinentry.record(transcript);
+    }
+
+    /// Sets the character set used by the `pinentry` process from now on (by default,
+    /// UTF-8).
+    ///
+    /// When a dialog is shown in this session, its own character set is ignored.
+    pub fn set_charset(&mut self, charset: Charset) {
+        self.pinentry.set_charset(charset);
+    }
+
+    /// Sends the diagnostic messages for this session from now on to the given
+    /// [`Diagnostics`] sink.
+    ///
+    /// When a dialog is shown in this session, its own diagnostics sink (if any) is
+    /// ignored.
+    pub fn set_diagnostics(&mut self, diagnostics: &Diagnostics) {
+        self.pinentry.set_diagnostics(diagnostics);
+    }
+
+    /// Sets how long to wait for each response from the `pinentry` process from now on,
+    /// after which it is killed and [`Error::Timeout`] is returned (by default, there is
+    /// no limit).
+    ///
+    /// Prompts are only bounded if the dialog has a timeout, in which case they are
+    /// allowed that much longer. When a dialog is shown in this session, its own
+    /// response timeout is ignored.
+    ///
+    /// [`Error::Timeout`]: crate::Error::Timeout
+    pub fn set_response_timeout(&mut self, response_timeout: Option<Duration>) {
+        self.pinentry.set_response_timeout(response_timeout);
+    }
+
+    /// Returns the flavor and version reported by the `pinentry` binary.
+    ///
+    /// The binary is only asked the first time; later calls return the same
+    /// information.
+    pub fn info(&mut self) -> &PinentryInfo {
+        self.pinentry.info()
+    }
+
+    /// Returns the `pinentry` process, ready for the next dialog.
+    pub(crate) fn start(&mut self) -> &mut assuan::Connection {
+        self.pinentry.set_max_data_len(None);
+        self.pinentry.set_utf8_policy(Utf8Policy::Strict);
+        self.pinentry.set_dismiss_handle(None);
+        #[cfg(unix)]
+        self.pinentry.set_cancel_on_interrupt(false);
+        #[cfg(feature = "diceware")]
+        self.pinentry.set_genpin(None);
+        &mut self.pinentry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn too_long_response_is_drained() {
+        use secrecy::ExposeSecret;
+
+        use super::PinentrySession;
+        use crate::{test_util::Harness, Error, PassphraseInput};
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("GETPIN", &["D hunter2", "D hunter2", "OK"])
+            .expect("GETPIN", &["D pass", "OK"]);
+
+        let mut session =
+            PinentrySession::with_binary_and_unix_options(harness.path(), &Harness::unix_options())
+                .unwrap()
+                .unwrap();
+        let mut input = PassphraseInput::with_binary(harness.path()).unwrap();
+        assert!(matches!(
+            input.with_max_length(4).interact_in(&mut session),
+            Err(Error::TooLong)
+        ));
+        // The next prompt reads its own response, not the rest of the previous one.
+        assert_eq!(
+            input.interact_in(&mut session).unwrap().expose_secret(),
+            "pass"
+        );
+        drop(session);
+        harness.assert_complete();
+    }
+
+    #[cfg(all(target_os = "macos", feature = "test-util"))]
+    #[test]
+    fn macos_options_survive_reset() {
+        use secrecy::ExposeSecret;
+
+        use super::PinentrySession;
+        use crate::{test_util::Harness, PassphraseInput};
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("OPTION allow-external-password-cache", &["OK"])
+            .expect("SETKEYINFO foobar/vault", &["OK"])
+            .expect("SETTITLE FooBar", &["OK"])
+            .expect("SETDESC Enter passphrase", &["OK"])
+            .expect("GETPIN", &["D first", "OK"])
+            .expect("RESET", &["OK"])
+            .expect("OPTION allow-external-password-cache", &["OK"])
+            .expect("SETKEYINFO foobar/vault", &["OK"])
+            .expect("SETTITLE FooBar", &["OK"])
+            .expect("GETPIN", &["D second", "OK"]);
+
+        let mut session = PinentrySession::with_binary_and_macos_options(
+            harness.path(),
+            &Harness::unix_options(),
+            crate::macos::Options::new()
+                .app_name("FooBar")
+                .cache_key("foobar/vault"),
+        )
+        .unwrap()
+        .unwrap();
+        let mut input = PassphraseInput::with_binary(harness.path()).unwrap();
+        assert_eq!(
+            input
+                .with_description("Enter passphrase")
+                .interact_in(&mut session)
+                .unwrap()
+                .expose_secret(),
+            "first"
+        );
+        // Unsetting the description resets the `pinentry` process, which must not
+        // stop the passphrase from being cached in the keychain.
+        let input = PassphraseInput::with_binary(harness.path()).unwrap();
+        assert_eq!(
+            input.interact_in(&mut session).unwrap().expose_secret(),
+            "second"
+        );
+        drop(session);
+        harness.assert_complete();
+    }
+}
@@ -0,0 +1,308 @@
+//! Serializable settings for each kind of dialog, enabled by the `serde` feature.
+//!
+//! These let applications keep prompt definitions in their own configuration files, or
+//! send them over IPC, and apply them to a dialog with `with_settings` (such as
+//! [`PassphraseInput::with_settings`]). The settings of an existing dialog can be
+//! extracted with `From`.
+//!
+//! Every field is optional, and unset fields leave the dialog's existing settings
+//! unchanged. Unknown fields are rejected.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use pinentry::{settings::PassphraseInputSettings, PassphraseInput};
+//!
+//! # fn load_prompt(_: &str) -> PassphraseInputSettings { unimplemented!() }
+//! let settings: PassphraseInputSettings = load_prompt("unlock-vault");
+//! if let Some(mut input) = PassphraseInput::with_default_binary() {
+//!     let passphrase = input.with_settings(&settings).interact()?;
+//! }
+//! # Ok::<(), pinentry::Error>(())
+//! ```
+
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Charset, ConfirmationDialog, MessageDialog, PassphraseInput, Utf8Policy};
+
+/// Serializable settings for a [`PassphraseInput`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PassphraseInputSettings {
+    /// The window title (see [`PassphraseInput::with_title`]).
+    pub title: Option<String>,
+    /// The descriptive text (see [`PassphraseInput::with_description`]).
+    pub description: Option<String>,
+    /// The error text (see [`PassphraseInput::with_error`]).
+    pub error: Option<String>,
+    /// The prompt (see [`PassphraseInput::with_prompt`]).
+    pub prompt: Option<String>,
+    /// The prompt for confirming the passphrase (see
+    /// [`PassphraseInput::with_confirmation_prompt`]).
+    pub confirmation_prompt: Option<String>,
+    /// The error shown when the confirmation does not match (see
+    /// [`PassphraseInput::with_confirmation`]). Ignored unless `confirmation_prompt` is
+    /// set.
+    pub mismatch_error: Option<String>,
+    /// The error shown when the passphrase is empty (see [`PassphraseInput::required`]).
+    pub required: Option<String>,
+    /// The text for the button signalling confirmation (see [`PassphraseInput::with_ok`]).
+    pub ok: Option<String>,
+    /// The text for the button signalling cancellation (see
+    /// [`PassphraseInput::with_cancel`]).
+    pub cancel: Option<String>,
+    /// The timeout in seconds (see [`PassphraseInput::with_timeout`]).
+    pub timeout: Option<u16>,
+    /// The maximum length of the passphrase (see [`PassphraseInput::with_max_length`]).
+    pub max_length: Option<usize>,
+    /// Whether to use localized default button texts (see
+    /// [`PassphraseInput::with_localized_defaults`]).
+    pub localized_defaults: bool,
+    /// The character set of the `pinentry` binary (see [`PassphraseInput::with_charset`]).
+    pub charset: Option<Charset>,
+    /// How a passphrase that is not valid UTF-8 is handled (see
+    /// [`PassphraseInput::with_utf8_policy`]).
+    pub utf8_policy: Option<Utf8Policy>,
+    /// Whether to respawn the `pinentry` binary if it crashes (see
+    /// [`PassphraseInput::respawn_on_crash`]).
+    pub respawn_on_crash: bool,
+}
+
+/// Serializable settings for a [`ConfirmationDialog`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfirmationDialogSettings {
+    /// The window title (see [`ConfirmationDialog::with_title`]).
+    pub title: Option<String>,
+    /// The text for the button signalling confirmation (see
+    /// [`ConfirmationDialog::with_ok`]).
+    pub ok: Option<String>,
+    /// The text for the button signalling cancellation (see
+    /// [`ConfirmationDialog::with_cancel`]).
+    pub cancel: Option<String>,
+    /// The text for the button signalling refusal (see
+    /// [`ConfirmationDialog::with_not_ok`]).
+    pub not_ok: Option<String>,
+    /// The timeout in seconds (see [`ConfirmationDialog::with_timeout`]).
+    pub timeout: Option<u16>,
+    /// Whether to use localized default button texts (see
+    /// [`ConfirmationDialog::with_localized_defaults`]).
+    pub localized_defaults: bool,
+    /// The character set of the `pinentry` binary (see
+    /// [`ConfirmationDialog::with_charset`]).
+    pub charset: Option<Charset>,
+}
+
+/// Serializable settings for a [`MessageDialog`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MessageDialogSettings {
+    /// The window title (see [`MessageDialog::with_title`]).
+    pub title: Option<String>,
+    /// The text for the button signalling confirmation (see [`MessageDialog::with_ok`]).
+    pub ok: Option<String>,
+    /// The timeout in seconds (see [`MessageDialog::with_timeout`]).
+    pub timeout: Option<u16>,
+    /// Whether to use localized default button texts (see
+    /// [`MessageDialog::with_localized_defaults`]).
+    pub localized_defaults: bool,
+    /// The character set of the `pinentry` binary (see [`MessageDialog::with_charset`]).
+    pub charset: Option<Charset>,
+}
+
+fn owned(text: &Option<Cow<'_, str>>) -> Option<String> {
+    text.as_deref().map(str::to_owned)
+}
+
+impl<'a> From<&PassphraseInput<'a>> for PassphraseInputSettings {
+    fn from(input: &PassphraseInput<'a>) -> Self {
+        PassphraseInputSettings {
+            title: owned(&input.title),
+            description: owned(&input.description),
+            error: owned(&input.error),
+            prompt: owned(&input.prompt),
+            confirmation_prompt: input
+                .confirmation
+                .as_ref()
+                .map(|(prompt, _)| prompt.to_string()),
+            mismatch_error: input
+                .confirmation
+                .as_ref()
+                .and_then(|(_, error)| owned(error)),
+            required: owned(&input.required),
+            ok: owned(&input.ok),
+            cancel: owned(&input.cancel),
+            timeout: input.timeout,
+            max_length: input.max_length,
+            localized_defaults: input.localized_defaults,
+            charset: Some(input.charset),
+            utf8_policy: Some(input.utf8_policy),
+            respawn_on_crash: input.respawn_on_crash,
+        }
+    }
+}
+
+impl<'a> From<&ConfirmationDialog<'a>> for ConfirmationDialogSettings {
+    fn from(dialog: &ConfirmationDialog<'a>) -> Self {
+        ConfirmationDialogSettings {
+            title: owned(&dialog.title),
+            ok: owned(&dialog.ok),
+            cancel: owned(&dialog.cancel),
+            not_ok: owned(&dialog.not_ok),
+            timeout: dialog.timeout,
+            localized_defaults: dialog.localized_defaults,
+            charset: Some(dialog.charset),
+        }
+    }
+}
+
+impl<'a> From<&MessageDialog<'a>> for MessageDialogSettings {
+    fn from(dialog: &MessageDialog<'a>) -> Self {
+        MessageDialogSettings {
+            title: owned(&dialog.title),
+            ok: owned(&dialog.ok),
+            timeout: dialog.timeout,
+            localized_defaults: dialog.localized_defaults,
+            charset: Some(dialog.charset),
+        }
+    }
+}
+
+impl<'a> PassphraseInput<'a> {
+    /// Applies the given settings to this dialog.
+    ///
+    /// Settings that are unset leave the dialog's existing settings unchanged.
+    pub fn with_settings(&mut self, settings: &'a PassphraseInputSettings) -> &mut Self {
+        if let Some(title) = &settings.title {
+            self.with_title(title);
+        }
+        if let Some(description) = &settings.description {
+            self.with_description(description);
+        }
+        if let Some(error) = &settings.error {
+            self.with_error(error);
+        }
+        if let Some(prompt) = &settings.prompt {
+            self.with_prompt(prompt);
+        }
+        match (&settings.confirmation_prompt, &settings.mismatch_error) {
+            (Some(prompt), Some(error)) => {
+                self.with_confirmation(prompt, error);
+            }
+            (Some(prompt), None) => {
+                self.with_confirmation_prompt(prompt);
+            }
+            (None, _) => (),
+        }
+        if let Some(required) = &settings.required {
+            self.required(required);
+        }
+        if let Some(ok) = &settings.ok {
+            self.with_ok(ok);
+        }
+        if let Some(cancel) = &settings.cancel {
+            self.with_cancel(cancel);
+        }
+        if let Some(timeout) = settings.timeout {
+            self.with_timeout(timeout);
+        }
+        if let Some(max_length) = settings.max_length {
+            self.with_max_length(max_length);
+        }
+        if settings.localized_defaults {
+            self.with_localized_defaults();
+        }
+        if let Some(charset) = settings.charset {
+            self.with_charset(charset);
+        }
+        if let Some(utf8_policy) = settings.utf8_policy {
+            self.with_utf8_policy(utf8_policy);
+        }
+        if settings.respawn_on_crash {
+            self.respawn_on_crash();
+        }
+        self
+    }
+}
+
+impl<'a> ConfirmationDialog<'a> {
+    /// Applies the given settings to this dialog.
+    ///
+    /// Settings that are unset leave the dialog's existing settings unchanged.
+    pub fn with_settings(&mut self, settings: &'a ConfirmationDialogSettings) -> &mut Self {
+        if let Some(title) = &settings.title {
+            self.with_title(title);
+        }
+        if let Some(ok) = &settings.ok {
+            self.with_ok(ok);
+        }
+        if let Some(cancel) = &settings.cancel {
+            self.with_cancel(cancel);
+        }
+        if let Some(not_ok) = &settings.not_ok {
+            self.with_not_ok(not_ok);
+        }
+        if let Some(timeout) = settings.timeout {
+            self.with_timeout(timeout);
+        }
+        if settings.localized_defaults {
+            self.with_localized_defaults();
+        }
+        if let Some(charset) = settings.charset {
+            self.with_charset(charset);
+        }
+        self
+    }
+}
+
+impl<'a> MessageDialog<'a> {
+    /// Applies the given settings to this dialog.
+    ///
+    /// Settings that are unset leave the dialog's existing settings unchanged.
+    pub fn with_settings(&mut self, settings: &'a MessageDialogSettings) -> &mut Self {
+        if let Some(title) = &settings.title {
+            self.with_title(title);
+        }
+        if let Some(ok) = &settings.ok {
+            self.with_ok(ok);
+        }
+        if let Some(timeout) = settings.timeout {
+            self.with_timeout(timeout);
+        }
+        if settings.localized_defaults {
+            self.with_localized_defaults();
+        }
+        if let Some(charset) = settings.charset {
+            self.with_charset(charset);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PassphraseInputSettings;
+    use crate::PassphraseInput;
+
+    #[cfg(unix)]
+    #[test]
+    fn roundtrip() {
+        let mut input = PassphraseInput::with_binary("/bin/sh").unwrap();
+        input
+            .with_unix_options(crate::unix::Options::new().tty_type("dumb"))
+            .with_description("Enter new passphrase")
+            .with_confirmation("Again:", "Mismatch")
+            .with_timeout(30);
+
+        let settings = PassphraseInputSettings::from(&input);
+        assert_eq!(settings.confirmation_prompt.as_deref(), Some("Again:"));
+        assert_eq!(settings.mismatch_error.as_deref(), Some("Mismatch"));
+
+        let mut copy = PassphraseInput::with_binary("/bin/sh").unwrap();
+        copy.with_unix_options(crate::unix::Options::new().tty_type("dumb"))
+            .with_settings(&settings);
+        assert_eq!(copy.dry_run(), input.dry_run());
+    }
+}
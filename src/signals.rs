@@ -0,0 +1,205 @@
+//! Cancelling prompts when the user presses Ctrl-C or Ctrl-Z in the parent's terminal.
+//!
+//! While at least one prompt is cancellable, `SIGINT` and `SIGTSTP` are caught by a
+//! handler that writes to a self-pipe. A watcher thread reads from the pipe and kills
+//! the `pinentry` processes of those prompts, which then return `Error::Cancelled`.
+//! The previous dispositions of the signals are restored once no prompts remain.
+
+use std::io::{self, Read};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Once};
+use std::thread;
+
+use crate::assuan::Killer;
+
+/// The signals that cancel prompts.
+const SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTSTP];
+
+/// The write end of the self-pipe, or -1 before it has been created.
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+/// The number of signals that have been caught, which the handler increments.
+static CAUGHT: AtomicUsize = AtomicUsize::new(0);
+
+struct State {
+    next_id: u64,
+    /// The prompts that are currently cancellable.
+    prompts: Vec<Prompt>,
+    /// The dispositions of the signals before the handler was installed, while it is.
+    previous: Option<[libc::sigaction; 2]>,
+}
+
+struct Prompt {
+    id: u64,
+    killer: Killer,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Returns the shared state, creating the self-pipe and its watcher thread on first
+/// use.
+fn state() -> io::Result<MutexGuard<'static, State>> {
+    static INIT: Once = Once::new();
+    static STATE: AtomicPtr<Mutex<State>> = AtomicPtr::new(ptr::null_mut());
+
+    let mut res = Ok(());
+    INIT.call_once(|| {
+        res = UnixStream::pair().and_then(|(reader, writer)| {
+            // The handler must never block if the watcher falls behind.
+            writer.set_nonblocking(true)?;
+            PIPE.store(writer.as_raw_fd(), Ordering::Release);
+            // The write end is never closed, as the handler may use it at any time.
+            mem::forget(writer);
+            thread::spawn(move || watch(reader));
+            Ok(())
+        });
+        let state = Mutex::new(State {
+            next_id: 0,
+            prompts: vec![],
+            previous: None,
+        });
+        STATE.store(Box::into_raw(Box::new(state)), Ordering::Release);
+    });
+    res?;
+    if PIPE.load(Ordering::Acquire) < 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "failed to set up signal handling",
+        ));
+    }
+    // Safety: the pointer was initialized above from a leaked `Box`, and is never
+    // freed.
+    let state = unsafe { &*STATE.load(Ordering::Acquire) };
+    Ok(state.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+extern "C" fn handle(_: libc::c_int) {
+    CAUGHT.fetch_add(1, Ordering::SeqCst);
+    wake();
+}
+
+/// Wakes the watcher by writing to the self-pipe.
+fn wake() {
+    let byte = 0u8;
+    // Safety: `write` is async-signal-safe, and `byte` is valid for one byte. If the
+    // pipe is full, the watcher has yet to handle an earlier signal anyway.
+    unsafe {
+        libc::write(
+            PIPE.load(Ordering::Relaxed),
+            &byte as *const u8 as *const libc::c_void,
+            1,
+        )
+    };
+}
+
+/// Kills the `pinentry` process of every cancellable prompt whenever a signal is
+/// caught.
+fn watch(mut reader: UnixStream) {
+    let mut byte = [0];
+    while reader.read_exact(&mut byte).is_ok() {
+        if let Ok(state) = state() {
+            for prompt in &state.prompts {
+                prompt.cancelled.store(true, Ordering::SeqCst);
+                let _ = prompt.killer.kill();
+            }
+        }
+    }
+}
+
+/// Installs the handler, returning the previous dispositions of the signals.
+fn install() -> io::Result<[libc::sigaction; 2]> {
+    // Safety: `sigaction` is valid when zeroed, and is then fully initialized.
+    let mut action: libc::sigaction = unsafe { mem::zeroed() };
+    action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESTART;
+    // Safety: `sa_mask` is a valid `sigset_t`.
+    unsafe { libc::sigemptyset(&mut action.sa_mask) };
+
+    // Safety: as above.
+    let mut previous: [libc::sigaction; 2] = unsafe { mem::zeroed() };
+    for i in 0..SIGNALS.len() {
+        // Safety: `action` and `previous[i]` are valid `sigaction`s.
+        if unsafe { libc::sigaction(SIGNALS[i], &action, &mut previous[i]) } != 0 {
+            let e = io::Error::last_os_error();
+            restore(&previous[..i]);
+            return Err(e);
+        }
+    }
+    Ok(previous)
+}
+
+/// Restores the given dispositions of the first signals.
+fn restore(previous: &[libc::sigaction]) {
+    for (signal, previous) in SIGNALS.iter().zip(previous) {
+        // Safety: `previous` was filled in by an earlier call to `sigaction`.
+        unsafe { libc::sigaction(*signal, previous, ptr::null_mut()) };
+    }
+}
+
+/// A prompt that is cancelled if the user presses Ctrl-C or Ctrl-Z, until this is
+/// dropped.
+pub(crate) struct Cancellable {
+    id: u64,
+    cancelled: Arc<AtomicBool>,
+    caught: usize,
+}
+
+impl Cancellable {
+    /// Makes the prompt of the `pinentry` process that `killer` kills cancellable.
+    pub(crate) fn new(killer: Killer) -> io::Result<Self> {
+        let mut state = state()?;
+        if state.previous.is_none() {
+            state.previous = Some(install()?);
+        }
+        let id = state.next_id;
+        state.next_id += 1;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        state.prompts.push(Prompt {
+            id,
+            killer,
+            cancelled: cancelled.clone(),
+        });
+        Ok(Cancellable {
+            id,
+            cancelled,
+            caught: CAUGHT.load(Ordering::SeqCst),
+        })
+    }
+
+    /// Returns true if a signal was caught while the prompt was cancellable.
+    ///
+    /// This is also true if the `pinentry` process received the signal itself (as it
+    /// does when it shares the parent's terminal), and exited before the watcher could
+    /// kill it.
+    pub(crate) fn was_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst) || CAUGHT.load(Ordering::SeqCst) != self.caught
+    }
+}
+
+impl Drop for Cancellable {
+    fn drop(&mut self) {
+        if let Ok(mut state) = state() {
+            state.prompts.retain(|prompt| prompt.id != self.id);
+            if state.prompts.is_empty() {
+                if let Some(previous) = state.previous.take() {
+                    restore(&previous);
+                }
+            }
+        }
+    }
+}
+
+/// Returns true if the handler is installed.
+#[cfg(all(test, feature = "test-util"))]
+pub(crate) fn is_installed() -> bool {
+    state().map_or(false, |state| state.previous.is_some())
+}
+
+/// Wakes the watcher as if a signal had been caught, without raising one in the test
+/// process.
+#[cfg(all(test, feature = "test-util"))]
+pub(crate) fn simulate() {
+    wake();
+}
@@ -0,0 +1,156 @@
+//! A preset for the confirmation that `ssh-agent` implementations show before each use
+//! of a key that was added with `ssh-add -c`.
+
+use crate::{ConfirmationDialog, Error, Result};
+
+/// The default timeout (in seconds) of a [`KeyUseConfirmation`].
+///
+/// This is much shorter than `pinentry`'s own default: the SSH client that is waiting
+/// for the signature will itself give up before long.
+pub const DEFAULT_TIMEOUT: u16 = 30;
+
+/// The user's answer to a [`KeyUseConfirmation`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyUse {
+    /// The user allowed the key to be used.
+    Allowed,
+    /// The user explicitly denied the use of the key (with the "Deny" button).
+    Denied,
+    /// The user closed the dialog without answering (with the "Cancel" button, the
+    /// window's close button, or the Escape key), or the dialog timed out.
+    ///
+    /// Agents should refuse the request, but may treat this differently from a denial
+    /// (for example, by not logging it as a rejected signature).
+    Dismissed,
+}
+
+/// A dialog asking the user to confirm a single use of an SSH key.
+///
+/// This wraps a [`ConfirmationDialog`], applying the conventions of OpenSSH's
+/// `ssh-askpass` confirmation: the dialog is titled "ssh-agent", describes the key by
+/// its comment and fingerprint, has "Allow" and "Deny" buttons alongside "Cancel", and
+/// times out after [`DEFAULT_TIMEOUT`] seconds. Any of these that are already set on
+/// the dialog are kept.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{ssh_agent::{KeyUse, KeyUseConfirmation}, ConfirmationDialog};
+///
+/// if let Some(dialog) = ConfirmationDialog::with_default_binary() {
+///     let confirmation = KeyUseConfirmation::new(dialog);
+///     match confirmation.confirm(
+///         "SHA256:47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU",
+///         "user@example.com",
+///     )? {
+///         KeyUse::Allowed => println!("Signing"),
+///         KeyUse::Denied | KeyUse::Dismissed => println!("Refusing"),
+///     }
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct KeyUseConfirmation<'a> {
+    dialog: ConfirmationDialog<'a>,
+}
+
+impl<'a> KeyUseConfirmation<'a> {
+    /// Creates a key use confirmation that shows the given dialog.
+    pub fn new(mut dialog: ConfirmationDialog<'a>) -> Self {
+        if dialog.title.is_none() {
+            dialog.with_title("ssh-agent");
+        }
+        if dialog.ok.is_none() {
+            dialog.with_ok("_Allow");
+        }
+        if dialog.not_ok.is_none() {
+            dialog.with_not_ok("_Deny");
+        }
+        if dialog.timeout.is_none() {
+            dialog.with_timeout(DEFAULT_TIMEOUT);
+        }
+        KeyUseConfirmation { dialog }
+    }
+
+    /// Returns the text that describes the key with the given fingerprint and comment.
+    ///
+    /// This is the text that OpenSSH's `ssh-agent` shows. Keys without a comment are
+    /// described by their fingerprint alone.
+    pub fn description(fingerprint: &str, comment: &str) -> String {
+        if comment.is_empty() {
+            format!("Allow use of key {}?", fingerprint)
+        } else {
+            format!(
+                "Allow use of key {}?\nKey fingerprint {}.",
+                comment, fingerprint
+            )
+        }
+    }
+
+    /// Asks the user to confirm a use of the key with the given fingerprint (such as
+    /// `SHA256:47DEQpj8...`) and comment.
+    ///
+    /// Errors other than the dialog being closed or timing out (such as the `pinentry`
+    /// binary failing to start) are returned as-is; agents should refuse the request in
+    /// that case too.
+    pub fn confirm(&self, fingerprint: &str, comment: &str) -> Result<KeyUse> {
+        match self
+            .dialog
+            .confirm(&Self::description(fingerprint, comment))
+        {
+            Ok(true) => Ok(KeyUse::Allowed),
+            Ok(false) => Ok(KeyUse::Denied),
+            Err(Error::Cancelled) | Err(Error::Timeout) => Ok(KeyUse::Dismissed),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the exact sequence of Assuan requests that
+    /// [`KeyUseConfirmation::confirm`] would send, without spawning anything.
+    pub fn dry_run(&self, fingerprint: &str, comment: &str) -> Vec<String> {
+        self.dialog
+            .dry_run(&Self::description(fingerprint, comment))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KeyUseConfirmation;
+    use crate::ConfirmationDialog;
+
+    #[cfg(unix)]
+    #[test]
+    fn dry_run() {
+        let mut dialog = ConfirmationDialog::with_binary("/bin/sh").unwrap();
+        dialog
+            .with_unix_options(
+                crate::unix::Options::new()
+                    .tty_name("/dev/tty")
+                    .tty_type("dumb"),
+            )
+            .with_timeout(10);
+        let confirmation = KeyUseConfirmation::new(dialog);
+        assert_eq!(
+            confirmation.dry_run("SHA256:47DEQpj8", "user@example.com"),
+            [
+                "OPTION ttyname=/dev/tty",
+                "OPTION ttytype=dumb",
+                "SETTITLE ssh-agent",
+                "SETDESC Allow use of key user@example.com?%0AKey fingerprint SHA256:47DEQpj8.",
+                "SETOK _Allow",
+                "SETNOTOK _Deny",
+                "SETTIMEOUT 10",
+                "CONFIRM",
+                "BYE",
+            ],
+        );
+    }
+
+    #[test]
+    fn description() {
+        assert_eq!(
+            KeyUseConfirmation::description("SHA256:47DEQpj8", ""),
+            "Allow use of key SHA256:47DEQpj8?"
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::assuan::Killer;
+
+/// How long the watchdog sleeps while there are no outstanding prompts.
+const IDLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A watchdog that enforces a wall-clock budget on every prompt it supervises.
+///
+/// Agents that show many prompts (possibly concurrently) can use a single `Supervisor`
+/// to ensure that one wedged `pinentry` process cannot block the whole service. Any
+/// supervised prompt that is still outstanding once the budget has elapsed has its
+/// `pinentry` process killed, and returns [`Error::Timeout`]. The reaped prompts can
+/// then be reported with [`Supervisor::take_reaped`].
+///
+/// Dialogs are placed under supervision with their `with_supervisor` method.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{ConfirmationDialog, Supervisor};
+/// use std::time::Duration;
+///
+/// let supervisor = Supervisor::new(Duration::from_secs(120));
+///
+/// if let Some(mut input) = ConfirmationDialog::with_default_binary() {
+///     input
+///         .with_supervisor(&supervisor, "key-use confirmation")
+///         .confirm("Allow use of key FooBar?")?;
+/// }
+///
+/// for reaped in supervisor.take_reaped() {
+///     eprintln!("Prompt '{}' was killed", reaped.label());
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+///
+/// [`Error::Timeout`]: crate::Error::Timeout
+#[derive(Clone)]
+pub struct Supervisor {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    budget: Duration,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    outstanding: Vec<Outstanding>,
+    /// The IDs of registered prompts that have been reaped.
+    reaped_ids: HashSet<u64>,
+    /// The reaped prompts that have not yet been reported.
+    reaped: Vec<ReapedPrompt>,
+}
+
+struct Outstanding {
+    id: u64,
+    label: String,
+    started: Instant,
+    budget: Duration,
+    killer: Killer,
+}
+
+/// A prompt that was killed by a [`Supervisor`] for exceeding its budget.
+#[derive(Clone, Debug)]
+pub struct ReapedPrompt {
+    label: String,
+    elapsed: Duration,
+}
+
+impl ReapedPrompt {
+    /// Returns the label that the prompt was placed under supervision with.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Returns how long the prompt had been outstanding when it was killed.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+impl Inner {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Supervisor {
+    /// Creates a new supervisor that kills any prompt that is outstanding for longer
+    /// than `budget`.
+    pub fn new(budget: Duration) -> Self {
+        let inner = Arc::new(Inner {
+            budget,
+            state: Mutex::new(State::default()),
+            changed: Condvar::new(),
+        });
+
+        let watched = Arc::downgrade(&inner);
+        thread::spawn(move || watchdog(watched));
+
+        Supervisor { inner }
+    }
+
+    /// Returns the budget of each supervised prompt.
+    pub(crate) fn budget(&self) -> Duration {
+        self.inner.budget
+    }
+
+    /// Returns the number of supervised prompts that are currently outstanding.
+    pub fn outstanding(&self) -> usize {
+        self.inner.state().outstanding.len()
+    }
+
+    /// Returns the prompts that have been reaped since the last call to this method.
+    pub fn take_reaped(&self) -> Vec<ReapedPrompt> {
+        std::mem::take(&mut self.inner.state().reaped)
+    }
+
+    pub(crate) fn register(&self, label: &str, killer: Killer) -> Registration {
+        self.register_with_budget(label, killer, self.inner.budget)
+    }
+
+    /// Places a prompt under supervision with its own budget, instead of this
+    /// supervisor's.
+    pub(crate) fn register_with_budget(
+        &self,
+        label: &str,
+        killer: Killer,
+        budget: Duration,
+    ) -> Registration {
+        let mut state = self.inner.state();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.outstanding.push(Outstanding {
+            id,
+            label: label.to_owned(),
+            started: Instant::now(),
+            budget,
+            killer,
+        });
+        self.inner.changed.notify_all();
+
+        Registration {
+            inner: self.inner.clone(),
+            id,
+        }
+    }
+}
+
+/// A prompt that is outstanding under a [`Supervisor`].
+///
+/// The prompt is deregistered when this is dropped.
+pub(crate) struct Registration {
+    inner: Arc<Inner>,
+    id: u64,
+}
+
+impl Registration {
+    /// Returns true if the supervisor killed this prompt.
+    pub(crate) fn was_reaped(&self) -> bool {
+        self.inner.state().reaped_ids.contains(&self.id)
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut state = self.inner.state();
+        state.outstanding.retain(|p| p.id != self.id);
+        state.reaped_ids.remove(&self.id);
+        self.inner.changed.notify_all();
+    }
+}
+
+fn watchdog(watched: Weak<Inner>) {
+    // Exit once every `Supervisor` and `Registration` has been dropped.
+    while let Some(inner) = watched.upgrade() {
+        let mut state = inner.state();
+
+        let now = Instant::now();
+        let (overdue, outstanding) = std::mem::take(&mut state.outstanding)
+            .into_iter()
+            .partition::<Vec<_>, _>(|p| now.duration_since(p.started) >= p.budget);
+        state.outstanding = outstanding;
+        if !overdue.is_empty() {
+            // The prompts are marked as reaped before they are killed, so that they
+            // return `Error::Timeout` once their responses fail.
+            state.reaped_ids.extend(overdue.iter().map(|p| p.id));
+            // The processes are killed without holding the lock, so that other prompts
+            // are not blocked behind a process that is slow to be killed.
+            drop(state);
+            let killed: Vec<_> = overdue
+                .into_iter()
+                .map(|prompt| {
+                    let killed = prompt.killer.kill();
+                    (prompt, killed)
+                })
+                .collect();
+            state = inner.state();
+            for (prompt, killed) in killed {
+                match killed {
+                    Ok(true) => state.reaped.push(ReapedPrompt {
+                        label: prompt.label,
+                        elapsed: now.duration_since(prompt.started),
+                    }),
+                    // The process had already exited, so its prompt was not reaped.
+                    _ => {
+                        state.reaped_ids.remove(&prompt.id);
+                    }
+                }
+            }
+        }
+
+        let now = Instant::now();
+        let timeout = state
+            .outstanding
+            .iter()
+            .map(|p| p.budget.saturating_sub(now.duration_since(p.started)))
+            .min()
+            .unwrap_or(IDLE_INTERVAL);
+        drop(inner.changed.wait_timeout(state, timeout));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(unix, feature = "test-util"))]
+    #[test]
+    fn reap() {
+        use std::time::Duration;
+
+        use super::Supervisor;
+        use crate::{test_util::Harness, ConfirmationDialog, Error};
+
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("SETDESC Continue?", &["OK"])
+            .expect("CONFIRM", &[]);
+
+        let supervisor = Supervisor::new(Duration::from_millis(200));
+        let res = ConfirmationDialog::with_binary(harness.path())
+            .unwrap()
+            .with_supervisor(&supervisor, "wedged")
+            .confirm("Continue?");
+        assert!(matches!(res, Err(Error::Timeout)));
+        let reaped = supervisor.take_reaped();
+        assert_eq!(reaped.len(), 1);
+        assert_eq!(reaped[0].label(), "wedged");
+        assert!(reaped[0].elapsed() >= Duration::from_millis(200));
+        assert_eq!(supervisor.outstanding(), 0);
+    }
+}
@@ -0,0 +1,26 @@
+//! Utilities for testing code that interacts with `pinentry` binaries, enabled by the
+//! `test-util` feature.
+//!
+//! A [`Harness`] is a scripted fake `pinentry` binary. A test declares the requests
+//! that it expects a dialog to send, and the responses to send back for each of them,
+//! and then runs the dialog against the harness instead of a real `pinentry` binary.
+//! The fake binary is a POSIX shell script, so harnesses are only available on Unix.
+//!
+//! A harness can also replay a [`Transcript`] of a real interaction (see
+//! [`Harness::replay`]), so that bugs reported against particular `pinentry` binaries
+//! can be turned into deterministic regression tests.
+//!
+//! [`assert_requests`] compares the requests that a dialog sent (or would send; see
+//! [`PassphraseInput::dry_run`]) against a golden transcript, so that refactors cannot
+//! silently change them.
+//!
+//! [`Transcript`]: crate::Transcript
+//! [`PassphraseInput::dry_run`]: crate::PassphraseInput::dry_run
+
+mod golden;
+#[cfg(unix)]
+mod harness;
+
+pub use golden::{assert_requests, compare_requests, requests};
+#[cfg(unix)]
+pub use harness::Harness;
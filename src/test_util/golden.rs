@@ -0,0 +1,146 @@
+use std::fmt::Write;
+
+use crate::Transcript;
+
+/// The placeholder that matches any text in a golden request line.
+const ANY: &str = "{any}";
+
+/// Returns the request lines recorded in the given transcript.
+pub fn requests(transcript: &Transcript) -> Vec<String> {
+    transcript
+        .lines()
+        .into_iter()
+        .filter_map(|line| line.strip_prefix("> ").map(String::from))
+        .collect()
+}
+
+/// Returns true if `line` matches the golden line `golden`.
+fn matches(line: &str, golden: &str) -> bool {
+    let mut parts = golden.split(ANY);
+    // `split` always yields at least one part.
+    let first = parts.next().unwrap();
+    let mut rest = match line.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let mut parts: Vec<_> = parts.collect();
+    let last = match parts.pop() {
+        Some(last) => last,
+        // There are no placeholders.
+        None => return rest.is_empty(),
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Compares request lines (without their line endings) against a golden transcript.
+///
+/// Each golden line must match the corresponding request exactly, except that the
+/// placeholder `{any}` matches any text (including none). For example,
+/// `SETTIMEOUT {any}` matches any `SETTIMEOUT` request that has parameters.
+///
+/// Returns a description of the differences if they do not match.
+pub fn compare_requests<S: AsRef<str>>(actual: &[S], golden: &[&str]) -> Result<(), String> {
+    let mismatch =
+        (0..actual.len().max(golden.len())).find(|&i| match (actual.get(i), golden.get(i)) {
+            (Some(line), Some(golden)) => !matches(line.as_ref(), golden),
+            _ => true,
+        });
+    let i = match mismatch {
+        Some(i) => i,
+        None => return Ok(()),
+    };
+
+    let mut description = match (actual.get(i), golden.get(i)) {
+        (Some(line), Some(golden)) => format!(
+            "request {} was {:?}, but expected {:?}",
+            i + 1,
+            line.as_ref(),
+            golden,
+        ),
+        (Some(line), None) => format!("unexpected request {}: {:?}", i + 1, line.as_ref()),
+        (None, Some(golden)) => format!("missing request {}: {:?}", i + 1, golden),
+        (None, None) => unreachable!(),
+    };
+    description.push_str("\nrequests:");
+    for (j, line) in actual.iter().enumerate() {
+        let marker = if i == j { ">" } else { " " };
+        let _ = write!(description, "\n{} {}", marker, line.as_ref());
+    }
+    Err(description)
+}
+
+/// Asserts that request lines (without their line endings) match a golden transcript.
+///
+/// See [`compare_requests`] for how lines are matched.
+///
+/// # Panics
+///
+/// Panics (with a description of the differences) if they do not match.
+///
+/// # Examples
+///
+/// ```
+/// use pinentry::{test_util::assert_requests, PassphraseInput};
+///
+/// if let Some(mut input) = PassphraseInput::with_binary("/bin/sh") {
+///     input.with_description("Enter passphrase for FooBar").with_timeout(30);
+///     assert_requests(
+///         &input.dry_run(),
+///         &[
+///             "OPTION ttyname={any}",
+///             "OPTION ttytype={any}",
+///             "SETDESC Enter passphrase for FooBar",
+///             "SETTIMEOUT {any}",
+///             "GETPIN",
+///             "BYE",
+///         ],
+///     );
+/// }
+/// ```
+pub fn assert_requests<S: AsRef<str>>(actual: &[S], golden: &[&str]) {
+    if let Err(description) = compare_requests(actual, golden) {
+        panic!("{}", description);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare_requests, matches};
+
+    #[test]
+    fn placeholders() {
+        assert!(matches("GETPIN", "GETPIN"));
+        assert!(!matches("GETPIN", "GETPI"));
+        assert!(!matches("GETPINS", "GETPIN"));
+        assert!(matches("SETTIMEOUT 30", "SETTIMEOUT {any}"));
+        assert!(matches("SETTIMEOUT ", "SETTIMEOUT {any}"));
+        assert!(!matches("SETTIMEOUT", "SETTIMEOUT {any}"));
+        assert!(matches("SETDESC a b c", "SETDESC {any} b {any}"));
+        assert!(matches("SETDESC a bb", "SETDESC {any}b"));
+        assert!(!matches("SETDESC ab", "SETDESC {any}b{any}b"));
+    }
+
+    #[test]
+    fn differences() {
+        assert_eq!(
+            compare_requests(&["GETPIN", "BYE"], &["GETPIN", "BYE"]),
+            Ok(())
+        );
+        assert_eq!(
+            compare_requests(&["GETPIN"], &["GETPIN", "BYE"]),
+            Err("missing request 2: \"BYE\"\nrequests:\n  GETPIN".to_owned()),
+        );
+        assert_eq!(
+            compare_requests(&["SETOK Yes", "CONFIRM"], &["SETOK No", "CONFIRM"]),
+            Err("request 1 was \"SETOK Yes\", but expected \"SETOK No\"\n\
+                 requests:\n> SETOK Yes\n  CONFIRM"
+                .to_owned()),
+        );
+    }
+}
@@ -0,0 +1,419 @@
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+use crate::{unix, Transcript};
+
+/// `GPG_ERR_ASS_UNKNOWN_CMD`, with the error source set to `pinentry`.
+const UNKNOWN_COMMAND: &str = "ERR 83886355 Unexpected command";
+
+/// Which requests a step of a [`Harness`] script accepts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expect {
+    /// Exactly this request line.
+    Exact(String),
+    /// Any request line starting with this prefix.
+    Prefix(String),
+    /// Any request line.
+    Any,
+}
+
+impl Expect {
+    fn matches(&self, line: &str) -> bool {
+        match self {
+            Expect::Exact(request) => line == request,
+            Expect::Prefix(prefix) => line.starts_with(prefix.as_str()),
+            Expect::Any => true,
+        }
+    }
+}
+
+impl std::fmt::Display for Expect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expect::Exact(request) => write!(f, "{:?}", request),
+            Expect::Prefix(prefix) => write!(f, "{:?}...", prefix),
+            Expect::Any => write!(f, "any request"),
+        }
+    }
+}
+
+/// A scripted fake `pinentry` binary.
+///
+/// The harness answers each request in its script, in order, with the scripted
+/// response lines (which may be any Assuan response lines, including `D`, `S`, `ERR`,
+/// and `INQUIRE` lines). Requests that do not match the next step of the script are
+/// answered with an `ERR` response. Once the script is complete, `BYE` is acknowledged
+/// and any other request is answered with an `ERR` response.
+///
+/// Request lines are matched as sent, so parameters must be percent-encoded in the
+/// same way as the dialog encodes them (for example, `%` is sent as `%25`).
+///
+/// The script is written to a temporary directory when [`Harness::path`] is first
+/// called, and removed when the harness is dropped.
+///
+/// # Examples
+///
+/// ```
+/// use pinentry::{test_util::Harness, PassphraseInput};
+/// use secrecy::ExposeSecret;
+///
+/// let mut harness = Harness::new();
+/// harness
+///     .expect_options()
+///     .expect("SETDESC Enter passphrase for FooBar", &["OK"])
+///     .expect("GETPIN", &["D hunter2", "OK"]);
+///
+/// let passphrase = PassphraseInput::with_binary(harness.path())
+///     .unwrap()
+///     .with_description("Enter passphrase for FooBar")
+///     .interact()?;
+/// assert_eq!(passphrase.expose_secret(), "hunter2");
+/// harness.assert_complete();
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Harness {
+    preamble: Vec<String>,
+    steps: Vec<(Expect, Vec<String>)>,
+    /// The number of steps after which the fake binary exits, if it does.
+    exit_after: Option<usize>,
+    dir: Option<PathBuf>,
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Harness {
+    /// Creates a harness with an empty script.
+    pub fn new() -> Self {
+        Harness {
+            preamble: vec![],
+            steps: vec![],
+            exit_after: None,
+            dir: None,
+        }
+    }
+
+    /// Creates a harness that replays the given transcript of an interaction with a
+    /// single `pinentry` process.
+    ///
+    /// Each recorded request becomes a step that expects the same request (`OPTION`
+    /// requests only need to set the same option, as their values depend on the
+    /// environment), and answers it with the responses that followed it. Responses
+    /// recorded before the first request (the greeting) and a trailing `BYE` request
+    /// are skipped, as the harness provides them itself.
+    ///
+    /// Responses are replayed as recorded, so data lines (which are redacted in
+    /// transcripts) are replayed as `D [REDACTED]`. Edit the transcript first to replay
+    /// specific data.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pinentry::{test_util::Harness, PassphraseInput, Transcript};
+    ///
+    /// let transcript = Transcript::load("tests/transcripts/pinentry-mac.txt")?;
+    /// let mut harness = Harness::replay(&transcript);
+    /// let passphrase = PassphraseInput::with_binary(harness.path())
+    ///     .unwrap()
+    ///     .with_description("Enter passphrase for FooBar")
+    ///     .interact();
+    /// harness.assert_complete();
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn replay(transcript: &Transcript) -> Self {
+        let mut lines = transcript.lines();
+        if let Some(last) = lines.iter().rposition(|line| line.starts_with("> ")) {
+            if lines[last] == "> BYE" {
+                lines.truncate(last);
+            }
+        }
+
+        let mut harness = Harness::new();
+        for line in lines {
+            if let Some(request) = line.strip_prefix("> ") {
+                let expect = match request.split_once('=') {
+                    Some((option, _)) if request.starts_with("OPTION ") => {
+                        Expect::Prefix(format!("{}=", option))
+                    }
+                    _ => Expect::Exact(request.to_owned()),
+                };
+                harness.steps.push((expect, vec![]));
+            } else if let (Some(response), Some((_, responses))) =
+                (line.strip_prefix("< "), harness.steps.last_mut())
+            {
+                responses.push(response.to_owned());
+            }
+        }
+        harness
+    }
+
+    /// Prints the given lines before the greeting, as some wrappers around `pinentry`
+    /// binaries do.
+    pub fn preamble(&mut self, lines: &[&str]) -> &mut Self {
+        self.preamble
+            .extend(lines.iter().map(|line| line.to_string()));
+        self
+    }
+
+    /// Expects exactly the given request line (without its line ending), and answers it
+    /// with the given response lines.
+    pub fn expect(&mut self, request: &str, responses: &[&str]) -> &mut Self {
+        self.step(Expect::Exact(request.to_owned()), responses)
+    }
+
+    /// Expects a request line starting with the given prefix, and answers it with the
+    /// given response lines.
+    pub fn expect_prefix(&mut self, prefix: &str, responses: &[&str]) -> &mut Self {
+        self.step(Expect::Prefix(prefix.to_owned()), responses)
+    }
+
+    /// Expects any request line, and answers it with the given response lines.
+    pub fn expect_any(&mut self, responses: &[&str]) -> &mut Self {
+        self.step(Expect::Any, responses)
+    }
+
+    /// Expects the `OPTION` requests that are sent to every newly spawned `pinentry`
+    /// binary, and acknowledges them.
+    pub fn expect_options(&mut self) -> &mut Self {
+        self.expect_prefix("OPTION ttyname=", &["OK"])
+            .expect_prefix("OPTION ttytype=", &["OK"])
+    }
+
+    /// Returns the Unix options for dialogs that are run against a harness.
+    ///
+    /// These set the `ttyname` and `ttytype` options that [`Harness::expect_options`]
+    /// expects, without depending on the environment of the test process.
+    pub fn unix_options() -> unix::Options<'static> {
+        let mut options = unix::Options::new();
+        options.tty_name("/dev/tty").tty_type("dumb");
+        options
+    }
+
+    /// Exits at this point of the script, without reading any further requests, as a
+    /// `pinentry` binary that crashes would.
+    ///
+    /// Every `pinentry` process spawned from this harness exits at the same point.
+    pub fn exit(&mut self) -> &mut Self {
+        assert!(
+            self.dir.is_none(),
+            "the harness script has already been written"
+        );
+        self.exit_after = Some(self.steps.len());
+        self
+    }
+
+    fn step(&mut self, expect: Expect, responses: &[&str]) -> &mut Self {
+        assert!(
+            self.dir.is_none(),
+            "the harness script has already been written"
+        );
+        self.steps.push((
+            expect,
+            responses.iter().map(|line| (*line).to_owned()).collect(),
+        ));
+        self
+    }
+
+    /// Returns the path to the fake `pinentry` binary, writing its script first if
+    /// necessary.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the script cannot be written.
+    pub fn path(&mut self) -> PathBuf {
+        if self.dir.is_none() {
+            let dir = self.write().expect("could not write the harness script");
+            self.dir = Some(dir);
+        }
+        self.dir.as_ref().unwrap().join("pinentry")
+    }
+
+    fn write(&self) -> io::Result<PathBuf> {
+        let dir = crate::unix::private_dir(&std::env::temp_dir(), "pinentry-harness")?;
+        let received = dir.join("received");
+        fs::write(&received, "")?;
+
+        let mut script = format!(
+            "#!/bin/sh\n\
+             received={}\n\
+             step() {{\n\
+             \x20 while IFS= read -r line; do\n\
+             \x20   printf '%s\\n' \"$line\" >> \"$received\"\n\
+             \x20   case \"$1\" in\n\
+             \x20     exact) [ \"$line\" = \"$2\" ] && return 0 ;;\n\
+             \x20     prefix) [ \"${{line#\"$2\"}}\" != \"$line\" ] && return 0 ;;\n\
+             \x20     any) return 0 ;;\n\
+             \x20   esac\n\
+             \x20   echo '{}'\n\
+             \x20 done\n\
+             \x20 exit 0\n\
+             }}\n",
+            quote(&received.to_string_lossy()),
+            UNKNOWN_COMMAND,
+        );
+        for line in &self.preamble {
+            script += &format!("printf '%s\\n' {}\n", quote(line));
+        }
+        script += "echo 'OK Pleased to meet you'\n";
+        for (i, (expect, responses)) in self.steps.iter().enumerate() {
+            if self.exit_after == Some(i) {
+                break;
+            }
+            match expect {
+                Expect::Exact(request) => script += &format!("step exact {}\n", quote(request)),
+                Expect::Prefix(prefix) => script += &format!("step prefix {}\n", quote(prefix)),
+                Expect::Any => script += "step any\n",
+            }
+            for response in responses {
+                script += &format!("printf '%s\\n' {}\n", quote(response));
+            }
+        }
+        if self.exit_after.is_some() {
+            script += "exit 1\n";
+        }
+        script += "step exact BYE\necho 'OK closing connection'\n";
+
+        // Write the script under a temporary name, so that it is never executed while
+        // partially written.
+        let path = dir.join("pinentry");
+        let partial = dir.join("pinentry.partial");
+        fs::write(&partial, script)?;
+        fs::set_permissions(&partial, fs::Permissions::from_mode(0o755))?;
+        fs::rename(&partial, &path)?;
+        Ok(dir)
+    }
+
+    /// Returns the request lines received so far (by every `pinentry` process spawned
+    /// from this harness), without their line endings.
+    pub fn received(&self) -> Vec<String> {
+        self.dir
+            .as_ref()
+            .and_then(|dir| fs::read_to_string(dir.join("received")).ok())
+            .map(|received| received.lines().map(String::from).collect())
+            .unwrap_or_default()
+    }
+
+    /// Checks that the requests received so far followed the script exactly, and that
+    /// every step of the script was reached.
+    ///
+    /// A trailing `BYE` request (which the harness always accepts) is allowed.
+    pub fn verify(&self) -> Result<(), String> {
+        let received = self.received();
+        let mut steps = self.steps.iter().map(|(expect, _)| expect).peekable();
+        for (i, line) in received.iter().enumerate() {
+            match steps.peek() {
+                Some(expect) if expect.matches(line) => {
+                    steps.next();
+                }
+                Some(expect) => {
+                    return Err(format!(
+                        "request {} was {:?}, but expected {}",
+                        i + 1,
+                        line,
+                        expect,
+                    ))
+                }
+                None if line == "BYE" && i + 1 == received.len() => (),
+                None => {
+                    return Err(format!(
+                        "request {} was {:?}, but the script was complete",
+                        i + 1,
+                        line,
+                    ))
+                }
+            }
+        }
+        match steps.next() {
+            Some(expect) => Err(format!("expected {}, but it was never received", expect)),
+            None => Ok(()),
+        }
+    }
+
+    /// Asserts that the requests received so far followed the script exactly, and that
+    /// every step of the script was reached.
+    ///
+    /// # Panics
+    ///
+    /// Panics (with the requests received so far) if [`Harness::verify`] fails.
+    pub fn assert_complete(&self) {
+        if let Err(e) = self.verify() {
+            panic!("{}\nreceived: {:#?}", e, self.received());
+        }
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.dir {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+/// Quotes a string for a POSIX shell.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+
+    use super::Harness;
+    use crate::{ConfirmationDialog, Error, PassphraseInput, Transcript};
+
+    #[test]
+    fn scripted_responses() {
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("SETDESC It's 100%25 fine?", &["OK"])
+            .expect("CONFIRM", &["S tick", "ERR 83886179 Operation cancelled"]);
+
+        let dialog = ConfirmationDialog::with_binary(harness.path()).unwrap();
+        assert!(!dialog.confirm("It's 100% fine?").unwrap());
+        harness.assert_complete();
+        assert_eq!(harness.received().last().unwrap(), "BYE");
+
+        match dialog.confirm("Something else?") {
+            Err(Error::Gpg(e)) => assert_eq!(e.code(), 275),
+            res => panic!("unexpected result: {:?}", res.map_err(|e| e.to_string())),
+        }
+        assert!(harness.verify().is_err());
+    }
+
+    #[test]
+    fn replay() {
+        let transcript = Transcript::new();
+        let mut harness = Harness::new();
+        harness
+            .expect_options()
+            .expect("SETDESC Line 1%0ALine 2", &["OK"])
+            .expect("GETPIN", &["S PASSWORD_FROM_CACHE", "D hunter2", "OK"]);
+        let mut input = PassphraseInput::with_binary(harness.path()).unwrap();
+        input
+            .with_transcript(&transcript)
+            .with_description("Line 1\nLine 2");
+        input.interact().unwrap();
+        harness.assert_complete();
+
+        let dir = crate::unix::private_dir(&std::env::temp_dir(), "pinentry-replay").unwrap();
+        let path = dir.join("transcript");
+        transcript.save(&path).unwrap();
+        let loaded = Transcript::load(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.lines(), transcript.lines());
+
+        let mut replay = Harness::replay(&loaded);
+        let mut input = PassphraseInput::with_binary(replay.path()).unwrap();
+        let passphrase = input.with_description("Line 1\nLine 2").interact().unwrap();
+        assert_eq!(passphrase.expose_secret(), "[REDACTED]");
+        replay.assert_complete();
+    }
+}
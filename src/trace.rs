@@ -0,0 +1,52 @@
+//! Optional `tracing` integration, enabled by the `tracing` feature.
+//!
+//! Each Assuan request is recorded as a span containing only the command name (see
+//! `command_context`), how long the request took, and the kind of response. Request
+//! parameters and response data are never recorded.
+
+#[cfg(feature = "tracing")]
+use crate::Error;
+use crate::Result;
+
+/// Runs `f`, which performs the Assuan request identified by `context`, inside a span.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn request<T>(context: &str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    #[cfg(feature = "tracing")]
+    {
+        let span = tracing::debug_span!(
+            "assuan_request",
+            command = context,
+            duration_ms = tracing::field::Empty,
+            response = tracing::field::Empty,
+        );
+        let start = std::time::Instant::now();
+        let res = span.in_scope(f);
+        span.record("duration_ms", start.elapsed().as_millis() as u64);
+        span.record("response", response_kind(&res));
+        res
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    f()
+}
+
+#[cfg(feature = "tracing")]
+fn response_kind<T>(res: &Result<T>) -> &'static str {
+    match res {
+        Ok(_) => "OK",
+        Err(Error::Cancelled)
+        | Err(Error::Timeout)
+        | Err(Error::NotConfirmed)
+        | Err(Error::Gpg(_))
+        | Err(Error::Unavailable(_)) => "ERR",
+        Err(Error::Io(_)) => "io error",
+        Err(Error::Protocol(_)) => "protocol error",
+        Err(Error::NotAPinentry(_)) => "not a pinentry",
+        Err(Error::Encoding(_)) => "encoding error",
+        Err(Error::TooLong) => "too long",
+        Err(Error::InvalidSetting(_)) => "invalid setting",
+        Err(Error::Exhausted(_)) => "exhausted",
+        Err(Error::RateLimited(_)) => "rate limited",
+        Err(Error::Dismissed) => "dismissed",
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A redacted record of the requests and responses exchanged with a `pinentry` binary.
+///
+/// Transcripts are useful for debugging the quirks of particular `pinentry` binaries
+/// (for example, when investigating a bug report from a user). Requests are recorded
+/// as sent (with their parameters percent-encoded), but responses are redacted in the
+/// same way as log messages: data lines (which contain passphrases) and `INQUIRE`
+/// parameters are replaced by placeholders.
+///
+/// A `Transcript` can be cloned cheaply, and all clones record into the same
+/// transcript. It is never cleared automatically, so long-running programs should call
+/// [`Transcript::clear`] once they no longer need it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{PassphraseInput, Transcript};
+///
+/// let transcript = Transcript::new();
+/// if let Some(mut input) = PassphraseInput::with_default_binary() {
+///     if let Err(e) = input
+///         .with_transcript(&transcript)
+///         .with_description("Enter passphrase for FooBar")
+///         .interact()
+///     {
+///         eprintln!("Error: {}\n{}", e, transcript);
+///     }
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct Transcript {
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+impl Transcript {
+    /// Creates a new, empty transcript.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the lines recorded so far.
+    ///
+    /// Requests are prefixed with `> `, and responses with `< `.
+    pub fn lines(&self) -> Vec<String> {
+        self.lock().clone()
+    }
+
+    /// Removes all recorded lines.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Writes the lines recorded so far to the given file, one per line.
+    ///
+    /// The file can be read back with [`Transcript::load`] (for example, to turn a bug
+    /// report into a regression test with the `test-util` feature).
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    /// Reads a transcript previously written by [`Transcript::save`].
+    ///
+    /// Returns an error if any line is not a request (prefixed with `> `) or a response
+    /// (prefixed with `< `).
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let lines = fs::read_to_string(path)?
+            .lines()
+            .map(|line| {
+                if line.starts_with("> ") || line.starts_with("< ") {
+                    Ok(line.to_owned())
+                } else {
+                    Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid transcript line: {:?}", line),
+                    ))
+                }
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Transcript {
+            lines: Arc::new(Mutex::new(lines)),
+        })
+    }
+
+    pub(crate) fn request(&self, request: impl fmt::Display) {
+        self.lock().push(format!("> {}", request));
+    }
+
+    pub(crate) fn response(&self, response: impl fmt::Display) {
+        self.lock().push(format!("< {}", response));
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<String>> {
+        self.lines.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.lock().iter()).finish()
+    }
+}
+
+/// Formats the transcript with one line per request or response.
+impl fmt::Display for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in self.lock().iter() {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
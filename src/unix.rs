@@ -0,0 +1,1008 @@
+//! Unix-specific options for spawning `pinentry` binaries.
+
+use std::borrow::Cow;
+use std::ffi::{CStr, OsStr, OsString};
+use std::fmt;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Once;
+
+use crate::encoding;
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub(crate) mod spawn;
+
+/// Returns the terminal type from `TERM`, which is resolved once per process.
+fn default_tty_type() -> &'static str {
+    static INIT: Once = Once::new();
+    static TTY_TYPE: AtomicPtr<String> = AtomicPtr::new(ptr::null_mut());
+
+    INIT.call_once(|| {
+        let tty_type = std::env::var("TERM").unwrap_or_else(|_| "xterm-256color".to_owned());
+        TTY_TYPE.store(Box::into_raw(Box::new(tty_type)), Ordering::Release)
+    });
+    // Safety: the pointer was initialized above from a leaked `Box`, and is never
+    // freed.
+    unsafe { &*TTY_TYPE.load(Ordering::Acquire) }
+}
+
+/// Returns the terminal device from `GPG_TTY`, or else the terminal connected to
+/// stdin, or else the controlling terminal, which is resolved once per process.
+///
+/// Falls back to `/dev/tty` (the controlling terminal of the `pinentry` process) if
+/// none is available.
+fn default_tty_name() -> &'static Path {
+    static INIT: Once = Once::new();
+    static TTY_NAME: AtomicPtr<PathBuf> = AtomicPtr::new(ptr::null_mut());
+
+    INIT.call_once(|| {
+        let tty_name = std::env::var_os("GPG_TTY")
+            .filter(|tty_name| !tty_name.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| tty_name_of(0))
+            .or_else(controlling_tty_name)
+            .unwrap_or_else(|| PathBuf::from("/dev/tty"));
+        TTY_NAME.store(Box::into_raw(Box::new(tty_name)), Ordering::Release)
+    });
+    // Safety: the pointer was initialized above from a leaked `Box`, and is never
+    // freed.
+    unsafe { &*TTY_NAME.load(Ordering::Acquire) }
+}
+
+/// Returns the path of the controlling terminal's device, if it can be found.
+///
+/// On some systems (such as FreeBSD), opening `/dev/tty` opens the device of the
+/// controlling terminal itself, so its path (such as `/dev/pts/3`) can be found even
+/// when stdin is redirected. Unlike `/dev/tty`, that path still refers to the caller's
+/// terminal from a `pinentry` process in a new session. Elsewhere (such as on Linux),
+/// only `/dev/tty` itself is found, so this returns `None`.
+fn controlling_tty_name() -> Option<PathBuf> {
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+
+    let tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+        .open("/dev/tty")
+        .ok()?;
+    tty_name_of(tty.as_raw_fd()).filter(|tty_name| tty_name != Path::new("/dev/tty"))
+}
+
+/// Returns the path of the terminal device that the given file descriptor is open on,
+/// if any.
+fn tty_name_of(fd: libc::c_int) -> Option<PathBuf> {
+    let mut buf = [0; 256];
+    // Safety: `ttyname_r` writes at most `buf.len()` bytes (including the trailing
+    // NUL) into `buf`.
+    if unsafe { libc::ttyname_r(fd, buf.as_mut_ptr(), buf.len()) } != 0 {
+        return None;
+    }
+    // Safety: on success, `buf` contains a NUL-terminated string.
+    let tty_name = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    Some(OsStr::from_bytes(tty_name.to_bytes()).into())
+}
+
+/// The environment variables that control the theme and scaling of GTK and Qt
+/// pinentries.
+const THEME_VARS: &[&str] = &[
+    "GTK_THEME",
+    "GTK2_RC_FILES",
+    "GDK_SCALE",
+    "GDK_DPI_SCALE",
+    "QT_STYLE_OVERRIDE",
+    "QT_QPA_PLATFORMTHEME",
+    "QT_SCALE_FACTOR",
+    "QT_AUTO_SCREEN_SCALE_FACTOR",
+    "QT_SCREEN_SCALE_FACTORS",
+    "XCURSOR_THEME",
+    "XCURSOR_SIZE",
+];
+
+/// How the spawned `pinentry` process is detached from the calling process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Detach {
+    /// The `pinentry` process shares the caller's process group and session.
+    None,
+    /// The `pinentry` process is placed in a new process group.
+    ProcessGroup,
+    /// The `pinentry` process is placed in a new session.
+    Session,
+}
+
+/// The locale environment that the `pinentry` process is spawned with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Locale {
+    /// The `pinentry` process inherits the caller's locale environment.
+    Inherit,
+    /// The `pinentry` process is spawned without any locale environment variables.
+    Clear,
+    /// The `pinentry` process is spawned with `LANG` and `LC_ALL` set to this locale,
+    /// and without any other locale environment variables.
+    Set(OsString),
+}
+
+/// A sandbox that the `pinentry` binary can be run inside.
+///
+/// Each sandbox gives the `pinentry` process a read-only view of the filesystem, and no
+/// network access. It can still connect to the display server and terminal, so that it
+/// can show its prompt.
+///
+/// The sandbox binary is looked up in `PATH` when the `pinentry` binary is spawned. If
+/// it cannot be found, the dialog returns [`Error::Io`] with
+/// [`io::ErrorKind::NotFound`].
+///
+/// [`Error::Io`]: crate::Error::Io
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Sandbox {
+    /// Runs the `pinentry` binary inside [bubblewrap](https://github.com/containers/bubblewrap)
+    /// (`bwrap`).
+    Bubblewrap,
+    /// Runs the `pinentry` binary inside [Firejail](https://firejail.wordpress.com/).
+    Firejail,
+}
+
+impl Sandbox {
+    /// Returns a command that runs `binary` inside this sandbox.
+    fn command(self, binary: &Path) -> Command {
+        let (sandbox, args): (_, &[&str]) = match self {
+            Sandbox::Bubblewrap => (
+                "bwrap",
+                &[
+                    "--ro-bind",
+                    "/",
+                    "/",
+                    "--dev-bind",
+                    "/dev",
+                    "/dev",
+                    "--proc",
+                    "/proc",
+                    "--unshare-all",
+                    "--die-with-parent",
+                    "--",
+                ],
+            ),
+            Sandbox::Firejail => (
+                "firejail",
+                &["--quiet", "--net=none", "--read-only=/", "--"],
+            ),
+        };
+        let mut command = Command::new(sandbox);
+        command.args(args).arg(binary);
+        command
+    }
+}
+
+/// Unix-specific options for spawning a `pinentry` binary.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{unix, PassphraseInput};
+///
+/// if let Some(mut input) = PassphraseInput::with_default_binary() {
+///     input
+///         .with_unix_options(unix::Options::new().new_session().tty_name("/dev/pts/3"))
+///         .with_description("Enter passphrase for FooBar")
+///         .interact()?;
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct Options<'a> {
+    detach: Detach,
+    posix_spawn: bool,
+    tty_name: Option<PathBuf>,
+    tty_type: Option<Cow<'a, str>>,
+    sandbox: Option<Sandbox>,
+    locale: Locale,
+    language: Option<OsString>,
+    /// Environment variables to set (or remove, if `None`) for the `pinentry` process.
+    env: Vec<(&'static str, Option<OsString>)>,
+}
+
+/// The values of environment variables passed to the `pinentry` process (such as
+/// `PINENTRY_USER_DATA`) are omitted, as they may contain arbitrary data.
+impl<'a> fmt::Debug for Options<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Options")
+            .field("detach", &self.detach)
+            .field("posix_spawn", &self.posix_spawn)
+            .field("tty_name", &self.tty_name)
+            .field("tty_type", &self.tty_type)
+            .field("sandbox", &self.sandbox)
+            .field("locale", &self.locale)
+            .field("language", &self.language)
+            .field(
+                "env",
+                &self.env.iter().map(|(var, _)| var).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl<'a> Default for Options<'a> {
+    fn default() -> Self {
+        Options {
+            detach: Detach::None,
+            posix_spawn: false,
+            tty_name: None,
+            tty_type: None,
+            sandbox: None,
+            locale: Locale::Inherit,
+            language: None,
+            env: vec![],
+        }
+    }
+}
+
+impl<'a> Options<'a> {
+    /// Creates a new set of options with the default behaviour.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new set of options from the conventional environment variables, in the
+    /// same way that `gpg-agent` launches `pinentry` binaries:
+    ///
+    /// - The terminal device is set from `GPG_TTY` (see [`Options::tty_name`]).
+    /// - The terminal type is set from `TERM` (see [`Options::tty_type`]).
+    /// - `PINENTRY_USER_DATA`, `DISPLAY`, `XAUTHORITY`, and `WAYLAND_DISPLAY` are passed
+    ///   to the `pinentry` process with the values they have when this is called (see
+    ///   [`Options::display`]).
+    /// - The GTK and Qt theme and scaling variables (such as `GTK_THEME` and
+    ///   `QT_SCALE_FACTOR`) are passed in the same way, so that GUI pinentries match
+    ///   the desktop even if the options are used from a process with a sanitized
+    ///   environment.
+    ///
+    /// Variables that are unset (or empty) are left at their defaults. Daemons that run
+    /// outside of the user's session (and so have neither `DISPLAY` nor
+    /// `WAYLAND_DISPLAY` set) can use [`Options::logind_session`] to find the display.
+    pub fn from_env() -> Options<'static> {
+        Options::from_vars(|var| std::env::var_os(var))
+    }
+
+    /// Creates a new set of options in the same way as [`Options::from_env`], from the
+    /// environment variables returned by `var`.
+    fn from_vars(var: impl Fn(&str) -> Option<OsString>) -> Options<'static> {
+        let var = |name: &str| var(name).filter(|value| !value.is_empty());
+
+        let mut options = Options::new();
+        options.tty_name = var("GPG_TTY").map(PathBuf::from);
+        options.tty_type = var("TERM")
+            .and_then(|value| value.into_string().ok())
+            .map(Cow::Owned);
+        options.env = [
+            "PINENTRY_USER_DATA",
+            "DISPLAY",
+            "XAUTHORITY",
+            "WAYLAND_DISPLAY",
+        ]
+        .iter()
+        .chain(THEME_VARS)
+        .filter_map(|&name| var(name).map(|value| (name, Some(value))))
+        .collect();
+        options
+    }
+
+    /// Runs the `pinentry` binary in a new process group.
+    ///
+    /// Signals generated by the terminal for its foreground process group (such as
+    /// `SIGINT` from Ctrl-C) will not be delivered to the `pinentry` process.
+    ///
+    /// A process outside the terminal's foreground process group is stopped when it
+    /// tries to read from the terminal, so this is only suitable for GUI pinentries. For
+    /// terminal-based pinentries, use [`Options::new_session`] instead.
+    pub fn new_process_group(&mut self) -> &mut Self {
+        self.detach = Detach::ProcessGroup;
+        self
+    }
+
+    /// Runs the `pinentry` binary in a new session (via `setsid()`).
+    ///
+    /// The `pinentry` process will not receive any signals generated by the calling
+    /// process's terminal, so Ctrl-C will not interrupt it mid-prompt.
+    ///
+    /// The `pinentry` process will not have a controlling terminal, so `/dev/tty` will
+    /// not refer to the caller's terminal. Terminal-based pinentries (such as
+    /// `pinentry-curses`) need the path of the terminal device, which is detected
+    /// automatically when possible (see [`Options::tty_name`]).
+    pub fn new_session(&mut self) -> &mut Self {
+        self.detach = Detach::Session;
+        self
+    }
+
+    /// Spawns the `pinentry` binary with `posix_spawn` (on Linux and macOS), to reduce
+    /// the startup latency of each prompt.
+    ///
+    /// [`Command`] falls back to fork/exec whenever it has to run code in the new
+    /// process, as it does for [`Options::new_process_group`] and
+    /// [`Options::new_session`], and forking a process with a large address space is
+    /// slow. With this option, these are applied as spawn attributes instead.
+    ///
+    /// The binary is still spawned with [`Command`] where `posix_spawn` cannot create a
+    /// new session (on macOS), and for dialogs with a spawn hook (such as
+    /// [`PassphraseInput::with_spawn_hook`]), which is called with the [`Child`].
+    ///
+    /// [`Child`]: std::process::Child
+    /// [`PassphraseInput::with_spawn_hook`]: crate::PassphraseInput::with_spawn_hook
+    pub fn posix_spawn(&mut self) -> &mut Self {
+        self.posix_spawn = true;
+        self
+    }
+
+    /// Sets the terminal device that terminal-based pinentries should use.
+    ///
+    /// Defaults to the value of the `GPG_TTY` environment variable if it is set, or
+    /// else the terminal connected to the calling process's stdin, or else the device of
+    /// its controlling terminal (which can be found on some systems, such as FreeBSD).
+    /// These are detected the first time a `pinentry` binary is spawned and reused for
+    /// the rest of the process. If none is available, `/dev/tty` is used.
+    pub fn tty_name(&mut self, tty_name: impl AsRef<Path>) -> &mut Self {
+        self.tty_name = Some(tty_name.as_ref().to_owned());
+        self
+    }
+
+    /// Sets the terminal type that terminal-based pinentries should assume.
+    ///
+    /// Defaults to the value of the `TERM` environment variable, which is read the first
+    /// time a `pinentry` binary is spawned and reused for the rest of the process (so
+    /// that changes to the environment of long-running programs have no surprising
+    /// effects). If `TERM` is unset, `xterm-256color` is used.
+    pub fn tty_type(&mut self, tty_type: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.tty_type = Some(tty_type.into());
+        self
+    }
+
+    /// Runs the `pinentry` binary inside the given sandbox.
+    ///
+    /// This is useful when the `pinentry` binary itself is only semi-trusted. See
+    /// [`Sandbox`] for the restrictions that are applied.
+    pub fn sandbox(&mut self, sandbox: Sandbox) -> &mut Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Spawns the `pinentry` binary without any locale environment variables (`LANG`,
+    /// `LANGUAGE`, `LC_ALL`, or any other `LC_*`), so that it uses the POSIX locale.
+    ///
+    /// By default, the `pinentry` process inherits the caller's locale environment,
+    /// which determines the translations that it (and its GUI toolkit) loads.
+    pub fn clear_locale(&mut self) -> &mut Self {
+        self.locale = Locale::Clear;
+        self
+    }
+
+    /// Spawns the `pinentry` binary with `LANG` and `LC_ALL` set to the given locale
+    /// (such as `de_DE.UTF-8`), and without any other locale environment variables.
+    pub fn locale(&mut self, locale: impl AsRef<OsStr>) -> &mut Self {
+        self.locale = Locale::Set(locale.as_ref().to_owned());
+        self
+    }
+
+    /// Spawns the `pinentry` binary with `LANGUAGE` set to the given colon-separated
+    /// list of languages (such as `pt_BR:pt`), which gettext uses to choose
+    /// translations.
+    ///
+    /// This is applied after [`Options::clear_locale`] or [`Options::locale`].
+    pub fn language(&mut self, language: impl AsRef<OsStr>) -> &mut Self {
+        self.language = Some(language.as_ref().to_owned());
+        self
+    }
+
+    /// Sets the `DISPLAY` environment variable of the `pinentry` process to the given X
+    /// display (such as `:0`), or removes it if `None`.
+    ///
+    /// The display is also sent with `OPTION display`, in the same way as `gpg-agent`
+    /// configures `pinentry` binaries (this is ignored by `pinentry` binaries that do not
+    /// support it). By default, the `pinentry` process inherits the caller's `DISPLAY`.
+    pub fn display(&mut self, display: Option<&str>) -> &mut Self {
+        self.set_env("DISPLAY", display.map(OsString::from));
+        self
+    }
+
+    /// Sets the `XAUTHORITY` environment variable of the `pinentry` process to the given
+    /// X authority file, or removes it if `None`.
+    ///
+    /// This is needed to show a dialog on another user's X display (for example, when
+    /// a daemon running as root prompts the desktop user), along with `DISPLAY`. The
+    /// file is also sent with `OPTION xauthority`, as for [`Options::display`]. By
+    /// default, the `pinentry` process inherits the caller's `XAUTHORITY`.
+    pub fn xauthority(&mut self, xauthority: Option<&Path>) -> &mut Self {
+        self.set_env(
+            "XAUTHORITY",
+            xauthority.map(|path| path.as_os_str().to_owned()),
+        );
+        self
+    }
+
+    /// Sets the `XDG_RUNTIME_DIR` environment variable of the `pinentry` process to the
+    /// given directory, or removes it if `None`.
+    ///
+    /// GUI pinentries (such as `pinentry-gnome3`) look up the D-Bus session bus and the
+    /// Wayland socket under this directory. By default, the `pinentry` process inherits
+    /// the caller's `XDG_RUNTIME_DIR`; daemons started outside of a login session often
+    /// lack it.
+    pub fn xdg_runtime_dir(&mut self, xdg_runtime_dir: Option<&Path>) -> &mut Self {
+        self.set_env(
+            "XDG_RUNTIME_DIR",
+            xdg_runtime_dir.map(|path| path.as_os_str().to_owned()),
+        );
+        self
+    }
+
+    /// Sets the `DBUS_SESSION_BUS_ADDRESS` environment variable of the `pinentry`
+    /// process to the given D-Bus address (such as `unix:path=/run/user/1000/bus`), or
+    /// removes it if `None`.
+    ///
+    /// `pinentry-gnome3` shows its dialog via the session bus, and fails (or falls back
+    /// to a terminal prompt) if it cannot connect to one. If this is unset, D-Bus looks
+    /// for the bus at `$XDG_RUNTIME_DIR/bus`. By default, the `pinentry` process inherits
+    /// the caller's `DBUS_SESSION_BUS_ADDRESS`.
+    pub fn dbus_session_bus_address(&mut self, address: Option<&str>) -> &mut Self {
+        self.set_env("DBUS_SESSION_BUS_ADDRESS", address.map(OsString::from));
+        self
+    }
+
+    /// Sets the `GTK_THEME` environment variable of the `pinentry` process to the given
+    /// GTK theme (such as `Adwaita:dark`), or removes it if `None`.
+    ///
+    /// By default, the `pinentry` process inherits the caller's `GTK_THEME`.
+    pub fn gtk_theme(&mut self, theme: Option<&str>) -> &mut Self {
+        self.set_env("GTK_THEME", theme.map(OsString::from));
+        self
+    }
+
+    /// Sets the `QT_STYLE_OVERRIDE` environment variable of the `pinentry` process to the
+    /// given Qt style (such as `kvantum`), or removes it if `None`.
+    ///
+    /// By default, the `pinentry` process inherits the caller's `QT_STYLE_OVERRIDE`.
+    pub fn qt_style(&mut self, style: Option<&str>) -> &mut Self {
+        self.set_env("QT_STYLE_OVERRIDE", style.map(OsString::from));
+        self
+    }
+
+    /// Sets the HiDPI scaling of GTK and Qt pinentries (via `GDK_SCALE` and
+    /// `QT_SCALE_FACTOR`) to the given integer factor, or removes both variables if
+    /// `None`.
+    ///
+    /// `GDK_DPI_SCALE` and `QT_AUTO_SCREEN_SCALE_FACTOR` are removed in either case, so
+    /// that they do not compound with the given factor. By default, the `pinentry`
+    /// process inherits the caller's scaling variables.
+    pub fn scale_factor(&mut self, scale: Option<u32>) -> &mut Self {
+        let scale = scale.map(|scale| OsString::from(scale.to_string()));
+        self.set_env("GDK_SCALE", scale.clone());
+        self.set_env("QT_SCALE_FACTOR", scale);
+        self.set_env("GDK_DPI_SCALE", None);
+        self.set_env("QT_AUTO_SCREEN_SCALE_FACTOR", None);
+        self
+    }
+
+    /// Configures the `pinentry` process to show its dialog in the graphical session of
+    /// the user with the given UID, as reported by systemd-logind (via `loginctl`).
+    ///
+    /// This sets `DISPLAY` (and `WAYLAND_DISPLAY` for Wayland sessions) from the user's
+    /// primary graphical session, and `XDG_RUNTIME_DIR` and `DBUS_SESSION_BUS_ADDRESS`
+    /// from the user's runtime directory. It is intended for daemons that need to
+    /// prompt a user, but do not run inside that user's session; [`Options::xauthority`]
+    /// may also be needed for X11 sessions.
+    ///
+    /// `loginctl` is run from `/usr/bin` or `/bin`, and never looked up in `PATH`.
+    /// Returns an error if it cannot be run, or if the user has no graphical session.
+    /// The options are left unchanged in that case.
+    pub fn logind_session(&mut self, uid: u32) -> io::Result<&mut Self> {
+        let user = loginctl_show("user", &uid.to_string())?;
+        let session = property(&user, "Display").ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("user {} has no graphical session", uid),
+            )
+        })?;
+        let session = loginctl_show("session", session)?;
+        let runtime_path = property(&user, "RuntimePath").map(PathBuf::from);
+
+        self.apply_logind_session(&session, runtime_path.as_deref());
+        Ok(self)
+    }
+
+    /// Sets the display and session environment variables from the properties of a
+    /// logind session, and the user's runtime directory.
+    fn apply_logind_session(&mut self, session: &[(String, String)], runtime_path: Option<&Path>) {
+        if let Some(display) = property(session, "Display") {
+            self.set_env("DISPLAY", Some(display.into()));
+        }
+        if property(session, "Type") == Some("wayland") {
+            let socket = runtime_path
+                .and_then(wayland_socket)
+                .unwrap_or_else(|| "wayland-0".into());
+            self.set_env("WAYLAND_DISPLAY", Some(socket));
+        }
+        if let Some(runtime_path) = runtime_path {
+            let mut bus = OsString::from("unix:path=");
+            bus.push(runtime_path.join("bus"));
+            self.set_env("XDG_RUNTIME_DIR", Some(runtime_path.into()));
+            self.set_env("DBUS_SESSION_BUS_ADDRESS", Some(bus));
+        }
+    }
+
+    /// Sets an environment variable of the `pinentry` process, or removes it if `None`.
+    fn set_env(&mut self, var: &'static str, value: Option<OsString>) {
+        self.env.retain(|(name, _)| *name != var);
+        self.env.push((var, value));
+    }
+
+    /// Returns true if the `pinentry` process will have somewhere to show a dialog: a
+    /// display (from `DISPLAY` or `WAYLAND_DISPLAY`, or always on macOS), or a terminal
+    /// at the configured terminal device.
+    pub(crate) fn can_display(&self) -> bool {
+        let has_var = |var: &str| {
+            self.env
+                .iter()
+                .find(|(name, _)| *name == var)
+                .map_or_else(|| std::env::var_os(var), |(_, value)| value.clone())
+                .map_or(false, |value| !value.is_empty())
+        };
+        cfg!(target_os = "macos")
+            || has_var("DISPLAY")
+            || has_var("WAYLAND_DISPLAY")
+            || is_terminal(self.ttyname())
+    }
+
+    /// Returns the `OPTION display` and `OPTION xauthority` requests (without line
+    /// endings) for the display that was configured, if any.
+    pub(crate) fn display_options(&self) -> Vec<String> {
+        [("DISPLAY", "display"), ("XAUTHORITY", "xauthority")]
+            .iter()
+            .filter_map(|(var, option)| {
+                self.env
+                    .iter()
+                    .find(|(name, _)| name == var)
+                    .and_then(|(_, value)| value.as_ref())
+                    .map(|value| {
+                        format!(
+                            "OPTION {}={}",
+                            option,
+                            encoding::encode_bytes(value.as_bytes())
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// Returns the value to send with `OPTION ttyname`.
+    pub(crate) fn ttyname(&self) -> &Path {
+        match &self.tty_name {
+            Some(tty_name) => tty_name,
+            None => default_tty_name(),
+        }
+    }
+
+    /// Returns the value to send with `OPTION ttytype`.
+    pub(crate) fn ttytype(&self) -> &str {
+        match &self.tty_type {
+            Some(tty_type) => tty_type,
+            None => default_tty_type(),
+        }
+    }
+
+    /// Returns the command that will spawn the `pinentry` binary with these options.
+    pub(crate) fn command(&self, binary: &Path) -> Command {
+        let mut command = match self.sandbox {
+            Some(sandbox) => sandbox.command(binary),
+            None => Command::new(binary),
+        };
+
+        match &self.locale {
+            Locale::Inherit => (),
+            Locale::Clear | Locale::Set(_) => {
+                for (var, _) in std::env::vars_os() {
+                    let is_locale_var = var.to_str().map_or(false, |var| {
+                        var == "LANG" || var == "LANGUAGE" || var.starts_with("LC_")
+                    });
+                    if is_locale_var {
+                        command.env_remove(var);
+                    }
+                }
+                if let Locale::Set(locale) = &self.locale {
+                    command.env("LANG", locale).env("LC_ALL", locale);
+                }
+            }
+        }
+        if let Some(language) = &self.language {
+            command.env("LANGUAGE", language);
+        }
+        for (var, value) in &self.env {
+            match value {
+                Some(value) => command.env(var, value),
+                None => command.env_remove(var),
+            };
+        }
+
+        // Safety: `setpgid` and `setsid` are async-signal-safe.
+        match self.detach {
+            Detach::None => (),
+            Detach::ProcessGroup => unsafe {
+                command.pre_exec(|| cvt(libc::setpgid(0, 0)));
+            },
+            Detach::Session => unsafe {
+                command.pre_exec(|| cvt(libc::setsid()));
+            },
+        }
+
+        command
+    }
+
+    /// Spawns `command` (as returned by [`Options::command`]) with `posix_spawn`, if
+    /// these options ask for it and it can apply them on this platform.
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub(crate) fn posix_spawn_command(
+        &self,
+        command: &Command,
+    ) -> Option<io::Result<spawn::Spawned>> {
+        if self.posix_spawn && spawn::supports(self.detach) {
+            Some(spawn::spawn(command, self.detach))
+        } else {
+            None
+        }
+    }
+}
+
+/// The locations of `loginctl`, which is run by absolute path so that it cannot be
+/// replaced through `PATH`.
+const LOGINCTL: &[&str] = &["/usr/bin/loginctl", "/bin/loginctl"];
+
+/// Runs `loginctl show-<kind> <id>`, and returns the properties that it prints.
+fn loginctl_show(kind: &str, id: &str) -> io::Result<Vec<(String, String)>> {
+    let loginctl = LOGINCTL
+        .iter()
+        .map(Path::new)
+        .find(|path| path.is_file())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "loginctl not found"))?;
+    let output = Command::new(loginctl)
+        .arg(format!("show-{}", kind))
+        .arg(id)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no logind {} {}", kind, id),
+        ));
+    }
+    Ok(parse_properties(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses the `Name=value` lines printed by `loginctl show-*`.
+fn parse_properties(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect()
+}
+
+/// Returns the value of the given property, if it is present and non-empty.
+fn property<'p>(properties: &'p [(String, String)], name: &str) -> Option<&'p str> {
+    properties
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, value)| value.as_str())
+        .filter(|value| !value.is_empty())
+}
+
+/// Returns the name of the first Wayland compositor socket in the given runtime
+/// directory.
+fn wayland_socket(runtime_path: &Path) -> Option<OsString> {
+    let mut sockets: Vec<_> = std::fs::read_dir(runtime_path)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name())
+        .filter(|name| {
+            name.to_str().map_or(false, |name| {
+                name.starts_with("wayland-") && !name.ends_with(".lock")
+            })
+        })
+        .collect();
+    sockets.sort();
+    sockets.into_iter().next()
+}
+
+/// Returns true if the given path can be opened as a terminal.
+///
+/// The terminal is opened without becoming the controlling terminal of the calling
+/// process (which would otherwise happen for a session leader without one, such as a
+/// daemon), and without blocking on devices that wait for a carrier.
+fn is_terminal(path: &Path) -> bool {
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+        .open(path)
+        // Safety: `isatty` only inspects the file descriptor, which is open.
+        .map_or(false, |tty| unsafe { libc::isatty(tty.as_raw_fd()) == 1 })
+}
+
+/// Creates a new directory in the given directory that only the current user can
+/// access.
+///
+/// The name has an unpredictable suffix, and the directory must not already exist, so
+/// that other users cannot create it in advance (or swap its contents for their own).
+pub(crate) fn private_dir(parent: &Path, prefix: &str) -> io::Result<PathBuf> {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    use std::os::unix::fs::DirBuilderExt;
+    use std::sync::atomic::AtomicUsize;
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    loop {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(COUNTER.fetch_add(1, Ordering::Relaxed));
+        let dir = parent.join(format!(
+            "{}-{}-{:016x}",
+            prefix,
+            std::process::id(),
+            hasher.finish(),
+        ));
+        match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Connects to the abstract Unix socket with the given name.
+#[cfg(target_os = "linux")]
+pub(crate) fn connect_abstract(name: &[u8]) -> io::Result<std::os::unix::net::UnixStream> {
+    use std::os::unix::io::FromRawFd;
+
+    // Safety: `socket` has no memory-safety preconditions.
+    let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Safety: `fd` is a newly created socket that nothing else owns.
+    let stream = unsafe { std::os::unix::net::UnixStream::from_raw_fd(fd) };
+
+    // Safety: `sockaddr_un` is valid when zeroed.
+    let mut addr: libc::sockaddr_un = unsafe { std::mem::zeroed() };
+    if name.len() >= addr.sun_path.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "socket name is too long",
+        ));
+    }
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    // Abstract socket names start with a NUL byte, and are not NUL-terminated.
+    for (dst, src) in addr.sun_path[1..].iter_mut().zip(name) {
+        *dst = *src as libc::c_char;
+    }
+    let len = std::mem::size_of::<libc::sa_family_t>() + 1 + name.len();
+    // Safety: `addr` is a valid `sockaddr_un`, of which `len` bytes are used.
+    cvt(unsafe {
+        libc::connect(
+            fd,
+            &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+            len as libc::socklen_t,
+        )
+    })?;
+    Ok(stream)
+}
+
+fn cvt(ret: libc::c_int) -> io::Result<()> {
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{OsStr, OsString};
+    use std::path::Path;
+
+    use super::{Options, Sandbox};
+
+    /// Returns how the command for `options` sets `var`: `None` if it inherits it, and
+    /// `Some(None)` if it removes it.
+    fn env(options: &Options<'_>, var: &str) -> Option<Option<OsString>> {
+        options
+            .command(Path::new("/usr/bin/pinentry"))
+            .get_envs()
+            .find(|(name, _)| *name == var)
+            .map(|(_, value)| value.map(OsStr::to_owned))
+    }
+
+    #[test]
+    fn private_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let a = super::private_dir(&std::env::temp_dir(), "pinentry-private").unwrap();
+        let b = super::private_dir(&std::env::temp_dir(), "pinentry-private").unwrap();
+        assert_ne!(a, b);
+        let mode = std::fs::metadata(&a).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        std::fs::remove_dir(&a).unwrap();
+        std::fs::remove_dir(&b).unwrap();
+    }
+
+    #[test]
+    fn sandboxed_command() {
+        let binary = Path::new("/usr/bin/pinentry");
+
+        let command = Options::new().command(binary);
+        assert_eq!(command.get_program(), binary);
+        assert_eq!(command.get_args().count(), 0);
+
+        for sandbox in [Sandbox::Bubblewrap, Sandbox::Firejail] {
+            let command = Options::new().sandbox(sandbox).command(binary);
+            assert_ne!(command.get_program(), binary);
+            let args: Vec<_> = command.get_args().collect();
+            assert_eq!(
+                args[args.len() - 2..],
+                [OsStr::new("--"), binary.as_os_str()]
+            );
+        }
+    }
+
+    #[test]
+    fn locale_env() {
+        let binary = Path::new("/usr/bin/pinentry");
+
+        assert_eq!(Options::new().command(binary).get_envs().count(), 0);
+        assert!(Options::new()
+            .clear_locale()
+            .command(binary)
+            .get_envs()
+            .all(|(_, value)| value.is_none()));
+
+        let command = Options::new()
+            .locale("de_DE.UTF-8")
+            .language("de:en")
+            .command(binary);
+        let mut set: Vec<_> = command
+            .get_envs()
+            .filter_map(|(var, value)| value.map(|value| (var, value)))
+            .collect();
+        set.sort();
+        assert_eq!(
+            set,
+            [
+                (OsStr::new("LANG"), OsStr::new("de_DE.UTF-8")),
+                (OsStr::new("LANGUAGE"), OsStr::new("de:en")),
+                (OsStr::new("LC_ALL"), OsStr::new("de_DE.UTF-8")),
+            ]
+        );
+    }
+
+    #[test]
+    fn from_env() {
+        let options = Options::from_vars(|var| match var {
+            "GPG_TTY" => Some("/dev/pts/7".into()),
+            "PINENTRY_USER_DATA" => Some("USE_CURSES=1".into()),
+            "DISPLAY" => Some("".into()),
+            _ => None,
+        });
+        assert_eq!(options.ttyname(), Path::new("/dev/pts/7"));
+        assert_eq!(options.ttytype(), super::default_tty_type());
+        let command = options.command(Path::new("/usr/bin/pinentry"));
+        assert!(command.get_envs().any(|env| env
+            == (
+                OsStr::new("PINENTRY_USER_DATA"),
+                Some(OsStr::new("USE_CURSES=1"))
+            )));
+        // Empty variables are left at their defaults.
+        assert!(command.get_envs().all(|(var, _)| var != "DISPLAY"));
+    }
+
+    #[test]
+    fn xauthority() {
+        let mut options = Options::new();
+        assert_eq!(env(&options, "XAUTHORITY"), None);
+        options.xauthority(Some(Path::new("/home/user/.Xauthority")));
+        assert_eq!(
+            env(&options, "XAUTHORITY"),
+            Some(Some("/home/user/.Xauthority".into()))
+        );
+        options.xauthority(None);
+        assert_eq!(env(&options, "XAUTHORITY"), Some(None));
+    }
+
+    #[test]
+    fn display_options() {
+        assert!(Options::new().display_options().is_empty());
+        assert_eq!(
+            Options::new()
+                .display(Some(":0"))
+                .xauthority(Some(Path::new("/home/user/.Xauthority")))
+                .display_options(),
+            [
+                "OPTION display=:0",
+                "OPTION xauthority=/home/user/.Xauthority"
+            ]
+        );
+        assert!(Options::new()
+            .display(None)
+            .xauthority(None)
+            .display_options()
+            .is_empty());
+    }
+
+    #[test]
+    fn session_bus() {
+        let mut options = Options::new();
+        options
+            .xdg_runtime_dir(Some(Path::new("/run/user/1000")))
+            .dbus_session_bus_address(None);
+        assert_eq!(
+            env(&options, "XDG_RUNTIME_DIR"),
+            Some(Some("/run/user/1000".into()))
+        );
+        assert_eq!(env(&options, "DBUS_SESSION_BUS_ADDRESS"), Some(None));
+
+        options.dbus_session_bus_address(Some("unix:path=/run/user/1000/bus"));
+        assert_eq!(
+            env(&options, "DBUS_SESSION_BUS_ADDRESS"),
+            Some(Some("unix:path=/run/user/1000/bus".into()))
+        );
+    }
+
+    #[test]
+    fn theme() {
+        let mut options = Options::new();
+        options
+            .gtk_theme(Some("Adwaita:dark"))
+            .qt_style(None)
+            .scale_factor(Some(2));
+        assert_eq!(
+            env(&options, "GTK_THEME"),
+            Some(Some("Adwaita:dark".into()))
+        );
+        assert_eq!(env(&options, "QT_STYLE_OVERRIDE"), Some(None));
+        assert_eq!(env(&options, "GDK_SCALE"), Some(Some("2".into())));
+        assert_eq!(env(&options, "QT_SCALE_FACTOR"), Some(Some("2".into())));
+        assert_eq!(env(&options, "GDK_DPI_SCALE"), Some(None));
+
+        options.scale_factor(None);
+        assert_eq!(env(&options, "GDK_SCALE"), Some(None));
+        assert_eq!(env(&options, "QT_SCALE_FACTOR"), Some(None));
+    }
+
+    #[test]
+    fn logind_session() {
+        let session = super::parse_properties("Id=2\nName=user\nType=wayland\nDisplay=:1\n");
+        let mut options = Options::new();
+        options.apply_logind_session(&session, Some(Path::new("/nonexistent/run/user/1000")));
+        assert_eq!(env(&options, "DISPLAY"), Some(Some(":1".into())));
+        assert_eq!(
+            env(&options, "WAYLAND_DISPLAY"),
+            Some(Some("wayland-0".into()))
+        );
+        assert_eq!(
+            env(&options, "XDG_RUNTIME_DIR"),
+            Some(Some("/nonexistent/run/user/1000".into()))
+        );
+        assert_eq!(
+            env(&options, "DBUS_SESSION_BUS_ADDRESS"),
+            Some(Some("unix:path=/nonexistent/run/user/1000/bus".into()))
+        );
+
+        let session = super::parse_properties("Type=x11\nDisplay=\n");
+        let mut options = Options::new();
+        options.apply_logind_session(&session, None);
+        assert_eq!(env(&options, "DISPLAY"), None);
+        assert_eq!(env(&options, "WAYLAND_DISPLAY"), None);
+    }
+}
@@ -0,0 +1,374 @@
+//! Spawning `pinentry` binaries with `posix_spawn` (see [`Options::posix_spawn`]).
+//!
+//! [`Options::posix_spawn`]: super::Options::posix_spawn
+
+use std::collections::BTreeMap;
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::File;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus};
+use std::ptr;
+
+use super::{cvt, Detach};
+
+/// A `pinentry` process spawned with `posix_spawn`.
+///
+/// Like [`std::process::Child`], the process is neither killed nor reaped when this is
+/// dropped.
+#[derive(Debug)]
+pub(crate) struct Child {
+    pid: libc::pid_t,
+    /// The exit status of the process, once it has been reaped.
+    status: Option<ExitStatus>,
+}
+
+impl Child {
+    pub(crate) fn id(&self) -> u32 {
+        self.pid as u32
+    }
+
+    /// Sends `SIGKILL` to the process, unless it has already been reaped.
+    pub(crate) fn kill(&mut self) -> io::Result<()> {
+        if self.status.is_some() {
+            return Ok(());
+        }
+        // Safety: `kill` has no memory-safety preconditions, and the process has not
+        // been reaped, so `pid` still refers to it.
+        cvt(unsafe { libc::kill(self.pid, libc::SIGKILL) })
+    }
+
+    /// Reaps the process if it has exited, without blocking.
+    pub(crate) fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        if let Some(status) = self.status {
+            return Ok(Some(status));
+        }
+        let mut status = 0;
+        // Safety: `status` is valid for writes.
+        match unsafe { libc::waitpid(self.pid, &mut status, libc::WNOHANG) } {
+            -1 => Err(io::Error::last_os_error()),
+            0 => Ok(None),
+            _ => {
+                let status = ExitStatus::from_raw(status);
+                self.status = Some(status);
+                Ok(Some(status))
+            }
+        }
+    }
+}
+
+/// A process spawned by [`spawn`], with the parent's ends of its standard streams.
+pub(crate) struct Spawned {
+    pub(crate) child: Child,
+    pub(crate) stdin: File,
+    pub(crate) stdout: File,
+    pub(crate) stderr: File,
+}
+
+/// Returns true if `posix_spawn` can apply the given detach mode on this platform.
+pub(super) fn supports(detach: Detach) -> bool {
+    match detach {
+        Detach::None | Detach::ProcessGroup => true,
+        // `POSIX_SPAWN_SETSID` is only available on Linux (since glibc 2.26 and musl
+        // 1.1.24).
+        Detach::Session => cfg!(target_os = "linux"),
+    }
+}
+
+/// Spawns the program of `command` with its arguments and environment, piping its
+/// standard streams.
+///
+/// Anything else that is configured on `command` (such as `pre_exec` closures) is
+/// ignored; `detach` (which must be [supported](supports)) is applied with spawn
+/// attributes instead. The program is spawned directly if it is a path, and looked up
+/// in `PATH` otherwise.
+pub(super) fn spawn(command: &Command, detach: Detach) -> io::Result<Spawned> {
+    debug_assert!(supports(detach));
+
+    let program = cstring(command.get_program())?;
+    let argv = [command.get_program()]
+        .into_iter()
+        .chain(command.get_args())
+        .map(cstring)
+        .collect::<io::Result<Vec<_>>>()?;
+    let envp = environment(command)?;
+
+    let (child_stdin, stdin) = pipe()?;
+    let (stdout, child_stdout) = pipe()?;
+    let (stderr, child_stderr) = pipe()?;
+
+    let mut actions = FileActions::new()?;
+    actions.dup2(&child_stdin, libc::STDIN_FILENO)?;
+    actions.dup2(&child_stdout, libc::STDOUT_FILENO)?;
+    actions.dup2(&child_stderr, libc::STDERR_FILENO)?;
+    let attributes = Attributes::new(detach)?;
+
+    let mut argv_ptrs: Vec<_> = argv.iter().map(|arg| arg.as_ptr() as *mut _).collect();
+    argv_ptrs.push(ptr::null_mut());
+    let mut envp_ptrs: Vec<_> = envp.iter().map(|var| var.as_ptr() as *mut _).collect();
+    envp_ptrs.push(ptr::null_mut());
+
+    let spawn = if command.get_program().as_bytes().contains(&b'/') {
+        libc::posix_spawn
+    } else {
+        libc::posix_spawnp
+    };
+    let mut pid = 0;
+    // Safety: every pointer is valid for the duration of the call, and `argv_ptrs` and
+    // `envp_ptrs` are NULL-terminated arrays of NUL-terminated strings.
+    let ret = unsafe {
+        spawn(
+            &mut pid,
+            program.as_ptr(),
+            &actions.0,
+            &attributes.0,
+            argv_ptrs.as_ptr(),
+            envp_ptrs.as_ptr(),
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret));
+    }
+
+    // The child's ends of the pipes are closed when they are dropped here, so that
+    // reads from the parent's ends see EOF once the child exits.
+    Ok(Spawned {
+        child: Child { pid, status: None },
+        stdin: stdin.into(),
+        stdout: stdout.into(),
+        stderr: stderr.into(),
+    })
+}
+
+fn cstring(s: &OsStr) -> io::Result<CString> {
+    CString::new(s.as_bytes()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "nul byte found in provided data",
+        )
+    })
+}
+
+/// Returns the environment of `command`, as `NAME=value` strings: the calling process's
+/// environment, with the changes made on `command` applied.
+fn environment(command: &Command) -> io::Result<Vec<CString>> {
+    let mut env: BTreeMap<OsString, OsString> = std::env::vars_os().collect();
+    for (var, value) in command.get_envs() {
+        match value {
+            Some(value) => env.insert(var.to_owned(), value.to_owned()),
+            None => env.remove(var),
+        };
+    }
+    env.into_iter()
+        .map(|(var, value)| {
+            let mut entry = var.into_vec();
+            entry.push(b'=');
+            entry.extend_from_slice(value.as_bytes());
+            cstring(OsStr::from_bytes(&entry))
+        })
+        .collect()
+}
+
+/// One end of a pipe, whose descriptor is close-on-exec and not a standard stream.
+struct PipeEnd(File);
+
+impl PipeEnd {
+    fn new(file: File) -> io::Result<Self> {
+        let fd = file.as_raw_fd();
+        #[cfg(not(target_os = "linux"))]
+        // Safety: `fcntl` has no memory-safety preconditions.
+        cvt(unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) })?;
+        if fd > libc::STDERR_FILENO {
+            return Ok(PipeEnd(file));
+        }
+        // `dup2`ing a descriptor onto itself would leave it close-on-exec, so a pipe
+        // end that took the place of a closed standard stream is moved out of the way.
+        // Safety: `fcntl` has no memory-safety preconditions.
+        let moved = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, libc::STDERR_FILENO + 1) };
+        if moved == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // Safety: `moved` was just created by `fcntl`, and nothing else owns it.
+        Ok(PipeEnd(unsafe { File::from_raw_fd(moved) }))
+    }
+}
+
+impl From<PipeEnd> for File {
+    fn from(end: PipeEnd) -> Self {
+        end.0
+    }
+}
+
+/// Creates a pipe, returning its read and write ends.
+fn pipe() -> io::Result<(PipeEnd, PipeEnd)> {
+    let mut fds = [0; 2];
+    // Safety: `fds` is valid for writes of two descriptors.
+    #[cfg(target_os = "linux")]
+    cvt(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) })?;
+    // Safety: as above. The descriptors are made close-on-exec by `PipeEnd::new`.
+    #[cfg(not(target_os = "linux"))]
+    cvt(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+    // Safety: both descriptors were just created by `pipe`, and nothing else owns them.
+    let (read, write) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    Ok((PipeEnd::new(read)?, PipeEnd::new(write)?))
+}
+
+/// Converts the return value of a `posix_spawn*` function into a result.
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(ret))
+    }
+}
+
+/// The file actions that set up the standard streams of the spawned process.
+struct FileActions(libc::posix_spawn_file_actions_t);
+
+impl FileActions {
+    fn new() -> io::Result<Self> {
+        let mut actions = MaybeUninit::uninit();
+        // Safety: `actions` is valid for writes, and is initialized on success.
+        check(unsafe { libc::posix_spawn_file_actions_init(actions.as_mut_ptr()) })?;
+        // Safety: as above.
+        Ok(FileActions(unsafe { actions.assume_init() }))
+    }
+
+    fn dup2(&mut self, end: &PipeEnd, target: libc::c_int) -> io::Result<()> {
+        // Safety: `self.0` was initialized by `posix_spawn_file_actions_init`.
+        check(unsafe {
+            libc::posix_spawn_file_actions_adddup2(&mut self.0, end.0.as_raw_fd(), target)
+        })
+    }
+}
+
+impl Drop for FileActions {
+    fn drop(&mut self) {
+        // Safety: `self.0` was initialized by `posix_spawn_file_actions_init`.
+        unsafe { libc::posix_spawn_file_actions_destroy(&mut self.0) };
+    }
+}
+
+/// The attributes of the spawned process.
+struct Attributes(libc::posix_spawnattr_t);
+
+impl Attributes {
+    fn new(detach: Detach) -> io::Result<Self> {
+        let mut attributes = MaybeUninit::uninit();
+        // Safety: `attributes` is valid for writes, and is initialized on success.
+        check(unsafe { libc::posix_spawnattr_init(attributes.as_mut_ptr()) })?;
+        // Safety: as above.
+        let mut attributes = Attributes(unsafe { attributes.assume_init() });
+
+        // As `std::process::Command` does, the process starts with no signals blocked,
+        // and with `SIGPIPE` (which the Rust runtime ignores) at its default action.
+        let mut flags = libc::POSIX_SPAWN_SETSIGMASK | libc::POSIX_SPAWN_SETSIGDEF;
+        // Safety: `sigset_t` is valid when zeroed, and is then initialized by
+        // `sigemptyset`; `attributes.0` was initialized by `posix_spawnattr_init`.
+        unsafe {
+            let mut set: libc::sigset_t = std::mem::zeroed();
+            cvt(libc::sigemptyset(&mut set))?;
+            check(libc::posix_spawnattr_setsigmask(&mut attributes.0, &set))?;
+            cvt(libc::sigaddset(&mut set, libc::SIGPIPE))?;
+            check(libc::posix_spawnattr_setsigdefault(&mut attributes.0, &set))?;
+        }
+
+        match detach {
+            Detach::None => (),
+            Detach::ProcessGroup => {
+                flags |= libc::POSIX_SPAWN_SETPGROUP;
+                // Safety: `attributes.0` was initialized by `posix_spawnattr_init`.
+                check(unsafe { libc::posix_spawnattr_setpgroup(&mut attributes.0, 0) })?;
+            }
+            // Other platforms do not support this.
+            Detach::Session => {
+                #[cfg(target_os = "linux")]
+                {
+                    flags |= libc::POSIX_SPAWN_SETSID;
+                }
+            }
+        }
+        // Safety: `attributes.0` was initialized by `posix_spawnattr_init`.
+        check(unsafe {
+            libc::posix_spawnattr_setflags(&mut attributes.0, flags as libc::c_short)
+        })?;
+        Ok(attributes)
+    }
+}
+
+impl Drop for Attributes {
+    fn drop(&mut self) {
+        // Safety: `self.0` was initialized by `posix_spawnattr_init`.
+        unsafe { libc::posix_spawnattr_destroy(&mut self.0) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{spawn, Detach};
+
+    #[test]
+    fn streams_and_environment() {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg("cat; printf '%s' \"$PINENTRY_TEST:${HOME-unset}\" >&2")
+            .env("PINENTRY_TEST", "set")
+            .env_remove("HOME");
+        let mut spawned = spawn(&command, Detach::None).unwrap();
+        spawned.stdin.write_all(b"OK\n").unwrap();
+        drop(spawned.stdin);
+
+        let mut stdout = String::new();
+        spawned.stdout.read_to_string(&mut stdout).unwrap();
+        assert_eq!(stdout, "OK\n");
+        let mut stderr = String::new();
+        spawned.stderr.read_to_string(&mut stderr).unwrap();
+        assert_eq!(stderr, "set:unset");
+        let status = loop {
+            match spawned.child.try_wait().unwrap() {
+                Some(status) => break status,
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        assert!(status.success());
+    }
+
+    #[test]
+    fn detach() {
+        for detach in [Detach::ProcessGroup, Detach::Session] {
+            if !super::supports(detach) {
+                continue;
+            }
+            let mut spawned = spawn(Command::new("sleep").arg("10"), detach).unwrap();
+            let pid = spawned.child.id() as libc::pid_t;
+            // Safety: `getpgid` and `getsid` have no memory-safety preconditions.
+            let (pgid, sid) = unsafe { (libc::getpgid(pid), libc::getsid(pid)) };
+            assert_eq!(pgid, pid);
+            assert_eq!(sid == pid, detach == Detach::Session);
+
+            spawned.child.kill().unwrap();
+            let status = loop {
+                match spawned.child.try_wait().unwrap() {
+                    Some(status) => break status,
+                    None => thread::sleep(Duration::from_millis(10)),
+                }
+            };
+            assert!(!status.success());
+        }
+    }
+
+    #[test]
+    fn not_found() {
+        assert!(spawn(&Command::new("/nonexistent/pinentry"), Detach::None).is_err());
+        assert!(spawn(&Command::new("nonexistent-pinentry"), Detach::None).is_err());
+    }
+}
@@ -0,0 +1,393 @@
+//! Windows-specific options for spawning `pinentry` binaries, and a console fallback
+//! for when no `pinentry` binary is available.
+//!
+//! # Services
+//!
+//! Windows services run in session 0, which has no interactive desktop: a `pinentry`
+//! binary spawned there shows its dialog where the user can never see it, and the
+//! dialog eventually times out. Dialogs report that they cannot interact (see
+//! [`PassphraseInput::can_interact`]) when the calling process is in session 0.
+//!
+//! Services running as `LocalSystem` can instead spawn the `pinentry` binary into the
+//! session of the user who is logged in at the console (see
+//! [`active_console_session`]), as that user, with [`Options::in_active_session`].
+//!
+//! [`PassphraseInput::can_interact`]) when the calling process is in session 0.
+//!
+//! A `pinentry` binary can only be spawned into the calling process's own session.
+//! Services that need to prompt the user should instead start a helper process in the
+//! user's session (for example, with `WTSQueryUserToken` and `CreateProcessAsUserW`
+//! for the session returned by [`active_console_session`]), and show the dialog from
+//! there.
+//!
+//! [`PassphraseInput::can_interact`]: crate::PassphraseInput::can_interact
+
+use std::ffi::c_void;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::windows::io::{AsRawHandle, RawHandle};
+use std::os::windows::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+use std::ptr;
+
+use secrecy::SecretString;
+use zeroize::Zeroizing;
+
+use crate::{Error, Result};
+
+pub(crate) mod session;
+
+/// The process is created without a console window.
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+/// The process is created without access to the caller's console.
+const DETACHED_PROCESS: u32 = 0x0000_0008;
+
+/// Characters read from the console are echoed.
+const ENABLE_ECHO_INPUT: u32 = 0x0004;
+/// Console reads return when a line ending is read.
+const ENABLE_LINE_INPUT: u32 = 0x0002;
+/// Ctrl-C is handled by the system, and line editing keys are processed.
+const ENABLE_PROCESSED_INPUT: u32 = 0x0001;
+
+#[link(name = "user32")]
+extern "system" {
+    fn AllowSetForegroundWindow(process_id: u32) -> i32;
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn WTSGetActiveConsoleSessionId() -> u32;
+    fn ProcessIdToSessionId(process_id: u32, session_id: *mut u32) -> i32;
+    fn GetConsoleMode(console: RawHandle, mode: *mut u32) -> i32;
+    fn SetConsoleMode(console: RawHandle, mode: u32) -> i32;
+    fn ReadConsoleW(
+        console: RawHandle,
+        buffer: *mut u16,
+        chars_to_read: u32,
+        chars_read: *mut u32,
+        input_control: *mut c_void,
+    ) -> i32;
+    fn WriteConsoleW(
+        console: RawHandle,
+        buffer: *const u16,
+        chars_to_write: u32,
+        chars_written: *mut u32,
+        reserved: *mut c_void,
+    ) -> i32;
+}
+
+/// How the spawned `pinentry` process is attached to the caller's console.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Console {
+    /// The `pinentry` process shares the caller's console, if any.
+    Inherit,
+    /// The `pinentry` process is given a new console, which is never shown.
+    NoWindow,
+    /// The `pinentry` process is not attached to any console.
+    Detached,
+}
+
+/// Windows-specific options for spawning a `pinentry` binary.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{windows, PassphraseInput};
+///
+/// # let hwnd = 0;
+/// if let Some(mut input) = PassphraseInput::with_default_binary() {
+///     input
+///         .with_windows_options(windows::Options::new().parent_window(hwnd))
+///         .with_description("Enter passphrase for FooBar")
+///         .interact()?;
+/// }
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Options {
+    parent_window: Option<isize>,
+    console: Console,
+    active_session: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            parent_window: None,
+            console: Console::Inherit,
+            active_session: false,
+        }
+    }
+}
+
+impl Options {
+    /// Creates a new set of options with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the window (given as the value of its `HWND`) that the dialog should be
+    /// shown on top of.
+    ///
+    /// The window is sent to the `pinentry` binary with `OPTION parent-wid`, so that
+    /// GUI pinentries can show a dialog that is modal for it. The `pinentry` process is
+    /// also allowed to bring its dialog to the foreground (via
+    /// `AllowSetForegroundWindow`), which Windows otherwise only permits for the
+    /// process that is in the foreground: without this, the dialog may open behind the
+    /// application's window.
+    pub fn parent_window(&mut self, hwnd: isize) -> &mut Self {
+        self.parent_window = Some(hwnd);
+        self
+    }
+
+    /// Spawns the `pinentry` binary without a visible console window (via
+    /// `CREATE_NO_WINDOW`).
+    ///
+    /// By default, the `pinentry` process shares the caller's console, which GUI
+    /// applications do not have: console-subsystem pinentries then flash up a console
+    /// window of their own.
+    pub fn no_console_window(&mut self) -> &mut Self {
+        self.console = Console::NoWindow;
+        self
+    }
+
+    /// Spawns the `pinentry` binary without access to the caller's console (via
+    /// `DETACHED_PROCESS`).
+    ///
+    /// The `pinentry` process will not receive the console's Ctrl-C and Ctrl-Break
+    /// events, so they will not interrupt it mid-prompt.
+    pub fn detach_console(&mut self) -> &mut Self {
+        self.console = Console::Detached;
+        self
+    }
+
+    /// Spawns the `pinentry` binary into the session of the user who is logged in at
+    /// the console, when the calling process is in session 0 (as services are).
+    ///
+    /// The `pinentry` process is spawned as that user (via `WTSQueryUserToken` and
+    /// `CreateProcessAsUserW`) on their interactive desktop, with their environment
+    /// (plus any variables set on the dialog). This requires the calling process to
+    /// run as `LocalSystem`; otherwise, spawning fails with an I/O error. Spawning
+    /// also fails if no user is logged in at the console, in which case the dialog
+    /// reports that it cannot interact.
+    ///
+    /// Spawn hooks (such as [`PassphraseInput::with_spawn_hook`]) are not called for
+    /// processes spawned this way. Outside of session 0, this option has no effect.
+    ///
+    /// [`PassphraseInput::with_spawn_hook`]: crate::PassphraseInput::with_spawn_hook
+    pub fn in_active_session(&mut self) -> &mut Self {
+        self.active_session = true;
+        self
+    }
+
+    /// Returns the value to send with `OPTION parent-wid`, if any.
+    pub(crate) fn parent_wid(&self) -> Option<isize> {
+        self.parent_window
+    }
+
+    /// Returns true if the `pinentry` process will be able to show a dialog to the user.
+    pub(crate) fn can_display(&self) -> bool {
+        match current_session() {
+            Ok(0) => self.active_session && active_console_session().is_some(),
+            _ => true,
+        }
+    }
+
+    /// Returns the command that will spawn the `pinentry` binary with these options.
+    pub(crate) fn command(&self, binary: &Path) -> Command {
+        let mut command = Command::new(binary);
+        command.creation_flags(self.creation_flags());
+        command
+    }
+
+    /// Returns the process creation flags for the `pinentry` process.
+    fn creation_flags(&self) -> u32 {
+        match self.console {
+            Console::Inherit => 0,
+            Console::NoWindow => CREATE_NO_WINDOW,
+            Console::Detached => DETACHED_PROCESS,
+        }
+    }
+
+    /// Spawns `command` (as returned by [`Options::command`]) into the active console
+    /// session, or returns `None` if it should be spawned as usual.
+    pub(crate) fn session_spawn(&self, command: &Command) -> Option<io::Result<session::Spawned>> {
+        if !self.active_session || current_session().ok()? != 0 {
+            return None;
+        }
+        Some(match active_console_session() {
+            Some(session) => session::spawn(command, self.creation_flags(), session),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no user is logged in at the console",
+            )),
+        })
+    }
+
+    /// Prepares the spawned `pinentry` process to show its dialog.
+    pub(crate) fn spawned(&self, pid: u32) {
+        if self.parent_window.is_some() {
+            // Safety: `AllowSetForegroundWindow` has no memory-safety preconditions. It
+            // fails harmlessly if the calling process cannot grant the permission.
+            unsafe { AllowSetForegroundWindow(pid) };
+        }
+    }
+}
+
+/// Returns the ID of the session attached to the physical console (the session of
+/// the user who is logged in at the machine), or `None` if no session is attached (for
+/// example, while the console is switching between users).
+pub fn active_console_session() -> Option<u32> {
+    // Safety: `WTSGetActiveConsoleSessionId` has no preconditions.
+    match unsafe { WTSGetActiveConsoleSessionId() } {
+        0xffff_ffff => None,
+        session => Some(session),
+    }
+}
+
+/// Returns the ID of the session that the calling process is running in.
+///
+/// Services run in session 0, and interactive logons (including Remote Desktop) in
+/// later sessions.
+pub fn current_session() -> io::Result<u32> {
+    let mut session = 0;
+    // Safety: `session` is a valid pointer.
+    cvt(unsafe { ProcessIdToSessionId(std::process::id(), &mut session) })?;
+    Ok(session)
+}
+
+/// Prompts for a passphrase directly on the console, without echoing it.
+///
+/// This is a fallback for when no `pinentry` binary can be found. The prompt is written
+/// to the console (`CONOUT$`) and the passphrase is read from it (`CONIN$`), even if
+/// stdin and stdout are redirected.
+///
+/// Returns [`Error::Cancelled`] if the user enters Ctrl-Z (end of input), or presses
+/// Ctrl-C while the calling process is handling console control events (otherwise,
+/// Ctrl-C terminates the process as usual). Returns an I/O error if the calling
+/// process has no console.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pinentry::{windows, PassphraseInput};
+///
+/// let passphrase = match PassphraseInput::with_default_binary() {
+///     Some(mut input) => input
+///         .with_description("Enter passphrase for FooBar")
+///         .with_prompt("Passphrase:")
+///         .interact()?,
+///     None => windows::read_passphrase("Enter passphrase for FooBar: ")?,
+/// };
+/// # Ok::<(), pinentry::Error>(())
+/// ```
+pub fn read_passphrase(prompt: &str) -> Result<SecretString> {
+    let input = OpenOptions::new().read(true).write(true).open("CONIN$")?;
+    let output = OpenOptions::new().write(true).open("CONOUT$")?;
+
+    write_console(&output, prompt)?;
+    let mut mode = 0;
+    // Safety: `mode` is a valid pointer, and the handle is open.
+    cvt(unsafe { GetConsoleMode(input.as_raw_handle(), &mut mode) })?;
+    let hidden = (mode & !ENABLE_ECHO_INPUT) | ENABLE_LINE_INPUT | ENABLE_PROCESSED_INPUT;
+    // Safety: the handle is open.
+    cvt(unsafe { SetConsoleMode(input.as_raw_handle(), hidden) })?;
+    let res = read_console_line(&input);
+    // Safety: the handle is open.
+    unsafe { SetConsoleMode(input.as_raw_handle(), mode) };
+    // The user's line ending was not echoed.
+    write_console(&output, "\r\n")?;
+
+    let line = res?;
+    let passphrase =
+        String::from_utf16(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(SecretString::from(passphrase))
+}
+
+/// Reads a line (without its line ending) from the console in line input mode.
+fn read_console_line(input: &File) -> Result<Zeroizing<Vec<u16>>> {
+    let mut line = Zeroizing::new(Vec::with_capacity(256));
+    let mut buf = Zeroizing::new([0u16; 256]);
+    loop {
+        let mut read = 0;
+        // Safety: `buf` is valid for `buf.len()` UTF-16 code units, and `read` is a
+        // valid pointer.
+        let ok = unsafe {
+            ReadConsoleW(
+                input.as_raw_handle(),
+                buf.as_mut_ptr(),
+                buf.len() as u32,
+                &mut read,
+                ptr::null_mut(),
+            )
+        };
+        // A read interrupted by Ctrl-C (if the process handles it) returns nothing.
+        if ok == 0 || read == 0 {
+            return Err(Error::Cancelled);
+        }
+        let chunk = &buf[..read as usize];
+        let end = chunk
+            .iter()
+            .position(|&c| c == u16::from(b'\r') || c == u16::from(b'\n'));
+        let chunk = &chunk[..end.unwrap_or(chunk.len())];
+        if line.len() + chunk.len() > line.capacity() {
+            // Grow without leaving an unwiped copy of the passphrase behind.
+            let mut grown = Zeroizing::new(Vec::with_capacity(2 * (line.len() + chunk.len())));
+            grown.extend_from_slice(&line);
+            line = grown;
+        }
+        line.extend_from_slice(chunk);
+        if end.is_some() {
+            break;
+        }
+    }
+    // Ctrl-Z at the start of a line signals the end of input.
+    if line.first() == Some(&0x1a) {
+        return Err(Error::Cancelled);
+    }
+    Ok(line)
+}
+
+/// Writes the given text to the console.
+fn write_console(output: &File, text: &str) -> io::Result<()> {
+    let text: Vec<u16> = text.encode_utf16().collect();
+    let mut remaining = &text[..];
+    while !remaining.is_empty() {
+        let mut written = 0;
+        // Safety: `remaining` is valid for `remaining.len()` UTF-16 code units, and
+        // `written` is a valid pointer.
+        cvt(unsafe {
+            WriteConsoleW(
+                output.as_raw_handle(),
+                remaining.as_ptr(),
+                remaining.len() as u32,
+                &mut written,
+                ptr::null_mut(),
+            )
+        })?;
+        remaining = &remaining[written as usize..];
+    }
+    Ok(())
+}
+
+fn cvt(ret: i32) -> io::Result<()> {
+    if ret == 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+
+    #[test]
+    fn parent_window() {
+        let mut options = Options::new();
+        assert_eq!(options.parent_wid(), None);
+        options.parent_window(0x1234);
+        assert_eq!(options.parent_wid(), Some(0x1234));
+    }
+}
@@ -0,0 +1,507 @@
+//! Spawning `pinentry` binaries into another user's session (see
+//! [`Options::in_active_session`]).
+//!
+//! [`Options::in_active_session`]: super::Options::in_active_session
+
+use std::collections::BTreeMap;
+use std::ffi::{c_void, OsStr, OsString};
+use std::fs::File;
+use std::io;
+use std::iter;
+use std::mem;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::os::windows::io::{FromRawHandle, RawHandle};
+use std::os::windows::process::ExitStatusExt;
+use std::process::{Command, ExitStatus};
+use std::ptr;
+
+use super::cvt;
+
+/// The standard handles of the process are set in its `STARTUPINFO`.
+const STARTF_USESTDHANDLES: u32 = 0x0000_0100;
+/// The environment block is made of UTF-16 strings.
+const CREATE_UNICODE_ENVIRONMENT: u32 = 0x0000_0400;
+/// The process is created with a `STARTUPINFOEX`.
+const EXTENDED_STARTUPINFO_PRESENT: u32 = 0x0008_0000;
+/// A handle is inherited by child processes.
+const HANDLE_FLAG_INHERIT: u32 = 0x0000_0001;
+/// The handles that the process inherits, instead of every inheritable handle.
+const PROC_THREAD_ATTRIBUTE_HANDLE_LIST: usize = 0x0002_0002;
+/// The process has exited.
+const WAIT_OBJECT_0: u32 = 0;
+/// The process is still running.
+const WAIT_TIMEOUT: u32 = 0x0000_0102;
+/// The buffer passed to a function is too small.
+const ERROR_INSUFFICIENT_BUFFER: i32 = 122;
+
+#[repr(C)]
+struct SecurityAttributes {
+    length: u32,
+    security_descriptor: *mut c_void,
+    inherit_handle: i32,
+}
+
+#[repr(C)]
+struct StartupInfo {
+    cb: u32,
+    reserved: *mut u16,
+    desktop: *mut u16,
+    title: *mut u16,
+    x: u32,
+    y: u32,
+    x_size: u32,
+    y_size: u32,
+    x_count_chars: u32,
+    y_count_chars: u32,
+    fill_attribute: u32,
+    flags: u32,
+    show_window: u16,
+    reserved2_len: u16,
+    reserved2: *mut u8,
+    std_input: RawHandle,
+    std_output: RawHandle,
+    std_error: RawHandle,
+}
+
+#[repr(C)]
+struct StartupInfoEx {
+    startup_info: StartupInfo,
+    attribute_list: *mut c_void,
+}
+
+#[repr(C)]
+struct ProcessInformation {
+    process: RawHandle,
+    thread: RawHandle,
+    process_id: u32,
+    thread_id: u32,
+}
+
+#[link(name = "wtsapi32")]
+extern "system" {
+    fn WTSQueryUserToken(session_id: u32, token: *mut RawHandle) -> i32;
+}
+
+#[link(name = "userenv")]
+extern "system" {
+    fn CreateEnvironmentBlock(environment: *mut *mut c_void, token: RawHandle, inherit: i32)
+        -> i32;
+    fn DestroyEnvironmentBlock(environment: *mut c_void) -> i32;
+}
+
+#[link(name = "advapi32")]
+extern "system" {
+    fn CreateProcessAsUserW(
+        token: RawHandle,
+        application_name: *const u16,
+        command_line: *mut u16,
+        process_attributes: *mut SecurityAttributes,
+        thread_attributes: *mut SecurityAttributes,
+        inherit_handles: i32,
+        creation_flags: u32,
+        environment: *mut c_void,
+        current_directory: *const u16,
+        startup_info: *mut StartupInfo,
+        process_information: *mut ProcessInformation,
+    ) -> i32;
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn CloseHandle(handle: RawHandle) -> i32;
+    fn CreatePipe(
+        read: *mut RawHandle,
+        write: *mut RawHandle,
+        attributes: *mut SecurityAttributes,
+        size: u32,
+    ) -> i32;
+    fn SetHandleInformation(handle: RawHandle, mask: u32, flags: u32) -> i32;
+    fn InitializeProcThreadAttributeList(
+        attribute_list: *mut c_void,
+        attribute_count: u32,
+        flags: u32,
+        size: *mut usize,
+    ) -> i32;
+    fn UpdateProcThreadAttribute(
+        attribute_list: *mut c_void,
+        flags: u32,
+        attribute: usize,
+        value: *mut c_void,
+        size: usize,
+        previous_value: *mut c_void,
+        return_size: *mut usize,
+    ) -> i32;
+    fn DeleteProcThreadAttributeList(attribute_list: *mut c_void);
+    fn TerminateProcess(process: RawHandle, exit_code: u32) -> i32;
+    fn WaitForSingleObject(handle: RawHandle, milliseconds: u32) -> u32;
+    fn GetExitCodeProcess(process: RawHandle, exit_code: *mut u32) -> i32;
+}
+
+/// A handle that is closed when this is dropped.
+struct Handle(RawHandle);
+
+// Safety: kernel object handles can be used from any thread.
+unsafe impl Send for Handle {}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        // Safety: the handle is owned by this, and is not used after this.
+        unsafe { CloseHandle(self.0) };
+    }
+}
+
+/// A `pinentry` process spawned into another session.
+///
+/// Like [`std::process::Child`], the process is not killed when this is dropped.
+pub(crate) struct Child {
+    process: Handle,
+    pid: u32,
+}
+
+impl Child {
+    pub(crate) fn id(&self) -> u32 {
+        self.pid
+    }
+
+    pub(crate) fn kill(&mut self) -> io::Result<()> {
+        // Safety: `self.process` is a valid process handle.
+        if unsafe { TerminateProcess(self.process.0, 1) } != 0 {
+            return Ok(());
+        }
+        let e = io::Error::last_os_error();
+        // Terminating a process that has already exited fails, which `Child::kill`
+        // does not report either.
+        match self.try_wait() {
+            Ok(Some(_)) => Ok(()),
+            _ => Err(e),
+        }
+    }
+
+    pub(crate) fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        // Safety: `self.process` is a valid process handle.
+        match unsafe { WaitForSingleObject(self.process.0, 0) } {
+            WAIT_OBJECT_0 => {
+                let mut code = 0;
+                // Safety: as above, and `code` is valid for writes.
+                cvt(unsafe { GetExitCodeProcess(self.process.0, &mut code) })?;
+                Ok(Some(ExitStatus::from_raw(code)))
+            }
+            WAIT_TIMEOUT => Ok(None),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+/// A process spawned by [`spawn`], with the parent's ends of its standard streams.
+pub(crate) struct Spawned {
+    pub(crate) child: Child,
+    pub(crate) stdin: File,
+    pub(crate) stdout: File,
+    pub(crate) stderr: File,
+}
+
+/// Spawns the program of `command` (which must be a path) with its arguments in the
+/// given session, as the user who is logged in to it, piping its standard streams.
+///
+/// The process gets the user's environment, with the changes made on `command`
+/// applied. Anything else that is configured on `command` is ignored, and
+/// `creation_flags` are used instead.
+pub(crate) fn spawn(command: &Command, creation_flags: u32, session: u32) -> io::Result<Spawned> {
+    let mut token = ptr::null_mut();
+    // Safety: `token` is valid for writes.
+    cvt(unsafe { WTSQueryUserToken(session, &mut token) })?;
+    let token = Handle(token);
+
+    let application = wide(command.get_program())?;
+    let mut command_line = command_line(command)?;
+    let mut environment = environment(&token, command)?;
+    let mut desktop = wide(OsStr::new("winsta0\\default"))?;
+
+    let (child_stdin, stdin) = pipe()?;
+    let (stdout, child_stdout) = pipe()?;
+    let (stderr, child_stderr) = pipe()?;
+    for parent in [&stdin, &stdout, &stderr] {
+        // Safety: `parent.0` is a valid handle.
+        cvt(unsafe { SetHandleInformation(parent.0, HANDLE_FLAG_INHERIT, 0) })?;
+    }
+
+    // Only the child's ends of the pipes are inherited, even if other threads are
+    // creating inheritable handles meanwhile.
+    let mut inherited = [child_stdin.0, child_stdout.0, child_stderr.0];
+    let mut attributes = AttributeList::new()?;
+    // Safety: `inherited` outlives the attribute list's use by `CreateProcessAsUserW`.
+    cvt(unsafe {
+        UpdateProcThreadAttribute(
+            attributes.as_mut_ptr(),
+            0,
+            PROC_THREAD_ATTRIBUTE_HANDLE_LIST,
+            inherited.as_mut_ptr() as *mut c_void,
+            mem::size_of_val(&inherited),
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    })?;
+
+    // Safety: `StartupInfoEx` is valid when zeroed.
+    let mut startup_info: StartupInfoEx = unsafe { mem::zeroed() };
+    startup_info.startup_info.cb = mem::size_of::<StartupInfoEx>() as u32;
+    startup_info.startup_info.desktop = desktop.as_mut_ptr();
+    startup_info.startup_info.flags = STARTF_USESTDHANDLES;
+    startup_info.startup_info.std_input = child_stdin.0;
+    startup_info.startup_info.std_output = child_stdout.0;
+    startup_info.startup_info.std_error = child_stderr.0;
+    startup_info.attribute_list = attributes.as_mut_ptr();
+
+    // Safety: `ProcessInformation` is valid when zeroed.
+    let mut information: ProcessInformation = unsafe { mem::zeroed() };
+    // Safety: every pointer is valid for the duration of the call, and the strings and
+    // the environment block are NUL-terminated.
+    cvt(unsafe {
+        CreateProcessAsUserW(
+            token.0,
+            application.as_ptr(),
+            command_line.as_mut_ptr(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            1,
+            creation_flags | CREATE_UNICODE_ENVIRONMENT | EXTENDED_STARTUPINFO_PRESENT,
+            environment.as_mut_ptr() as *mut c_void,
+            ptr::null(),
+            &mut startup_info.startup_info,
+            &mut information,
+        )
+    })?;
+    drop(Handle(information.thread));
+
+    // The child's ends of the pipes are closed when they are dropped here, so that
+    // reads from the parent's ends see EOF once the child exits.
+    Ok(Spawned {
+        child: Child {
+            process: Handle(information.process),
+            pid: information.process_id,
+        },
+        stdin: stdin.into_file(),
+        stdout: stdout.into_file(),
+        stderr: stderr.into_file(),
+    })
+}
+
+/// Returns `s` as a NUL-terminated UTF-16 string.
+fn wide(s: &OsStr) -> io::Result<Vec<u16>> {
+    let mut wide: Vec<u16> = s.encode_wide().collect();
+    if wide.contains(&0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "nul byte found in provided data",
+        ));
+    }
+    wide.push(0);
+    Ok(wide)
+}
+
+/// Returns the NUL-terminated command line of `command`, quoted in the way that
+/// `CommandLineToArgvW` (and the Microsoft C runtime) unquote it.
+fn command_line(command: &Command) -> io::Result<Vec<u16>> {
+    let program = command.get_program();
+    if program.encode_wide().any(|c| c == u16::from(b'"')) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "program path contains a quote",
+        ));
+    }
+    // The program is always quoted, and its backslashes are never escapes.
+    let mut line = vec![u16::from(b'"')];
+    line.extend(program.encode_wide());
+    line.push(u16::from(b'"'));
+    for arg in command.get_args() {
+        line.push(u16::from(b' '));
+        append_arg(&mut line, arg);
+    }
+    if line.contains(&0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "nul byte found in provided data",
+        ));
+    }
+    line.push(0);
+    Ok(line)
+}
+
+/// Appends `arg` to a command line, quoting it if necessary.
+fn append_arg(line: &mut Vec<u16>, arg: &OsStr) {
+    let quote = arg.is_empty()
+        || arg
+            .encode_wide()
+            .any(|c| c == u16::from(b' ') || c == u16::from(b'\t'));
+    if quote {
+        line.push(u16::from(b'"'));
+    }
+    // Backslashes are only escapes when they precede a quote.
+    let mut backslashes = 0;
+    for c in arg.encode_wide() {
+        if c == u16::from(b'\\') {
+            backslashes += 1;
+        } else {
+            if c == u16::from(b'"') {
+                line.extend(iter::repeat(u16::from(b'\\')).take(backslashes + 1));
+            }
+            backslashes = 0;
+        }
+        line.push(c);
+    }
+    if quote {
+        line.extend(iter::repeat(u16::from(b'\\')).take(backslashes));
+        line.push(u16::from(b'"'));
+    }
+}
+
+/// Returns the environment block of the user with the given token, with the changes
+/// made on `command` applied.
+fn environment(token: &Handle, command: &Command) -> io::Result<Vec<u16>> {
+    let mut block = ptr::null_mut();
+    // Safety: `block` is valid for writes, and `token.0` is a valid token.
+    cvt(unsafe { CreateEnvironmentBlock(&mut block, token.0, 0) })?;
+
+    // Variable names are case-insensitive, and the block is sorted by name.
+    let mut env = BTreeMap::new();
+    let mut entry = block as *const u16;
+    loop {
+        // Safety: the block is a list of NUL-terminated strings, ending with an empty
+        // string.
+        let len = unsafe { (0..).take_while(|&i| *entry.add(i) != 0).count() };
+        if len == 0 {
+            break;
+        }
+        // Safety: `entry` is valid for reads of `len` characters.
+        let var = unsafe { std::slice::from_raw_parts(entry, len) };
+        // Names of hidden variables (such as `=C:`) start with `=`.
+        if let Some(i) = var.iter().skip(1).position(|&c| c == u16::from(b'=')) {
+            let name = OsString::from_wide(&var[..i + 1]);
+            let value = OsString::from_wide(&var[i + 2..]);
+            env.insert(key(&name), (name, value));
+        }
+        // Safety: the next string starts after this one's NUL.
+        entry = unsafe { entry.add(len + 1) };
+    }
+    // Safety: `block` was created by `CreateEnvironmentBlock`.
+    unsafe { DestroyEnvironmentBlock(block) };
+
+    for (name, value) in command.get_envs() {
+        match value {
+            Some(value) => env.insert(key(name), (name.to_owned(), value.to_owned())),
+            None => env.remove(&key(name)),
+        };
+    }
+
+    let mut block = vec![];
+    for (name, value) in env.values() {
+        let mut var = name.clone();
+        var.push("=");
+        var.push(value);
+        let var = wide(&var)?;
+        block.extend_from_slice(&var);
+    }
+    block.push(0);
+    Ok(block)
+}
+
+/// Returns the key by which environment variables with the given name are compared.
+fn key(name: &OsStr) -> OsString {
+    name.to_string_lossy().to_uppercase().into()
+}
+
+/// One end of a pipe.
+struct PipeEnd(RawHandle);
+
+impl PipeEnd {
+    fn into_file(self) -> File {
+        let handle = self.0;
+        mem::forget(self);
+        // Safety: the handle is owned by this, which no longer closes it.
+        unsafe { File::from_raw_handle(handle) }
+    }
+}
+
+impl Drop for PipeEnd {
+    fn drop(&mut self) {
+        // Safety: the handle is owned by this, and is not used after this.
+        unsafe { CloseHandle(self.0) };
+    }
+}
+
+/// Creates a pipe with inheritable handles, returning its read and write ends.
+fn pipe() -> io::Result<(PipeEnd, PipeEnd)> {
+    let mut attributes = SecurityAttributes {
+        length: mem::size_of::<SecurityAttributes>() as u32,
+        security_descriptor: ptr::null_mut(),
+        inherit_handle: 1,
+    };
+    let (mut read, mut write) = (ptr::null_mut(), ptr::null_mut());
+    // Safety: every pointer is valid for the duration of the call.
+    cvt(unsafe { CreatePipe(&mut read, &mut write, &mut attributes, 0) })?;
+    Ok((PipeEnd(read), PipeEnd(write)))
+}
+
+/// A list of attributes for a new process, with room for one attribute.
+struct AttributeList(Vec<u8>);
+
+impl AttributeList {
+    fn new() -> io::Result<Self> {
+        let mut size = 0;
+        // Safety: this only writes the required size.
+        let ret = unsafe { InitializeProcThreadAttributeList(ptr::null_mut(), 1, 0, &mut size) };
+        let e = io::Error::last_os_error();
+        if ret == 0 && e.raw_os_error() != Some(ERROR_INSUFFICIENT_BUFFER) {
+            return Err(e);
+        }
+        let mut list = vec![0; size];
+        // Safety: `list` is valid for writes of `size` bytes.
+        cvt(unsafe {
+            InitializeProcThreadAttributeList(list.as_mut_ptr() as *mut c_void, 1, 0, &mut size)
+        })?;
+        Ok(AttributeList(list))
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut c_void {
+        self.0.as_mut_ptr() as *mut c_void
+    }
+}
+
+impl Drop for AttributeList {
+    fn drop(&mut self) {
+        // Safety: the list was initialized by `InitializeProcThreadAttributeList`.
+        unsafe { DeleteProcThreadAttributeList(self.as_mut_ptr()) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::command_line;
+
+    #[test]
+    fn quoting() {
+        let line = |command: &mut Command| {
+            let mut line = command_line(command).unwrap();
+            assert_eq!(line.pop(), Some(0));
+            String::from_utf16(&line).unwrap()
+        };
+        assert_eq!(
+            line(&mut Command::new(r"C:\Program Files\pinentry.exe")),
+            r#""C:\Program Files\pinentry.exe""#,
+        );
+        assert_eq!(
+            line(Command::new("pinentry.exe").args([
+                "--display",
+                "",
+                "two words",
+                r#"say "hi""#,
+                r"C:\dir\",
+                r"C:\some dir\",
+            ])),
+            r#""pinentry.exe" --display "" "two words" "say \"hi\"" C:\dir\ "C:\some dir\\""#,
+        );
+        assert!(command_line(&Command::new(r#"pin"entry.exe"#)).is_err());
+    }
+}